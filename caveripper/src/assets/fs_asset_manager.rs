@@ -2,14 +2,18 @@ use std::{
     fs::{read, read_dir, read_to_string},
     path::{Path, PathBuf},
     pin::Pin,
+    sync::OnceLock,
 };
 
 use encoding_rs::SHIFT_JIS;
 use error_stack::{Report, Result, ResultExt};
 use image::RgbaImage;
-use log::info;
+use log::{info, warn};
 
-use super::{parse_treasure_config, pinmap::PinMap, AssetManager, CaveConfig, ImageKind, Treasure};
+use super::{
+    load_cave_floors, parse_treasure_config, pinmap::PinMap, AssetManager, CaveConfig, DisplayNames, ImageKind, TekiSizes, Treasure,
+    DISPLAY_NAMES_PATH, TEKI_SIZES_PATH,
+};
 use crate::{
     caveinfo::CaveInfo,
     errors::CaveripperError,
@@ -33,6 +37,12 @@ pub struct FsAssetManager {
 
     /// All known room names.
     rooms: PinMap<String, Vec<String>>,
+
+    /// Loaded the first time a teki is drawn.
+    teki_sizes: OnceLock<TekiSizes>,
+
+    /// Loaded the first time a display name is looked up.
+    display_names: OnceLock<DisplayNames>,
 }
 
 impl AssetManager for FsAssetManager {
@@ -94,6 +104,23 @@ impl AssetManager for FsAssetManager {
             })
     }
 
+    // Checks the cached lists directly rather than combining them like `all_teki` does.
+    fn has_teki(&self, game: Option<&str>, name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        self.games
+            .iter()
+            .filter(|g| game.is_none_or(|v| v == *g))
+            .any(|game| self.teki_for_game(game).is_ok_and(|teki| teki.contains(&name)))
+    }
+
+    fn has_unit(&self, game: Option<&str>, name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        self.games
+            .iter()
+            .filter(|g| game.is_none_or(|v| v == *g))
+            .any(|game| self.units_for_game(game).is_ok_and(|units| units.contains(&name)))
+    }
+
     /// Get a file as raw bytes. Does not cache the file.
     fn load_raw<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>, CaveripperError> {
         let path = path.as_ref();
@@ -134,6 +161,28 @@ impl AssetManager for FsAssetManager {
         Ok(text)
     }
 
+    fn teki_sizes(&self) -> &TekiSizes {
+        self.teki_sizes.get_or_init(|| {
+            self.load_txt(TEKI_SIZES_PATH)
+                .and_then(|txt| TekiSizes::parse_from_file(&txt))
+                .unwrap_or_else(|e| {
+                    warn!("Couldn't load teki sizes, so every teki will be drawn at the normal size: {e:?}");
+                    TekiSizes::default()
+                })
+        })
+    }
+
+    fn display_names(&self) -> &DisplayNames {
+        self.display_names.get_or_init(|| {
+            self.load_txt(DISPLAY_NAMES_PATH)
+                .and_then(|txt| DisplayNames::parse_from_file(&txt))
+                .unwrap_or_else(|e| {
+                    warn!("Couldn't load display names, so everything will be shown by its internal name: {e:?}");
+                    DisplayNames::default()
+                })
+        })
+    }
+
     fn load_caveinfo<'a>(&'a self, sublevel: &Sublevel) -> Result<&'a CaveInfo, CaveripperError> {
         if let Some(value) = self.caveinfo_cache.get(sublevel)
             && !sublevel.cfg.game.eq_ignore_ascii_case(DIRECT_MODE_TAG)
@@ -186,10 +235,7 @@ impl FsAssetManager {
     pub const ASSET_VERSION: u32 = 1;
 
     pub fn init() -> Result<FsAssetManager, CaveripperError> {
-        let mut asset_dir = dirs::home_dir()
-            .ok_or(CaveripperError::AssetLoadingError)
-            .attach_printable("Couldn't access home directory!")?;
-        asset_dir.push(".config/caveripper");
+        let asset_dir = Self::default_asset_dir()?;
 
         let resources_dir = asset_dir.join("resources");
         if !resources_dir.is_dir() {
@@ -211,16 +257,58 @@ impl FsAssetManager {
             .map(|dir_entry| dir_entry.unwrap().file_name().to_str().unwrap().to_string())
             .collect::<Vec<String>>();
 
-        Ok(Self {
+        Ok(Self::with_config(asset_dir, cave_cfg, games_with_assets))
+    }
+
+    /// Like [FsAssetManager::init], but doesn't require the resources and assets
+    /// folders to exist or be readable. Anything that can't be read is left empty.
+    /// Used to diagnose broken installs without failing immediately.
+    pub fn init_unchecked() -> Result<FsAssetManager, CaveripperError> {
+        let asset_dir = Self::default_asset_dir()?;
+
+        let cave_cfg = read_to_string(asset_dir.join("resources/caveinfo_config.txt"))
+            .map(|txt| CaveConfig::parse_from_file(&txt))
+            .unwrap_or_default();
+
+        let games_with_assets = read_dir(asset_dir.join("assets"))
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.path().is_dir())
+                    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self::with_config(asset_dir, cave_cfg, games_with_assets))
+    }
+
+    fn default_asset_dir() -> Result<PathBuf, CaveripperError> {
+        let mut asset_dir = dirs::home_dir()
+            .ok_or(CaveripperError::AssetLoadingError)
+            .attach_printable("Couldn't access home directory!")?;
+        asset_dir.push(".config/caveripper");
+        Ok(asset_dir)
+    }
+
+    fn with_config(asset_dir: PathBuf, cave_cfg: Vec<CaveConfig>, games: Vec<String>) -> FsAssetManager {
+        Self {
             asset_dir,
             caveinfo_cache: PinMap::new(),
             img_cache: PinMap::new(),
             cave_cfg,
-            games: games_with_assets,
+            games,
             treasures: PinMap::new(),
             teki: PinMap::new(),
             rooms: PinMap::new(),
-        })
+            teki_sizes: OnceLock::new(),
+            display_names: OnceLock::new(),
+        }
+    }
+
+    /// The folder that assets and resources are loaded from.
+    pub fn asset_dir(&self) -> &Path {
+        &self.asset_dir
     }
 
     fn teki_for_game(&self, game: &str) -> Result<&Vec<String>, CaveripperError> {
@@ -336,13 +424,6 @@ impl FsAssetManager {
     pub fn caveinfos_from_cave(&self, compound_name: &str) -> Result<Vec<&CaveInfo>, CaveripperError> {
         let (game_name, cave_name) = compound_name.split_once(':').unwrap_or(("pikmin2", compound_name));
         let cfg = self.get_cave_cfg(cave_name, Some(game_name), false)?;
-
-        let mut floor = 1;
-        let mut caveinfos = Vec::new();
-        while let Ok(caveinfo) = self.load_caveinfo(&Sublevel::from_cfg(cfg, floor)) {
-            caveinfos.push(caveinfo);
-            floor += 1;
-        }
-        Ok(caveinfos)
+        Ok(load_cave_floors(self, cfg))
     }
 }
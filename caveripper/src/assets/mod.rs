@@ -1,6 +1,8 @@
 #[cfg(not(feature = "wasm"))]
 pub mod fs_asset_manager;
 pub mod pinmap;
+#[cfg(test)]
+mod test;
 
 use std::{
     collections::HashMap,
@@ -8,7 +10,7 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use error_stack::Result;
+use error_stack::{report, Result, ResultExt};
 use image::RgbaImage;
 use itertools::Itertools;
 use serde::Serialize;
@@ -25,8 +27,102 @@ pub trait AssetManager {
     fn all_units(&self, game: Option<&str>) -> Result<Vec<String>, CaveripperError>;
     fn all_treasures(&self, game: Option<&str>) -> Result<Vec<Treasure>, CaveripperError>;
 
+    /// Whether a teki with this internal name exists in the given game, or in any game
+    /// if `game` is None. Case insensitive. Assets that fail to load count as not
+    /// containing the teki.
+    fn has_teki(&self, game: Option<&str>, name: &str) -> bool {
+        self.all_teki(game)
+            .is_ok_and(|teki| teki.iter().any(|t| t.eq_ignore_ascii_case(name)))
+    }
+
+    /// Same as [AssetManager::has_teki], but for map units.
+    fn has_unit(&self, game: Option<&str>, name: &str) -> bool {
+        self.all_units(game)
+            .is_ok_and(|units| units.iter().any(|u| u.eq_ignore_ascii_case(name)))
+    }
+
+    /// Same as [AssetManager::has_teki], but for treasures.
+    fn has_treasure(&self, game: Option<&str>, name: &str) -> bool {
+        self.all_treasures(game)
+            .is_ok_and(|treasures| treasures.iter().any(|t| t.internal_name.eq_ignore_ascii_case(name)))
+    }
+
     fn get_treasure_info(&self, game: &str, name: &str) -> Result<&Treasure, CaveripperError>;
     fn get_cave_cfg(&self, name: &str, game: Option<&str>, force_challenge_mode: bool) -> Result<&CaveConfig, CaveripperError>;
+
+    /// Teki that should be drawn larger than normal. See [TekiSizes].
+    fn teki_sizes(&self) -> &TekiSizes;
+
+    /// Friendly names for teki and treasures. See [DisplayNames].
+    fn display_names(&self) -> &DisplayNames;
+
+    /// The name players know a teki or treasure by, e.g. "Violet Candypop Bud" for
+    /// `blackpom`. `None` if there's no translation for it, in which case callers should
+    /// show the internal name instead.
+    fn display_name(&self, kind: NameKind, internal_name: &str) -> Option<String> {
+        self.display_names().get(kind, internal_name).map(str::to_string)
+    }
+}
+
+/// Resource files that every install needs regardless of which games have been
+/// extracted, relative to the asset directory.
+const REQUIRED_RESOURCES: [&str; 6] = [
+    "resources/caveinfo_config.txt",
+    "resources/BalooChettan2-SemiBold.ttf",
+    "resources/BalooChettan2-ExtraBold.ttf",
+    "resources/enemytex_special/pod_icon.png",
+    "resources/enemytex_special/36px-Clog_icon.png",
+    "resources/enemytex_special/Gray_bramble_gate_icon.png",
+];
+
+/// Checks that the files Caveripper needs are present and readable, returning a
+/// description of each problem found. An empty result means the install is complete.
+///
+/// `games` should be the names of all games with extracted assets. At least one is
+/// required, and each must have its treasure configs and every CaveInfo file listed
+/// for it in `cave_cfg`.
+pub fn find_missing_assets(mgr: &impl AssetManager, cave_cfg: &[CaveConfig], games: &[String]) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    for path in REQUIRED_RESOURCES {
+        if mgr.load_raw(path).is_err() {
+            problems.push(format!("Missing or unreadable resource file: {path}"));
+        }
+    }
+
+    if games.is_empty() {
+        problems.push("No extracted games found in the assets folder. Run `caveripper extract` on a game ISO first.".to_string());
+    }
+
+    for game in games {
+        for config_file in ["otakara_config.txt", "item_config.txt"] {
+            let path = PathBuf::from_iter(["assets", game, config_file]);
+            if mgr.load_txt(&path).is_err() {
+                problems.push(format!("Missing or unreadable treasure config: {}", path.to_string_lossy()));
+            }
+        }
+
+        for cfg in cave_cfg.iter().filter(|cfg| cfg.game.eq_ignore_ascii_case(game)) {
+            let path = cfg.get_caveinfo_path();
+            if mgr.load_txt(&path).is_err() {
+                problems.push(format!(
+                    "Missing or unreadable CaveInfo for {}: {}",
+                    cfg.full_name,
+                    path.to_string_lossy()
+                ));
+            }
+        }
+    }
+
+    problems
+}
+
+/// Loads the CaveInfo for each floor of the given cave in order, stopping at the
+/// first floor that doesn't exist.
+pub fn load_cave_floors<'a>(mgr: &'a impl AssetManager, cfg: &CaveConfig) -> Vec<&'a CaveInfo> {
+    (1..)
+        .map_while(|floor| mgr.load_caveinfo(&Sublevel::from_cfg(cfg, floor)).ok())
+        .collect()
 }
 
 #[derive(PartialEq)]
@@ -92,6 +188,123 @@ impl CaveConfig {
     }
 }
 
+/// Resource file listing teki that should be drawn larger than normal, relative to the
+/// asset directory. Optional; if it's missing every teki is drawn at the normal size.
+pub const TEKI_SIZES_PATH: &str = "resources/teki_sizes.txt";
+
+/// How much larger than normal to draw certain teki in layout images, e.g. bosses, so
+/// their sprites aren't shrunk down to the size of a Bulborb. Defined in
+/// resources/teki_sizes.txt.
+///
+/// Each line of the file is `game, internal name, scale`, where a scale of 2.0 draws the
+/// teki twice as wide and tall as normal. Blank lines and lines starting with `#` are
+/// ignored. Romhacks mostly reuse vanilla teki, so a romhack teki without its own entry
+/// uses the pikmin2 entry of the same name if there is one.
+#[derive(Debug, Clone, Default)]
+pub struct TekiSizes {
+    /// Keyed by game, then by lowercase internal name.
+    scales: HashMap<String, HashMap<String, f32>>,
+}
+
+impl TekiSizes {
+    pub fn parse_from_file(file_txt: &str) -> Result<TekiSizes, CaveripperError> {
+        let mut sizes = TekiSizes::default();
+        for line in file_txt
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        {
+            let [game, name, scale] = line.split(',').map(str::trim).collect_vec()[..] else {
+                return Err(report!(CaveripperError::AssetLoadingError))
+                    .attach_printable_lazy(|| format!("Invalid teki size line '{line}'"));
+            };
+            let scale: f32 = scale
+                .parse()
+                .ok()
+                .filter(|scale: &f32| *scale > 0.0)
+                .ok_or(report!(CaveripperError::AssetLoadingError))
+                .attach_printable_lazy(|| format!("Invalid teki scale '{scale}' for {name}"))?;
+            sizes
+                .scales
+                .entry(game.to_ascii_lowercase())
+                .or_default()
+                .insert(name.to_ascii_lowercase(), scale);
+        }
+        Ok(sizes)
+    }
+
+    /// How many times larger than normal to draw the named teki. 1.0 if it isn't listed.
+    pub fn scale(&self, game: &str, internal_name: &str) -> f32 {
+        let name = internal_name.to_ascii_lowercase();
+        [game.to_ascii_lowercase().as_str(), "pikmin2"]
+            .into_iter()
+            .find_map(|game| self.scales.get(game).and_then(|scales| scales.get(&name)))
+            .copied()
+            .unwrap_or(1.0)
+    }
+}
+
+/// Resource file mapping internal teki and treasure names to the names players know them
+/// by, relative to the asset directory. Optional; if it's missing everything is shown by
+/// its internal name.
+pub const DISPLAY_NAMES_PATH: &str = "resources/display_names.txt";
+
+/// Which kind of object an internal name refers to when looking up its display name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NameKind {
+    Teki,
+    Treasure,
+}
+
+/// Friendly names for teki and treasures, shown in rendered labels and text output in
+/// place of internal names like `blackpom`. Only used for display; queries still take
+/// internal names. Defined in resources/display_names.txt.
+///
+/// Each line of the file is `kind, internal name, display name`, where kind is `teki` or
+/// `treasure`. Everything after the second comma is the display name, so it can contain
+/// commas itself. Blank lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct DisplayNames {
+    /// Keyed by kind and lowercase internal name.
+    names: HashMap<(NameKind, String), String>,
+}
+
+impl DisplayNames {
+    pub fn parse_from_file(file_txt: &str) -> Result<DisplayNames, CaveripperError> {
+        let mut display_names = DisplayNames::default();
+        for line in file_txt
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        {
+            let [kind, name, display_name] = line.splitn(3, ',').map(str::trim).collect_vec()[..] else {
+                return Err(report!(CaveripperError::AssetLoadingError))
+                    .attach_printable_lazy(|| format!("Invalid display name line '{line}'"));
+            };
+            let kind = match kind.to_ascii_lowercase().as_str() {
+                "teki" => NameKind::Teki,
+                "treasure" => NameKind::Treasure,
+                _ => {
+                    return Err(report!(CaveripperError::AssetLoadingError))
+                        .attach_printable_lazy(|| format!("Invalid display name kind '{kind}' for {name}"));
+                }
+            };
+            if display_name.is_empty() {
+                return Err(report!(CaveripperError::AssetLoadingError)).attach_printable_lazy(|| format!("Empty display name for {name}"));
+            }
+            display_names
+                .names
+                .insert((kind, name.to_ascii_lowercase()), display_name.to_string());
+        }
+        Ok(display_names)
+    }
+
+    /// The display name of the given teki or treasure, if it has one. Case insensitive.
+    pub fn get(&self, kind: NameKind, internal_name: &str) -> Option<&str> {
+        self.names.get(&(kind, internal_name.to_ascii_lowercase())).map(String::as_str)
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Default)]
 pub struct Treasure {
     pub internal_name: String,
@@ -0,0 +1,77 @@
+use super::{fs_asset_manager::FsAssetManager, AssetManager, DisplayNames, NameKind, TekiSizes};
+use crate::sublevel::Sublevel;
+
+#[test]
+fn test_has_asset_names() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+
+    assert!(mgr.has_teki(None, "MiniHoudai"));
+    assert!(mgr.has_teki(Some("pikmin2"), "kogane"));
+    assert!(!mgr.has_teki(None, "not_a_teki"));
+
+    assert!(mgr.has_treasure(Some("pikmin2"), "bey_goma"));
+    assert!(!mgr.has_treasure(None, "not_a_treasure"));
+
+    assert!(mgr.has_unit(None, "room_north4_1_tsuchi"));
+    assert!(!mgr.has_unit(None, "not_a_unit"));
+
+    assert!(!mgr.has_teki(Some("not_a_game"), "kogane"));
+}
+
+#[test]
+fn test_sublevel_resolved_game() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+
+    let vanilla = Sublevel::try_from_str("scx6", &mgr).unwrap();
+    assert_eq!(vanilla.resolved_game(), "pikmin2");
+    assert_eq!(vanilla.resolved_name(), "Subterranean Complex 6 [pikmin2]");
+
+    let romhack = Sublevel::try_from_str("216:tr12", &mgr).unwrap();
+    assert_eq!(romhack.resolved_game(), "216");
+    assert!(romhack.resolved_name().ends_with(" [216]"), "{}", romhack.resolved_name());
+}
+
+#[test]
+fn test_teki_sizes() {
+    let sizes = TekiSizes::parse_from_file(
+        "# comment\n\
+         pikmin2, BigFoot, 2.0\n\
+         \n\
+         216, CustomBoss, 1.5\n\
+         216, BigFoot, 1.0\n",
+    )
+    .unwrap();
+    assert_eq!(sizes.scale("pikmin2", "bigfoot"), 2.0);
+    assert_eq!(sizes.scale("pikmin2", "Chappy"), 1.0);
+    assert_eq!(sizes.scale("216", "CustomBoss"), 1.5);
+    assert_eq!(sizes.scale("216", "BigFoot"), 1.0);
+    // Romhack teki without their own entry fall back to the vanilla one.
+    assert_eq!(sizes.scale("newyear", "BigFoot"), 2.0);
+    assert_eq!(sizes.scale("pikmin2", "CustomBoss"), 1.0);
+
+    assert!(TekiSizes::parse_from_file("pikmin2, BigFoot").is_err());
+    assert!(TekiSizes::parse_from_file("pikmin2, BigFoot, big").is_err());
+    assert!(TekiSizes::parse_from_file("pikmin2, BigFoot, 0").is_err());
+}
+
+#[test]
+fn test_display_names() {
+    let names = DisplayNames::parse_from_file(
+        "# comment\n\
+         teki, BlackPom, Violet Candypop Bud\n\
+         \n\
+         treasure, some_treasure, Name, with a comma\n",
+    )
+    .unwrap();
+    assert_eq!(names.get(NameKind::Teki, "blackpom"), Some("Violet Candypop Bud"));
+    assert_eq!(names.get(NameKind::Treasure, "Some_Treasure"), Some("Name, with a comma"));
+    assert_eq!(names.get(NameKind::Treasure, "blackpom"), None);
+    assert_eq!(names.get(NameKind::Teki, "chappy"), None);
+
+    assert!(DisplayNames::parse_from_file("teki, BlackPom").is_err());
+    assert!(DisplayNames::parse_from_file("teki, BlackPom, ").is_err());
+    assert!(DisplayNames::parse_from_file("plant, BlackPom, Violet Candypop Bud").is_err());
+
+    // The bundled table should always parse.
+    assert!(DisplayNames::parse_from_file(include_str!("../../../resources/display_names.txt")).is_ok());
+}
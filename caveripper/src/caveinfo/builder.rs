@@ -0,0 +1,119 @@
+use error_stack::{report, Result, ResultExt};
+
+use super::{CaveInfo, TekiInfo};
+use crate::errors::CaveripperError;
+
+/// Makes a modified copy of a [CaveInfo] for sensitivity analysis, e.g. to see how
+/// layouts change if a teki's filler weight were higher. Start one with
+/// [CaveInfo::to_builder].
+///
+/// Every CaveInfo produced by [CaveInfoBuilder::build] is marked as modified, even if
+/// nothing was changed, and so is the sublevel of every layout generated from it. This
+/// keeps modified layouts from being mistaken for real seeds.
+#[derive(Debug, Clone)]
+pub struct CaveInfoBuilder {
+    caveinfo: CaveInfo,
+}
+
+impl CaveInfoBuilder {
+    pub(super) fn new(caveinfo: &CaveInfo) -> Self {
+        CaveInfoBuilder {
+            caveinfo: caveinfo.clone(),
+        }
+    }
+
+    pub fn max_main_objects(mut self, amount: u32) -> Self {
+        self.caveinfo.max_main_objects = amount;
+        self
+    }
+
+    pub fn max_treasures(mut self, amount: u32) -> Self {
+        self.caveinfo.max_treasures = amount;
+        self
+    }
+
+    pub fn max_gates(mut self, amount: u32) -> Self {
+        self.caveinfo.max_gates = amount;
+        self
+    }
+
+    pub fn num_rooms(mut self, amount: u32) -> Self {
+        self.caveinfo.num_rooms = amount;
+        self
+    }
+
+    /// In range [0-1], same as [CaveInfo::corridor_probability].
+    pub fn corridor_probability(mut self, probability: f32) -> Self {
+        self.caveinfo.corridor_probability = probability;
+        self
+    }
+
+    /// In range [0-1], same as [CaveInfo::cap_probability].
+    pub fn cap_probability(mut self, probability: f32) -> Self {
+        self.caveinfo.cap_probability = probability;
+        self
+    }
+
+    /// Sets the filler distribution weight of every TekiInfo entry with the given internal
+    /// name. Fails if the sublevel has no such teki.
+    pub fn teki_weight(self, internal_name: &str, weight: u32) -> Result<Self, CaveripperError> {
+        self.modify_teki(internal_name, |teki| teki.filler_distribution_weight = weight)
+    }
+
+    /// Sets the minimum amount of every TekiInfo entry with the given internal name.
+    /// Fails if the sublevel has no such teki.
+    pub fn teki_min_amount(self, internal_name: &str, amount: u32) -> Result<Self, CaveripperError> {
+        self.modify_teki(internal_name, |teki| teki.minimum_amount = amount)
+    }
+
+    /// Sets the filler distribution weight of every CapInfo entry with the given internal
+    /// name. Fails if the sublevel has no such cap teki.
+    pub fn cap_teki_weight(mut self, internal_name: &str, weight: u32) -> Result<Self, CaveripperError> {
+        let mut found = false;
+        for cap in self
+            .caveinfo
+            .cap_info
+            .iter_mut()
+            .filter(|cap| cap.internal_name.eq_ignore_ascii_case(internal_name))
+        {
+            cap.filler_distribution_weight = weight;
+            found = true;
+        }
+        if found {
+            Ok(self)
+        } else {
+            Err(report!(CaveripperError::CaveinfoError)).attach_printable_lazy(|| format!("No cap teki named '{internal_name}'"))
+        }
+    }
+
+    /// Generates layouts as if this sublevel were in Challenge Mode (`true`) or Story
+    /// Mode (`false`), regardless of which one it's actually from. The sublevel keeps
+    /// its name either way.
+    pub fn challenge_mode(mut self, challenge_mode: bool) -> Self {
+        self.caveinfo.challenge_mode_override = Some(challenge_mode);
+        self
+    }
+
+    pub fn build(mut self) -> CaveInfo {
+        self.caveinfo.modified = true;
+        self.caveinfo
+    }
+
+    fn modify_teki(mut self, internal_name: &str, f: impl Fn(&mut TekiInfo)) -> Result<Self, CaveripperError> {
+        let mut found = false;
+        for teki in self
+            .caveinfo
+            .teki_info
+            .iter_mut()
+            .filter(|teki| teki.internal_name.eq_ignore_ascii_case(internal_name))
+        {
+            f(teki);
+            found = true;
+        }
+        if found {
+            Ok(self)
+        } else {
+            Err(report!(CaveripperError::CaveinfoError)).attach_printable_lazy(|| format!("No teki named '{internal_name}'"))
+        }
+    }
+}
@@ -1,3 +1,5 @@
+mod builder;
+mod diff;
 mod error;
 mod parse;
 /// CaveInfo is a representation of the generation parameters for a given
@@ -18,14 +20,17 @@ use std::{
     fmt::{Display, Formatter},
 };
 
+pub use builder::CaveInfoBuilder;
+pub use diff::diff_caveinfo;
 use error_stack::{report, Report, Result, ResultExt};
-use parse::parse_caveinfo;
+use parse::{parse_caveinfo, parse_caveinfo_txt};
 use serde::Serialize;
 
 use crate::{
-    assets::{AssetManager, CaveConfig},
+    assets::{AssetManager, CaveConfig, NameKind},
     errors::CaveripperError,
     point::Point,
+    sublevel::MODIFIED_TAG,
 };
 
 /// Corresponds to one "FloorInfo" segment in a CaveInfo file, plus all the
@@ -52,6 +57,8 @@ pub struct CaveInfo {
     pub cap_info: Vec<CapInfo>,
     pub is_final_floor: bool,
     pub waterwraith_timer: f32, // How long, in seconds, it'll take for the waterwraith to fall. 0 = never.
+    pub modified: bool,         // Not part of the file format. Set on CaveInfos made with CaveInfoBuilder.
+    pub challenge_mode_override: Option<bool>, // Not part of the file format. See CaveInfoBuilder::challenge_mode.
 }
 
 impl CaveInfo {
@@ -60,6 +67,14 @@ impl CaveInfo {
         self.teki_info.iter().filter(move |teki| teki.group == group)
     }
 
+    /// Every room that the ship can be placed in at the start of generation, i.e. rooms
+    /// with a group 7 spawnpoint. Each unit is only returned once, in its unrotated form.
+    pub fn possible_start_rooms(&self) -> impl Iterator<Item = &CaveUnit> {
+        self.cave_units
+            .iter()
+            .filter(|unit| unit.rotation == 0 && unit.has_start_spawnpoint())
+    }
+
     /// Out of all the possible map tiles on this floor, finds the one with the highest
     /// number of doors and returns that number.
     pub fn max_num_doors_single_unit(&self) -> usize {
@@ -69,6 +84,15 @@ impl CaveInfo {
     /// Returns the human-readable sublevel name for this floor, e.g. "SCx6".
     /// Not part of the generation algorithm at all.
     pub fn name(&self) -> String {
+        let name = self.unmodified_name();
+        if self.modified {
+            format!("{name}-{MODIFIED_TAG}")
+        } else {
+            name
+        }
+    }
+
+    fn unmodified_name(&self) -> String {
         if self.cave_cfg.is_challenge_mode {
             format!(
                 "{}-{}{}",
@@ -88,7 +112,16 @@ impl CaveInfo {
 
     /// Constructs the long name of this sublevel, e.g. "Subterranean Complex 3" with the full cave name.
     pub fn long_name(&self) -> String {
-        format!("{} {}", self.cave_cfg.full_name, self.floor_num + 1)
+        if self.modified {
+            format!("{} {} ({MODIFIED_TAG})", self.cave_cfg.full_name, self.floor_num + 1)
+        } else {
+            format!("{} {}", self.cave_cfg.full_name, self.floor_num + 1)
+        }
+    }
+
+    /// Starts a modified copy of this CaveInfo. See [CaveInfoBuilder].
+    pub fn to_builder(&self) -> CaveInfoBuilder {
+        CaveInfoBuilder::new(self)
     }
 
     pub fn parse_from(cave: &CaveConfig, mgr: &impl AssetManager) -> Result<Vec<CaveInfo>, CaveripperError> {
@@ -97,13 +130,53 @@ impl CaveInfo {
             .attach_printable_lazy(|| format!("{} ({}/{})", cave.full_name, cave.game, cave.caveinfo_filename))
     }
 
+    /// Parses the text of a CaveInfo file that isn't in the assets folder, e.g. one being
+    /// edited for a romhack. Returns one CaveInfo per floor. Unit files and map units are
+    /// loaded from the assets of `cave.game`.
+    pub fn parse_from_txt(caveinfo_txt: &str, cave: &CaveConfig, mgr: &impl AssetManager) -> Result<Vec<CaveInfo>, CaveripperError> {
+        parse_caveinfo_txt(caveinfo_txt, cave, mgr)
+            .change_context(CaveripperError::CaveinfoError)
+            .attach_printable_lazy(|| format!("{} ({})", cave.full_name, cave.caveinfo_filename))
+    }
+
+    /// Whether layouts are generated the Challenge Mode way, which places the hole and
+    /// treasures differently. Normally this comes from the cave config, but it can be
+    /// overridden with [CaveInfoBuilder::challenge_mode].
     pub fn is_challenge_mode(&self) -> bool {
-        self.cave_cfg.is_challenge_mode
+        self.challenge_mode_override.unwrap_or(self.cave_cfg.is_challenge_mode)
     }
 }
 
 impl Display for CaveInfo {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.write_summary(f, |_, internal_name| internal_name.to_string())
+    }
+}
+
+/// See [CaveInfo::with_display_names].
+struct CaveInfoWithDisplayNames<'a, M: AssetManager> {
+    caveinfo: &'a CaveInfo,
+    mgr: &'a M,
+}
+
+impl<M: AssetManager> Display for CaveInfoWithDisplayNames<'_, M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.caveinfo.write_summary(f, |kind, internal_name| {
+            self.mgr
+                .display_name(kind, internal_name)
+                .unwrap_or_else(|| internal_name.to_string())
+        })
+    }
+}
+
+impl CaveInfo {
+    /// The same text summary as the [Display] impl, but with teki and treasures listed by
+    /// their display names where they have one. See [AssetManager::display_name].
+    pub fn with_display_names<'a, M: AssetManager>(&'a self, mgr: &'a M) -> impl Display + 'a {
+        CaveInfoWithDisplayNames { caveinfo: self, mgr }
+    }
+
+    fn write_summary(&self, f: &mut Formatter<'_>, name: impl Fn(NameKind, &str) -> String) -> std::fmt::Result {
         writeln!(
             f,
             "NumRooms: {}\tNumGates: {}\tCorridorBetweenRoomsProb: {}%\tCapVsHallProb: {}%",
@@ -130,7 +203,9 @@ impl Display for CaveInfo {
             write!(
                 f,
                 "\t{} (group: {}, num: {}",
-                tekiinfo.internal_name, tekiinfo.group, tekiinfo.minimum_amount
+                name(NameKind::Teki, &tekiinfo.internal_name),
+                tekiinfo.group,
+                tekiinfo.minimum_amount
             )?;
             if tekiinfo.filler_distribution_weight > 0 {
                 write!(f, ", weight: {}", tekiinfo.filler_distribution_weight)?;
@@ -140,19 +215,25 @@ impl Display for CaveInfo {
             }
             write!(f, ")")?;
             if let Some(carrying) = &tekiinfo.carrying {
-                write!(f, " Carrying: {carrying}")?;
+                write!(f, " Carrying: {}", name(NameKind::Treasure, carrying))?;
             }
             writeln!(f)?;
         }
 
         writeln!(f, "Treasures:")?;
         for (i, iteminfo) in self.item_info.iter().enumerate() {
-            writeln!(f, "\t{}: {}", i + 1, iteminfo.internal_name)?;
+            writeln!(f, "\t{}: {}", i + 1, name(NameKind::Treasure, &iteminfo.internal_name))?;
         }
 
         writeln!(f, "Cap Teki:")?;
         for (i, capinfo) in self.cap_info.iter().enumerate() {
-            write!(f, "\t{}: {} (num: {}", i + 1, capinfo.internal_name, capinfo.minimum_amount)?;
+            write!(
+                f,
+                "\t{}: {} (num: {}",
+                i + 1,
+                name(NameKind::Teki, &capinfo.internal_name),
+                capinfo.minimum_amount
+            )?;
             if capinfo.filler_distribution_weight > 0 {
                 write!(f, ", weight: {}", capinfo.filler_distribution_weight)?;
             }
@@ -363,6 +444,15 @@ impl CaveUnit {
         new_unit
     }
 
+    /// Whether this is an alcove that objects can spawn in, as opposed to an empty cap that
+    /// only closes off a door. The generator tells the two apart purely by name: dead end
+    /// units with "item" in their folder name are alcoves, and the rest (usually named
+    /// `cap...`) stay empty. Cap teki, alcove treasures, and the gates placed in front of
+    /// alcoves only ever go in these, using the group 9 spawn point added to them when parsing.
+    pub fn is_item_alcove(&self) -> bool {
+        self.room_type == RoomType::DeadEnd && self.unit_folder_name.contains("item")
+    }
+
     pub fn has_start_spawnpoint(&self) -> bool {
         self.spawnpoints.iter().any(|spawnpoint| spawnpoint.group == 7)
     }
@@ -444,6 +534,7 @@ pub struct DoorLink {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RoomType {
     Room,
     Hallway,
@@ -0,0 +1,254 @@
+use std::{collections::BTreeSet, fmt::Display};
+
+use super::CaveInfo;
+
+/// Compares two CaveInfos field by field and describes each difference on its own line.
+/// Lines for changed values look like `num_rooms: 5 -> 6`, and entries that only exist in
+/// one of the two CaveInfos are prefixed with `-` (only in `a`) or `+` (only in `b`).
+///
+/// Teki and cap teki are matched up by name, carried treasure, and group; treasures by
+/// name; gates by their position in the list; and units by name, ignoring rotation.
+/// Only the floor values above and these lists are compared. Units are compared by name
+/// alone, not by their doors, spawn points, or waypoints, and forced game modes are
+/// ignored, so an empty result doesn't guarantee the two generate identically.
+pub fn diff_caveinfo(a: &CaveInfo, b: &CaveInfo) -> Vec<String> {
+    let mut diffs = Vec::new();
+
+    diff_value("max_main_objects", a.max_main_objects, b.max_main_objects, &mut diffs);
+    diff_value("max_treasures", a.max_treasures, b.max_treasures, &mut diffs);
+    diff_value("max_gates", a.max_gates, b.max_gates, &mut diffs);
+    diff_value("num_rooms", a.num_rooms, b.num_rooms, &mut diffs);
+    diff_value("corridor_probability", a.corridor_probability, b.corridor_probability, &mut diffs);
+    diff_value("cap_probability", a.cap_probability, b.cap_probability, &mut diffs);
+    diff_value("has_geyser", a.has_geyser, b.has_geyser, &mut diffs);
+    diff_value("exit_plugged", a.exit_plugged, b.exit_plugged, &mut diffs);
+    diff_value("is_final_floor", a.is_final_floor, b.is_final_floor, &mut diffs);
+    diff_value("waterwraith_timer", a.waterwraith_timer, b.waterwraith_timer, &mut diffs);
+
+    diff_entries(
+        "teki",
+        &a.teki_info,
+        &b.teki_info,
+        |teki| entry_key(&teki.internal_name, &teki.carrying, teki.group),
+        |teki| {
+            vec![
+                ("num", teki.minimum_amount.to_string()),
+                ("weight", teki.filler_distribution_weight.to_string()),
+                ("spawn method", teki.spawn_method.clone().unwrap_or_default()),
+            ]
+        },
+        &mut diffs,
+    );
+    diff_entries(
+        "treasure",
+        &a.item_info,
+        &b.item_info,
+        |item| item.internal_name.clone(),
+        |item| {
+            vec![
+                ("num", item.min_amount.to_string()),
+                ("weight", item.filler_distribution_weight.to_string()),
+            ]
+        },
+        &mut diffs,
+    );
+    diff_entries(
+        "cap teki",
+        &a.cap_info,
+        &b.cap_info,
+        |cap| entry_key(&cap.internal_name, &cap.carrying, cap.group as u32),
+        |cap| {
+            vec![
+                ("num", cap.minimum_amount.to_string()),
+                ("weight", cap.filler_distribution_weight.to_string()),
+                ("spawn method", cap.spawn_method.clone().unwrap_or_default()),
+            ]
+        },
+        &mut diffs,
+    );
+
+    let gates_a: Vec<_> = a.gate_info.iter().enumerate().collect();
+    let gates_b: Vec<_> = b.gate_info.iter().enumerate().collect();
+    diff_entries(
+        "gate",
+        &gates_a,
+        &gates_b,
+        |(i, _)| format!("#{}", i + 1),
+        |(_, gate)| {
+            vec![
+                ("health", gate.health.to_string()),
+                ("weight", gate.spawn_distribution_weight.to_string()),
+            ]
+        },
+        &mut diffs,
+    );
+
+    let units_a: BTreeSet<&str> = a.cave_units.iter().map(|unit| unit.unit_folder_name.as_str()).collect();
+    let units_b: BTreeSet<&str> = b.cave_units.iter().map(|unit| unit.unit_folder_name.as_str()).collect();
+    diffs.extend(units_a.difference(&units_b).map(|unit| format!("- unit {unit}")));
+    diffs.extend(units_b.difference(&units_a).map(|unit| format!("+ unit {unit}")));
+
+    diffs
+}
+
+fn diff_value<T: PartialEq + Display>(name: &str, a: T, b: T, diffs: &mut Vec<String>) {
+    if a != b {
+        diffs.push(format!("{name}: {a} -> {b}"));
+    }
+}
+
+fn entry_key(internal_name: &str, carrying: &Option<String>, group: u32) -> String {
+    match carrying {
+        Some(treasure) => format!("{internal_name}/{treasure} (group {group})"),
+        None => format!("{internal_name} (group {group})"),
+    }
+}
+
+/// Diffs two lists of entries that are matched up with each other by `key`. Entries with
+/// duplicate keys are matched in the order they appear.
+fn diff_entries<T>(
+    kind: &str,
+    a: &[T],
+    b: &[T],
+    key: impl Fn(&T) -> String,
+    fields: impl Fn(&T) -> Vec<(&'static str, String)>,
+    diffs: &mut Vec<String>,
+) {
+    let describe = |entry: &T| {
+        fields(entry)
+            .into_iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(name, value)| format!("{name}: {value}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut unmatched_b: Vec<&T> = b.iter().collect();
+    for entry_a in a.iter() {
+        let entry_key = key(entry_a);
+        let Some(idx) = unmatched_b.iter().position(|entry_b| key(entry_b) == entry_key) else {
+            diffs.push(format!("- {kind} {entry_key} ({})", describe(entry_a)));
+            continue;
+        };
+        let entry_b = unmatched_b.remove(idx);
+        for ((name, value_a), (_, value_b)) in fields(entry_a).into_iter().zip(fields(entry_b)) {
+            if value_a != value_b {
+                diffs.push(format!("{kind} {entry_key}: {name} {value_a} -> {value_b}"));
+            }
+        }
+    }
+    for entry_b in unmatched_b {
+        diffs.push(format!("+ {kind} {} ({})", key(entry_b), describe(entry_b)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::diff_caveinfo;
+    use crate::{
+        assets::CaveConfig,
+        caveinfo::{CapInfo, CaveInfo, CaveUnit, GateInfo, ItemInfo, RoomType, TekiInfo},
+    };
+
+    fn unit(name: &str, rotation: u16) -> CaveUnit {
+        CaveUnit {
+            game: "pikmin2".to_string(),
+            unit_folder_name: name.to_string(),
+            width: 1,
+            height: 1,
+            room_type: RoomType::Room,
+            num_doors: 0,
+            doors: Vec::new(),
+            rotation,
+            spawnpoints: Vec::new(),
+            waterboxes: Vec::new(),
+            waypoints: Vec::new(),
+        }
+    }
+
+    fn teki(name: &str, minimum_amount: u32) -> TekiInfo {
+        TekiInfo {
+            game: "pikmin2".to_string(),
+            internal_name: name.to_string(),
+            carrying: None,
+            minimum_amount,
+            filler_distribution_weight: 0,
+            group: 0,
+            spawn_method: None,
+        }
+    }
+
+    fn sublevel() -> CaveInfo {
+        CaveInfo {
+            cave_cfg: CaveConfig {
+                game: "pikmin2".to_string(),
+                full_name: "Test Cave".to_string(),
+                is_challenge_mode: false,
+                shortened_names: vec!["test".to_string()],
+                caveinfo_filename: String::new(),
+            },
+            floor_num: 0,
+            max_main_objects: 10,
+            max_treasures: 1,
+            max_gates: 1,
+            num_rooms: 5,
+            corridor_probability: 0.1,
+            cap_probability: 0.5,
+            has_geyser: true,
+            exit_plugged: false,
+            cave_units: vec![unit("room_a", 0), unit("room_a", 1)],
+            teki_info: vec![teki("Kochappy", 3), teki("Tank", 1)],
+            item_info: vec![ItemInfo {
+                game: "pikmin2".to_string(),
+                internal_name: "compass".to_string(),
+                min_amount: 1,
+                filler_distribution_weight: 0,
+            }],
+            gate_info: vec![GateInfo {
+                health: 300.0,
+                spawn_distribution_weight: 10,
+            }],
+            cap_info: Vec::new(),
+            is_final_floor: false,
+            waterwraith_timer: 0.0,
+            modified: false,
+            challenge_mode_override: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_caveinfo() {
+        let a = sublevel();
+        assert!(diff_caveinfo(&a, &a).is_empty());
+
+        let mut b = sublevel();
+        b.num_rooms = 6;
+        b.teki_info[0].minimum_amount = 4;
+        b.teki_info.remove(1);
+        b.item_info.clear();
+        b.cap_info.push(CapInfo {
+            game: "pikmin2".to_string(),
+            internal_name: "Egg".to_string(),
+            carrying: None,
+            minimum_amount: 0,
+            filler_distribution_weight: 5,
+            group: 0,
+            spawn_method: None,
+        });
+        b.gate_info[0].health = 600.0;
+        b.cave_units[1] = unit("room_b", 0);
+
+        assert_eq!(
+            diff_caveinfo(&a, &b),
+            [
+                "num_rooms: 5 -> 6",
+                "teki Kochappy (group 0): num 3 -> 4",
+                "- teki Tank (group 0) (num: 1, weight: 0)",
+                "- treasure compass (num: 1, weight: 0)",
+                "+ cap teki Egg (group 0) (num: 0, weight: 5)",
+                "gate #1: health 300 -> 600",
+                "+ unit room_b",
+            ]
+        );
+    }
+}
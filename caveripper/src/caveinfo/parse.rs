@@ -40,8 +40,18 @@ pub(crate) fn parse_caveinfo(cave_cfg: &CaveConfig, mgr: &impl AssetManager) ->
         .load_txt(cave_cfg.get_caveinfo_path())
         .change_context(CaveInfoError::FileRead)
         .attach_printable_lazy(|| cave_cfg.get_caveinfo_path().to_string_lossy().into_owned())?;
+    parse_caveinfo_txt(&caveinfo_txt, cave_cfg, mgr)
+}
 
-    let mut caveinfos = parse_sections(&caveinfo_txt)
+/// Same as [parse_caveinfo], but with the CaveInfo file's text supplied directly
+/// instead of loaded from the assets folder. Unit files and map units are still
+/// loaded from `cave_cfg.game`'s assets.
+pub(crate) fn parse_caveinfo_txt(
+    caveinfo_txt: &str,
+    cave_cfg: &CaveConfig,
+    mgr: &impl AssetManager,
+) -> Result<Vec<CaveInfo>, CaveInfoError> {
+    let mut caveinfos = parse_sections(caveinfo_txt)
         .attach_printable_lazy(|| format!("Failed to parse {} into sections", cave_cfg.caveinfo_filename))?
         .skip(1)
         .chunks(5)
@@ -74,6 +84,8 @@ pub(crate) fn parse_caveinfo(cave_cfg: &CaveConfig, mgr: &impl AssetManager) ->
                 cap_info: try_parse_capinfo(cap, &cave_cfg.game)?,
                 is_final_floor: false,
                 waterwraith_timer: header.get_tag("{f016}").unwrap_or(0.0f32),
+                modified: false,
+                challenge_mode_override: None,
             })
         })
         .collect::<Result<Vec<CaveInfo>, CaveInfoError>>()?;
@@ -30,4 +30,7 @@ pub enum CaveripperError {
 
     #[error("Invalid seed string")]
     SeedError,
+
+    #[error("Invalid permalink")]
+    PermalinkError,
 }
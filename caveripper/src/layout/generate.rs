@@ -2,13 +2,17 @@ use std::{
     cell::{OnceCell, RefCell},
     cmp::{max, min},
     rc::Rc,
+    time::Duration,
 };
 
 use log::debug;
 
 use crate::{
     caveinfo::{CapInfo, CaveInfo, CaveUnit, ItemInfo, RoomType, TekiInfo},
-    layout::{boxes_overlap, Layout, PlacedDoor, PlacedMapUnit, PlacedSpawnPoint, SpawnObject},
+    layout::{
+        boxes_overlap, GenerationStats, GenerationTimings, Layout, PlacedDoor, PlacedMapUnit, PlacedSpawnPoint, SpawnObject, TekiPlacement,
+        MAX_MAP_UNIT_LOOPS,
+    },
     pikmin_math::{self, PikminRng},
     point::Point,
     sublevel::Sublevel,
@@ -39,10 +43,13 @@ pub struct LayoutBuilder<'a> {
 }
 
 impl<'a> LayoutBuilder<'a> {
-    pub fn generate(seed: u32, caveinfo: &'a CaveInfo) -> Layout<'a> {
+    /// Generates a layout starting from the RNG's current state. The RNG's seed at
+    /// this point is recorded as the layout's starting seed.
+    pub fn generate(rng: PikminRng, caveinfo: &'a CaveInfo) -> Layout<'a> {
+        rng.reset_call_counts();
         let builder = LayoutBuilder {
-            rng: PikminRng::new(seed),
-            starting_seed: seed,
+            starting_seed: rng.seed(),
+            rng,
             cave_name: caveinfo.name(),
             map_units: Vec::new(),
             cap_queue: Vec::new(),
@@ -74,6 +81,8 @@ impl<'a> LayoutBuilder<'a> {
     /// implementation; a more optimized one will follow.
     fn _generate(mut self, caveinfo: &'a CaveInfo) -> Layout<'a> {
         let is_challenge_mode = caveinfo.is_challenge_mode();
+        let mut timer = PhaseTimer::start();
+        let mut timings = GenerationTimings::default();
 
         // ** mapUnitsInitialSorting ** //
         // https://github.com/JHaack4/CaveGen/blob/2c99bf010d2f6f80113ed7eaf11d9d79c6cff367/CaveGen.java#L644
@@ -129,9 +138,9 @@ impl<'a> LayoutBuilder<'a> {
         self.place_map_unit(PlacedMapUnit::new(start_map_unit, 0, 0), true);
 
         // Keep placing map units until all doors have been closed
+        let mut num_loops = 0;
         if self.open_doors().next().is_some() {
-            let mut num_loops = 0;
-            while num_loops <= 10000 {
+            while num_loops <= MAX_MAP_UNIT_LOOPS {
                 num_loops += 1;
                 let mut unit_to_place = None;
 
@@ -742,6 +751,8 @@ impl<'a> LayoutBuilder<'a> {
         }
         debug!("Recentered map.");
 
+        timer.lap(&mut timings.map_units);
+
         // Set the start point, a.k.a. the Research Pod
         {
             let mut candidates: Vec<&mut PlacedSpawnPoint> = self.map_units[0]
@@ -755,7 +766,10 @@ impl<'a> LayoutBuilder<'a> {
             debug!("Placed ship pod at {}.", candidates[chosen].pos);
         }
 
+        timer.lap(&mut timings.start_and_exits);
+
         self.set_score();
+        timer.lap(&mut timings.scoring);
 
         // Place the exit hole and/or geyser, as applicable.
         if !caveinfo.is_final_floor {
@@ -765,6 +779,8 @@ impl<'a> LayoutBuilder<'a> {
             self.place_hole(SpawnObject::Geyser(is_challenge_mode && caveinfo.is_final_floor), is_challenge_mode);
         }
 
+        timer.lap(&mut timings.start_and_exits);
+
         // Place door hazards, AKA 'seam teki' (Enemy Group 5)
         {
             for num_spawned in 0..self.allocated_enemy_slots_by_group[5] {
@@ -802,17 +818,13 @@ impl<'a> LayoutBuilder<'a> {
                 // NOTE: This will still hit RNG, even if the chosen spot check above fails!
                 let teki_to_spawn = choose_rand_teki(&self.rng as *const _, caveinfo, 5, num_spawned);
 
-                if let (Some(chosen_spot), Some(teki_to_spawn)) = (chosen_spot, teki_to_spawn) {
+                if let (Some(chosen_spot), Some((teki_to_spawn, placement))) = (chosen_spot, teki_to_spawn) {
                     chosen_spot.borrow_mut().seam_spawnpoint = Rc::new(Some(SpawnObject::Teki(teki_to_spawn, Point::default())));
-                    chosen_spot
-                        .borrow()
-                        .adjacent_door
-                        .as_ref()
-                        .unwrap()
-                        .upgrade()
-                        .unwrap()
-                        .borrow_mut()
-                        .seam_spawnpoint = Rc::clone(&chosen_spot.borrow().seam_spawnpoint);
+                    chosen_spot.borrow_mut().seam_teki_placement = Some(placement);
+                    let adjacent_door = chosen_spot.borrow().adjacent_door.as_ref().unwrap().upgrade().unwrap();
+                    let mut adjacent_door = adjacent_door.borrow_mut();
+                    adjacent_door.seam_spawnpoint = Rc::clone(&chosen_spot.borrow().seam_spawnpoint);
+                    adjacent_door.seam_teki_placement = Some(placement);
                     self.placed_teki += 1;
                     debug!(
                         "Placed Teki \'{}\' on door seam at ({}, {}).",
@@ -828,6 +840,8 @@ impl<'a> LayoutBuilder<'a> {
             }
         }
 
+        timer.lap(&mut timings.seam_teki);
+
         // Place 'special enemies', AKA Enemy Group 8
         {
             // Valid spawn points are >=300 units away from the ship, and >=150 units away from the hole or geyser.
@@ -865,8 +879,9 @@ impl<'a> LayoutBuilder<'a> {
                 // Note: this *still hits RNG* even if the above results in None.
                 let teki_to_spawn = choose_rand_teki(&self.rng as *const _, caveinfo, 8, num_spawned);
 
-                if let (Some(chosen_spot), Some(teki_to_spawn)) = (chosen_spot, teki_to_spawn) {
+                if let (Some(chosen_spot), Some((teki_to_spawn, placement))) = (chosen_spot, teki_to_spawn) {
                     chosen_spot.contains.push(SpawnObject::Teki(teki_to_spawn, Point::default()));
+                    chosen_spot.teki_placement = Some(placement);
                     self.placed_teki += 1;
                     debug!("Placed Teki \'{}\' in Group 8 at {}.", teki_to_spawn.internal_name, chosen_spot.pos);
                 } else {
@@ -875,6 +890,8 @@ impl<'a> LayoutBuilder<'a> {
             }
         }
 
+        timer.lap(&mut timings.special_teki);
+
         // Place 'hard enemies', AKA Enemy Group 1
         {
             // Valid spawn points are >=300 units away from the ship, and >=200 units away from the hole or geyser.
@@ -912,8 +929,9 @@ impl<'a> LayoutBuilder<'a> {
                 // Note: this *still hits RNG* even if the above results in None.
                 let teki_to_spawn = choose_rand_teki(&self.rng as *const _, caveinfo, 1, num_spawned);
 
-                if let (Some(chosen_spot), Some(teki_to_spawn)) = (chosen_spot, teki_to_spawn) {
+                if let (Some(chosen_spot), Some((teki_to_spawn, placement))) = (chosen_spot, teki_to_spawn) {
                     chosen_spot.contains.push(SpawnObject::Teki(teki_to_spawn, Point::default()));
+                    chosen_spot.teki_placement = Some(placement);
                     self.placed_teki += 1;
                     debug!("Placed Teki \'{}\' in Group 1 at {}.", teki_to_spawn.internal_name, chosen_spot.pos);
                 } else {
@@ -922,6 +940,8 @@ impl<'a> LayoutBuilder<'a> {
             }
         }
 
+        timer.lap(&mut timings.hard_teki);
+
         // Place 'easy enemies', AKA Enemy Group 0
         {
             // Valid spawn points are >=300 units away from the ship.
@@ -982,9 +1002,10 @@ impl<'a> LayoutBuilder<'a> {
                     break;
                 }
 
-                if let (Some(chosen_spot), Some(teki_to_spawn)) = (chosen_spot, teki_to_spawn) {
+                if let (Some(chosen_spot), Some((teki_to_spawn, placement))) = (chosen_spot, teki_to_spawn) {
                     // Create the teki objects
                     let mut to_spawn: Vec<SpawnObject> = Vec::new();
+                    let mut initial_offsets = Vec::new();
                     for _ in 0..num_to_spawn {
                         // Calculate initial random offset
                         let radius = chosen_spot.spawnpoint_unit.radius * self.rng.rand_f32();
@@ -994,6 +1015,7 @@ impl<'a> LayoutBuilder<'a> {
                         let offset = Point([angle.sin() * radius, 0.0, angle.cos() * radius]);
 
                         to_spawn.push(SpawnObject::Teki(teki_to_spawn, offset));
+                        initial_offsets.push(offset);
                         num_spawned += 1;
                         self.placed_teki += 1;
                     }
@@ -1036,6 +1058,8 @@ impl<'a> LayoutBuilder<'a> {
                     // Spawn the enemies
                     let num_spawned_final = to_spawn.len();
                     chosen_spot.contains.append(&mut to_spawn);
+                    chosen_spot.initial_teki_offsets.append(&mut initial_offsets);
+                    chosen_spot.teki_placement = Some(placement);
                     debug!(
                         "Placed {} Teki \'{}\' in Group 0 near the spawnpoint at {}.",
                         num_spawned_final, teki_to_spawn.internal_name, chosen_spot.pos,
@@ -1046,9 +1070,12 @@ impl<'a> LayoutBuilder<'a> {
             }
         }
 
+        timer.lap(&mut timings.easy_teki);
+
         // Recalculate score, this time including Teki Score and Seam Teki Score in addition
         // to Door Score.
         self.set_score();
+        timer.lap(&mut timings.scoring);
 
         // Place Plants, a.k.a. Teki Group 6.
         // Note that group 6 is the "plant spawn group", but it does not necessarily only
@@ -1075,8 +1102,9 @@ impl<'a> LayoutBuilder<'a> {
 
                 let teki_to_spawn = choose_rand_teki(&self.rng as *const _, caveinfo, 6, num_spawned);
 
-                if let (Some(chosen_spot), Some(teki_to_spawn)) = (chosen_spot, teki_to_spawn) {
+                if let (Some(chosen_spot), Some((teki_to_spawn, placement))) = (chosen_spot, teki_to_spawn) {
                     chosen_spot.contains.push(SpawnObject::Teki(teki_to_spawn, Point::default()));
+                    chosen_spot.teki_placement = Some(placement);
                     self.placed_teki += 1;
                     debug!(
                         "Placed Plant-Group Teki \'{}\' at {}.",
@@ -1090,6 +1118,8 @@ impl<'a> LayoutBuilder<'a> {
             }
         }
 
+        timer.lap(&mut timings.plants);
+
         // Place Items, a.k.a. Treasures.
         {
             for num_spawned in 0..caveinfo.max_treasures {
@@ -1172,13 +1202,15 @@ impl<'a> LayoutBuilder<'a> {
             }
         }
 
+        timer.lap(&mut timings.treasures);
+
         // Place Cap Teki.
         {
             // Place non-falling Cap Teki. This is *not* random, which is why things like Mitites
             // on Hole of Beasts 4 have a predictable spawn location.
             let mut num_spawned = 0;
             for map_unit in self.map_units.iter_mut() {
-                if map_unit.unit.room_type != RoomType::DeadEnd || !map_unit.unit.unit_folder_name.contains("item") {
+                if !map_unit.unit.is_item_alcove() {
                     continue;
                 }
 
@@ -1201,7 +1233,7 @@ impl<'a> LayoutBuilder<'a> {
             // Place falling Cap Teki. These can be placed on top of other Cap Teki except Candypop Buds.
             num_spawned = 0;
             for map_unit in self.map_units.iter_mut() {
-                if map_unit.unit.room_type != RoomType::DeadEnd || !map_unit.unit.unit_folder_name.contains("item") {
+                if !map_unit.unit.is_item_alcove() {
                     continue;
                 }
 
@@ -1228,6 +1260,8 @@ impl<'a> LayoutBuilder<'a> {
             }
         }
 
+        timer.lap(&mut timings.cap_teki);
+
         // Place Gates
         {
             for _ in 0..caveinfo.max_gates {
@@ -1264,6 +1298,8 @@ impl<'a> LayoutBuilder<'a> {
             }
         }
 
+        timer.lap(&mut timings.gates);
+
         // In Colossal Caverns, place Onions
         if caveinfo.cave_cfg.is_colossal_caverns() {
             const NUM_ONIONS: usize = 3;
@@ -1296,12 +1332,23 @@ impl<'a> LayoutBuilder<'a> {
             }
         }
 
+        timings.total = timer.total();
+
         // Done!
         Layout {
-            sublevel: Sublevel::from_cfg(&caveinfo.cave_cfg, caveinfo.floor_num as usize + 1),
+            sublevel: Sublevel {
+                modified: caveinfo.modified,
+                ..Sublevel::from_cfg(&caveinfo.cave_cfg, caveinfo.floor_num as usize + 1)
+            },
             starting_seed: self.starting_seed,
             cave_name: self.cave_name,
             map_units: self.map_units,
+            rng_calls: self.rng.call_counts(),
+            generation_stats: GenerationStats {
+                map_unit_loops: num_loops,
+                hit_loop_cap: num_loops > MAX_MAP_UNIT_LOOPS,
+            },
+            timings,
             waypoint_graph: OnceCell::new(),
         }
     }
@@ -1570,7 +1617,7 @@ impl<'a> LayoutBuilder<'a> {
 
         // Spawn path 1: in front of filled item alcoves.
         for map_unit in self.map_units.iter() {
-            if map_unit.unit.room_type != RoomType::DeadEnd || !map_unit.unit.unit_folder_name.contains("item") {
+            if !map_unit.unit.is_item_alcove() {
                 continue;
             }
 
@@ -1997,7 +2044,9 @@ impl<'a> LayoutBuilder<'a> {
 
 /// https://github.com/JHaack4/CaveGen/blob/2c99bf010d2f6f80113ed7eaf11d9d79c6cff367/CaveGen.java#L2177
 /// RNG is a raw pointer to avoid issues with borrowing self (LayoutBuilder).
-fn choose_rand_teki(rng: *const PikminRng, caveinfo: &CaveInfo, group: u32, num_spawned: u32) -> Option<&TekiInfo> {
+/// Picks the next teki to place from the given spawn group, and whether it's going toward
+/// a minimum amount or is filler.
+fn choose_rand_teki(rng: *const PikminRng, caveinfo: &CaveInfo, group: u32, num_spawned: u32) -> Option<(&TekiInfo, TekiPlacement)> {
     let mut cumulative_mins = 0;
     let mut filler_teki = Vec::new();
     let mut filler_teki_weights = Vec::new();
@@ -2005,7 +2054,7 @@ fn choose_rand_teki(rng: *const PikminRng, caveinfo: &CaveInfo, group: u32, num_
     for teki in caveinfo.teki_group(group) {
         cumulative_mins += teki.minimum_amount;
         if num_spawned < cumulative_mins {
-            return Some(teki);
+            return Some((teki, TekiPlacement::Minimum));
         }
 
         if teki.filler_distribution_weight > 0 {
@@ -2015,7 +2064,8 @@ fn choose_rand_teki(rng: *const PikminRng, caveinfo: &CaveInfo, group: u32, num_
     }
 
     if !filler_teki.is_empty() {
-        Some(unsafe { filler_teki[rng.as_ref().unwrap().rand_index_weight(filler_teki_weights.as_slice()).unwrap()] })
+        let teki = unsafe { filler_teki[rng.as_ref().unwrap().rand_index_weight(filler_teki_weights.as_slice()).unwrap()] };
+        Some((teki, TekiPlacement::Filler))
     } else {
         None
     }
@@ -2086,3 +2136,42 @@ fn choose_rand_cap_teki(rng: *const PikminRng, caveinfo: &CaveInfo, num_spawned:
         None
     }
 }
+
+/// Coarse wall-clock timer for [GenerationTimings]. Compiles to nothing without the
+/// `gen_timing` feature so normal generation doesn't pay for it (and so generation
+/// still works on wasm, where `Instant` isn't available).
+struct PhaseTimer {
+    #[cfg(feature = "gen_timing")]
+    start: std::time::Instant,
+    #[cfg(feature = "gen_timing")]
+    last_lap: std::time::Instant,
+}
+
+impl PhaseTimer {
+    fn start() -> Self {
+        PhaseTimer {
+            #[cfg(feature = "gen_timing")]
+            start: std::time::Instant::now(),
+            #[cfg(feature = "gen_timing")]
+            last_lap: std::time::Instant::now(),
+        }
+    }
+
+    /// Adds the time since the previous lap (or the start) to `_phase`.
+    #[inline(always)]
+    fn lap(&mut self, _phase: &mut Duration) {
+        #[cfg(feature = "gen_timing")]
+        {
+            let now = std::time::Instant::now();
+            *_phase += now - self.last_lap;
+            self.last_lap = now;
+        }
+    }
+
+    fn total(&self) -> Duration {
+        #[cfg(feature = "gen_timing")]
+        return self.start.elapsed();
+        #[cfg(not(feature = "gen_timing"))]
+        return Duration::ZERO;
+    }
+}
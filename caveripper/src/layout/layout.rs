@@ -1,9 +1,15 @@
 mod generate;
 pub(crate) mod waypoint;
 
+#[cfg(test)]
+mod test;
+
 use std::{
     cell::{OnceCell, Ref, RefCell},
+    collections::HashSet,
+    fmt, ptr,
     rc::{Rc, Weak},
+    time::Duration,
 };
 
 use generate::LayoutBuilder;
@@ -12,6 +18,7 @@ use waypoint::WaypointGraph;
 
 use crate::{
     caveinfo::{CapInfo, CaveInfo, CaveUnit, DoorUnit, GateInfo, ItemInfo, SpawnPoint, TekiInfo},
+    pikmin_math::{PikminRng, RngCallCounts},
     point::Point,
     sublevel::Sublevel,
 };
@@ -28,12 +35,108 @@ pub struct Layout<'a> {
     pub starting_seed: u32,
     pub cave_name: String,
     pub map_units: Vec<PlacedMapUnit<'a>>,
+    /// RNG calls made while generating this layout, for profiling. Only
+    /// counted when the `rng_stats` feature is enabled; zero otherwise.
+    pub rng_calls: RngCallCounts,
+    pub generation_stats: GenerationStats,
+    /// Time spent in each phase of generating this layout, for profiling. Only
+    /// recorded when the `gen_timing` feature is enabled; zero otherwise.
+    pub timings: GenerationTimings,
     waypoint_graph: OnceCell<WaypointGraph>,
 }
 
+/// Limit on the map unit placement loop during generation. Like CaveGen, the loop only
+/// stops once its iteration count goes past this, so it can run one more time than this.
+pub const MAX_MAP_UNIT_LOOPS: u32 = 10000;
+
+/// How hard generation had to work to produce a layout, for finding sublevels where the
+/// generation algorithm struggles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GenerationStats {
+    /// Iterations of the map unit placement loop, which keeps trying to place units until
+    /// every door is closed. 0 if the starting room had no doors.
+    pub map_unit_loops: u32,
+    /// Whether the placement loop ran out of iterations (see [MAX_MAP_UNIT_LOOPS]) rather
+    /// than finishing normally. Sublevels without both 1x1 and 2x1 straight hallways
+    /// always do this, since the loop keeps retrying the step that combines hallways, so
+    /// this alone doesn't mean the layout is broken.
+    pub hit_loop_cap: bool,
+}
+
+/// Wall-clock time spent in each phase of generation. Each spawn group gets its own entry.
+/// Each phase is timed from the end of the one before it, so small steps in between count
+/// towards the phase that follows them. Only work after the last phase, i.e. placing the
+/// onions in Colossal Caverns, is counted in `total` alone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GenerationTimings {
+    /// Sorting the unit lists, allocating teki slots, placing map units, and recentering.
+    pub map_units: Duration,
+    /// Both passes of door and teki score calculation.
+    pub scoring: Duration,
+    /// The ship pod, hole, and geyser.
+    pub start_and_exits: Duration,
+    /// Seam teki, AKA Enemy Group 5.
+    pub seam_teki: Duration,
+    /// Special teki, AKA Enemy Group 8.
+    pub special_teki: Duration,
+    /// Hard teki, AKA Enemy Group 1.
+    pub hard_teki: Duration,
+    /// Easy teki, AKA Enemy Group 0, including pushing bunches apart.
+    pub easy_teki: Duration,
+    /// Plants, AKA Enemy Group 6.
+    pub plants: Duration,
+    pub treasures: Duration,
+    pub cap_teki: Duration,
+    pub gates: Duration,
+    pub total: Duration,
+}
+
+impl GenerationTimings {
+    /// Each phase's name and duration, not including `total`.
+    pub fn phases(&self) -> [(&'static str, Duration); 11] {
+        [
+            ("Map units", self.map_units),
+            ("Scoring", self.scoring),
+            ("Ship and exits", self.start_and_exits),
+            ("Seam teki (group 5)", self.seam_teki),
+            ("Special teki (group 8)", self.special_teki),
+            ("Hard teki (group 1)", self.hard_teki),
+            ("Easy teki (group 0)", self.easy_teki),
+            ("Plants (group 6)", self.plants),
+            ("Treasures", self.treasures),
+            ("Cap teki", self.cap_teki),
+            ("Gates", self.gates),
+        ]
+    }
+}
+
 impl<'a> Layout<'a> {
     pub fn generate(seed: u32, caveinfo: &CaveInfo) -> Layout {
-        LayoutBuilder::generate(seed, caveinfo)
+        LayoutBuilder::generate(PikminRng::new(seed), caveinfo)
+    }
+
+    /// Generates a layout from an RNG that has already been advanced to some known state,
+    /// e.g. after simulating the RNG calls made on previous floors. Since Pikmin 2's RNG
+    /// state is just its seed, this is equivalent to calling [Layout::generate] with the
+    /// RNG's current seed, which is recorded as the layout's `starting_seed`.
+    pub fn generate_from_rng(rng: PikminRng, caveinfo: &CaveInfo) -> Layout<'_> {
+        LayoutBuilder::generate(rng, caveinfo)
+    }
+
+    /// Builds a layout out of map units that were placed by hand rather than generated, so
+    /// tests can check queries against layouts whose contents are known exactly.
+    #[cfg(test)]
+    pub(crate) fn from_placed_units(sublevel: Sublevel, map_units: Vec<PlacedMapUnit<'a>>) -> Layout<'a> {
+        Layout {
+            cave_name: sublevel.cfg.full_name.clone(),
+            sublevel,
+            starting_seed: 0,
+            map_units,
+            rng_calls: RngCallCounts::default(),
+            generation_stats: GenerationStats::default(),
+            timings: GenerationTimings::default(),
+            waypoint_graph: OnceCell::new(),
+        }
     }
 
     /// Gets all SpawnObjects in the layout plus their global coordinates
@@ -53,9 +156,194 @@ impl<'a> Layout<'a> {
         room_sps.chain(seam_sps)
     }
 
+    /// Like [Layout::get_spawn_objects], but lists each seam spawn object only once. A seam
+    /// object is shared by the doors on both sides of its seam, so [Layout::get_spawn_objects]
+    /// returns it twice; use this instead when counting or summing over spawn objects.
+    pub fn get_unique_spawn_objects(&self) -> impl Iterator<Item = (&SpawnObject<'a>, Point<3, f32>)> {
+        let mut seen = HashSet::new();
+        self.get_spawn_objects().filter(move |(so, _)| seen.insert(ptr::from_ref(*so)))
+    }
+
+    /// Every teki in the layout along with how it was chosen (see [TekiPlacement]). Cap
+    /// teki aren't included. Seam teki are listed the same way as in [Layout::get_spawn_objects].
+    pub fn teki_placements(&self) -> impl Iterator<Item = (&SpawnObject<'a>, TekiPlacement)> {
+        let room_teki = self.map_units.iter().flat_map(|unit| unit.spawnpoints.iter()).flat_map(|sp| {
+            sp.contains
+                .iter()
+                .filter(|so| matches!(so, SpawnObject::Teki(..)))
+                .filter_map(|so| sp.teki_placement.map(|placement| (so, placement)))
+        });
+        let seam_teki = self.map_units.iter().flat_map(|unit| unit.doors.iter()).filter_map(|door| {
+            let door = Ref::leak(door.borrow());
+            match (Option::as_ref(&door.seam_spawnpoint), door.seam_teki_placement) {
+                (Some(so @ SpawnObject::Teki(..)), Some(placement)) => Some((so, placement)),
+                _ => None,
+            }
+        });
+        room_teki.chain(seam_teki)
+    }
+
+    /// Width and height of the smallest box containing every map unit in this layout,
+    /// in map unit grid cells.
+    pub fn grid_size(&self) -> (u32, u32) {
+        let min_x = self.map_units.iter().map(|unit| unit.x).min().unwrap_or(0);
+        let min_z = self.map_units.iter().map(|unit| unit.z).min().unwrap_or(0);
+        let max_x = self.map_units.iter().map(|unit| unit.x + unit.unit.width as i32).max().unwrap_or(0);
+        let max_z = self
+            .map_units
+            .iter()
+            .map(|unit| unit.z + unit.unit.height as i32)
+            .max()
+            .unwrap_or(0);
+        ((max_x - min_x) as u32, (max_z - min_z) as u32)
+    }
+
+    /// Removes all plants (see [SpawnObject::is_plant]) from the layout. Scores are left
+    /// as generated, which is fine since group 6 teki never contribute to score anyway.
+    pub fn remove_plants(&mut self) {
+        for spawnpoint in self.map_units.iter_mut().flat_map(|unit| unit.spawnpoints.iter_mut()) {
+            spawnpoint.contains.retain(|so| !so.is_plant());
+        }
+    }
+
     pub fn waypoint_graph(&self) -> &WaypointGraph {
         self.waypoint_graph.get_or_init(|| WaypointGraph::build(self))
     }
+
+    /// The map units in this layout and the doors connecting them as an undirected GraphViz
+    /// DOT graph, e.g. for rendering with `dot -Tpng`. Nodes are named by their index in
+    /// [Layout::map_units] and labeled with the unit's internal name. Nodes also carry
+    /// `ship`, `exit`, and `treasures` attributes for what was placed in that unit, where
+    /// `exit` lists every exit in the unit and treasures include ones carried by teki.
+    ///
+    /// Each door gets its own edge, labeled with the distance from the center of one unit
+    /// through the door to the center of the other. The edge's `len` is the same distance
+    /// in map unit cells, so layout engines that honor it like `neato` draw it to scale.
+    pub fn waypoint_graph_dot(&self) -> String {
+        let unit_center = |unit: &PlacedMapUnit| {
+            Point([
+                (unit.x as f32 + unit.unit.width as f32 / 2.0) * 170.0,
+                (unit.z as f32 + unit.unit.height as f32 / 2.0) * 170.0,
+            ])
+        };
+
+        let mut dot = format!("graph \"{}\" {{\n", self.cave_name.replace('"', "\\\""));
+        for (idx, unit) in self.map_units.iter().enumerate() {
+            let mut attributes = vec![format!("label=\"{}\"", unit.unit.unit_folder_name)];
+            if unit.spawn_objects().any(|so| matches!(so, SpawnObject::Ship)) {
+                attributes.push("ship=true".to_string());
+            }
+            let exits: Vec<&str> = unit
+                .spawn_objects()
+                .filter(|so| matches!(so, SpawnObject::Hole(_) | SpawnObject::Geyser(_)))
+                .map(|so| so.name())
+                .collect();
+            if !exits.is_empty() {
+                attributes.push(format!("exit=\"{}\"", exits.join(",")));
+            }
+            let treasures = unit
+                .spawn_objects()
+                .filter(|so| matches!(so, SpawnObject::Item(_) | SpawnObject::Teki(TekiInfo { carrying: Some(_), .. }, _)))
+                .count();
+            if treasures > 0 {
+                attributes.push(format!("treasures={treasures}"));
+            }
+            dot.push_str(&format!("    {idx} [{}];\n", attributes.join(", ")));
+        }
+
+        for (idx, unit) in self.map_units.iter().enumerate() {
+            for door in unit.doors.iter() {
+                let door = door.borrow();
+                let Some(adjacent) = door.adjacent_door.as_ref().and_then(|d| d.upgrade()) else {
+                    continue;
+                };
+                let Some(other) = adjacent.borrow().parent_idx else {
+                    continue;
+                };
+                if other <= idx {
+                    continue;
+                }
+                let door_pos = door.center().two_d();
+                let distance = unit_center(unit).dist(&door_pos) + door_pos.dist(&unit_center(&self.map_units[other]));
+                let len = distance / 170.0;
+                dot.push_str(&format!("    {idx} -- {other} [len={len:.2}, label=\"{distance:.0}\"];\n"));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// A compact string uniquely describing the contents of this layout: every placed
+    /// map unit followed by every spawn object and its position. Does not include the
+    /// seed, so two seeds producing the same layout will have the same slug.
+    ///
+    /// Spawn object entries include everything that can tell two teki at the same position
+    /// apart (see [spawn_object_slug]) and are sorted, so the slug doesn't depend on the
+    /// order objects were placed in.
+    pub fn slug(&self) -> String {
+        let mut slug = self.structure_slug();
+        slug.push_str(";(");
+
+        let mut spawn_object_slugs: Vec<String> = self.get_spawn_objects().map(|(so, pos)| spawn_object_slug(so, pos)).collect();
+        spawn_object_slugs.sort();
+        for so_slug in spawn_object_slugs {
+            slug.push_str(&so_slug);
+        }
+        slug.push(')');
+
+        slug
+    }
+
+    /// The part of [Layout::slug] describing only the map units in this layout, i.e. their
+    /// names, positions, and rotations. Spawn objects are not included, so two seeds with
+    /// the same room and corridor placement have the same structure slug even if their
+    /// teki and treasures differ.
+    ///
+    /// The format of this string is stable and can be stored for later comparison. It is
+    /// always a prefix of the full slug.
+    pub fn structure_slug(&self) -> String {
+        let mut slug = String::new();
+        self.write_structure_slug(&mut slug).expect("writing to a String can't fail");
+        slug
+    }
+
+    /// A hash of this layout's [slug](Layout::slug), computed without building the slug
+    /// itself. Cheap enough to bucket millions of layouts by identity: layouts with equal
+    /// slugs always have equal fingerprints, and different slugs almost never collide.
+    ///
+    /// Uses 64-bit FNV-1a, so fingerprints are stable across runs and platforms and can be
+    /// stored for later comparison. They are NOT the same as hashing the slug string
+    /// directly, since spawn objects are hashed individually and then combined in sorted
+    /// order rather than being sorted as strings.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+        self.write_structure_slug(&mut hasher).expect("hashing can't fail");
+        let mut spawn_object_hashes: Vec<u64> = self
+            .get_spawn_objects()
+            .map(|(so, pos)| {
+                let mut so_hasher = Fnv1a::new();
+                write_spawn_object_slug(&mut so_hasher, so, pos).expect("hashing can't fail");
+                so_hasher.0
+            })
+            .collect();
+        spawn_object_hashes.sort_unstable();
+        for so_hash in spawn_object_hashes {
+            hasher.write_bytes(&so_hash.to_le_bytes());
+        }
+        hasher.0
+    }
+
+    fn write_structure_slug(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        write!(out, "{};(", self.sublevel.short_name())?;
+        for map_unit in self.map_units.iter() {
+            write!(
+                out,
+                "{},x{}z{}r{};",
+                map_unit.unit.unit_folder_name, map_unit.x, map_unit.z, map_unit.unit.rotation
+            )?;
+        }
+        out.write_char(')')
+    }
 }
 
 impl Serialize for Layout<'_> {
@@ -182,6 +470,7 @@ impl<'a> PlacedMapUnit<'a> {
                     door_score: Some(0),
                     seam_teki_score: 0,
                     seam_spawnpoint: Rc::new(None),
+                    seam_teki_placement: None,
                 }))
             })
             .collect();
@@ -208,6 +497,8 @@ impl<'a> PlacedMapUnit<'a> {
                     hole_score: 0,
                     treasure_score: 0,
                     contains: vec![],
+                    initial_teki_offsets: vec![],
+                    teki_placement: None,
                 }
             })
             .collect();
@@ -239,11 +530,6 @@ impl<'a> PlacedMapUnit<'a> {
     pub fn spawn_objects(&self) -> impl Iterator<Item = &SpawnObject> {
         self.spawnpoints.iter().flat_map(|sp| sp.contains.iter())
     }
-
-    /// Identifier string unique to this unit within a layout.
-    pub(crate) fn key(&self) -> String {
-        format!("{}/{}/{}", self.x, self.z, self.unit.unit_folder_name)
-    }
 }
 
 impl Serialize for PlacedMapUnit<'_> {
@@ -274,6 +560,9 @@ pub struct PlacedDoor<'a> {
     pub door_score: Option<u32>,
     pub seam_teki_score: u32,
     pub seam_spawnpoint: Rc<Option<SpawnObject<'a>>>,
+    /// How the teki in `seam_spawnpoint` was chosen, if it holds one. Set on the doors on
+    /// both sides of the seam.
+    pub seam_teki_placement: Option<TekiPlacement>,
 }
 
 impl<'a> PlacedDoor<'a> {
@@ -304,8 +593,57 @@ pub struct PlacedSpawnPoint<'a> {
     pub hole_score: u32,
     pub treasure_score: u32,
     pub contains: Vec<SpawnObject<'a>>,
+    /// Where each group 0 teki in `contains` was first placed, relative to this spawn
+    /// point, before the bunch was pushed apart. In the same order as the teki. Empty for
+    /// spawn points that don't hold group 0 teki.
+    pub initial_teki_offsets: Vec<Point<3, f32>>,
+    /// How the teki in `contains` were chosen. Teki are only ever placed in empty spawn
+    /// points, one choice per spawn point, so this applies to all of them. `None` if no
+    /// teki were placed here. Cap teki aren't tracked.
+    pub teki_placement: Option<TekiPlacement>,
 }
 
+/// Why the generator placed a particular teki: either to make up a [TekiInfo::minimum_amount],
+/// or as filler chosen randomly by [TekiInfo::filler_distribution_weight]. This mirrors the
+/// choice made while generating: within each spawn group, the first teki placed go toward
+/// each entry's minimum amount in the order the entries are listed, and everything after
+/// that is filler. A teki species with both a minimum and a filler weight can be placed
+/// both ways in the same layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TekiPlacement {
+    Minimum,
+    Filler,
+}
+
+/// Internal names of teki that wander around the sublevel rather than staying where they
+/// spawned, e.g. Gatling Groinks (`minihoudai`) and the Titan Dweevil (`bigtreasure`).
+/// This is a judgement call rather than something the game defines, so add to this list
+/// if another species turns out to roam. Everything that treats teki as roaming, like
+/// quickglance circles and the `roaming chokepoint` query, goes through it.
+pub const ROAMING_TEKI: [&str; 4] = ["minihoudai", "kumochappy", "leafchappy", "bigtreasure"];
+
+/// Internal names of the decorative plant species in the base game, e.g. Clovers and
+/// Glowcaps (`hikarikinoko`). See [SpawnObject::is_plant_species].
+pub const PLANT_TEKI: [&str; 17] = [
+    "ooinu_s",
+    "ooinu_l",
+    "kareooinu_s",
+    "kareooinu_l",
+    "wakame_s",
+    "wakame_l",
+    "tanpopo",
+    "watage",
+    "clover",
+    "hikarikinoko",
+    "tukushi",
+    "magaret",
+    "nekojarashi",
+    "chiyogami",
+    "zenmai",
+    "daiodored",
+    "daiodogreen",
+];
+
 /// Any object that can be placed in a SpawnPoint.
 #[derive(Debug, Clone)]
 pub enum SpawnObject<'a> {
@@ -363,6 +701,105 @@ impl<'a> SpawnObject<'a> {
             SpawnObject::Onion(_) => 0,
         }
     }
+
+    /// Whether this is a teki that spawned from group 6, the plant spawn group.
+    ///
+    /// This deliberately goes by spawn group rather than species: a plant species listed
+    /// in another group (e.g. as a group 0 filler teki) is placed in the normal teki spawn
+    /// points and counts toward unit score like any other teki, so it isn't a plant here.
+    pub fn is_plant(&self) -> bool {
+        matches!(self, SpawnObject::Teki(TekiInfo { group: 6, .. }, _))
+    }
+
+    /// Whether this teki is a plant species, no matter which group it spawned from. Unlike
+    /// [SpawnObject::is_plant], this goes by species: anything in [PLANT_TEKI] counts, as
+    /// does any teki spawned from group 6 so that plants added by romhacks are included.
+    pub fn is_plant_species(&self) -> bool {
+        match self {
+            SpawnObject::Teki(TekiInfo { internal_name, .. }, _) | SpawnObject::CapTeki(CapInfo { internal_name, .. }, _) => {
+                self.is_plant() || PLANT_TEKI.iter().any(|name| name.eq_ignore_ascii_case(internal_name))
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether this is one of the [ROAMING_TEKI], whether spawned normally or from a cap.
+    pub fn is_roaming(&self) -> bool {
+        match self {
+            SpawnObject::Teki(TekiInfo { internal_name, .. }, _) | SpawnObject::CapTeki(CapInfo { internal_name, .. }, _) => {
+                ROAMING_TEKI.iter().any(|name| name.eq_ignore_ascii_case(internal_name))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Describes one spawn object for [Layout::slug] by its name and position. Teki use their
+/// exact internal name, so falling variants like `fkabuto` aren't merged with `kabuto`,
+/// followed by `/` and their carried treasure and `@` and their spawn method, if any.
+fn spawn_object_slug(so: &SpawnObject, pos: Point<3, f32>) -> String {
+    let mut slug = String::new();
+    write_spawn_object_slug(&mut slug, so, pos).expect("writing to a String can't fail");
+    slug
+}
+
+fn write_spawn_object_slug(out: &mut impl fmt::Write, so: &SpawnObject, pos: Point<3, f32>) -> fmt::Result {
+    match so {
+        SpawnObject::Teki(
+            TekiInfo {
+                internal_name,
+                carrying,
+                spawn_method,
+                ..
+            },
+            _,
+        )
+        | SpawnObject::CapTeki(
+            CapInfo {
+                internal_name,
+                carrying,
+                spawn_method,
+                ..
+            },
+            _,
+        ) => {
+            out.write_str(internal_name)?;
+            if let Some(carrying) = carrying {
+                write!(out, "/{carrying}")?;
+            }
+            if let Some(spawn_method) = spawn_method {
+                write!(out, "@{spawn_method}")?;
+            }
+        }
+        _ => out.write_str(so.name())?,
+    }
+    write!(out, ",x{:.0}z{:.0};", pos[0], pos[2])
+}
+
+/// 64-bit FNV-1a, used for [Layout::fingerprint]. Unlike [std::hash::DefaultHasher], its
+/// output is fixed and doesn't depend on the platform or Rust version.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    fn new() -> Self {
+        Fnv1a(Self::OFFSET_BASIS)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(Self::PRIME);
+        }
+    }
+}
+
+impl fmt::Write for Fnv1a {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
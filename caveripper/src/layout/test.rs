@@ -1,14 +1,560 @@
-use rand::random;
+use std::{collections::HashMap, rc::Rc};
 
-use crate::assets::AssetManager;
-use super::Layout;
+use super::{spawn_object_slug, GenerationStats, Layout, PlacedMapUnit, SpawnObject, TekiPlacement, MAX_MAP_UNIT_LOOPS};
+use crate::{
+    assets::{fs_asset_manager::FsAssetManager, AssetManager, CaveConfig},
+    caveinfo::{CaveInfo, CaveUnit, DoorUnit, RoomType, SpawnPoint, TekiInfo},
+    point::Point,
+    sublevel::Sublevel,
+};
 
 #[test]
 fn test_bloysterless() {
-    AssetManager::init_global("../assets", "..").unwrap();
-    let caveinfo = AssetManager::get_caveinfo(&"SR7".try_into().unwrap()).unwrap();
+    let mgr = FsAssetManager::init().unwrap();
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("SR7", &mgr).unwrap()).unwrap();
     let layout = Layout::generate(0x31D70855, caveinfo);
 
-    let bloyster = layout.get_spawn_objects().find(|so| so.name().eq_ignore_ascii_case("UmiMushi"));
+    let bloyster = layout.get_spawn_objects().find(|(so, _)| so.name().eq_ignore_ascii_case("UmiMushi"));
     assert!(bloyster.is_none());
 }
+
+#[test]
+fn test_structure_slug_matches_map_unit_placement() {
+    let mgr = FsAssetManager::init().unwrap();
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("ec1", &mgr).unwrap()).unwrap();
+
+    // Group seeds by their actual map unit placement, then make sure every seed in a
+    // group shares the same structure slug and no two groups share one.
+    type Structure = Vec<(String, i32, i32, u16)>;
+    let mut slugs_by_structure: HashMap<Structure, Vec<String>> = HashMap::new();
+    for seed in 0..5000 {
+        let layout = Layout::generate(seed, caveinfo);
+        let structure = layout
+            .map_units
+            .iter()
+            .map(|unit| (unit.unit.unit_folder_name.clone(), unit.x, unit.z, unit.unit.rotation))
+            .collect();
+        let structure_slug = layout.structure_slug();
+        assert!(layout.slug().starts_with(&structure_slug));
+        slugs_by_structure.entry(structure).or_default().push(structure_slug);
+    }
+
+    for slugs in slugs_by_structure.values() {
+        assert!(slugs.iter().all(|slug| slug == &slugs[0]));
+    }
+    let mut distinct_slugs: Vec<&String> = slugs_by_structure.values().map(|slugs| &slugs[0]).collect();
+    distinct_slugs.sort();
+    distinct_slugs.dedup();
+    assert_eq!(distinct_slugs.len(), slugs_by_structure.len());
+}
+
+#[test]
+fn test_remove_plants_only_removes_group_6() {
+    let mgr = FsAssetManager::init().unwrap();
+    let mut num_plants_removed = 0;
+    for sublevel in ["ec1", "hob1", "fc1", "wfg1", "sh1"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel, &mgr).unwrap()).unwrap();
+        for seed in 0..100 {
+            let mut layout = Layout::generate(seed, caveinfo);
+            let total = layout.get_spawn_objects().count();
+            let non_plants = layout.get_spawn_objects().filter(|(so, _)| !so.is_plant()).count();
+            layout.remove_plants();
+            assert!(layout.get_spawn_objects().all(|(so, _)| !so.is_plant()));
+            assert_eq!(layout.get_spawn_objects().count(), non_plants);
+            num_plants_removed += total - non_plants;
+        }
+    }
+    assert!(num_plants_removed > 0);
+}
+
+#[test]
+fn test_slug_is_deterministic() {
+    let mgr = FsAssetManager::init().unwrap();
+    for sublevel in ["ec1", "sh6", "fc4", "scx7"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel, &mgr).unwrap()).unwrap();
+        for seed in [0x1234ABCD, 0xB5E72294, 0x42AC4C0F] {
+            assert_eq!(
+                Layout::generate(seed, caveinfo).slug(),
+                Layout::generate(seed, caveinfo).slug(),
+                "{sublevel} {seed:#010X}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_fingerprint_matches_slug() {
+    let mgr = FsAssetManager::init().unwrap();
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("ec1", &mgr).unwrap()).unwrap();
+    let mut slugs_by_fingerprint: HashMap<u64, String> = HashMap::new();
+    let mut fingerprints_by_slug: HashMap<String, u64> = HashMap::new();
+    for seed in 0..5000 {
+        let layout = Layout::generate(seed, caveinfo);
+        let (slug, fingerprint) = (layout.slug(), layout.fingerprint());
+        assert_eq!(fingerprint, layout.fingerprint());
+        assert_eq!(
+            *fingerprints_by_slug.entry(slug.clone()).or_insert(fingerprint),
+            fingerprint,
+            "{slug}"
+        );
+        assert_eq!(
+            *slugs_by_fingerprint.entry(fingerprint).or_insert(slug.clone()),
+            slug,
+            "{fingerprint:#018X}"
+        );
+    }
+}
+
+#[test]
+fn test_slug_distinguishes_objects_at_same_position() {
+    let teki = |carrying: Option<&str>| TekiInfo {
+        game: "pikmin2".to_string(),
+        internal_name: "kogane".to_string(),
+        carrying: carrying.map(str::to_string),
+        minimum_amount: 1,
+        filler_distribution_weight: 0,
+        group: 1,
+        spawn_method: None,
+    };
+    let (plain, carrying) = (teki(None), teki(Some("bey_goma")));
+    let unit = CaveUnit {
+        game: "pikmin2".to_string(),
+        unit_folder_name: "room_test".to_string(),
+        width: 1,
+        height: 1,
+        room_type: RoomType::Room,
+        num_doors: 0,
+        doors: Vec::new(),
+        rotation: 0,
+        spawnpoints: vec![SpawnPoint {
+            group: 1,
+            pos: Point([0.0, 0.0, 0.0]),
+            angle_degrees: 0.0,
+            radius: 0.0,
+            min_num: 1,
+            max_num: 1,
+        }],
+        waterboxes: Vec::new(),
+        waypoints: Vec::new(),
+    };
+    let cfg = CaveConfig {
+        game: "pikmin2".to_string(),
+        full_name: "Test Cave".to_string(),
+        is_challenge_mode: false,
+        shortened_names: vec!["test".to_string()],
+        caveinfo_filename: String::new(),
+    };
+    let slug = |objects| {
+        let mut map_unit = PlacedMapUnit::new(&unit, 0, 0);
+        map_unit.spawnpoints[0].contains = objects;
+        Layout::from_placed_units(Sublevel::from_cfg(&cfg, 1), vec![map_unit]).slug()
+    };
+
+    // Both teki are at the same position, so which one was placed first can't change the
+    // slug, and neither can where the ship is in the list.
+    let origin = Point([0.0, 0.0, 0.0]);
+    let forward = slug(vec![
+        SpawnObject::Teki(&carrying, origin),
+        SpawnObject::Teki(&plain, origin),
+        SpawnObject::Ship,
+    ]);
+    let backward = slug(vec![
+        SpawnObject::Ship,
+        SpawnObject::Teki(&plain, origin),
+        SpawnObject::Teki(&carrying, origin),
+    ]);
+    assert_eq!(forward, backward);
+    assert_eq!(
+        forward,
+        "test1;(room_test,x0z0r0;);(kogane,x85z85;kogane/bey_goma,x85z85;ship,x85z85;)"
+    );
+
+    // Teki that only differ in what they carry, how they spawn, or by being a falling
+    // variant still get different slugs.
+    let falling = TekiInfo {
+        spawn_method: Some("$".to_string()),
+        ..plain.clone()
+    };
+    let fkabuto = TekiInfo {
+        internal_name: "fkabuto".to_string(),
+        ..plain.clone()
+    };
+    let kabuto = TekiInfo {
+        internal_name: "kabuto".to_string(),
+        ..plain.clone()
+    };
+    let plain_slug = slug(vec![SpawnObject::Teki(&plain, origin)]);
+    assert_ne!(plain_slug, slug(vec![SpawnObject::Teki(&carrying, origin)]));
+    assert_eq!(
+        slug(vec![SpawnObject::Teki(&falling, origin)]),
+        "test1;(room_test,x0z0r0;);(kogane@$,x85z85;)"
+    );
+    assert_ne!(
+        slug(vec![SpawnObject::Teki(&fkabuto, origin)]),
+        slug(vec![SpawnObject::Teki(&kabuto, origin)])
+    );
+}
+
+#[test]
+fn test_initial_teki_offsets() {
+    let mgr = FsAssetManager::init().unwrap();
+    for sublevel in ["ec1", "fc1", "bk4"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel, &mgr).unwrap()).unwrap();
+        for seed in 0..100 {
+            let layout = Layout::generate(seed, caveinfo);
+            for sp in layout.map_units.iter().flat_map(|unit| unit.spawnpoints.iter()) {
+                if sp.spawnpoint_unit.group != 0 {
+                    assert!(sp.initial_teki_offsets.is_empty());
+                    continue;
+                }
+                let num_teki = sp.contains.iter().filter(|so| matches!(so, SpawnObject::Teki(..))).count();
+                assert_eq!(sp.initial_teki_offsets.len(), num_teki, "{sublevel} {seed:#010X}");
+                for offset in sp.initial_teki_offsets.iter() {
+                    assert!(offset.p2_dist(&Point([0.0, 0.0, 0.0])) <= sp.spawnpoint_unit.radius + 0.01);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_modified_caveinfo() {
+    let mgr = FsAssetManager::init().unwrap();
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("scx7", &mgr).unwrap()).unwrap();
+    let modified = caveinfo
+        .to_builder()
+        .teki_weight("minihoudai", 0)
+        .unwrap()
+        .teki_min_amount("minihoudai", 0)
+        .unwrap()
+        .build();
+    assert!(caveinfo.to_builder().teki_weight("not_a_teki", 10).is_err());
+
+    for seed in [0x1234ABCD, 0xB5E72294, 0x42AC4C0F] {
+        let vanilla_layout = Layout::generate(seed, caveinfo);
+        let modified_layout = Layout::generate(seed, &modified);
+        assert!(!vanilla_layout.sublevel.modified);
+        assert!(modified_layout.sublevel.modified);
+        assert_ne!(vanilla_layout.sublevel, modified_layout.sublevel);
+        assert!(modified_layout.cave_name.ends_with("-modified"), "{}", modified_layout.cave_name);
+        assert!(modified_layout.slug().starts_with("SCx7-modified;"), "{}", modified_layout.slug());
+        assert!(modified_layout
+            .get_spawn_objects()
+            .all(|(so, _)| !so.name().eq_ignore_ascii_case("minihoudai")));
+    }
+}
+
+#[test]
+fn test_challenge_mode_override() {
+    let mgr = FsAssetManager::init().unwrap();
+    for sublevel_name in ["scx7", "ch24-1"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel_name, &mgr).unwrap()).unwrap();
+        let other_mode = !caveinfo.is_challenge_mode();
+        let forced = caveinfo.to_builder().challenge_mode(other_mode).build();
+        assert_eq!(forced.is_challenge_mode(), other_mode);
+        assert_eq!(forced.long_name(), format!("{} (modified)", caveinfo.long_name()));
+
+        let mut num_different = 0;
+        for seed in 0..20 {
+            let vanilla_layout = Layout::generate(seed, caveinfo);
+            let forced_layout = Layout::generate(seed, &forced);
+            assert!(forced_layout.sublevel.modified);
+
+            // Only hole and treasure placement depend on the mode, so compare what got placed
+            // where rather than the whole slug, which also includes the sublevel name.
+            let object_slugs = |layout: &Layout| {
+                let mut slugs = layout
+                    .get_spawn_objects()
+                    .map(|(so, pos)| spawn_object_slug(so, pos))
+                    .collect::<Vec<_>>();
+                slugs.sort();
+                slugs
+            };
+            num_different += (object_slugs(&vanilla_layout) != object_slugs(&forced_layout)) as u32;
+        }
+        assert!(
+            num_different > 0,
+            "expected {sublevel_name} layouts to change when generated in the other mode"
+        );
+    }
+}
+
+#[test]
+fn test_is_roaming() {
+    let teki = |internal_name: &str| TekiInfo {
+        game: "pikmin2".to_string(),
+        internal_name: internal_name.to_string(),
+        carrying: None,
+        minimum_amount: 1,
+        filler_distribution_weight: 0,
+        group: 1,
+        spawn_method: None,
+    };
+    let groink = teki("MiniHoudai");
+    let bulborb = teki("chappy");
+    assert!(SpawnObject::Teki(&groink, Point::default()).is_roaming());
+    assert!(!SpawnObject::Teki(&bulborb, Point::default()).is_roaming());
+}
+
+#[test]
+fn test_possible_start_rooms() {
+    let mgr = FsAssetManager::init().unwrap();
+    for sublevel in ["scx7", "bk4", "fc3", "ch24-1"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel, &mgr).unwrap()).unwrap();
+        let start_rooms: Vec<&str> = caveinfo.possible_start_rooms().map(|unit| unit.unit_folder_name.as_str()).collect();
+        assert!(!start_rooms.is_empty(), "{sublevel}");
+
+        for seed in 0..100 {
+            let layout = Layout::generate(seed, caveinfo);
+            let start_room = &layout.map_units[0].unit.unit_folder_name;
+            assert!(start_rooms.contains(&start_room.as_str()), "{sublevel} {seed:#010X}: {start_room}");
+        }
+    }
+}
+
+#[test]
+fn test_waypoint_graph_dot() {
+    let mgr = FsAssetManager::init().unwrap();
+    for sublevel in ["fc1", "bk4", "scx7"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel, &mgr).unwrap()).unwrap();
+        for seed in 0..20 {
+            let layout = Layout::generate(seed, caveinfo);
+            let dot = layout.waypoint_graph_dot();
+            assert!(dot.starts_with("graph ") && dot.trim_end().ends_with('}'), "{dot}");
+
+            let nodes: Vec<&str> = dot.lines().filter(|line| line.contains("label=") && !line.contains("--")).collect();
+            let edges: Vec<&str> = dot.lines().filter(|line| line.contains("--")).collect();
+            assert_eq!(nodes.len(), layout.map_units.len(), "{sublevel} {seed:#010X}");
+            for (idx, unit) in layout.map_units.iter().enumerate() {
+                assert!(nodes[idx].contains(&unit.unit.unit_folder_name), "{}", nodes[idx]);
+            }
+
+            // Every connected door is shared by two units but only gets one edge.
+            let connected_doors = layout
+                .map_units
+                .iter()
+                .flat_map(|unit| unit.doors.iter())
+                .filter(|door| door.borrow().adjacent_door.is_some())
+                .count();
+            assert_eq!(edges.len() * 2, connected_doors, "{sublevel} {seed:#010X}");
+
+            assert_eq!(nodes.iter().filter(|node| node.contains("ship=true")).count(), 1);
+            let exit_units = layout
+                .map_units
+                .iter()
+                .filter(|unit| {
+                    unit.spawn_objects()
+                        .any(|so| matches!(so, SpawnObject::Hole(_) | SpawnObject::Geyser(_)))
+                })
+                .count();
+            assert_eq!(nodes.iter().filter(|node| node.contains("exit=")).count(), exit_units);
+        }
+    }
+}
+
+#[test]
+fn test_waypoint_graph_dot_edges() {
+    // Two 1x1 rooms side by side, joined by one door on their shared edge.
+    let unit = |direction| CaveUnit {
+        game: "pikmin2".to_string(),
+        unit_folder_name: format!("room_test{direction}"),
+        width: 1,
+        height: 1,
+        room_type: RoomType::Room,
+        num_doors: 1,
+        doors: vec![DoorUnit {
+            direction,
+            side_lateral_offset: 0,
+            waypoint_index: 0,
+            num_links: 0,
+            door_links: Vec::new(),
+        }],
+        rotation: 0,
+        spawnpoints: Vec::new(),
+        waterboxes: Vec::new(),
+        waypoints: Vec::new(),
+    };
+    let units = [unit(1), unit(3)];
+    let map_units = vec![PlacedMapUnit::new(&units[0], 0, 0), PlacedMapUnit::new(&units[1], 1, 0)];
+    let doors = [Rc::clone(&map_units[0].doors[0]), Rc::clone(&map_units[1].doors[0])];
+    for (idx, door) in doors.iter().enumerate() {
+        let mut door = door.borrow_mut();
+        door.parent_idx = Some(idx);
+        door.adjacent_door = Some(Rc::downgrade(&doors[1 - idx]));
+    }
+    let cfg = CaveConfig {
+        game: "pikmin2".to_string(),
+        full_name: "Test Cave".to_string(),
+        is_challenge_mode: false,
+        shortened_names: vec!["test".to_string()],
+        caveinfo_filename: String::new(),
+    };
+    let layout = Layout::from_placed_units(Sublevel::from_cfg(&cfg, 1), map_units);
+
+    // Each unit center is 85 units from the door, one map unit cell in total.
+    assert_eq!(
+        layout.waypoint_graph_dot(),
+        "graph \"Test Cave\" {\n    0 [label=\"room_test1\"];\n    1 [label=\"room_test3\"];\n    0 -- 1 [len=1.00, label=\"170\"];\n}\n"
+    );
+}
+
+/// A sublevel made of one start room with a single door, plus a cap to close it off and
+/// optionally the straight hallways that let the map unit placement loop finish.
+fn minimal_caveinfo(with_hallways: bool) -> CaveInfo {
+    let unit = |name: &str, room_type, height, door_directions: &[u16], spawn_group: Option<u16>| CaveUnit {
+        game: "pikmin2".to_string(),
+        unit_folder_name: name.to_string(),
+        width: 1,
+        height,
+        room_type,
+        num_doors: door_directions.len(),
+        doors: door_directions
+            .iter()
+            .map(|&direction| DoorUnit {
+                direction,
+                side_lateral_offset: 0,
+                waypoint_index: 0,
+                num_links: 0,
+                door_links: Vec::new(),
+            })
+            .collect(),
+        rotation: 0,
+        spawnpoints: spawn_group
+            .map(|group| SpawnPoint {
+                group,
+                pos: Point([0.0, 0.0, 0.0]),
+                angle_degrees: 0.0,
+                radius: 0.0,
+                min_num: 1,
+                max_num: 1,
+            })
+            .into_iter()
+            .collect(),
+        waterboxes: Vec::new(),
+        waypoints: Vec::new(),
+    };
+    let mut units = vec![
+        unit("room_start", RoomType::Room, 1, &[0], Some(7)),
+        unit("cap_test", RoomType::DeadEnd, 1, &[0], None),
+    ];
+    if with_hallways {
+        units.push(unit("way_1x1", RoomType::Hallway, 1, &[0, 2], None));
+        units.push(unit("way_2x1", RoomType::Hallway, 2, &[0, 2], None));
+    }
+    CaveInfo {
+        cave_cfg: CaveConfig {
+            game: "pikmin2".to_string(),
+            full_name: "Test Cave".to_string(),
+            is_challenge_mode: false,
+            shortened_names: vec!["test".to_string()],
+            caveinfo_filename: String::new(),
+        },
+        floor_num: 0,
+        max_main_objects: 0,
+        max_treasures: 0,
+        max_gates: 0,
+        num_rooms: 1,
+        corridor_probability: 0.0,
+        cap_probability: 0.0,
+        has_geyser: false,
+        exit_plugged: false,
+        cave_units: units
+            .iter()
+            .flat_map(|unit| (0..4).map(|rotation| unit.copy_and_rotate_to(rotation)))
+            .collect(),
+        teki_info: Vec::new(),
+        item_info: Vec::new(),
+        gate_info: Vec::new(),
+        cap_info: Vec::new(),
+        is_final_floor: false,
+        waterwraith_timer: 0.0,
+        modified: false,
+        challenge_mode_override: None,
+    }
+}
+
+#[test]
+fn test_generation_stats() {
+    // The start room's only door gets capped on the first iteration, after which the
+    // loop is done.
+    let caveinfo = minimal_caveinfo(true);
+    for seed in [0, 0x1234ABCD] {
+        let layout = Layout::generate(seed, &caveinfo);
+        assert_eq!(layout.map_units.len(), 2);
+        assert_eq!(
+            layout.generation_stats,
+            GenerationStats {
+                map_unit_loops: 1,
+                hit_loop_cap: false,
+            }
+        );
+    }
+
+    // Without both kinds of straight hallway the loop retries the hallway combining step
+    // until it runs out, even though every door was closed right away.
+    let caveinfo = minimal_caveinfo(false);
+    for seed in [0, 0x1234ABCD] {
+        let layout = Layout::generate(seed, &caveinfo);
+        assert_eq!(layout.map_units.len(), 2);
+        assert_eq!(
+            layout.generation_stats,
+            GenerationStats {
+                map_unit_loops: MAX_MAP_UNIT_LOOPS + 1,
+                hit_loop_cap: true,
+            }
+        );
+    }
+}
+
+#[test]
+fn test_teki_placements() {
+    let mgr = FsAssetManager::init().unwrap();
+    for sublevel in ["ec1", "scx7", "bk4", "sh6", "fc1"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel, &mgr).unwrap()).unwrap();
+        for seed in 0..20 {
+            let layout = Layout::generate(seed, caveinfo);
+            let num_teki = layout
+                .get_spawn_objects()
+                .filter(|(so, _)| matches!(so, SpawnObject::Teki(..)))
+                .count();
+            assert_eq!(layout.teki_placements().count(), num_teki, "{sublevel} {seed:#010X}");
+
+            for (so, placement) in layout.teki_placements() {
+                let SpawnObject::Teki(teki, _) = so else {
+                    panic!("{so:?} isn't a teki");
+                };
+                // Teki without a filler weight can only be placed to make up their minimum.
+                if teki.filler_distribution_weight == 0 {
+                    assert_eq!(placement, TekiPlacement::Minimum, "{sublevel} {seed:#010X} {}", teki.internal_name);
+                }
+                if teki.minimum_amount == 0 {
+                    assert_eq!(placement, TekiPlacement::Filler, "{sublevel} {seed:#010X} {}", teki.internal_name);
+                }
+            }
+            for teki in caveinfo.teki_info.iter() {
+                let num_minimum = layout
+                    .teki_placements()
+                    .filter(|(so, placement)| {
+                        *placement == TekiPlacement::Minimum && matches!(so, SpawnObject::Teki(t, _) if std::ptr::eq(*t, teki))
+                    })
+                    .count();
+                assert!(
+                    num_minimum <= teki.minimum_amount as usize,
+                    "{sublevel} {seed:#010X} {}",
+                    teki.internal_name
+                );
+            }
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "gen_timing")]
+fn test_generation_timings() {
+    let mgr = FsAssetManager::init().unwrap();
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("scx7", &mgr).unwrap()).unwrap();
+    let timings = Layout::generate(0x1234ABCD, caveinfo).timings;
+    let phases_sum: std::time::Duration = timings.phases().iter().map(|(_, phase)| *phase).sum();
+    assert!(!timings.map_units.is_zero(), "{timings:?}");
+    assert!(phases_sum <= timings.total, "{timings:?}");
+}
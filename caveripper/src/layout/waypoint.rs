@@ -195,6 +195,59 @@ impl WaypointGraph {
         }
         iter::once(pos).chain(ret.into_iter().map(|wp| wp.pos))
     }
+
+    /// Total length of the carry path from the provided point back to the ship. `None` if
+    /// there's nothing to carry along, i.e. the graph has no connected waypoints.
+    pub fn carry_path_len(&self, pos: Point<3, f32>) -> Option<f32> {
+        if self.graph.edge_count() == 0 {
+            return None;
+        }
+        Some(
+            self.carry_path_wps(pos)
+                .tuple_windows()
+                .map(|(p1, p2)| p1.dist(&p2))
+                .sum(),
+        )
+    }
+
+    /// Whether any route exists through the waypoint graph between the waypoints closest to
+    /// `from` and `to` without passing through a waypoint for which `avoid` returns true.
+    /// Unlike carry paths, this ignores the direction of travel and considers every route
+    /// rather than just the shortest one.
+    pub fn has_path_avoiding(
+        &self,
+        from: Point<3, f32>,
+        to: Point<3, f32>,
+        avoid: impl Fn(&WaypointGraphNode) -> bool,
+    ) -> bool {
+        let closest_wp = |pos: Point<3, f32>| {
+            self.graph
+                .node_indices()
+                .min_by_key(|wp| FloatOrd(self.graph[*wp].pos.p2_dist(&pos)))
+        };
+        let (Some(start), Some(end)) = (closest_wp(from), closest_wp(to)) else {
+            return false;
+        };
+        if avoid(&self.graph[start]) || avoid(&self.graph[end]) {
+            return false;
+        }
+
+        let mut visited = vec![false; self.graph.node_count()];
+        visited[start.index()] = true;
+        let mut stack = vec![start];
+        while let Some(wp) = stack.pop() {
+            if wp == end {
+                return true;
+            }
+            for neighbor in self.graph.neighbors_undirected(wp) {
+                if !visited[neighbor.index()] && !avoid(&self.graph[neighbor]) {
+                    visited[neighbor.index()] = true;
+                    stack.push(neighbor);
+                }
+            }
+        }
+        false
+    }
 }
 
 #[derive(Debug, Clone)]
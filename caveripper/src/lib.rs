@@ -6,6 +6,8 @@
 #![allow(stable_features)] // This feature is required to be able to build on NixOS for some reason.
 #![feature(let_chains)]
 
+use std::{fmt::Display, str::FromStr};
+
 use error_stack::{report, Report, ResultExt};
 use errors::CaveripperError;
 use rand::random;
@@ -25,20 +27,67 @@ pub mod query;
 
 pub mod assets;
 pub mod errors;
+pub mod permalink;
 pub mod pikmin_math;
 mod point;
 pub mod render;
 pub mod sublevel;
 
-pub fn parse_seed(src: &str) -> Result<u32, Report<CaveripperError>> {
+/// A seed for Pikmin 2's RNG, i.e. what determines which layout a sublevel generates.
+/// Displays in the canonical `0x1234ABCD` form (see [format_seed]) and parses from
+/// anything [parse_seed] accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Seed(pub u32);
+
+impl Seed {
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+impl From<u32> for Seed {
+    fn from(seed: u32) -> Self {
+        Seed(seed)
+    }
+}
+
+impl From<Seed> for u32 {
+    fn from(seed: Seed) -> Self {
+        seed.0
+    }
+}
+
+impl FromStr for Seed {
+    type Err = Report<CaveripperError>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_seed(s)
+    }
+}
+
+impl Display for Seed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format_seed(self.0))
+    }
+}
+
+/// Parses a seed written as 8 hex digits, optionally prefixed with "0x", or the word
+/// "random" for a randomly chosen seed. Not case sensitive.
+pub fn parse_seed(src: &str) -> Result<Seed, Report<CaveripperError>> {
     if src.eq_ignore_ascii_case("random") {
-        return Ok(random());
+        return Ok(Seed(random()));
     }
 
     let trimmed = src.strip_prefix("0x").unwrap_or(src);
     if trimmed.len() != 8 {
         Err(report!(CaveripperError::SeedError))
     } else {
-        u32::from_str_radix(trimmed, 16).change_context(CaveripperError::SeedError)
+        u32::from_str_radix(trimmed, 16).map(Seed).change_context(CaveripperError::SeedError)
     }
 }
+
+/// Formats a seed the way Caveripper displays seeds everywhere, e.g. "0x1234ABCD".
+/// The result can always be read back with [parse_seed].
+pub fn format_seed(seed: u32) -> String {
+    format!("{seed:#010X}")
+}
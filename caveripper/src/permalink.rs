@@ -0,0 +1,180 @@
+//! Compact, URL-safe strings describing a rendered layout, so a layout viewed in the
+//! CLI can be opened in the web viewer (or vice versa) exactly as it was seen.
+//!
+//! A permalink looks like `1.pikmin2.SCx-3.1234ABCD.2.0`: a format version, the game,
+//! the sublevel, the seed, the enabled render flags as a hex bitfield, and the palette.
+//! Only on/off render options and the palette are included; numeric tweaks like the
+//! quickglance radius are left at their defaults.
+
+use clap::ValueEnum;
+use error_stack::{report, Result, ResultExt};
+
+use crate::{
+    assets::AssetManager,
+    errors::CaveripperError,
+    render::{LayoutRenderOptions, Palette},
+    sublevel::Sublevel,
+};
+
+/// Bumped whenever the meaning of an existing field changes. Adding a new flag to the
+/// end of [flags] doesn't require a new version since old links will just leave it off.
+const PERMALINK_VERSION: &str = "1";
+const SEPARATOR: char = '.';
+
+/// A sublevel, seed, and set of render options decoded from a permalink.
+#[derive(Debug)]
+pub struct Permalink {
+    pub sublevel: Sublevel,
+    pub seed: u32,
+    pub options: LayoutRenderOptions,
+}
+
+pub fn encode_permalink(sublevel: &Sublevel, seed: u32, options: &LayoutRenderOptions) -> String {
+    let bitfield = flags(options)
+        .iter()
+        .enumerate()
+        .fold(0u32, |bits, (i, flag)| bits | ((*flag as u32) << i));
+    let palette = Palette::value_variants()
+        .iter()
+        .position(|p| *p == options.palette)
+        .unwrap_or_default();
+    [
+        PERMALINK_VERSION.to_string(),
+        sublevel.cfg.game.clone(),
+        sublevel.normalized_name(),
+        format!("{seed:08X}"),
+        format!("{bitfield:X}"),
+        palette.to_string(),
+    ]
+    .join(&SEPARATOR.to_string())
+}
+
+pub fn decode_permalink(permalink: &str, mgr: &impl AssetManager) -> Result<Permalink, CaveripperError> {
+    let parts: Vec<&str> = permalink.trim().trim_start_matches('#').split(SEPARATOR).collect();
+    let [version, game, sublevel, seed, bitfield, palette] = parts.as_slice() else {
+        return Err(report!(CaveripperError::PermalinkError)).attach_printable_lazy(|| permalink.to_string());
+    };
+    if *version != PERMALINK_VERSION {
+        return Err(report!(CaveripperError::PermalinkError)).attach_printable_lazy(|| format!("Unsupported permalink version '{version}'"));
+    }
+
+    let sublevel = Sublevel::try_from_str(&format!("{game}:{sublevel}"), mgr).change_context(CaveripperError::PermalinkError)?;
+    let seed = u32::from_str_radix(seed, 16)
+        .change_context(CaveripperError::PermalinkError)
+        .attach_printable_lazy(|| format!("Invalid seed '{seed}'"))?;
+    let bitfield = u32::from_str_radix(bitfield, 16)
+        .change_context(CaveripperError::PermalinkError)
+        .attach_printable_lazy(|| format!("Invalid render flags '{bitfield}'"))?;
+    let palette = palette
+        .parse::<usize>()
+        .ok()
+        .and_then(|idx| Palette::value_variants().get(idx))
+        .ok_or(report!(CaveripperError::PermalinkError))
+        .attach_printable_lazy(|| format!("Invalid palette '{palette}'"))?;
+
+    let mut options = LayoutRenderOptions {
+        palette: *palette,
+        ..Default::default()
+    };
+    for (i, flag) in flags_mut(&mut options).into_iter().enumerate() {
+        *flag = bitfield & (1 << i) != 0;
+    }
+    Ok(Permalink { sublevel, seed, options })
+}
+
+/// Every on/off render option, in bitfield order. Must stay in the same order as [flags_mut],
+/// and new options must only ever be added to the end.
+fn flags(options: &LayoutRenderOptions) -> [bool; 21] {
+    [
+        options.draw_grid,
+        options.quickglance,
+        options.quickglance_glyphs,
+        options.draw_gauge_range,
+        options.draw_score,
+        options.draw_waypoints,
+        options.draw_comedown_square,
+        options.draw_teki_groups,
+        options.hide_plants,
+        options.draw_gate_hp,
+        options.draw_spawn_radius,
+        options.draw_spawn_push,
+        options.draw_unit_names,
+        options.shade_waterbox_depth,
+        options.normalize_orientation,
+        options.dim_start_room,
+        options.crisp_shapes,
+        options.skeleton,
+        options.draw_door_scores,
+        options.draw_world_coords,
+        options.draw_shadows,
+    ]
+}
+
+fn flags_mut(options: &mut LayoutRenderOptions) -> [&mut bool; 21] {
+    [
+        &mut options.draw_grid,
+        &mut options.quickglance,
+        &mut options.quickglance_glyphs,
+        &mut options.draw_gauge_range,
+        &mut options.draw_score,
+        &mut options.draw_waypoints,
+        &mut options.draw_comedown_square,
+        &mut options.draw_teki_groups,
+        &mut options.hide_plants,
+        &mut options.draw_gate_hp,
+        &mut options.draw_spawn_radius,
+        &mut options.draw_spawn_push,
+        &mut options.draw_unit_names,
+        &mut options.shade_waterbox_depth,
+        &mut options.normalize_orientation,
+        &mut options.dim_start_room,
+        &mut options.crisp_shapes,
+        &mut options.skeleton,
+        &mut options.draw_door_scores,
+        &mut options.draw_world_coords,
+        &mut options.draw_shadows,
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_permalink, encode_permalink, flags};
+    use crate::{
+        assets::fs_asset_manager::FsAssetManager,
+        render::{LayoutRenderOptions, Palette},
+        sublevel::Sublevel,
+    };
+
+    #[test]
+    fn test_permalink_roundtrip() {
+        let mgr = FsAssetManager::init().unwrap();
+        for sublevel_name in ["scx3", "bk4", "ch24-1", "216:tr12"] {
+            let sublevel = Sublevel::try_from_str(sublevel_name, &mgr).unwrap();
+            let options = LayoutRenderOptions {
+                quickglance: true,
+                draw_gauge_range: true,
+                normalize_orientation: true,
+                palette: Palette::Deuteranopia,
+                ..Default::default()
+            };
+            let permalink = encode_permalink(&sublevel, 0x1234ABCD, &options);
+            assert!(permalink.chars().all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c)), "{permalink}");
+
+            let decoded = decode_permalink(&permalink, &mgr).unwrap_or_else(|e| panic!("{permalink}: {e:?}"));
+            assert_eq!(decoded.sublevel, sublevel);
+            assert_eq!(decoded.seed, 0x1234ABCD);
+            assert_eq!(decoded.options.palette, Palette::Deuteranopia);
+            assert_eq!(flags(&decoded.options), flags(&options));
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid_permalink() {
+        let mgr = FsAssetManager::init().unwrap();
+        assert!(decode_permalink("1.pikmin2.SCx-3.1234ABCD.0", &mgr).is_err());
+        assert!(decode_permalink("2.pikmin2.SCx-3.1234ABCD.0.0", &mgr).is_err());
+        assert!(decode_permalink("1.pikmin2.SCx-3.nothex!!.0.0", &mgr).is_err());
+        assert!(decode_permalink("1.pikmin2.SCx-3.1234ABCD.0.99", &mgr).is_err());
+        assert!(decode_permalink("1.pikmin2.SCx-3.1234ABCD.0.0", &mgr).is_ok());
+    }
+}
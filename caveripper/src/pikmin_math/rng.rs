@@ -14,8 +14,22 @@ use std::{cell::Cell, num::NonZeroU32};
 #[derive(Default, Debug)]
 pub struct PikminRng {
     seed: Cell<u32>,
-    #[cfg(debug_assertions)]
-    pub(crate) num_rng_calls: Cell<u32>
+    #[cfg(feature = "rng_stats")]
+    call_counts: Cell<RngCallCounts>,
+}
+
+/// Number of RNG calls made, broken down by which [PikminRng] method made them.
+/// The helper methods call [PikminRng::rand_int] internally, so those calls are
+/// counted under both. Only tracked when the `rng_stats` feature is enabled.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RngCallCounts {
+    /// Total number of steps the RNG has been advanced by, from any method.
+    pub raw: u32,
+    pub rand_int: u32,
+    pub rand_f32: u32,
+    pub rand_backs: u32,
+    pub rand_index_weight: u32,
+    pub rand_swaps: u32,
 }
 
 impl PikminRng {
@@ -23,8 +37,33 @@ impl PikminRng {
     pub fn new(seed: u32) -> Self {
         Self {
             seed: Cell::new(seed),
-            #[cfg(debug_assertions)]
-            num_rng_calls: Cell::new(0),
+            #[cfg(feature = "rng_stats")]
+            call_counts: Cell::new(RngCallCounts::default()),
+        }
+    }
+
+    /// RNG calls made since this RNG was created or its counts were last reset.
+    /// Always zero unless the `rng_stats` feature is enabled.
+    pub fn call_counts(&self) -> RngCallCounts {
+        #[cfg(feature = "rng_stats")]
+        return self.call_counts.get();
+        #[cfg(not(feature = "rng_stats"))]
+        return RngCallCounts::default();
+    }
+
+    pub fn reset_call_counts(&self) {
+        #[cfg(feature = "rng_stats")]
+        self.call_counts.set(RngCallCounts::default());
+    }
+
+    /// Compiles to nothing without the `rng_stats` feature so searches don't pay for it.
+    #[inline(always)]
+    fn count(&self, _update: impl FnOnce(&mut RngCallCounts)) {
+        #[cfg(feature = "rng_stats")]
+        {
+            let mut counts = self.call_counts.get();
+            _update(&mut counts);
+            self.call_counts.set(counts);
         }
     }
 
@@ -57,12 +96,7 @@ impl PikminRng {
         let new_seed = old_seed.wrapping_mul(a_n).wrapping_add(b_factor);
         self.seed.set(new_seed);
 
-        #[cfg(debug_assertions)]
-        {
-            // Update RNG call count
-            let old_count = self.num_rng_calls.get();
-            self.num_rng_calls.set(old_count + <NonZeroU32 as Into<u32>>::into(n));
-        }
+        self.count(|c| c.raw += <NonZeroU32 as Into<u32>>::into(n));
 
         (new_seed >> 0x10) & 0x7FFF
     }
@@ -75,6 +109,7 @@ impl PikminRng {
     /// Most of the game's internal values are 16-bit integers, so it crunches
     /// the raw RNG results down into 16-bit space via division for compatibility.
     pub fn rand_int(&self, max: u32) -> u32 {
+        self.count(|c| c.rand_int += 1);
         (self.rand_raw() as f32 * (max as f32 / 32768f32)) as u32
     }
 
@@ -86,12 +121,14 @@ impl PikminRng {
         // the whole range of u32. what does rust do in this case? what does java
         // (original CaveGen implementation) do in this case?
         // possible alternative: `(self.rand_raw() as f64 / 32768f64) as f32`
+        self.count(|c| c.rand_f32 += 1);
         self.rand_raw() as f32 / 32768f32
     }
 
     /// Shuffles the given list by pushing randomly-chosen elements to the
     /// back of the list. Do this N times.
     pub fn rand_backs_n<T>(&self, list: &mut Vec<T>, n: usize) {
+        self.count(|c| c.rand_backs += 1);
         for _ in 0..n {
             let index = self.rand_int(list.len() as u32);
             let elem = list.remove(index as usize);
@@ -108,6 +145,7 @@ impl PikminRng {
     /// the chosen weight in the original list rather than the weight itself.
     #[allow(clippy::needless_range_loop)]
     pub fn rand_index_weight(&self, weights: &[u32]) -> Option<usize> {
+        self.count(|c| c.rand_index_weight += 1);
         let total: u32 = weights.iter().sum();
         let mut cumulative_sum: u32 = 0;
         let threshold = self.rand_int(total);
@@ -122,6 +160,7 @@ impl PikminRng {
 
     /// For each element of the list, swaps the element there with a random element.
     pub fn rand_swaps<T>(&self, list: &mut Vec<T>) {
+        self.count(|c| c.rand_swaps += 1);
         for i in 0..list.len() {
             let swap_to = self.rand_int(list.len() as u32) as usize;
             list.swap(i, swap_to);
@@ -103,3 +103,20 @@ fn test_sqrt() {
         assert_eq!(e, a, "{i}");
     }
 }
+
+#[test]
+#[cfg(feature = "rng_stats")]
+fn test_rng_call_counts() {
+    let rng = PikminRng::new(TEST_SEED);
+    let mut list = vec![1, 2, 3, 4];
+    rng.rand_f32();
+    rng.rand_backs(&mut list);
+    let counts = rng.call_counts();
+    assert_eq!(counts.raw, 5);
+    assert_eq!(counts.rand_int, 4);
+    assert_eq!(counts.rand_f32, 1);
+    assert_eq!(counts.rand_backs, 1);
+
+    rng.reset_call_counts();
+    assert_eq!(rng.call_counts(), super::RngCallCounts::default());
+}
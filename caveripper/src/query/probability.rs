@@ -0,0 +1,71 @@
+use error_stack::Result;
+use rand::random;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use super::EntityMatcher;
+use crate::{assets::AssetManager, errors::CaveripperError, layout::Layout, sublevel::Sublevel};
+
+/// z-score for a 95% confidence interval.
+const CONFIDENCE_Z: f64 = 1.96;
+
+/// How many out of a sample of random layouts had at least one of some object in them.
+/// See [spawn_probability].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpawnProbability {
+    pub num_matched: u32,
+    pub samples: u32,
+}
+
+impl SpawnProbability {
+    /// The fraction of sampled layouts that had the object, from 0.0 to 1.0.
+    pub fn estimate(&self) -> f64 {
+        if self.samples == 0 {
+            return 0.0;
+        }
+        self.num_matched as f64 / self.samples as f64
+    }
+
+    /// The range the true probability is in with 95% confidence, computed with the Wilson
+    /// score interval. Unlike the simpler normal approximation, this stays within 0.0 to
+    /// 1.0 and is still sensible when the object (almost) always or never spawns.
+    pub fn confidence_interval(&self) -> (f64, f64) {
+        if self.samples == 0 {
+            return (0.0, 1.0);
+        }
+        let n = self.samples as f64;
+        let p = self.estimate();
+        let z2 = CONFIDENCE_Z * CONFIDENCE_Z;
+        let denominator = 1.0 + z2 / n;
+        let center = (p + z2 / (2.0 * n)) / denominator;
+        let half_width = CONFIDENCE_Z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / denominator;
+        ((center - half_width).max(0.0), (center + half_width).min(1.0))
+    }
+}
+
+/// Estimates the chance that a sublevel has at least one of the named object by generating
+/// `samples` layouts from random seeds and counting how many have it. `object_name` accepts
+/// anything a query accepts as an entity name, e.g. "hole", "blackpom", or "carrying:any".
+///
+/// This is the same thing a `stats` query like `bk4 blackpom > 0` measures, without having
+/// to build a [super::StructuralQuery].
+pub fn spawn_probability(
+    mgr: &impl AssetManager,
+    sublevel: &Sublevel,
+    object_name: &str,
+    samples: u32,
+) -> Result<SpawnProbability, CaveripperError> {
+    let caveinfo = mgr.load_caveinfo(sublevel)?;
+    let object = EntityMatcher::from(object_name);
+    let num_matched = (0..samples)
+        .into_par_iter()
+        .filter(|_| {
+            Layout::generate(random(), caveinfo)
+                .get_spawn_objects()
+                .any(|(so, _)| object.matches(so))
+        })
+        .count();
+    Ok(SpawnProbability {
+        num_matched: num_matched as u32,
+        samples,
+    })
+}
@@ -1,3 +1,4 @@
+mod probability;
 mod search;
 pub mod special;
 
@@ -6,60 +7,161 @@ mod test;
 
 use std::{
     cmp::Ordering,
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     fmt::Display,
 };
 
-use error_stack::{report, Result, ResultExt};
+use error_stack::{report, Report, Result, ResultExt};
 use itertools::Itertools;
 use pest::{
+    error::InputLocation,
     iterators::{Pair, Pairs},
     Parser,
 };
 use pest_derive::Parser;
+use rayon::iter::{ParallelBridge, ParallelIterator};
+pub use probability::{spawn_probability, SpawnProbability};
 pub use search::find_matching_layouts_parallel;
 
 use crate::{
-    assets::AssetManager,
+    assets::{get_special_texture_name, AssetManager, Treasure},
     caveinfo::{CapInfo, CaveUnit, RoomType, TekiInfo},
     errors::CaveripperError,
-    layout::{Layout, SpawnObject},
+    layout::{Layout, PlacedMapUnit, SpawnObject, TekiPlacement, PLANT_TEKI},
+    pikmin_math::PikminRng,
     point::Point,
     sublevel::Sublevel,
 };
 
+/// Minimum distance from a hard teki at which a waypoint is considered dangerous to walk
+/// through, for the purposes of the `exit blocked` heuristic.
+const HARD_TEKI_DANGER_RADIUS: f32 = 150.0;
+
+/// How far outside a one-wide hallway, in game units, a roaming teki can be and still count
+/// as next to it for the `roaming chokepoint` heuristic. This is one map unit grid cell.
+const ROAMING_CHOKEPOINT_MARGIN: f32 = 170.0;
+
 #[derive(Parser)]
 #[grammar = "query/query_grammar.pest"]
 struct QueryParser;
 
 pub trait Query {
     fn matches(&self, seed: u32, mgr: &impl AssetManager) -> bool;
+
+    /// Checks a layout that's already been generated, e.g. one that's also being rendered
+    /// or checked against other queries, instead of generating it again from its seed.
+    /// Queries that can't make use of an existing layout just check its starting seed.
+    fn matches_layout(&self, layout: &Layout, mgr: &impl AssetManager) -> bool {
+        self.matches(layout.starting_seed, mgr)
+    }
+
+    /// Counts how many of the `count` seeds following `start` in Pikmin 2's RNG sequence
+    /// match, checking them in parallel. These are the same seeds `search-from` checks:
+    /// `start` itself is not included. Only the count is kept, so this is suitable for
+    /// very large ranges.
+    fn count_matches_in_range(&self, mgr: &(impl AssetManager + Sync), start: u32, count: u64) -> u64
+    where
+        Self: Sync,
+    {
+        PikminRng::new(start)
+            .take(count as usize)
+            .par_bridge()
+            .filter(|seed| self.matches(*seed, mgr))
+            .count() as u64
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct StructuralQuery {
     pub clauses: Vec<QueryClause>,
+    /// Evaluate clauses as if plants weren't there. See [SpawnObject::is_plant] for
+    /// which teki count as plants.
+    pub ignore_plants: bool,
 }
 
 impl Query for StructuralQuery {
     fn matches(&self, seed: u32, mgr: &impl AssetManager) -> bool {
-        let unique_sublevels: HashSet<&Sublevel> = self.clauses.iter().map(|clause| &clause.sublevel).collect();
-        let layouts: HashMap<&Sublevel, Layout> = unique_sublevels
+        match self.clauses.first() {
+            Some(clause) => self.matches_layout(&generate_layout(&clause.sublevel, seed, self.ignore_plants, mgr), mgr),
+            None => true,
+        }
+    }
+
+    /// Clauses about the layout's own sublevel are checked against it directly, even if it
+    /// was generated from a modified CaveInfo. Layouts for any other sublevels in the query
+    /// are generated from the layout's starting seed. If plants are ignored, they're removed
+    /// from a copy of the layout rather than the original.
+    fn matches_layout(&self, layout: &Layout, mgr: &impl AssetManager) -> bool {
+        let stripped;
+        let layout = if self.ignore_plants && layout.get_spawn_objects().any(|(so, _)| so.is_plant()) {
+            let mut without_plants = layout.clone();
+            without_plants.remove_plants();
+            stripped = without_plants;
+            &stripped
+        } else {
+            layout
+        };
+        let is_layout_sublevel = |sublevel: &Sublevel| sublevel.cfg == layout.sublevel.cfg && sublevel.floor == layout.sublevel.floor;
+
+        let other_sublevels: HashSet<&Sublevel> = self
+            .clauses
+            .iter()
+            .map(|clause| &clause.sublevel)
+            .filter(|sublevel| !is_layout_sublevel(sublevel))
+            .collect();
+        let other_layouts: HashMap<&Sublevel, Layout> = other_sublevels
             .into_iter()
-            .map(|sublevel| {
-                let caveinfo = mgr.load_caveinfo(sublevel).unwrap();
-                (sublevel, Layout::generate(seed, caveinfo))
-            })
+            .map(|sublevel| (sublevel, generate_layout(sublevel, layout.starting_seed, self.ignore_plants, mgr)))
             .collect();
-        self.clauses.iter().all(|clause| clause.matches(&layouts[&clause.sublevel]))
+        // Layouts generated here borrow from `mgr` rather than living as long as `layout`,
+        // so they can't be mixed into one reference.
+        self.clauses.iter().all(|clause| match other_layouts.get(&clause.sublevel) {
+            Some(other_layout) => clause.matches(other_layout, mgr),
+            None => clause.matches(layout, mgr),
+        })
     }
 }
 
+/// Checks several queries against the same seed, returning whether each one matched in
+/// the same order as `queries`. Each sublevel's layout is only generated once no matter
+/// how many of the queries look at it, so this is much faster than calling
+/// [Query::matches] on every query separately.
+pub fn matches_batch(queries: &[StructuralQuery], seed: u32, mgr: &impl AssetManager) -> Vec<bool> {
+    // Queries that ignore plants need their own copy of the layout with the plants removed.
+    let mut layouts: HashMap<(&Sublevel, bool), Layout> = HashMap::new();
+    for query in queries {
+        for clause in query.clauses.iter() {
+            layouts
+                .entry((&clause.sublevel, query.ignore_plants))
+                .or_insert_with(|| generate_layout(&clause.sublevel, seed, query.ignore_plants, mgr));
+        }
+    }
+    queries
+        .iter()
+        .map(|query| {
+            query
+                .clauses
+                .iter()
+                .all(|clause| clause.matches(&layouts[&(&clause.sublevel, query.ignore_plants)], mgr))
+        })
+        .collect()
+}
+
+fn generate_layout<'a>(sublevel: &Sublevel, seed: u32, ignore_plants: bool, mgr: &'a impl AssetManager) -> Layout<'a> {
+    let caveinfo = mgr.load_caveinfo(sublevel).unwrap();
+    let mut layout = Layout::generate(seed, caveinfo);
+    if ignore_plants {
+        layout.remove_plants();
+    }
+    layout
+}
+
 impl StructuralQuery {
     /// Parse a series of SearchConditions from a query string, usually passed in by the CLI.
     /// This effectively defines a DSL for search terms.
     pub fn try_parse(input: &str, mgr: &impl AssetManager) -> Result<Self, CaveripperError> {
-        let pairs = QueryParser::parse(Rule::query, input).change_context(CaveripperError::QueryParseError)?;
+        let pairs = QueryParser::parse(Rule::query, input)
+            .map_err(|e| report!(CaveripperError::QueryParseError).attach_printable(QueryParseError::from(e)))?;
         let mut sublevel: Option<Sublevel> = None;
         let mut clauses = Vec::new();
         for pair in pairs {
@@ -68,7 +170,10 @@ impl StructuralQuery {
                     sublevel = Some(
                         Sublevel::try_from_str(pair.as_str(), mgr)
                             .change_context(CaveripperError::QueryParseError)
-                            .attach_printable_lazy(|| pair.as_str().to_string())?,
+                            .attach_printable_lazy(|| pair.as_str().to_string())
+                            .attach_printable_lazy(|| {
+                                QueryParseError::new(pair.as_span().start(), format!("unrecognized sublevel '{}'", pair.as_str()))
+                            })?,
                     );
                 }
                 Rule::expression => {
@@ -87,7 +192,10 @@ impl StructuralQuery {
                 }
             }
         }
-        Ok(StructuralQuery { clauses })
+        Ok(StructuralQuery {
+            clauses,
+            ignore_plants: false,
+        })
     }
 }
 
@@ -103,6 +211,63 @@ impl Display for StructuralQuery {
     }
 }
 
+/// Where and why a query string failed to parse. Attached to
+/// [CaveripperError::QueryParseError] reports whenever the location of the problem
+/// is known; retrieve it with `report.downcast_ref::<QueryParseError>()`.
+#[derive(Debug, Clone)]
+pub struct QueryParseError {
+    /// Byte offset into the query string where the problem was found.
+    pub offset: usize,
+    pub message: String,
+}
+
+impl QueryParseError {
+    fn new(offset: usize, message: impl Into<String>) -> Self {
+        QueryParseError {
+            offset,
+            message: message.into(),
+        }
+    }
+
+    /// Formats the query string that failed to parse with a caret underneath the
+    /// location of the problem, followed by the error message.
+    pub fn caret_diagram(&self, input: &str) -> String {
+        let column = input.get(..self.offset).map_or(0, |before| before.chars().count());
+        format!("{input}\n{}^ {}", " ".repeat(column), self.message)
+    }
+}
+
+impl From<pest::error::Error<Rule>> for QueryParseError {
+    fn from(err: pest::error::Error<Rule>) -> Self {
+        let offset = match err.location {
+            InputLocation::Pos(pos) => pos,
+            InputLocation::Span((start, _)) => start,
+        };
+        let err = err.renamed_rules(|rule| match rule {
+            Rule::number => "number".to_string(),
+            Rule::comparator => "comparison ('<', '=', or '>')".to_string(),
+            Rule::ident => "name".to_string(),
+            Rule::door_count => "room type and door count".to_string(),
+            Rule::sublevel_ident => "sublevel name".to_string(),
+            Rule::entity => "teki, treasure, or other object name".to_string(),
+            Rule::room_path_component => "room name".to_string(),
+            Rule::path_link => "'->'".to_string(),
+            Rule::path_quantifier => "'path' or 'any_path'".to_string(),
+            Rule::path_unit_check => "condition for each room on the path".to_string(),
+            Rule::expression => "query condition".to_string(),
+            Rule::EOI => "end of query".to_string(),
+            rule => format!("{rule:?}"),
+        });
+        QueryParseError::new(offset, err.variant.message())
+    }
+}
+
+impl Display for QueryParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at position {}", self.message, self.offset)
+    }
+}
+
 /// A pairing of a sublevel with a single query statement.
 #[derive(Clone, Debug)]
 pub struct QueryClause {
@@ -111,8 +276,8 @@ pub struct QueryClause {
 }
 
 impl QueryClause {
-    fn matches<'a>(&self, layout: &'a Layout<'a>) -> bool {
-        self.querykind.matches(layout)
+    fn matches(&self, layout: &Layout, mgr: &impl AssetManager) -> bool {
+        self.querykind.matches(layout, mgr)
     }
 }
 
@@ -135,6 +300,73 @@ pub enum QueryKind {
         relationship: Ordering,
         amount: usize,
     },
+    /// Number of distinct teki species present. Cap teki and treasure carriers count
+    /// the same as any other teki. Plants (spawn group 6) only count if `include_plants`
+    /// is set, and seam teki (group 5) only if `include_seam` is set.
+    CountSpecies {
+        include_plants: bool,
+        include_seam: bool,
+        relationship: Ordering,
+        amount: usize,
+    },
+    /// Number of teki spawned from the easy, hard, and special teki groups (0, 1, and 8),
+    /// counting each teki in a bunch separately. Seam teki (group 5) and plants (group 6)
+    /// only count if `include_seam_and_plants` is set, and each seam teki counts once even
+    /// though both doors on its seam hold it. Cap teki are never counted.
+    CountEnemies {
+        include_seam_and_plants: bool,
+        relationship: Ordering,
+        amount: usize,
+    },
+    /// Number of loose treasures (not ones carried by teki) placed in alcoves.
+    AlcoveTreasures {
+        relationship: Ordering,
+        amount: usize,
+    },
+    /// Number of alcoves that objects can spawn in. Empty caps that only close off a door
+    /// aren't counted. See [CaveUnit::is_item_alcove].
+    ItemAlcoves {
+        relationship: Ordering,
+        amount: usize,
+    },
+    /// Number of treasures, loose or carried by a teki, in map units that are cut off from
+    /// the main path by a single door. See [isolated_units] for the exact definition.
+    IsolatedTreasures {
+        relationship: Ordering,
+        amount: usize,
+    },
+    /// The larger of the layout's width and height, in map unit grid cells. See
+    /// [Layout::grid_size].
+    MapSize {
+        relationship: Ordering,
+        amount: usize,
+    },
+    /// Total HP of every gate in the layout. Layouts without gates have a total of 0.
+    GateHp {
+        relationship: Ordering,
+        req_hp: f32,
+    },
+    /// Total number of Pikmin that every Candypop Bud in the layout can convert, counting
+    /// each bud according to [CANDYPOP_PETALS].
+    Petals {
+        relationship: Ordering,
+        amount: u32,
+    },
+    /// Number of bomb rocks in the layout (see [is_bomb_rock]), including ones that fall
+    /// from the ceiling. If `reachable_only` is set, only bomb rocks that can be walked to
+    /// from the ship without breaking a gate are counted, the same as `reachable_no_gate`.
+    BombRocks {
+        reachable_only: bool,
+        relationship: Ordering,
+        amount: usize,
+    },
+    /// Rough difficulty metric: the straight-line length of a round trip from the ship
+    /// through every treasure, loose or carried by a teki. See [nearest_neighbor_tour_len]
+    /// for how the route is chosen.
+    TourLength {
+        relationship: Ordering,
+        req_dist: f32,
+    },
     CarryDist {
         entity: EntityMatcher,
         relationship: Ordering,
@@ -146,14 +378,89 @@ pub enum QueryKind {
         relationship: Ordering,
         req_dist: f32,
     },
+    /// Number of matching entities within `radius` of the ship, in straight-line distance.
+    /// Seam spawns count once. Written without a comparison, matches layouts with at least one.
+    NearShip {
+        entity: EntityMatcher,
+        radius: f32,
+        relationship: Ordering,
+        amount: usize,
+    },
     Gated(EntityMatcher),
     NotGated(EntityMatcher),
+    /// Whether any matching entity can be reached from the ship without passing through
+    /// a door with a gate in it.
+    ReachableNoGate(EntityMatcher),
+    /// Whether a matching entity was placed in a door seam belonging to a unit of the given
+    /// type. Seam spawns are shared by the doors on both sides of a connection, so one
+    /// between a room and a hallway matches both `seam:room` and `seam:hall`.
+    SeamSpawn {
+        room_type: RoomType,
+        entity: EntityMatcher,
+    },
+    /// Whether a matching entity was placed in the alcove with the given 1-based index, in
+    /// the order given by [alcoves_in_reading_order]. Mainly for non-falling cap teki,
+    /// which are placed in alcoves without any randomness.
+    AlcoveSpawn {
+        alcove: usize,
+        entity: EntityMatcher,
+    },
+    /// Heuristic for whether getting from the ship to the exit requires passing close to a
+    /// hard (group 1) teki. `true` matches layouts where every route is blocked, `false`
+    /// matches layouts with at least one safe route.
+    ExitBlocked(bool),
+    /// Heuristic for whether a roaming teki (see [crate::layout::ROAMING_TEKI]) spawned inside or right
+    /// next to a one-wide hallway, where it's hard to get around. `true` matches layouts
+    /// with at least one such teki.
+    RoamingChokepoint(bool),
+    /// The internal name of the starting room, i.e. the one the ship is placed in.
+    StartRoom(String),
+    /// Whether the layout contains any source of the named hazard, e.g. "fire". See
+    /// [HAZARDS] for what counts as each hazard.
+    Hazard(String),
+    /// Heuristic for whether the layout can be finished without Pikmin of the given color.
+    /// Only `blue` is supported so far; see [needs_blue_pikmin] for how it's decided.
+    Without(String),
+    /// Whether any placed map unit's internal name starts with the given text, ignoring
+    /// case. A full name matches only that unit, while a prefix like `room_` matches any
+    /// of a family of units.
+    UsesUnit(String),
+    /// Whether the ship and an exit were placed in the same map unit. `None` matches either
+    /// exit, while `hole` or `geyser` only considers that one.
+    ShipExitSameRoom(Option<String>),
+    /// Whether every treasure in the layout, loose or carried by a teki, can be carried by
+    /// this many Pikmin, i.e. has a minimum carry count no higher than it. Layouts without
+    /// treasures always match.
+    CarryWith(u32),
+    /// Number of matching teki that were placed to make up a minimum amount, or as filler.
+    /// See [TekiPlacement]. Cap teki are never counted.
+    CountPlacement {
+        placement: TekiPlacement,
+        entity_matcher: EntityMatcher,
+        relationship: Ordering,
+        amount: usize,
+    },
+    /// Whether the map units along the shortest route between two entities all pass a
+    /// check, e.g. `path(ship -> exit): !hard_teki`. See [ShortestPath].
+    ShortestPath(ShortestPath),
+    /// Whether the layout has no more than this many side branches off the route from the
+    /// ship to the exit. `0` means the layout is a single line of units. See [side_branches].
+    Linear(usize),
+    /// Whether a matching treasure is the `rank`th farthest (or nearest, if `farthest` is
+    /// false) treasure from the ship by carry distance, counting from 1. Treasures at the
+    /// same distance share a rank, and treasures without a carry path aren't ranked.
+    TreasureRank {
+        farthest: bool,
+        rank: usize,
+        entity: EntityMatcher,
+    },
     RoomPath(RoomPath),
 }
 
 impl QueryKind {
-    /// Checks whether the given layout matches the query condition.
-    pub fn matches<'a>(&self, layout: &'a Layout<'a>) -> bool {
+    /// Checks whether the given layout matches the query condition. `mgr` is only used to
+    /// look up treasure info.
+    pub fn matches(&self, layout: &Layout, mgr: &impl AssetManager) -> bool {
         match self {
             QueryKind::CountEntity {
                 entity_matcher,
@@ -174,6 +481,107 @@ impl QueryKind {
                 let unit_count = layout.map_units.iter().filter(|unit| room_matcher.matches(unit.unit)).count();
                 unit_count.cmp(amount) == *relationship
             }
+            QueryKind::CountSpecies {
+                include_plants,
+                include_seam,
+                relationship,
+                amount,
+            } => {
+                let species: HashSet<String> = layout
+                    .get_spawn_objects()
+                    .filter(|(so, _pos)| match so {
+                        SpawnObject::Teki(info, _) => (*include_plants || info.group != 6) && (*include_seam || info.group != 5),
+                        SpawnObject::CapTeki(..) => true,
+                        _ => false,
+                    })
+                    .map(|(so, _pos)| so.name().to_ascii_lowercase())
+                    .collect();
+                species.len().cmp(amount) == *relationship
+            }
+            QueryKind::CountEnemies {
+                include_seam_and_plants,
+                relationship,
+                amount,
+            } => {
+                let enemies = layout
+                    .get_unique_spawn_objects()
+                    .filter(|(so, _pos)| match so {
+                        SpawnObject::Teki(TekiInfo { group: 0 | 1 | 8, .. }, _) => true,
+                        SpawnObject::Teki(TekiInfo { group: 5 | 6, .. }, _) => *include_seam_and_plants,
+                        _ => false,
+                    })
+                    .count();
+                enemies.cmp(amount) == *relationship
+            }
+            QueryKind::AlcoveTreasures { relationship, amount } => {
+                let alcove_treasures = layout
+                    .map_units
+                    .iter()
+                    .filter(|unit| unit.unit.room_type == RoomType::DeadEnd)
+                    .flat_map(|unit| unit.spawn_objects())
+                    .filter(|so| matches!(so, SpawnObject::Item(_)))
+                    .count();
+                alcove_treasures.cmp(amount) == *relationship
+            }
+            QueryKind::ItemAlcoves { relationship, amount } => {
+                let item_alcoves = layout.map_units.iter().filter(|unit| unit.unit.is_item_alcove()).count();
+                item_alcoves.cmp(amount) == *relationship
+            }
+            QueryKind::IsolatedTreasures { relationship, amount } => {
+                let isolated = isolated_units(layout);
+                let isolated_treasures = layout
+                    .map_units
+                    .iter()
+                    .zip(isolated)
+                    .filter(|(_, isolated)| *isolated)
+                    .flat_map(|(unit, _)| unit.spawn_objects())
+                    .filter(|so| matches!(so, SpawnObject::Item(_) | SpawnObject::Teki(TekiInfo { carrying: Some(_), .. }, _)))
+                    .count();
+                isolated_treasures.cmp(amount) == *relationship
+            }
+            QueryKind::MapSize { relationship, amount } => {
+                let (width, height) = layout.grid_size();
+                (width.max(height) as usize).cmp(amount) == *relationship
+            }
+            QueryKind::GateHp { relationship, req_hp } => {
+                let total_hp: f32 = layout
+                    .get_unique_spawn_objects()
+                    .filter_map(|(so, _pos)| match so {
+                        SpawnObject::Gate(info, _) => Some(info.health),
+                        _ => None,
+                    })
+                    .sum();
+                total_hp.partial_cmp(req_hp).is_some_and(|ordering| ordering == *relationship)
+            }
+            QueryKind::Petals { relationship, amount } => {
+                let total_petals: u32 = layout
+                    .get_spawn_objects()
+                    .filter_map(|(so, _pos)| match so {
+                        SpawnObject::CapTeki(info, num_spawned) if info.is_candypop() => {
+                            Some(candypop_petals(&info.internal_name) * num_spawned)
+                        }
+                        _ => None,
+                    })
+                    .sum();
+                total_petals.cmp(amount) == *relationship
+            }
+            QueryKind::TourLength { relationship, req_dist } => {
+                let Some(ship_pos) = layout
+                    .get_spawn_objects()
+                    .find(|(so, _)| matches!(so, SpawnObject::Ship))
+                    .map(|(_, pos)| pos)
+                else {
+                    return false;
+                };
+                let treasures = layout
+                    .get_spawn_objects()
+                    .filter(|(so, _)| matches!(so, SpawnObject::Item(_) | SpawnObject::Teki(TekiInfo { carrying: Some(_), .. }, _)))
+                    .map(|(_, pos)| pos)
+                    .collect_vec();
+                nearest_neighbor_tour_len(ship_pos, &treasures)
+                    .partial_cmp(req_dist)
+                    .is_some_and(|ordering| ordering == *relationship)
+            }
             QueryKind::CarryDist {
                 entity,
                 relationship,
@@ -203,6 +611,47 @@ impl QueryKind {
                     d.partial_cmp(req_dist).map(|ordering| ordering == *relationship).unwrap_or(false)
                 })
             }
+            QueryKind::NearShip {
+                entity,
+                radius,
+                relationship,
+                amount,
+            } => {
+                let Some(ship_pos) = layout
+                    .get_spawn_objects()
+                    .find(|(so, _)| matches!(so, SpawnObject::Ship))
+                    .map(|(_, pos)| pos)
+                else {
+                    return false;
+                };
+                let count = layout
+                    .get_unique_spawn_objects()
+                    .filter(|(so, pos)| entity.matches(so) && pos.p2_dist(&ship_pos) <= *radius)
+                    .count();
+                count.cmp(amount) == *relationship
+            }
+            QueryKind::BombRocks {
+                reachable_only,
+                relationship,
+                amount,
+            } => {
+                let reachable = reachable_only.then(|| units_reachable_without_gates(layout));
+                let count: usize = layout
+                    .map_units
+                    .iter()
+                    .enumerate()
+                    .filter(|(idx, _)| reachable.as_ref().is_none_or(|reachable| reachable[*idx]))
+                    .map(|(_, unit)| {
+                        let seam_bombs = unit
+                            .doors
+                            .iter()
+                            .filter(|door| Option::as_ref(&door.borrow().seam_spawnpoint).is_some_and(is_bomb_rock))
+                            .count();
+                        unit.spawn_objects().filter(|so| is_bomb_rock(so)).count() + seam_bombs
+                    })
+                    .sum();
+                count.cmp(amount) == *relationship
+            }
             QueryKind::Gated(entity_matcher) => {
                 let gates = layout
                     .get_spawn_objects()
@@ -238,6 +687,103 @@ impl QueryKind {
                             .all(|(p1, p2)| gates.iter().all(|gate_pos| point_to_line_dist(*gate_pos, p1, p2) > 80.0))
                     })
             }
+            QueryKind::ReachableNoGate(entity_matcher) => {
+                let reachable = units_reachable_without_gates(layout);
+                layout
+                    .map_units
+                    .iter()
+                    .enumerate()
+                    .filter(|(idx, _)| reachable[*idx])
+                    .any(|(_, unit)| {
+                        unit.spawn_objects().any(|so| entity_matcher.matches(so))
+                            || unit
+                                .doors
+                                .iter()
+                                .any(|door| Option::as_ref(&door.borrow().seam_spawnpoint).is_some_and(|so| entity_matcher.matches(so)))
+                    })
+            }
+            QueryKind::SeamSpawn { room_type, entity } => layout
+                .map_units
+                .iter()
+                .filter(|unit| unit.unit.room_type == *room_type)
+                .flat_map(|unit| unit.doors.iter())
+                .any(|door| Option::as_ref(&door.borrow().seam_spawnpoint).is_some_and(|so| entity.matches(so))),
+            QueryKind::AlcoveSpawn { alcove, entity } => alcoves_in_reading_order(layout)
+                .get(alcove.wrapping_sub(1))
+                .is_some_and(|unit| unit.spawn_objects().any(|so| entity.matches(so))),
+            QueryKind::ExitBlocked(blocked) => exit_blocked_by_hard_teki(layout) == *blocked,
+            QueryKind::RoamingChokepoint(present) => roaming_teki_at_chokepoint(layout) == *present,
+            QueryKind::StartRoom(unit_name) => layout
+                .map_units
+                .first()
+                .is_some_and(|unit| unit.unit.unit_folder_name.eq_ignore_ascii_case(unit_name)),
+            QueryKind::Hazard(hazard) => {
+                let hazard_teki = hazard_teki(hazard).unwrap_or_default();
+                let has_hazard_teki = layout.get_spawn_objects().any(|(so, _)| match so {
+                    SpawnObject::Teki(..) | SpawnObject::CapTeki(..) => hazard_teki.iter().any(|name| name.eq_ignore_ascii_case(so.name())),
+                    _ => false,
+                });
+                let has_water =
+                    hazard.eq_ignore_ascii_case("water") && layout.map_units.iter().any(|unit| !unit.unit.waterboxes.is_empty());
+                has_hazard_teki || has_water
+            }
+            QueryKind::Without(_) => !needs_blue_pikmin(layout),
+            QueryKind::UsesUnit(prefix) => layout
+                .map_units
+                .iter()
+                .any(|unit| unit.unit.unit_folder_name.to_ascii_lowercase().starts_with(prefix.as_str())),
+            QueryKind::ShipExitSameRoom(exit) => layout.map_units.iter().any(|unit| {
+                unit.spawn_objects().any(|so| matches!(so, SpawnObject::Ship))
+                    && unit.spawn_objects().any(|so| {
+                        matches!(so, SpawnObject::Hole(_) | SpawnObject::Geyser(_))
+                            && exit.as_deref().is_none_or(|exit| so.name().eq_ignore_ascii_case(exit))
+                    })
+            }),
+            QueryKind::CarryWith(pikmin) => layout.get_spawn_objects().all(|(so, _)| {
+                let treasure = match so {
+                    SpawnObject::Item(info) => Some((&info.game, &info.internal_name)),
+                    SpawnObject::Teki(
+                        TekiInfo {
+                            carrying: Some(carrying),
+                            game,
+                            ..
+                        },
+                        _,
+                    ) => Some((game, carrying)),
+                    _ => None,
+                };
+                // Treasures without known carry counts can't be confirmed as light enough.
+                treasure.is_none_or(|(game, name)| mgr.get_treasure_info(game, name).is_ok_and(|t| t.min_carry <= *pikmin))
+            }),
+            QueryKind::CountPlacement {
+                placement,
+                entity_matcher,
+                relationship,
+                amount,
+            } => {
+                let count = layout
+                    .teki_placements()
+                    .filter(|(so, p)| p == placement && entity_matcher.matches(so))
+                    .count();
+                count.cmp(amount) == *relationship
+            }
+            QueryKind::ShortestPath(shortest_path) => shortest_path.matches(layout),
+            QueryKind::Linear(max_branches) => side_branches(layout) <= *max_branches,
+            QueryKind::TreasureRank { farthest, rank, entity } => {
+                let waypoint_graph = layout.waypoint_graph();
+                let treasures = layout
+                    .get_spawn_objects()
+                    .filter(|(so, _)| matches!(so, SpawnObject::Item(_) | SpawnObject::Teki(TekiInfo { carrying: Some(_), .. }, _)))
+                    .filter_map(|(so, pos)| Some((so, waypoint_graph.carry_path_len(pos)?)))
+                    .collect_vec();
+                treasures.iter().filter(|(so, _)| entity.matches(so)).any(|(_, dist)| {
+                    let ahead = treasures
+                        .iter()
+                        .filter(|(_, other)| if *farthest { other > dist } else { other < dist })
+                        .count();
+                    ahead + 1 == *rank
+                })
+            }
             QueryKind::RoomPath(search_path) => search_path.matches(layout),
         }
     }
@@ -251,7 +797,9 @@ impl QueryKind {
         let expr = input.into_inner().next().unwrap();
         match (expr.as_rule(), expr.into_inner()) {
             (Rule::compare, inner) => {
-                let values: Vec<&str> = inner.map(|v| v.as_str().trim()).collect();
+                let inner = inner.collect_vec();
+                let name_offset = inner[0].as_span().start();
+                let values: Vec<&str> = inner.iter().map(|v| v.as_str().trim()).collect();
                 let bare_name = values[0].find('/').map_or(values[0], |idx| &values[0][..idx]);
                 let bare_name_lowercase = bare_name.to_ascii_lowercase();
 
@@ -259,12 +807,81 @@ impl QueryKind {
                 let treasure_list = mgr.all_treasures(None).change_context(CaveripperError::QueryParseError)?;
                 let room_list = mgr.all_units(None).change_context(CaveripperError::QueryParseError)?;
 
-                if teki_list.contains(&bare_name_lowercase)
-                    || treasure_list.iter().any(|t| t.internal_name.eq_ignore_ascii_case(bare_name))
-                    || ["hole", "geyser", "ship", "gate"].contains(&bare_name_lowercase.as_str())
-                {
+                let entity_matcher: EntityMatcher = values[0].into();
+                if ["species", "all_species", "nonseam_species"].contains(&bare_name_lowercase.as_str()) {
+                    Ok(QueryKind::CountSpecies {
+                        include_plants: bare_name_lowercase == "all_species",
+                        include_seam: bare_name_lowercase != "nonseam_species",
+                        relationship: char_to_ordering(values[1]),
+                        amount: values[2].parse::<usize>().change_context(CaveripperError::QueryParseError)?,
+                    })
+                } else if bare_name_lowercase == "enemies" || bare_name_lowercase == "all_enemies" {
+                    Ok(QueryKind::CountEnemies {
+                        include_seam_and_plants: bare_name_lowercase == "all_enemies",
+                        relationship: char_to_ordering(values[1]),
+                        amount: values[2].parse::<usize>().change_context(CaveripperError::QueryParseError)?,
+                    })
+                } else if bare_name_lowercase == "alcove_treasure" {
+                    Ok(QueryKind::AlcoveTreasures {
+                        relationship: char_to_ordering(values[1]),
+                        amount: values[2].parse::<usize>().change_context(CaveripperError::QueryParseError)?,
+                    })
+                } else if bare_name_lowercase == "alcoves" {
+                    Ok(QueryKind::ItemAlcoves {
+                        relationship: char_to_ordering(values[1]),
+                        amount: values[2].parse::<usize>().change_context(CaveripperError::QueryParseError)?,
+                    })
+                } else if bare_name_lowercase == "isolated_treasure" {
+                    Ok(QueryKind::IsolatedTreasures {
+                        relationship: char_to_ordering(values[1]),
+                        amount: values[2].parse::<usize>().change_context(CaveripperError::QueryParseError)?,
+                    })
+                } else if bare_name_lowercase == "size" {
+                    Ok(QueryKind::MapSize {
+                        relationship: char_to_ordering(values[1]),
+                        amount: values[2].parse::<usize>().change_context(CaveripperError::QueryParseError)?,
+                    })
+                } else if bare_name_lowercase == "gate_hp" {
+                    Ok(QueryKind::GateHp {
+                        relationship: char_to_ordering(values[1]),
+                        req_hp: values[2].parse::<f32>().change_context(CaveripperError::QueryParseError)?,
+                    })
+                } else if bare_name_lowercase == "petals" {
+                    Ok(QueryKind::Petals {
+                        relationship: char_to_ordering(values[1]),
+                        amount: values[2].parse::<u32>().change_context(CaveripperError::QueryParseError)?,
+                    })
+                } else if bare_name_lowercase == "bombs" || bare_name_lowercase == "reachable_bombs" {
+                    Ok(QueryKind::BombRocks {
+                        reachable_only: bare_name_lowercase == "reachable_bombs",
+                        relationship: char_to_ordering(values[1]),
+                        amount: values[2].parse::<usize>().change_context(CaveripperError::QueryParseError)?,
+                    })
+                } else if bare_name_lowercase == "tour_len" {
+                    Ok(QueryKind::TourLength {
+                        relationship: char_to_ordering(values[1]),
+                        req_dist: values[2].parse::<f32>().change_context(CaveripperError::QueryParseError)?,
+                    })
+                } else if !matches!(entity_matcher, EntityMatcher::Entity { .. }) {
+                    match entity_matcher.unrecognized(&teki_list, &treasure_list) {
+                        None => Ok(QueryKind::CountEntity {
+                            entity_matcher,
+                            relationship: char_to_ordering(values[1]),
+                            amount: values[2].parse::<usize>().change_context(CaveripperError::QueryParseError)?,
+                        }),
+                        Some(problem) => Err(report!(CaveripperError::QueryParseError))
+                            .attach_printable_lazy(|| full_txt.to_owned())
+                            .attach_printable_lazy(|| QueryParseError::new(name_offset, problem)),
+                    }
+                } else if let door_count @ UnitMatcher::DoorCount { .. } = UnitMatcher::from(values[0]) {
+                    Ok(QueryKind::CountRoom {
+                        unit_matcher: door_count,
+                        relationship: char_to_ordering(values[1]),
+                        amount: values[2].parse::<usize>().change_context(CaveripperError::QueryParseError)?,
+                    })
+                } else if entity_matcher.unrecognized(&teki_list, &treasure_list).is_none() {
                     Ok(QueryKind::CountEntity {
-                        entity_matcher: values[0].into(),
+                        entity_matcher,
                         relationship: char_to_ordering(values[1]),
                         amount: values[2].parse::<usize>().change_context(CaveripperError::QueryParseError)?,
                     })
@@ -275,7 +892,14 @@ impl QueryKind {
                         amount: values[2].parse::<usize>().change_context(CaveripperError::QueryParseError)?,
                     })
                 } else {
-                    Err(report!(CaveripperError::QueryParseError)).attach_printable_lazy(|| full_txt.to_owned())
+                    Err(report!(CaveripperError::QueryParseError))
+                        .attach_printable_lazy(|| full_txt.to_owned())
+                        .attach_printable_lazy(|| {
+                            QueryParseError::new(
+                                name_offset,
+                                format!("expected teki, treasure, or room name but found '{}'", values[0]),
+                            )
+                        })
                 }
             }
             (Rule::carry_dist, inner) => {
@@ -295,9 +919,180 @@ impl QueryKind {
                     req_dist: values[3].parse::<f32>().change_context(CaveripperError::QueryParseError)?,
                 })
             }
+            (Rule::near_ship, inner) => {
+                let inner = inner.collect_vec();
+                let entity = validate_entity(&inner[0], &full_txt, mgr)?;
+                let values: Vec<&str> = inner.iter().map(|v| v.as_str()).collect();
+                let (relationship, amount) = match values.get(2..4) {
+                    Some([comparator, amount]) => (
+                        char_to_ordering(comparator),
+                        amount.parse::<usize>().change_context(CaveripperError::QueryParseError)?,
+                    ),
+                    _ => (Ordering::Greater, 0),
+                };
+                Ok(QueryKind::NearShip {
+                    entity,
+                    radius: values[1].parse::<f32>().change_context(CaveripperError::QueryParseError)?,
+                    relationship,
+                    amount,
+                })
+            }
             (Rule::gated, inner) => Ok(QueryKind::Gated(inner.as_str().into())),
             (Rule::not_gated, inner) => Ok(QueryKind::NotGated(inner.as_str().into())),
-            (Rule::room_path, inner) => Ok(QueryKind::RoomPath(inner.into())),
+            (Rule::reachable_no_gate, mut inner) => {
+                Ok(QueryKind::ReachableNoGate(validate_entity(&inner.next().unwrap(), &full_txt, mgr)?))
+            }
+            (Rule::seam, mut inner) => {
+                let room_type = inner.next().unwrap();
+                let entity = inner.next().unwrap();
+                match RoomType::try_from(room_type.as_str()) {
+                    Ok(rt) => Ok(QueryKind::SeamSpawn {
+                        room_type: rt,
+                        entity: entity.as_str().into(),
+                    }),
+                    Err(e) => Err(e).attach_printable_lazy(|| full_txt.to_owned()).attach_printable_lazy(|| {
+                        QueryParseError::new(
+                            room_type.as_span().start(),
+                            format!("expected 'room' or 'hall' but found '{}'", room_type.as_str()),
+                        )
+                    }),
+                }
+            }
+            (Rule::alcove_spawn, mut inner) => {
+                let alcove = inner.next().unwrap();
+                let entity = inner.next().unwrap();
+                match alcove.as_str().parse::<usize>() {
+                    Ok(idx) if idx > 0 => Ok(QueryKind::AlcoveSpawn {
+                        alcove: idx,
+                        entity: entity.as_str().into(),
+                    }),
+                    _ => Err(report!(CaveripperError::QueryParseError))
+                        .attach_printable_lazy(|| full_txt.to_owned())
+                        .attach_printable_lazy(|| QueryParseError::new(alcove.as_span().start(), "alcoves are numbered starting from 1")),
+                }
+            }
+            (Rule::exit_blocked, _) => Ok(QueryKind::ExitBlocked(true)),
+            (Rule::exit_not_blocked, _) => Ok(QueryKind::ExitBlocked(false)),
+            (Rule::roaming_chokepoint, _) => Ok(QueryKind::RoamingChokepoint(true)),
+            (Rule::no_roaming_chokepoint, _) => Ok(QueryKind::RoamingChokepoint(false)),
+            (Rule::start_room, mut inner) => {
+                let unit_name = inner.next().unwrap();
+                if mgr.has_unit(None, unit_name.as_str()) {
+                    Ok(QueryKind::StartRoom(unit_name.as_str().to_string()))
+                } else {
+                    Err(report!(CaveripperError::QueryParseError))
+                        .attach_printable_lazy(|| full_txt.to_owned())
+                        .attach_printable_lazy(|| {
+                            QueryParseError::new(unit_name.as_span().start(), format!("unrecognized room '{}'", unit_name.as_str()))
+                        })
+                }
+            }
+            (Rule::hazard, mut inner) => {
+                let hazard = inner.next().unwrap();
+                if hazard_teki(hazard.as_str()).is_some() {
+                    Ok(QueryKind::Hazard(hazard.as_str().to_ascii_lowercase()))
+                } else {
+                    Err(report!(CaveripperError::QueryParseError))
+                        .attach_printable_lazy(|| full_txt.to_owned())
+                        .attach_printable_lazy(|| {
+                            QueryParseError::new(hazard.as_span().start(), format!("unrecognized hazard '{}'", hazard.as_str()))
+                        })
+                }
+            }
+            (Rule::uses, mut inner) => {
+                let prefix = inner.next().unwrap();
+                let prefix_lowercase = prefix.as_str().to_ascii_lowercase();
+                let room_list = mgr.all_units(None).change_context(CaveripperError::QueryParseError)?;
+                if room_list
+                    .iter()
+                    .any(|name| name.to_ascii_lowercase().starts_with(&prefix_lowercase))
+                {
+                    Ok(QueryKind::UsesUnit(prefix_lowercase))
+                } else {
+                    Err(report!(CaveripperError::QueryParseError))
+                        .attach_printable_lazy(|| full_txt.to_owned())
+                        .attach_printable_lazy(|| {
+                            QueryParseError::new(prefix.as_span().start(), format!("no room name starts with '{}'", prefix.as_str()))
+                        })
+                }
+            }
+            (Rule::ship_exit_same_room, mut inner) => match inner.next() {
+                None => Ok(QueryKind::ShipExitSameRoom(None)),
+                Some(exit) if ["hole", "geyser"].iter().any(|name| exit.as_str().eq_ignore_ascii_case(name)) => {
+                    Ok(QueryKind::ShipExitSameRoom(Some(exit.as_str().to_ascii_lowercase())))
+                }
+                Some(exit) => Err(report!(CaveripperError::QueryParseError))
+                    .attach_printable_lazy(|| full_txt.to_owned())
+                    .attach_printable_lazy(|| {
+                        QueryParseError::new(
+                            exit.as_span().start(),
+                            format!("expected 'hole' or 'geyser' but found '{}'", exit.as_str()),
+                        )
+                    }),
+            },
+            (Rule::carry_with, mut inner) => Ok(QueryKind::CarryWith(
+                inner
+                    .next()
+                    .unwrap()
+                    .as_str()
+                    .parse::<u32>()
+                    .change_context(CaveripperError::QueryParseError)?,
+            )),
+            (Rule::without, mut inner) => {
+                let color = inner.next().unwrap();
+                if color.as_str().eq_ignore_ascii_case("blue") {
+                    Ok(QueryKind::Without(color.as_str().to_ascii_lowercase()))
+                } else {
+                    Err(report!(CaveripperError::QueryParseError))
+                        .attach_printable_lazy(|| full_txt.to_owned())
+                        .attach_printable_lazy(|| {
+                            QueryParseError::new(
+                                color.as_span().start(),
+                                format!("'without:' only supports 'blue' but found '{}'", color.as_str()),
+                            )
+                        })
+                }
+            }
+            (Rule::placement_count, inner) => {
+                let values: Vec<&str> = inner.map(|v| v.as_str()).collect();
+                Ok(QueryKind::CountPlacement {
+                    placement: if values[0].eq_ignore_ascii_case("minimum") {
+                        TekiPlacement::Minimum
+                    } else {
+                        TekiPlacement::Filler
+                    },
+                    entity_matcher: values[1].into(),
+                    relationship: char_to_ordering(values[2]),
+                    amount: values[3].parse::<usize>().change_context(CaveripperError::QueryParseError)?,
+                })
+            }
+            (Rule::shortest_path, inner) => Ok(QueryKind::ShortestPath(inner.try_into()?)),
+            (Rule::linear, mut inner) => Ok(QueryKind::Linear(match inner.next() {
+                Some(max_branches) => max_branches
+                    .as_str()
+                    .parse::<usize>()
+                    .change_context(CaveripperError::QueryParseError)?,
+                None => 0,
+            })),
+            (Rule::treasure_rank, inner) => {
+                let mut values = inner.collect_vec();
+                let entity = values.pop().unwrap();
+                let rank = match values.get(1) {
+                    Some(rank) => rank.as_str().parse::<usize>().change_context(CaveripperError::QueryParseError)?,
+                    None => 1,
+                };
+                if rank == 0 {
+                    return Err(report!(CaveripperError::QueryParseError))
+                        .attach_printable_lazy(|| full_txt.to_owned())
+                        .attach_printable_lazy(|| QueryParseError::new(values[1].as_span().start(), "ranks start from 1"));
+                }
+                Ok(QueryKind::TreasureRank {
+                    farthest: values[0].as_str().eq_ignore_ascii_case("farthest"),
+                    rank,
+                    entity: entity.as_str().into(),
+                })
+            }
+            (Rule::room_path, inner) => Ok(QueryKind::RoomPath(inner.try_into()?)),
             _ => Err(report!(CaveripperError::QueryParseError).attach_printable(full_txt)),
         }
     }
@@ -311,11 +1106,7 @@ impl Display for QueryKind {
                 relationship,
                 amount,
             } => {
-                let order_char = match relationship {
-                    Ordering::Less => '<',
-                    Ordering::Equal => '=',
-                    Ordering::Greater => '>',
-                };
+                let order_char = ordering_char(*relationship);
                 write!(f, "{entity_matcher} {order_char} {amount}")
             }
             QueryKind::CountRoom {
@@ -323,23 +1114,75 @@ impl Display for QueryKind {
                 relationship,
                 amount,
             } => {
-                let order_char = match relationship {
-                    Ordering::Less => '<',
-                    Ordering::Equal => '=',
-                    Ordering::Greater => '>',
-                };
+                let order_char = ordering_char(*relationship);
                 write!(f, "{unit_matcher} {order_char} {amount}")
             }
+            QueryKind::CountSpecies {
+                include_plants,
+                include_seam,
+                relationship,
+                amount,
+            } => {
+                let order_char = ordering_char(*relationship);
+                let keyword = match (include_plants, include_seam) {
+                    (true, _) => "all_species",
+                    (false, true) => "species",
+                    (false, false) => "nonseam_species",
+                };
+                write!(f, "{keyword} {order_char} {amount}")
+            }
+            QueryKind::CountEnemies {
+                include_seam_and_plants,
+                relationship,
+                amount,
+            } => {
+                let order_char = ordering_char(*relationship);
+                let keyword = if *include_seam_and_plants { "all_enemies" } else { "enemies" };
+                write!(f, "{keyword} {order_char} {amount}")
+            }
+            QueryKind::AlcoveTreasures { relationship, amount } => {
+                let order_char = ordering_char(*relationship);
+                write!(f, "alcove_treasure {order_char} {amount}")
+            }
+            QueryKind::ItemAlcoves { relationship, amount } => {
+                let order_char = ordering_char(*relationship);
+                write!(f, "alcoves {order_char} {amount}")
+            }
+            QueryKind::IsolatedTreasures { relationship, amount } => {
+                let order_char = ordering_char(*relationship);
+                write!(f, "isolated_treasure {order_char} {amount}")
+            }
+            QueryKind::MapSize { relationship, amount } => {
+                let order_char = ordering_char(*relationship);
+                write!(f, "size {order_char} {amount}")
+            }
+            QueryKind::GateHp { relationship, req_hp } => {
+                let order_char = ordering_char(*relationship);
+                write!(f, "gate_hp {order_char} {req_hp}")
+            }
+            QueryKind::Petals { relationship, amount } => {
+                let order_char = ordering_char(*relationship);
+                write!(f, "petals {order_char} {amount}")
+            }
+            QueryKind::BombRocks {
+                reachable_only,
+                relationship,
+                amount,
+            } => {
+                let order_char = ordering_char(*relationship);
+                let name = if *reachable_only { "reachable_bombs" } else { "bombs" };
+                write!(f, "{name} {order_char} {amount}")
+            }
+            QueryKind::TourLength { relationship, req_dist } => {
+                let order_char = ordering_char(*relationship);
+                write!(f, "tour_len {order_char} {req_dist}")
+            }
             QueryKind::CarryDist {
                 entity,
                 relationship,
                 req_dist: dist,
             } => {
-                let order_char = match relationship {
-                    Ordering::Less => '<',
-                    Ordering::Equal => '=',
-                    Ordering::Greater => '>',
-                };
+                let order_char = ordering_char(*relationship);
                 write!(f, "{entity} carry dist {order_char} {dist}")
             }
             QueryKind::StraightLineDist {
@@ -348,22 +1191,68 @@ impl Display for QueryKind {
                 relationship,
                 req_dist: dist,
             } => {
-                let order_char = match relationship {
-                    Ordering::Less => '<',
-                    Ordering::Equal => '=',
-                    Ordering::Greater => '>',
-                };
+                let order_char = ordering_char(*relationship);
                 write!(f, "{entity1} straight dist {entity2} {order_char} {dist}")
             }
+            QueryKind::NearShip {
+                entity,
+                radius,
+                relationship: Ordering::Greater,
+                amount: 0,
+            } => write!(f, "near_ship:{entity} within {radius}"),
+            QueryKind::NearShip {
+                entity,
+                radius,
+                relationship,
+                amount,
+            } => {
+                let order_char = ordering_char(*relationship);
+                write!(f, "near_ship:{entity} within {radius} {order_char} {amount}")
+            }
             QueryKind::Gated(entity) => write!(f, "{entity} gated"),
             QueryKind::NotGated(entity) => write!(f, "{entity} not gated"),
+            QueryKind::ReachableNoGate(entity) => write!(f, "reachable_no_gate:{entity}"),
+            QueryKind::SeamSpawn { room_type, entity } => write!(f, "seam:{room_type}:{entity}"),
+            QueryKind::AlcoveSpawn { alcove, entity } => write!(f, "cap{alcove}:{entity}"),
+            QueryKind::ExitBlocked(true) => write!(f, "exit blocked"),
+            QueryKind::ExitBlocked(false) => write!(f, "exit not blocked"),
+            QueryKind::RoamingChokepoint(true) => write!(f, "roaming chokepoint"),
+            QueryKind::RoamingChokepoint(false) => write!(f, "no roaming chokepoint"),
+            QueryKind::StartRoom(unit_name) => write!(f, "start:{unit_name}"),
+            QueryKind::Hazard(hazard) => write!(f, "hazard:{hazard}"),
+            QueryKind::Without(color) => write!(f, "without:{color}"),
+            QueryKind::UsesUnit(prefix) => write!(f, "uses:{prefix}"),
+            QueryKind::ShipExitSameRoom(None) => write!(f, "ship_exit_same_room"),
+            QueryKind::ShipExitSameRoom(Some(exit)) => write!(f, "ship_exit_same_room:{exit}"),
+            QueryKind::CarryWith(pikmin) => write!(f, "carry_with<={pikmin}"),
+            QueryKind::CountPlacement {
+                placement,
+                entity_matcher,
+                relationship,
+                amount,
+            } => {
+                let order_char = ordering_char(*relationship);
+                let placement = match placement {
+                    TekiPlacement::Minimum => "minimum",
+                    TekiPlacement::Filler => "filler",
+                };
+                write!(f, "{placement}:{entity_matcher} {order_char} {amount}")
+            }
+            QueryKind::ShortestPath(shortest_path) => write!(f, "{shortest_path}"),
+            QueryKind::Linear(0) => write!(f, "linear"),
+            QueryKind::Linear(max_branches) => write!(f, "linear:{max_branches}"),
+            QueryKind::TreasureRank { farthest, rank, entity } => {
+                let direction = if *farthest { "farthest" } else { "nearest" };
+                if *rank == 1 {
+                    write!(f, "{direction}:{entity}")
+                } else {
+                    write!(f, "{direction}{rank}:{entity}")
+                }
+            }
             QueryKind::RoomPath(room_path) => {
-                let mut first = true;
-                for (unit_matcher, entity_matchers) in room_path.components.iter() {
-                    if !first {
-                        write!(f, " -> ")?;
-                    } else {
-                        first = false;
+                for (i, (unit_matcher, entity_matchers)) in room_path.components.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " {} ", room_path.links[i - 1])?;
                     }
 
                     write!(f, "{unit_matcher}")?;
@@ -377,84 +1266,309 @@ impl Display for QueryKind {
     }
 }
 
+/// Checks the map units on the shortest route, counted in doors, from any unit containing
+/// `from` to the nearest unit containing `to`. Both end units are part of the route. A
+/// layout without either entity never matches.
+///
+/// Layouts often have several routes of the same shortest length. With `path(...)`, every
+/// one of them must pass, i.e. every unit on any of them. With `any_path(...)`, one route
+/// passing is enough.
+///
+/// Like `room + entity` components in a [RoomPath], only objects in a unit's spawn points
+/// are checked, so gates and seam teki aren't counted, and gates don't block the route.
+#[derive(Debug, Clone)]
+pub struct ShortestPath {
+    from: EntityMatcher,
+    to: EntityMatcher,
+    any: bool,
+    check: UnitCheck,
+}
+
+/// A condition on the objects in a single map unit, for [ShortestPath]. `entity` alone
+/// requires at least one matching object, `!entity` requires none, and `entity < N`
+/// (etc.) compares the number of matching objects.
+#[derive(Debug, Clone)]
+pub struct UnitCheck {
+    negated: bool,
+    entity: EntityMatcher,
+    count: Option<(Ordering, usize)>,
+}
+
+impl ShortestPath {
+    fn matches(&self, layout: &Layout) -> bool {
+        let contains = |idx: usize, entity: &EntityMatcher| layout.map_units[idx].spawn_objects().any(|so| entity.matches(so));
+
+        // Breadth-first search outward from every starting unit at once. `order` ends up
+        // sorted by distance, which the passes below rely on.
+        let connections = door_connections(layout);
+        let mut dist: Vec<Option<usize>> = vec![None; layout.map_units.len()];
+        let mut queue: VecDeque<usize> = (0..layout.map_units.len()).filter(|idx| contains(*idx, &self.from)).collect();
+        for idx in queue.iter() {
+            dist[*idx] = Some(0);
+        }
+        let mut order = Vec::new();
+        while let Some(idx) = queue.pop_front() {
+            order.push(idx);
+            for next in connections.iter().filter_map(|connection| other_end(*connection, idx)) {
+                if dist[next].is_none() {
+                    dist[next] = Some(dist[idx].unwrap() + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        let Some(path_len) = (0..layout.map_units.len())
+            .filter(|idx| contains(*idx, &self.to))
+            .filter_map(|idx| dist[idx])
+            .min()
+        else {
+            return false;
+        };
+        let ends = (0..layout.map_units.len())
+            .filter(|idx| dist[*idx] == Some(path_len) && contains(*idx, &self.to))
+            .collect_vec();
+        // Units one step closer to the start that `idx` can be reached from on a shortest route.
+        let (dist, connections) = (&dist, &connections);
+        let predecessors = |idx: usize| {
+            connections
+                .iter()
+                .filter_map(move |connection| other_end(*connection, idx))
+                .filter(move |prev| dist[idx].is_some_and(|d| dist[*prev] == d.checked_sub(1)))
+        };
+
+        if self.any {
+            // Whether some shortest route to each unit passes the check all the way there.
+            let mut passing = vec![false; layout.map_units.len()];
+            for idx in order {
+                passing[idx] =
+                    self.check.matches(&layout.map_units[idx]) && (dist[idx] == Some(0) || predecessors(idx).any(|prev| passing[prev]));
+            }
+            ends.iter().any(|idx| passing[*idx])
+        } else {
+            let mut on_path = vec![false; layout.map_units.len()];
+            let mut stack = ends;
+            while let Some(idx) = stack.pop() {
+                if !on_path[idx] {
+                    on_path[idx] = true;
+                    stack.extend(predecessors(idx));
+                }
+            }
+            layout
+                .map_units
+                .iter()
+                .zip(on_path)
+                .filter(|(_, on_path)| *on_path)
+                .all(|(unit, _)| self.check.matches(unit))
+        }
+    }
+}
+
+impl UnitCheck {
+    fn matches(&self, unit: &PlacedMapUnit) -> bool {
+        let count = unit.spawn_objects().filter(|so| self.entity.matches(so)).count();
+        let passes = match self.count {
+            Some((relationship, amount)) => count.cmp(&amount) == relationship,
+            None => count > 0,
+        };
+        passes != self.negated
+    }
+}
+
+impl TryFrom<Pairs<'_, Rule>> for ShortestPath {
+    type Error = Report<CaveripperError>;
+
+    fn try_from(mut input: Pairs<'_, Rule>) -> Result<Self, CaveripperError> {
+        let any = input.next().unwrap().as_str().eq_ignore_ascii_case("any_path");
+        let from = input.next().unwrap().as_str().into();
+        let to = input.next().unwrap().as_str().into();
+
+        let mut check = input.next().unwrap().into_inner().peekable();
+        let negated = check.next_if(|pair| pair.as_rule() == Rule::path_negation).is_some();
+        let entity = check.next().unwrap().as_str().into();
+        let count = match (check.next(), check.next()) {
+            (Some(comparator), Some(amount)) => Some((
+                char_to_ordering(comparator.as_str()),
+                amount.as_str().parse::<usize>().change_context(CaveripperError::QueryParseError)?,
+            )),
+            _ => None,
+        };
+
+        Ok(ShortestPath {
+            from,
+            to,
+            any,
+            check: UnitCheck { negated, entity, count },
+        })
+    }
+}
+
+impl Display for ShortestPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let quantifier = if self.any { "any_path" } else { "path" };
+        write!(f, "{quantifier}({} -> {}): ", self.from, self.to)?;
+        if self.check.negated {
+            write!(f, "!")?;
+        }
+        write!(f, "{}", self.check.entity)?;
+        if let Some((relationship, amount)) = self.check.count {
+            let order_char = ordering_char(relationship);
+            write!(f, " {order_char} {amount}")?;
+        }
+        Ok(())
+    }
+}
+
 /// Matches a sequence of rooms connected in order, optionally with constraints on
 /// the entities they must contain.
 #[derive(Debug, Clone)]
 pub struct RoomPath {
     components: Vec<(UnitMatcher, Vec<EntityMatcher>)>,
+    /// How far apart each pair of consecutive components may be. Always one shorter
+    /// than `components`.
+    links: Vec<PathDepth>,
+}
+
+/// The maximum number of doors that may be passed through to get from one room path
+/// component to the next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathDepth {
+    /// `->N`. `->0` means both components must match the same unit, and plain `->`
+    /// is the same as `->1`, i.e. the next unit must be directly adjacent.
+    Within(usize),
+    /// `->*`: the next unit can be anywhere reachable from the previous one.
+    Unbounded,
 }
 
 impl RoomPath {
     fn matches(&self, layout: &Layout) -> bool {
-        layout.map_units.iter().any(|start_unit| {
-            let mut frontier = vec![start_unit];
+        (0..layout.map_units.len()).any(|start_idx| {
+            let mut frontier = vec![start_idx];
             let mut visited = Vec::new();
-            for (unit_matcher, entity_matchers) in self.components.iter() {
-                if frontier.is_empty() {
+            for (i, (unit_matcher, entity_matchers)) in self.components.iter().enumerate() {
+                // A depth of 0 asks for the same unit again, so it mustn't be excluded
+                // for having been matched already.
+                let same_unit = i > 0 && self.links[i - 1] == PathDepth::Within(0);
+                let matched: Vec<usize> = frontier
+                    .iter()
+                    .copied()
+                    .filter(|idx| same_unit || !visited.contains(idx))
+                    .filter(|idx| {
+                        let unit = &layout.map_units[*idx];
+                        unit_matcher.matches(unit.unit) && entity_matchers.iter().all(|em| unit.spawn_objects().any(|so| em.matches(so)))
+                    })
+                    .collect();
+                if matched.is_empty() {
                     return false;
                 }
-                let mut new_frontier = Vec::new();
-                let mut matched = false;
-                for unit in frontier.iter() {
-                    if visited.contains(&unit.key()) {
-                        continue;
-                    }
-                    visited.push(unit.key());
-                    if unit_matcher.matches(unit.unit) && entity_matchers.iter().all(|em| unit.spawn_objects().any(|so| em.matches(so))) {
-                        matched = true;
-                        let neighbors = unit
-                            .doors
-                            .iter()
-                            .map(|door| {
-                                door.borrow()
-                                    .adjacent_door
-                                    .as_ref()
-                                    .unwrap()
-                                    .upgrade()
-                                    .unwrap()
-                                    .borrow()
-                                    .parent_idx
-                                    .unwrap()
-                            })
-                            .map(|parent_idx| &layout.map_units[parent_idx])
-                            .filter(|neighbor| neighbor.key() != unit.key());
-                        new_frontier.extend(neighbors);
-                    }
-                }
-                if !matched {
-                    return false;
+                visited.extend(matched.iter().copied());
+
+                if let Some(depth) = self.links.get(i) {
+                    frontier = units_within(layout, &matched, *depth);
                 }
-                frontier = new_frontier;
             }
             true
         })
     }
 }
 
-impl From<Pairs<'_, Rule>> for RoomPath {
-    fn from(input: Pairs<'_, Rule>) -> Self {
-        let components = input
-            .map(|component| {
-                let mut pairs = component.into_inner();
-                (pairs.next().unwrap().as_str().into(), pairs.map(|e| e.as_str().into()).collect())
-            })
-            .collect::<Vec<(UnitMatcher, Vec<EntityMatcher>)>>();
-        RoomPath { components }
+impl TryFrom<Pairs<'_, Rule>> for RoomPath {
+    type Error = Report<CaveripperError>;
+
+    fn try_from(input: Pairs<'_, Rule>) -> Result<Self, CaveripperError> {
+        let mut components = Vec::new();
+        let mut links = Vec::new();
+        for pair in input {
+            match pair.as_rule() {
+                Rule::room_path_component => {
+                    let mut pairs = pair.into_inner();
+                    components.push((pairs.next().unwrap().as_str().into(), pairs.map(|e| e.as_str().into()).collect()));
+                }
+                Rule::path_link => {
+                    let depth = match pair.into_inner().next().map(|d| d.as_str()) {
+                        None => PathDepth::Within(1),
+                        Some("*") => PathDepth::Unbounded,
+                        Some(n) => PathDepth::Within(n.parse::<usize>().change_context(CaveripperError::QueryParseError)?),
+                    };
+                    links.push(depth);
+                }
+                rule => unreachable!("Unexpected rule in room path: {rule:?}"),
+            }
+        }
+        Ok(RoomPath { components, links })
+    }
+}
+
+impl Display for PathDepth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathDepth::Within(1) => write!(f, "->"),
+            PathDepth::Within(n) => write!(f, "->{n}"),
+            PathDepth::Unbounded => write!(f, "->*"),
+        }
     }
 }
 
 /// Matches entities or categories of entities.
 #[derive(Debug, Clone)]
 pub enum EntityMatcher {
-    Entity { name: String, carrying: Option<String> },
+    Entity {
+        name: String,
+        carrying: Option<String>,
+    },
+    /// Any teki carrying the named treasure, regardless of what the teki is.
+    Carrying(String),
+    /// Candypop Buds of the given color, e.g. "violet". Matched by internal name; see
+    /// [CANDYPOP_COLORS]. Both grounded and falling Candypop Buds match.
+    Candypop(String),
+    /// Teki of the given name that spawned from the given spawn group, e.g. `group:1:chappy`.
+    /// Only matches regular teki since cap teki groups don't control where they spawn.
+    Group {
+        group: u32,
+        name: String,
+    },
+    /// Plants of the given species or category, e.g. `plant:clover` or `plant:figwort`, or
+    /// any plant for a bare `plant`. Goes by species rather than spawn group; see
+    /// [SpawnObject::is_plant_species]. Use `group:6:plant` to only match plants that
+    /// spawned from the plant group.
+    Plant(String),
     Hole,
     Geyser,
+    /// Either a hole or a geyser.
+    Exit,
     Ship,
     Gate,
 }
 
 impl EntityMatcher {
-    fn matches(&self, spawn_object: &SpawnObject) -> bool {
+    /// Describes the problem if this names a teki, treasure, Candypop color, or plant that
+    /// doesn't exist. Plain names have to be a teki or treasure; `ship`, `hole` and the
+    /// other fixed names are always recognized.
+    fn unrecognized(&self, teki_list: &[String], treasure_list: &[Treasure]) -> Option<String> {
+        let is_teki = |name: &str| teki_list.contains(&name.to_ascii_lowercase());
+        let is_treasure = |name: &str| treasure_list.iter().any(|t| t.internal_name.eq_ignore_ascii_case(name));
+        match self {
+            EntityMatcher::Entity { name, .. } => {
+                (!is_teki(name) && !is_treasure(name)).then(|| format!("expected teki or treasure name but found '{name}'"))
+            }
+            EntityMatcher::Carrying(treasure) => {
+                (!treasure.eq_ignore_ascii_case("any") && !is_treasure(treasure)).then(|| format!("unrecognized treasure '{treasure}'"))
+            }
+            EntityMatcher::Candypop(color) => (!color.eq_ignore_ascii_case("any") && candypop_internal_name(color).is_none())
+                .then(|| format!("unrecognized candypop color '{color}'")),
+            EntityMatcher::Plant(name) => (!name.eq_ignore_ascii_case("any")
+                && plant_category(name).is_none()
+                && !PLANT_TEKI.iter().any(|p| p.eq_ignore_ascii_case(name))
+                && !is_teki(name))
+            .then(|| format!("unrecognized plant '{name}'")),
+            EntityMatcher::Group { name, .. } => {
+                (!name.eq_ignore_ascii_case("any") && !name.eq_ignore_ascii_case("plant") && !is_teki(name))
+                    .then(|| format!("unrecognized teki '{name}'"))
+            }
+            EntityMatcher::Hole | EntityMatcher::Geyser | EntityMatcher::Exit | EntityMatcher::Ship | EntityMatcher::Gate => None,
+        }
+    }
+
+    pub(crate) fn matches(&self, spawn_object: &SpawnObject) -> bool {
         match (self, spawn_object) {
             (
                 EntityMatcher::Entity { name, carrying },
@@ -486,8 +1600,54 @@ impl EntityMatcher {
             (EntityMatcher::Entity { name, carrying }, SpawnObject::Item(iteminfo)) => {
                 (name.eq_ignore_ascii_case("any") || name.eq_ignore_ascii_case(&iteminfo.internal_name)) && carrying.is_none()
             }
+            (
+                EntityMatcher::Carrying(treasure),
+                SpawnObject::Teki(
+                    TekiInfo {
+                        carrying: Some(carrying), ..
+                    },
+                    _,
+                )
+                | SpawnObject::CapTeki(
+                    CapInfo {
+                        carrying: Some(carrying), ..
+                    },
+                    _,
+                ),
+            ) => treasure.eq_ignore_ascii_case("any") || treasure.eq_ignore_ascii_case(carrying),
+            (EntityMatcher::Candypop(color), SpawnObject::CapTeki(capinfo, _)) => {
+                capinfo.is_candypop()
+                    && (color.eq_ignore_ascii_case("any")
+                        || candypop_internal_name(color).is_some_and(|name| name.eq_ignore_ascii_case(&capinfo.internal_name)))
+            }
+            (
+                EntityMatcher::Group { group, name },
+                SpawnObject::Teki(
+                    TekiInfo {
+                        internal_name,
+                        group: t_group,
+                        ..
+                    },
+                    _,
+                ),
+            ) => {
+                group == t_group
+                    && (name.eq_ignore_ascii_case("any")
+                        || name.eq_ignore_ascii_case(internal_name)
+                        || (name.eq_ignore_ascii_case("plant") && spawn_object.is_plant_species()))
+            }
+            (
+                EntityMatcher::Plant(name),
+                SpawnObject::Teki(TekiInfo { internal_name, .. }, _) | SpawnObject::CapTeki(CapInfo { internal_name, .. }, _),
+            ) => {
+                spawn_object.is_plant_species()
+                    && (name.eq_ignore_ascii_case("any")
+                        || name.eq_ignore_ascii_case(internal_name)
+                        || plant_category(name).is_some_and(|species| species.iter().any(|s| s.eq_ignore_ascii_case(internal_name))))
+            }
             (EntityMatcher::Hole, SpawnObject::Hole(_)) => true,
             (EntityMatcher::Geyser, SpawnObject::Geyser(_)) => true,
+            (EntityMatcher::Exit, SpawnObject::Hole(_) | SpawnObject::Geyser(_)) => true,
             (EntityMatcher::Ship, SpawnObject::Ship) => true,
             (EntityMatcher::Gate, SpawnObject::Gate(_, _)) => true,
             _ => false,
@@ -496,22 +1656,49 @@ impl EntityMatcher {
 }
 
 impl From<&str> for EntityMatcher {
+    /// Names are stored lowercased. Keywords and prefixes like `carrying:` are case
+    /// insensitive, the same as in the query grammar.
     fn from(s: &str) -> Self {
-        match s.to_ascii_lowercase().trim() {
+        let s = s.trim();
+        match s.to_ascii_lowercase().as_str() {
             "hole" => EntityMatcher::Hole,
             "geyser" => EntityMatcher::Geyser,
+            "exit" => EntityMatcher::Exit,
             "ship" => EntityMatcher::Ship,
+            "easy_teki" => EntityMatcher::Group {
+                group: 0,
+                name: "any".to_string(),
+            },
+            "hard_teki" => EntityMatcher::Group {
+                group: 1,
+                name: "any".to_string(),
+            },
+            "special_teki" => EntityMatcher::Group {
+                group: 8,
+                name: "any".to_string(),
+            },
             "gate" => EntityMatcher::Gate,
-            s => {
-                if s.contains('/') {
-                    let (name, carrying) = s.split_once('/').unwrap();
+            "plant" => EntityMatcher::Plant("any".to_string()),
+            lowercase => {
+                let name = |name: &str| name.trim().to_ascii_lowercase();
+                if let Some(treasure) = strip_prefix_ignore_case(s, "carrying:") {
+                    EntityMatcher::Carrying(name(treasure))
+                } else if let Some(color) = strip_prefix_ignore_case(s, "candypop:") {
+                    EntityMatcher::Candypop(name(color))
+                } else if let Some(plant) = strip_prefix_ignore_case(s, "plant:") {
+                    EntityMatcher::Plant(name(plant))
+                } else if let Some((group, teki)) = strip_prefix_ignore_case(s, "group:").and_then(|rest| rest.split_once(':'))
+                    && let Ok(group) = group.trim().parse::<u32>()
+                {
+                    EntityMatcher::Group { group, name: name(teki) }
+                } else if let Some((teki, carrying)) = s.split_once('/') {
                     EntityMatcher::Entity {
-                        name: name.trim().to_string(),
-                        carrying: Some(carrying.trim().to_string()),
+                        name: name(teki),
+                        carrying: Some(name(carrying)),
                     }
                 } else {
                     EntityMatcher::Entity {
-                        name: s.to_string(),
+                        name: lowercase.to_string(),
                         carrying: None,
                     }
                 }
@@ -525,8 +1712,14 @@ impl Display for EntityMatcher {
         match self {
             EntityMatcher::Hole => write!(f, "hole"),
             EntityMatcher::Geyser => write!(f, "geyser"),
+            EntityMatcher::Exit => write!(f, "exit"),
             EntityMatcher::Ship => write!(f, "ship"),
             EntityMatcher::Gate => write!(f, "gate"),
+            EntityMatcher::Carrying(treasure) => write!(f, "carrying:{treasure}"),
+            EntityMatcher::Candypop(color) => write!(f, "candypop:{color}"),
+            EntityMatcher::Group { group, name } => write!(f, "group:{group}:{name}"),
+            EntityMatcher::Plant(name) if name.eq_ignore_ascii_case("any") => write!(f, "plant"),
+            EntityMatcher::Plant(name) => write!(f, "plant:{name}"),
             EntityMatcher::Entity { name, carrying: None } => write!(f, "{name}"),
             EntityMatcher::Entity {
                 name,
@@ -541,12 +1734,23 @@ impl Display for EntityMatcher {
 pub enum UnitMatcher {
     UnitType(RoomType),
     Named(String),
+    /// `TYPE#N`, e.g. `room#4`: units of the given type (or any type for `any#N`) with
+    /// exactly `num_doors` doors, per [CaveUnit::num_doors]. Every door of a placed unit
+    /// is connected to something since unused doors get closed off with caps, so this is
+    /// also the number of neighbouring units, counting any caps.
+    DoorCount {
+        room_type: Option<RoomType>,
+        num_doors: usize,
+    },
 }
 
 impl UnitMatcher {
     fn matches(&self, unit: &CaveUnit) -> bool {
         match self {
             UnitMatcher::UnitType(t) => &unit.room_type == t,
+            UnitMatcher::DoorCount { room_type, num_doors } => {
+                room_type.as_ref().is_none_or(|t| &unit.room_type == t) && unit.num_doors == *num_doors
+            }
             UnitMatcher::Named(name) if name.eq_ignore_ascii_case("any") => true,
             UnitMatcher::Named(name) => unit.unit_folder_name.eq_ignore_ascii_case(name),
         }
@@ -555,6 +1759,22 @@ impl UnitMatcher {
 
 impl From<&str> for UnitMatcher {
     fn from(input: &str) -> Self {
+        if let Some((base, num_doors)) = input.split_once('#')
+            && let Ok(num_doors) = num_doors.parse::<usize>()
+        {
+            if base.eq_ignore_ascii_case("any") {
+                return UnitMatcher::DoorCount {
+                    room_type: None,
+                    num_doors,
+                };
+            } else if let Ok(room_type) = RoomType::try_from(base) {
+                return UnitMatcher::DoorCount {
+                    room_type: Some(room_type),
+                    num_doors,
+                };
+            }
+        }
+
         if let Ok(room_type) = RoomType::try_from(input) {
             UnitMatcher::UnitType(room_type)
         } else {
@@ -567,12 +1787,508 @@ impl Display for UnitMatcher {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             UnitMatcher::UnitType(t) => write!(f, "{t}"),
+            UnitMatcher::DoorCount {
+                room_type: Some(t),
+                num_doors,
+            } => write!(f, "{t}#{num_doors}"),
+            UnitMatcher::DoorCount {
+                room_type: None,
+                num_doors,
+            } => write!(f, "any#{num_doors}"),
             UnitMatcher::Named(name) if name.eq_ignore_ascii_case("any") => write!(f, "any(room)"),
             UnitMatcher::Named(name) => write!(f, "{name}"),
         }
     }
 }
 
+/// Whether a spawn object is a bomb rock. Goes by the special texture its internal name
+/// is drawn with (see [get_special_texture_name]) rather than the name itself, so any
+/// name drawn as a bomb rock counts as one.
+pub(crate) fn is_bomb_rock(spawn_object: &SpawnObject) -> bool {
+    matches!(spawn_object, SpawnObject::Teki(..) | SpawnObject::CapTeki(..))
+        && get_special_texture_name(spawn_object.name()).is_some_and(|texture| Some(texture) == get_special_texture_name("bomb"))
+}
+
+/// Finds which map units can be walked to from the ship without passing through a gate.
+/// Returns one entry per map unit in the layout, in the same order as [Layout::map_units].
+fn units_reachable_without_gates(layout: &Layout) -> Vec<bool> {
+    let mut reachable = vec![false; layout.map_units.len()];
+    let Some(start) = layout
+        .map_units
+        .iter()
+        .position(|unit| unit.spawn_objects().any(|so| matches!(so, SpawnObject::Ship)))
+    else {
+        return reachable;
+    };
+
+    reachable[start] = true;
+    let mut stack = vec![start];
+    while let Some(idx) = stack.pop() {
+        for door in layout.map_units[idx].doors.iter() {
+            let door = door.borrow();
+            let Some(adjacent) = door.adjacent_door.as_ref().and_then(|d| d.upgrade()) else {
+                continue;
+            };
+            let adjacent = adjacent.borrow();
+
+            // Gates are only ever placed in door seams, and the seam spawnpoint can be on either
+            // side of the connection.
+            let gated = [&door.seam_spawnpoint, &adjacent.seam_spawnpoint]
+                .into_iter()
+                .any(|seam| matches!(Option::as_ref(seam), Some(SpawnObject::Gate(..))));
+            if gated {
+                continue;
+            }
+
+            if let Some(next) = adjacent.parent_idx
+                && !reachable[next]
+            {
+                reachable[next] = true;
+                stack.push(next);
+            }
+        }
+    }
+    reachable
+}
+
+/// Every pair of map units joined by a door, as indices into [Layout::map_units]. Units
+/// joined by more than one door appear once per door.
+fn door_connections(layout: &Layout) -> Vec<(usize, usize)> {
+    let mut connections = Vec::new();
+    for (idx, unit) in layout.map_units.iter().enumerate() {
+        for door in unit.doors.iter() {
+            let Some(adjacent) = door.borrow().adjacent_door.as_ref().and_then(|d| d.upgrade()) else {
+                continue;
+            };
+            let other = adjacent.borrow().parent_idx;
+            if let Some(other) = other
+                && idx < other
+            {
+                connections.push((idx, other));
+            }
+        }
+    }
+    connections
+}
+
+/// Every alcove (dead end) in the layout in reading order: sorted top to bottom by the
+/// grid row of the alcove's top-left corner, then left to right. This is the numbering
+/// used by `capN:` queries, starting from 1. It depends only on where alcoves were placed,
+/// so it's easy to work out from a rendered layout image.
+fn alcoves_in_reading_order<'a, 'l>(layout: &'a Layout<'l>) -> Vec<&'a PlacedMapUnit<'l>> {
+    layout
+        .map_units
+        .iter()
+        .filter(|unit| unit.unit.room_type == RoomType::DeadEnd)
+        .sorted_by_key(|unit| (unit.z, unit.x))
+        .collect()
+}
+
+/// If `idx` is one end of a door connection, the unit at the other end.
+fn other_end((a, b): (usize, usize), idx: usize) -> Option<usize> {
+    if a == idx {
+        Some(b)
+    } else if b == idx {
+        Some(a)
+    } else {
+        None
+    }
+}
+
+/// The map units on the shortest route, counted in doors, from the ship to the closest
+/// exit (hole or geyser). Sublevels without an exit have only the ship's unit on their
+/// main path. Returns one entry per map unit, in the same order as [Layout::map_units].
+fn main_path_units(layout: &Layout, connections: &[(usize, usize)]) -> Vec<bool> {
+    let mut on_path = vec![false; layout.map_units.len()];
+    let Some(start) = layout
+        .map_units
+        .iter()
+        .position(|unit| unit.spawn_objects().any(|so| matches!(so, SpawnObject::Ship)))
+    else {
+        return on_path;
+    };
+
+    let mut came_from: Vec<Option<usize>> = vec![None; layout.map_units.len()];
+    let mut queue = VecDeque::from([start]);
+    let mut end = start;
+    while let Some(idx) = queue.pop_front() {
+        if layout.map_units[idx]
+            .spawn_objects()
+            .any(|so| matches!(so, SpawnObject::Hole(_) | SpawnObject::Geyser(_)))
+        {
+            end = idx;
+            break;
+        }
+        for next in connections.iter().filter_map(|connection| other_end(*connection, idx)) {
+            if next != start && came_from[next].is_none() {
+                came_from[next] = Some(idx);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    on_path[end] = true;
+    let mut idx = end;
+    while let Some(prev) = came_from[idx] {
+        on_path[prev] = true;
+        idx = prev;
+    }
+    on_path
+}
+
+/// Counts the side branches off the main path (see [main_path_units]). A side branch is a
+/// connected group of map units off the main path that includes at least one room or
+/// hallway. Groups made of nothing but alcoves don't count, since every door left open
+/// during generation gets closed off with one, and a group that joins the main path in
+/// more than one place still counts once.
+fn side_branches(layout: &Layout) -> usize {
+    let connections = door_connections(layout);
+    let mut visited = main_path_units(layout, &connections);
+    let mut branches = 0;
+    while let Some(start) = visited.iter().position(|v| !v) {
+        visited[start] = true;
+        let mut stack = vec![start];
+        let mut has_room = false;
+        while let Some(idx) = stack.pop() {
+            has_room |= layout.map_units[idx].unit.room_type != RoomType::DeadEnd;
+            for next in connections.iter().filter_map(|connection| other_end(*connection, idx)) {
+                if !visited[next] {
+                    visited[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+        if has_room {
+            branches += 1;
+        }
+    }
+    branches
+}
+
+/// Finds which map units can only be reached from the main path (see [main_path_units])
+/// through one particular door, i.e. there's a door whose removal would disconnect the
+/// unit from every unit on the main path. Getting anything out of such a unit means
+/// walking back out the same way, so they're the dead-end branches of the layout.
+/// Units on the main path are never isolated. Returns one entry per map unit, in the
+/// same order as [Layout::map_units].
+///
+/// This ignores gates and distance, so a branch that's a single short alcove counts the
+/// same as a long detour.
+fn isolated_units(layout: &Layout) -> Vec<bool> {
+    let connections = door_connections(layout);
+    let main_path = main_path_units(layout, &connections);
+    if !main_path.contains(&true) {
+        return main_path;
+    }
+
+    let reaches_main_path_without = |from: usize, removed: usize| {
+        let mut seen = vec![false; layout.map_units.len()];
+        seen[from] = true;
+        let mut stack = vec![from];
+        while let Some(idx) = stack.pop() {
+            if main_path[idx] {
+                return true;
+            }
+            for (i, connection) in connections.iter().enumerate() {
+                if let Some(next) = other_end(*connection, idx)
+                    && i != removed
+                    && !seen[next]
+                {
+                    seen[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+        false
+    };
+    (0..layout.map_units.len())
+        .map(|idx| !main_path[idx] && (0..connections.len()).any(|removed| !reaches_main_path_without(idx, removed)))
+        .collect()
+}
+
+/// Finds the units that are between 1 and `depth` doors away from the nearest of `sources`,
+/// or just `sources` themselves if `depth` is 0. Indices are into [Layout::map_units].
+fn units_within(layout: &Layout, sources: &[usize], depth: PathDepth) -> Vec<usize> {
+    let max_dist = match depth {
+        PathDepth::Within(0) => return sources.to_vec(),
+        PathDepth::Within(n) => n,
+        PathDepth::Unbounded => usize::MAX,
+    };
+
+    let mut dist: Vec<Option<usize>> = vec![None; layout.map_units.len()];
+    let mut queue = VecDeque::new();
+    for &idx in sources {
+        dist[idx] = Some(0);
+        queue.push_back(idx);
+    }
+
+    while let Some(idx) = queue.pop_front() {
+        let next_dist = dist[idx].unwrap() + 1;
+        if next_dist > max_dist {
+            continue;
+        }
+        for door in layout.map_units[idx].doors.iter() {
+            let Some(adjacent) = door.borrow().adjacent_door.as_ref().and_then(|d| d.upgrade()) else {
+                continue;
+            };
+            let next = adjacent.borrow().parent_idx;
+            if let Some(next) = next
+                && dist[next].is_none()
+            {
+                dist[next] = Some(next_dist);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    dist.iter()
+        .enumerate()
+        .filter(|(_, d)| matches!(d, Some(d) if *d > 0))
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// Checks whether every route through the waypoint graph from the ship to the exit passes
+/// near a hard (group 1) teki. Waypoints within the teki's spawn radius, or within
+/// [HARD_TEKI_DANGER_RADIUS] if that's larger, are treated as impassable.
+///
+/// This is only a heuristic: teki move around, and plenty of group 1 teki can be walked past
+/// safely. If there are multiple exits (e.g. a hole and a geyser), the exit counts as blocked
+/// only if all of them are.
+fn exit_blocked_by_hard_teki(layout: &Layout) -> bool {
+    let hard_teki: Vec<(Point<3, f32>, f32)> = layout
+        .map_units
+        .iter()
+        .flat_map(|unit| unit.spawnpoints.iter())
+        .flat_map(|sp| {
+            sp.contains.iter().filter_map(|so| match so {
+                SpawnObject::Teki(TekiInfo { group: 1, .. }, offset) => {
+                    Some((sp.pos + *offset, f32::max(sp.spawnpoint_unit.radius, HARD_TEKI_DANGER_RADIUS)))
+                }
+                _ => None,
+            })
+        })
+        .collect();
+
+    let Some(ship_pos) = layout
+        .get_spawn_objects()
+        .find(|(so, _)| matches!(so, SpawnObject::Ship))
+        .map(|(_, pos)| pos)
+    else {
+        return false;
+    };
+    let exits = layout
+        .get_spawn_objects()
+        .filter(|(so, _)| matches!(so, SpawnObject::Hole(_) | SpawnObject::Geyser(_)))
+        .map(|(_, pos)| pos)
+        .collect_vec();
+    if exits.is_empty() {
+        return false;
+    }
+
+    exits.into_iter().all(|exit_pos| {
+        !layout.waypoint_graph().has_path_avoiding(ship_pos, exit_pos, |wp| {
+            hard_teki.iter().any(|(teki_pos, radius)| wp.pos.p2_dist(teki_pos) < *radius)
+        })
+    })
+}
+
+/// Checks whether any roaming teki is inside a one-wide hallway unit, or within
+/// [ROAMING_CHOKEPOINT_MARGIN] of one.
+fn roaming_teki_at_chokepoint(layout: &Layout) -> bool {
+    let hallway_bounds = layout
+        .map_units
+        .iter()
+        .filter(|unit| unit.unit.room_type == RoomType::Hallway && unit.unit.width.min(unit.unit.height) == 1)
+        .map(|unit| {
+            let min = Point([unit.x as f32 * 170.0, unit.z as f32 * 170.0]);
+            let max = Point([
+                (unit.x + unit.unit.width as i32) as f32 * 170.0,
+                (unit.z + unit.unit.height as i32) as f32 * 170.0,
+            ]);
+            (min, max)
+        })
+        .collect_vec();
+    layout.get_spawn_objects().filter(|(so, _)| so.is_roaming()).any(|(_, pos)| {
+        hallway_bounds.iter().any(|(min, max)| {
+            (min[0] - ROAMING_CHOKEPOINT_MARGIN..=max[0] + ROAMING_CHOKEPOINT_MARGIN).contains(&pos[0])
+                && (min[1] - ROAMING_CHOKEPOINT_MARGIN..=max[1] + ROAMING_CHOKEPOINT_MARGIN).contains(&pos[2])
+        })
+    })
+}
+
+/// Heuristic for whether any objective in the layout is only reachable through water.
+/// Objectives are every treasure, loose or carried by a teki, plus the exit. With more than
+/// one exit (e.g. a hole and a geyser), only one of them needs to be reachable.
+///
+/// An objective counts as reachable if it isn't inside a waterbox and there's a route to it
+/// from the ship through the waypoint graph that doesn't pass through any waterbox.
+/// Waterboxes are only compared from above, so shallow puddles count the same as deep
+/// water, and teki or other obstacles in the way aren't considered at all.
+fn needs_blue_pikmin(layout: &Layout) -> bool {
+    let waterboxes = waterbox_bounds(layout);
+    if waterboxes.is_empty() {
+        return false;
+    }
+    let in_water = |pos: Point<2, f32>| {
+        waterboxes
+            .iter()
+            .any(|(min, max)| (min[0]..=max[0]).contains(&pos[0]) && (min[1]..=max[1]).contains(&pos[1]))
+    };
+
+    let Some(ship_pos) = layout
+        .get_spawn_objects()
+        .find(|(so, _)| matches!(so, SpawnObject::Ship))
+        .map(|(_, pos)| pos)
+    else {
+        return false;
+    };
+    let reachable_dry = |pos: Point<3, f32>| {
+        !in_water(pos.two_d())
+            && layout
+                .waypoint_graph()
+                .has_path_avoiding(ship_pos, pos, |wp| in_water(wp.pos.two_d()))
+    };
+
+    let treasure_needs_blue = layout
+        .get_spawn_objects()
+        .filter(|(so, _)| matches!(so, SpawnObject::Item(_) | SpawnObject::Teki(TekiInfo { carrying: Some(_), .. }, _)))
+        .any(|(_, pos)| !reachable_dry(pos));
+    let exits = layout
+        .get_spawn_objects()
+        .filter(|(so, _)| matches!(so, SpawnObject::Hole(_) | SpawnObject::Geyser(_)))
+        .map(|(_, pos)| pos)
+        .collect_vec();
+    let exit_needs_blue = !exits.is_empty() && !exits.into_iter().any(reachable_dry);
+
+    treasure_needs_blue || exit_needs_blue
+}
+
+/// The corners of every waterbox in the layout in world coordinates, seen from above.
+fn waterbox_bounds(layout: &Layout) -> Vec<(Point<2, f32>, Point<2, f32>)> {
+    layout
+        .map_units
+        .iter()
+        .flat_map(|unit| {
+            let center = Point([
+                (unit.x as f32 + unit.unit.width as f32 / 2.0) * 170.0,
+                (unit.z as f32 + unit.unit.height as f32 / 2.0) * 170.0,
+            ]);
+            unit.unit
+                .waterboxes
+                .iter()
+                .map(move |wb| (center + wb.p1.two_d(), center + wb.p2.two_d()))
+        })
+        .collect()
+}
+
+/// Length of a closed route that starts at `start`, goes to every stop, and comes back,
+/// always heading to the closest stop that hasn't been visited yet.
+///
+/// This is the nearest-neighbor approximation of the shortest such route, not the optimal
+/// one, so it usually comes out somewhat longer than the best possible route. Distances
+/// are straight lines and ignore walls and carry paths.
+fn nearest_neighbor_tour_len(start: Point<3, f32>, stops: &[Point<3, f32>]) -> f32 {
+    let mut remaining = stops.to_vec();
+    let mut current = start;
+    let mut total = 0.0;
+    while let Some((idx, dist)) = remaining
+        .iter()
+        .map(|stop| current.p2_dist(stop))
+        .enumerate()
+        .min_by(|(_, d1), (_, d2)| d1.total_cmp(d2))
+    {
+        total += dist;
+        current = remaining.swap_remove(idx);
+    }
+    total + current.p2_dist(&start)
+}
+
+/// How many Pikmin each kind of Candypop Bud can convert before it wilts, by internal name.
+/// Regular buds take up to five Pikmin, while a Queen Candypop Bud only takes one (and
+/// turns it into a large number of seeds). Candypop Buds that aren't listed here, such as
+/// ones added by romhacks, count as [DEFAULT_CANDYPOP_PETALS] until they're added.
+const CANDYPOP_PETALS: [(&str, u32); 6] = [
+    ("redpom", 5),
+    ("yellowpom", 5),
+    ("bluepom", 5),
+    ("blackpom", 5),
+    ("whitepom", 5),
+    ("randpom", 1),
+];
+const DEFAULT_CANDYPOP_PETALS: u32 = 5;
+
+fn candypop_petals(internal_name: &str) -> u32 {
+    CANDYPOP_PETALS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(internal_name))
+        .map_or(DEFAULT_CANDYPOP_PETALS, |(_, petals)| *petals)
+}
+
+/// Candypop Bud colors accepted by `candypop:COLOR` queries, and the internal names
+/// of the corresponding teki.
+const CANDYPOP_COLORS: [(&str, &str); 6] = [
+    ("red", "redpom"),
+    ("yellow", "yellowpom"),
+    ("blue", "bluepom"),
+    ("violet", "blackpom"),
+    ("ivory", "whitepom"),
+    ("queen", "randpom"),
+];
+
+/// Plant categories accepted by `plant:CATEGORY` queries, and the internal names of the
+/// species in each one. Every species is also accepted on its own by internal name.
+const PLANT_CATEGORIES: [(&str, &[&str]); 5] = [
+    ("figwort", &["ooinu_s", "ooinu_l", "kareooinu_s", "kareooinu_l"]),
+    ("shoot", &["wakame_s", "wakame_l"]),
+    ("dandelion", &["tanpopo", "watage"]),
+    ("mushroom", &["hikarikinoko"]),
+    ("glowstem", &["daiodored", "daiodogreen"]),
+];
+
+fn plant_category(category: &str) -> Option<&'static [&'static str]> {
+    PLANT_CATEGORIES
+        .iter()
+        .find(|(c, _)| c.eq_ignore_ascii_case(category))
+        .map(|(_, species)| *species)
+}
+
+fn candypop_internal_name(color: &str) -> Option<&'static str> {
+    CANDYPOP_COLORS
+        .iter()
+        .find(|(c, _)| c.eq_ignore_ascii_case(color))
+        .map(|(_, internal_name)| *internal_name)
+}
+
+/// Parses an entity, rejecting names that aren't a known teki or treasure the same way
+/// counting queries do, so a typo is an error instead of a query that never matches.
+fn validate_entity(entity: &Pair<'_, Rule>, full_txt: &str, mgr: &impl AssetManager) -> Result<EntityMatcher, CaveripperError> {
+    let teki_list = mgr.all_teki(None).change_context(CaveripperError::QueryParseError)?;
+    let treasure_list = mgr.all_treasures(None).change_context(CaveripperError::QueryParseError)?;
+    let entity_matcher: EntityMatcher = entity.as_str().into();
+    match entity_matcher.unrecognized(&teki_list, &treasure_list) {
+        None => Ok(entity_matcher),
+        Some(problem) => Err(report!(CaveripperError::QueryParseError))
+            .attach_printable_lazy(|| full_txt.to_owned())
+            .attach_printable_lazy(|| QueryParseError::new(entity.as_span().start(), problem)),
+    }
+}
+
+/// Like [str::strip_prefix], but ignores ASCII case when matching the prefix.
+fn strip_prefix_ignore_case<'s>(s: &'s str, prefix: &str) -> Option<&'s str> {
+    s.get(..prefix.len())
+        .filter(|start| start.eq_ignore_ascii_case(prefix))
+        .map(|_| &s[prefix.len()..])
+}
+
+fn ordering_char(ordering: Ordering) -> char {
+    match ordering {
+        Ordering::Less => '<',
+        Ordering::Equal => '=',
+        Ordering::Greater => '>',
+    }
+}
+
 fn char_to_ordering(c: &str) -> Ordering {
     match c {
         "<" => Ordering::Less,
@@ -599,3 +2315,17 @@ fn point_to_line_dist(p: Point<3, f32>, l1: Point<3, f32>, l2: Point<3, f32>) ->
         ((norm * len * t) + l1 - p).length()
     }
 }
+
+/// Hazards accepted by `hazard:TYPE` queries, and the internal names of the teki that count
+/// as a source of each one. Add to this table to support hazard teki from romhacks. Water
+/// is special: any waterbox in the layout counts too, on top of the teki listed here.
+const HAZARDS: [(&str, &[&str]); 4] = [
+    ("fire", &["hiba"]),
+    ("electric", &["elechiba"]),
+    ("poison", &["gashiba"]),
+    ("water", &[]),
+];
+
+fn hazard_teki(hazard: &str) -> Option<&'static [&'static str]> {
+    HAZARDS.iter().find(|(h, _)| h.eq_ignore_ascii_case(hazard)).map(|(_, teki)| *teki)
+}
@@ -1,3 +1,5 @@
 mod consecutive_identical_seeds;
+mod slug;
 
 pub use consecutive_identical_seeds::*;
+pub use slug::*;
@@ -0,0 +1,23 @@
+use crate::{assets::AssetManager, layout::Layout, query::Query, sublevel::Sublevel};
+
+/// Matches the seed(s) whose layout has exactly the given slug. Mostly useful for
+/// tracking down the seed behind a layout someone has reported.
+pub struct SlugQuery {
+    pub sublevel: Sublevel,
+    pub slug: String,
+}
+
+impl Query for SlugQuery {
+    fn matches(&self, seed: u32, mgr: &impl AssetManager) -> bool {
+        let caveinfo = mgr.load_caveinfo(&self.sublevel).unwrap();
+        self.matches_layout(&Layout::generate(seed, caveinfo), mgr)
+    }
+
+    fn matches_layout(&self, layout: &Layout, mgr: &impl AssetManager) -> bool {
+        if layout.sublevel == self.sublevel {
+            layout.slug() == self.slug.trim()
+        } else {
+            self.matches(layout.starting_seed, mgr)
+        }
+    }
+}
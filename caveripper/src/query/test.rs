@@ -1,5 +1,21 @@
-use super::StructuralQuery;
-use crate::{assets::fs_asset_manager::FsAssetManager, query::Query};
+use std::{cmp::Ordering, rc::Rc};
+
+use itertools::Itertools;
+use pest::Parser;
+
+use super::{
+    alcoves_in_reading_order, door_connections, isolated_units, main_path_units, nearest_neighbor_tour_len, EntityMatcher, PathDepth,
+    QueryKind, QueryParseError, QueryParser, RoomPath, Rule, StructuralQuery,
+};
+use crate::{
+    assets::{fs_asset_manager::FsAssetManager, load_cave_floors, AssetManager, CaveConfig},
+    caveinfo::{CapInfo, CaveUnit, DoorUnit, GateInfo, ItemInfo, RoomType, SpawnPoint, TekiInfo, Waterbox, Waypoint},
+    layout::{Layout, PlacedMapUnit, SpawnObject, TekiPlacement},
+    pikmin_math::PikminRng,
+    point::Point,
+    query::{matches_batch, spawn_probability, Query, SpawnProbability},
+    sublevel::Sublevel,
+};
 
 fn test_query(query_str: &str, success_seeds: &[u32], failure_seeds: &[u32]) {
     let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
@@ -12,6 +28,104 @@ fn test_query(query_str: &str, success_seeds: &[u32], failure_seeds: &[u32]) {
     }
 }
 
+/// A 1x1 unit with a door in each of `door_directions` and one spawn point in its center.
+fn test_unit(room_type: RoomType, door_directions: &[u16]) -> CaveUnit {
+    CaveUnit {
+        game: "pikmin2".to_string(),
+        unit_folder_name: "room_test".to_string(),
+        width: 1,
+        height: 1,
+        room_type,
+        num_doors: door_directions.len(),
+        doors: door_directions
+            .iter()
+            .map(|&direction| DoorUnit {
+                direction,
+                side_lateral_offset: 0,
+                waypoint_index: 0,
+                num_links: 0,
+                door_links: Vec::new(),
+            })
+            .collect(),
+        rotation: 0,
+        spawnpoints: vec![SpawnPoint {
+            group: 0,
+            pos: Point([0.0, 0.0, 0.0]),
+            angle_degrees: 0.0,
+            radius: 0.0,
+            min_num: 1,
+            max_num: 1,
+        }],
+        waterboxes: Vec::new(),
+        waypoints: Vec::new(),
+    }
+}
+
+/// Connects door `a.1` of map unit `a.0` to door `b.1` of map unit `b.0`, the way generation
+/// would when placing one unit against the other.
+fn connect_doors(map_units: &[PlacedMapUnit], a: (usize, usize), b: (usize, usize)) {
+    let doors = [Rc::clone(&map_units[a.0].doors[a.1]), Rc::clone(&map_units[b.0].doors[b.1])];
+    for (i, (parent_idx, _)) in [a, b].into_iter().enumerate() {
+        let mut door = doors[i].borrow_mut();
+        door.parent_idx = Some(parent_idx);
+        door.adjacent_door = Some(Rc::downgrade(&doors[1 - i]));
+    }
+}
+
+/// Two 1x1 rooms facing each other across a single door, each with one spawn point in
+/// its center. Used with [seam_layout].
+fn seam_units() -> [CaveUnit; 2] {
+    [test_unit(RoomType::Room, &[1]), test_unit(RoomType::Room, &[3])]
+}
+
+/// A layout made from [seam_units] by hand, so its contents are known exactly: `seam_object`
+/// sits on the seam between the two rooms and `room_objects` in the first room's spawn point.
+fn seam_layout<'a>(units: &'a [CaveUnit; 2], seam_object: Option<SpawnObject<'a>>, room_objects: Vec<SpawnObject<'a>>) -> Layout<'a> {
+    let mut map_units = vec![PlacedMapUnit::new(&units[0], 0, 0), PlacedMapUnit::new(&units[1], 1, 0)];
+    map_units[0].spawnpoints[0].contains = room_objects;
+
+    connect_doors(&map_units, (0, 0), (1, 0));
+    let seam = Rc::new(seam_object);
+    for unit in map_units.iter() {
+        unit.doors[0].borrow_mut().seam_spawnpoint = Rc::clone(&seam);
+    }
+
+    Layout::from_placed_units(test_sublevel(), map_units)
+}
+
+/// The sublevel every hand-built layout in these tests claims to be from.
+fn test_sublevel() -> Sublevel {
+    let cfg = CaveConfig {
+        game: "pikmin2".to_string(),
+        full_name: "Test Cave".to_string(),
+        is_challenge_mode: false,
+        shortened_names: vec!["test".to_string()],
+        caveinfo_filename: String::new(),
+    };
+    Sublevel::from_cfg(&cfg, 1)
+}
+
+/// A teki entry with nothing special about it: no carried treasure, no spawn method, and a
+/// minimum of one. Use struct update syntax for anything else.
+fn teki(internal_name: &str, group: u32) -> TekiInfo {
+    TekiInfo {
+        game: "pikmin2".to_string(),
+        internal_name: internal_name.to_string(),
+        carrying: None,
+        minimum_amount: 1,
+        filler_distribution_weight: 0,
+        group,
+        spawn_method: None,
+    }
+}
+
+fn gate(health: f32) -> GateInfo {
+    GateInfo {
+        health,
+        spawn_distribution_weight: 1,
+    }
+}
+
 #[test]
 fn test_towerless() {
     test_query(
@@ -38,6 +152,39 @@ fn test_room_path() {
     );
 }
 
+#[test]
+fn test_room_path_depth() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let same_unit = StructuralQuery::try_parse("sh6 any + ship ->0 any + ship", &mgr).unwrap();
+    let adjacent = StructuralQuery::try_parse("sh6 any + ship -> any + hole", &mgr).unwrap();
+    let explicit_adjacent = StructuralQuery::try_parse("sh6 any + ship ->1 any + hole", &mgr).unwrap();
+    let within_two = StructuralQuery::try_parse("sh6 any + ship ->2 any + hole", &mgr).unwrap();
+    let unbounded = StructuralQuery::try_parse("sh6 any + ship ->* any + hole", &mgr).unwrap();
+    for seed in 0..100 {
+        assert!(same_unit.matches(seed, &mgr));
+        assert_eq!(adjacent.matches(seed, &mgr), explicit_adjacent.matches(seed, &mgr));
+        assert!(!adjacent.matches(seed, &mgr) || within_two.matches(seed, &mgr));
+        assert!(unbounded.matches(seed, &mgr));
+    }
+}
+
+#[test]
+fn test_parse_room_path_depth() {
+    let mut pairs = QueryParser::parse(Rule::query, "sh6 any + ship ->0 any -> alcove ->3 room ->* any + hole").unwrap();
+    let expression = pairs.nth(1).unwrap().into_inner().next().unwrap();
+    let room_path = RoomPath::try_from(expression.into_inner()).unwrap();
+    assert_eq!(room_path.components.len(), 5);
+    assert_eq!(
+        room_path.links,
+        [
+            PathDepth::Within(0),
+            PathDepth::Within(1),
+            PathDepth::Within(3),
+            PathDepth::Unbounded
+        ]
+    );
+}
+
 #[test]
 fn test_clackerless() {
     test_query(
@@ -95,12 +242,36 @@ fn test_parse_room_type_names() {
 #[test]
 fn test_parse_count_queries() {
     let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
-    let query_strings = ["fc2 room_saka1_1_snow = 2", "scx7 room_ari1_3_metal < 2", "bd1 geyser = 0"];
+    let query_strings = [
+        "fc2 room_saka1_1_snow = 2",
+        "scx7 room_ari1_3_metal < 2",
+        "bd1 geyser = 0",
+        "bk4 species > 3",
+        "bk4 all_species < 5",
+        "bk4 nonseam_species > 2",
+        "sh6 size < 31",
+        "scx7 size > 20",
+    ];
     for s in query_strings {
         StructuralQuery::try_parse(s, &mgr).unwrap_or_else(|_| panic!("Failed to parse query string \"{s}\""));
     }
 }
 
+#[test]
+fn test_map_size() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let sublevel = Sublevel::try_from_str("scx7", &mgr).unwrap();
+    let caveinfo = mgr.load_caveinfo(&sublevel).unwrap();
+    for seed in [0xB5E72294, 0x42AC4C0F, 0x1234ABCD] {
+        let (width, height) = Layout::generate(seed, caveinfo).grid_size();
+        let size = width.max(height);
+        let fits = StructuralQuery::try_parse(&format!("scx7 size < {}", size + 1), &mgr).unwrap();
+        let too_small = StructuralQuery::try_parse(&format!("scx7 size < {size}"), &mgr).unwrap();
+        assert!(fits.matches(seed, &mgr));
+        assert!(!too_small.matches(seed, &mgr));
+    }
+}
+
 #[test]
 fn test_room_path_whitespace() {
     let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
@@ -120,3 +291,1470 @@ fn test_game_specifier_in_sublevel() {
     let query_string = "216:tr12 randpom < 1";
     StructuralQuery::try_parse(query_string, &mgr).unwrap_or_else(|_| panic!("Failed to parse query string \"{query_string}\""));
 }
+
+#[test]
+fn test_carrying_matches_carried_treasure_only() {
+    let carrier = TekiInfo {
+        carrying: Some("bane_red".to_string()),
+        ..teki("Kochappy", 1)
+    };
+    let loose = ItemInfo {
+        game: "pikmin2".to_string(),
+        internal_name: "bane_red".to_string(),
+        min_amount: 1,
+        filler_distribution_weight: 0,
+    };
+    let carried = SpawnObject::Teki(&carrier, Point([0.0, 0.0, 0.0]));
+    let loose = SpawnObject::Item(&loose);
+
+    let matcher: EntityMatcher = "carrying:bane_red".into();
+    assert!(matcher.matches(&carried));
+    assert!(!matcher.matches(&loose));
+
+    let any_carried: EntityMatcher = "carrying:any".into();
+    assert!(any_carried.matches(&carried));
+    assert!(!any_carried.matches(&loose));
+
+    let loose_matcher: EntityMatcher = "bane_red".into();
+    assert!(!loose_matcher.matches(&carried));
+    assert!(loose_matcher.matches(&loose));
+
+    // Prefixes are case insensitive, the same as in the query grammar.
+    let mixed_case: EntityMatcher = "Carrying:Bane_Red".into();
+    assert!(mixed_case.matches(&carried));
+    assert_eq!(mixed_case.to_string(), "carrying:bane_red");
+    let mixed_case_group: EntityMatcher = "GROUP:1:Kochappy".into();
+    assert!(mixed_case_group.matches(&carried));
+}
+
+#[test]
+fn test_parse_carrying() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query_strings = [
+        "scx7 carrying:denchi_1_black > 0",
+        "scx7 carrying: denchi_1_black = 0",
+        "sh6 any + bluekochappy + carrying:bey_goma",
+        "sh6 carrying:bey_goma carry dist < 500",
+    ];
+    for s in query_strings {
+        StructuralQuery::try_parse(s, &mgr).unwrap_or_else(|_| panic!("Failed to parse query string \"{s}\""));
+    }
+}
+
+#[test]
+fn test_candypop_matches_color_whether_falling_or_grounded() {
+    let candypop = |internal_name: &str, spawn_method: Option<&str>| CapInfo {
+        game: "pikmin2".to_string(),
+        internal_name: internal_name.to_string(),
+        carrying: None,
+        minimum_amount: 1,
+        filler_distribution_weight: 0,
+        group: 0,
+        spawn_method: spawn_method.map(str::to_string),
+    };
+    let grounded_violet = candypop("BlackPom", None);
+    let falling_violet = candypop("BlackPom", Some("1"));
+    let ivory = candypop("WhitePom", None);
+
+    let violet: EntityMatcher = "candypop:violet".into();
+    assert!(violet.matches(&SpawnObject::CapTeki(&grounded_violet, 0)));
+    assert!(violet.matches(&SpawnObject::CapTeki(&falling_violet, 0)));
+    assert!(!violet.matches(&SpawnObject::CapTeki(&ivory, 0)));
+
+    let any: EntityMatcher = "candypop:any".into();
+    assert!(any.matches(&SpawnObject::CapTeki(&ivory, 0)));
+}
+
+#[test]
+fn test_parse_candypop() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query_strings = ["sh6 candypop:violet > 1", "scx1 candypop:Ivory = 0", "bk4 candypop:any < 3"];
+    for s in query_strings {
+        StructuralQuery::try_parse(s, &mgr).unwrap_or_else(|_| panic!("Failed to parse query string \"{s}\""));
+    }
+    assert!(StructuralQuery::try_parse("sh6 candypop:green > 0", &mgr).is_err());
+}
+
+#[test]
+fn test_parse_reachable_no_gate() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query_strings = [
+        "cos2 reachable_no_gate:ahiru_head",
+        "cos4 reachable_no_gate: hole",
+        "sh6 reachable_no_gate:carrying:bey_goma",
+    ];
+    for s in query_strings {
+        StructuralQuery::try_parse(s, &mgr).unwrap_or_else(|_| panic!("Failed to parse query string \"{s}\""));
+    }
+    assert!(StructuralQuery::try_parse("cos2 reachable_no_gate:ahiru_hed", &mgr).is_err());
+    assert!(StructuralQuery::try_parse("sh6 reachable_no_gate:carrying:not_a_treasure", &mgr).is_err());
+}
+
+#[test]
+fn test_reachable_no_gate() {
+    let mgr = FsAssetManager::init_unchecked().expect("Couldn't init asset manager");
+    let gate = gate(300.0);
+    let treasure = ItemInfo {
+        game: "pikmin2".to_string(),
+        internal_name: "ahiru_head".to_string(),
+        min_amount: 1,
+        filler_distribution_weight: 0,
+    };
+    let reachable = |layout: &Layout, entity: &str| QueryKind::ReachableNoGate(entity.into()).matches(layout, &mgr);
+
+    // The ship is in the first room and the treasure in the second, with the gate (if
+    // any) on the only door between them.
+    let units = seam_units();
+    let layout = |seam_object| {
+        let mut layout = seam_layout(&units, seam_object, vec![SpawnObject::Ship]);
+        layout.map_units[1].spawnpoints[0].contains.push(SpawnObject::Item(&treasure));
+        layout
+    };
+
+    let gated = layout(Some(SpawnObject::Gate(&gate, 0)));
+    assert!(!reachable(&gated, "ahiru_head"));
+    assert!(reachable(&gated, "ship"));
+    // The gate itself can be walked up to.
+    assert!(reachable(&gated, "gate"));
+
+    let gateless = layout(None);
+    assert!(reachable(&gateless, "ahiru_head"));
+    assert!(!reachable(&gateless, "hole"));
+}
+
+#[test]
+fn test_parse_exit_blocked() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query_strings = ["bk4 exit blocked", "bk4 exit not blocked & hole carry dist < 1000"];
+    for s in query_strings {
+        StructuralQuery::try_parse(s, &mgr).unwrap_or_else(|_| panic!("Failed to parse query string \"{s}\""));
+    }
+}
+
+#[test]
+fn test_exit_blocked() {
+    let mgr = FsAssetManager::init_unchecked().expect("Couldn't init asset manager");
+    let (hard_teki, easy_teki) = (teki("Kochappy", 1), teki("Kochappy", 0));
+
+    // The ship is in the first room and the hole in the second, each next to the single
+    // waypoint in the middle of its room, at (85, 85) and (255, 85).
+    let units = seam_units().map(|mut unit| {
+        unit.waypoints.push(Waypoint {
+            pos: Point([0.0, 0.0, 0.0]),
+            r: 10.0,
+            index: 0,
+            links: Vec::new(),
+        });
+        unit
+    });
+    let exit_blocked = |teki: Option<SpawnObject>| {
+        let mut layout = seam_layout(&units, None, vec![SpawnObject::Ship]);
+        layout.map_units[1].spawnpoints[0].contains.push(SpawnObject::Hole(false));
+        layout.map_units[1].spawnpoints[0].contains.extend(teki);
+        let blocked = QueryKind::ExitBlocked(true).matches(&layout, &mgr);
+        assert_ne!(blocked, QueryKind::ExitBlocked(false).matches(&layout, &mgr));
+        blocked
+    };
+
+    assert!(!exit_blocked(None));
+    // A hard teki right on top of the exit's waypoint blocks the only route.
+    assert!(exit_blocked(Some(SpawnObject::Teki(&hard_teki, Point([0.0, 0.0, 0.0])))));
+    // Just inside and just outside the danger radius.
+    assert!(exit_blocked(Some(SpawnObject::Teki(&hard_teki, Point([140.0, 0.0, 0.0])))));
+    assert!(!exit_blocked(Some(SpawnObject::Teki(&hard_teki, Point([160.0, 0.0, 0.0])))));
+    // Only hard teki count.
+    assert!(!exit_blocked(Some(SpawnObject::Teki(&easy_teki, Point([0.0, 0.0, 0.0])))));
+}
+
+#[test]
+fn test_parse_error_position() {
+    let query = "bk4 hole carry dist < ";
+    let err: QueryParseError = QueryParser::parse(Rule::query, query).unwrap_err().into();
+    assert_eq!(err.offset, 22);
+    assert!(err.message.contains("number"), "{}", err.message);
+
+    let diagram = err.caret_diagram(query);
+    let caret_line = diagram.lines().nth(1).unwrap();
+    assert_eq!(caret_line.find('^'), Some(22));
+}
+
+#[test]
+fn test_start_room() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("bk4", &mgr).unwrap()).unwrap();
+
+    let mut start_rooms: Vec<(u32, String)> = Vec::new();
+    for seed in 0..50 {
+        let layout = Layout::generate(seed, caveinfo);
+        start_rooms.push((seed, layout.map_units[0].unit.unit_folder_name.clone()));
+    }
+    let mut distinct: Vec<&String> = start_rooms.iter().map(|(_, name)| name).collect();
+    distinct.sort();
+    distinct.dedup();
+    assert!(distinct.len() > 1, "expected multiple possible starting rooms on bk4");
+
+    for name in distinct {
+        let query = StructuralQuery::try_parse(&format!("bk4 start:{name}"), &mgr).unwrap();
+        for (seed, start) in start_rooms.iter() {
+            assert_eq!(query.matches(*seed, &mgr), start == name, "{name} {seed:#010X}");
+        }
+    }
+}
+
+#[test]
+fn test_parse_start_room() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query_string = "fc4 start:room_north4_1_tsuchi & any + ship + hole";
+    StructuralQuery::try_parse(query_string, &mgr).unwrap_or_else(|_| panic!("Failed to parse query string \"{query_string}\""));
+    assert!(StructuralQuery::try_parse("fc4 start:not_a_room", &mgr).is_err());
+}
+
+#[test]
+fn test_uses_unit() {
+    let mgr = FsAssetManager::init_unchecked().expect("Couldn't init asset manager");
+    let mut units = seam_units();
+    units[0].unit_folder_name = "room_north4_1_tsuchi".to_string();
+    units[1].unit_folder_name = "way4_tsuchi".to_string();
+    let layout = seam_layout(&units, None, Vec::new());
+    let uses = |prefix: &str| QueryKind::UsesUnit(prefix.to_string()).matches(&layout, &mgr);
+
+    assert!(uses("room_north4_1_tsuchi"));
+    assert!(uses("way4_tsuchi"));
+    assert!(uses("room_"));
+    assert!(uses("way"));
+    assert!(!uses("room_north4_1_tsuchi_extra"));
+    assert!(!uses("room_north4_2"));
+    assert!(!uses("tsuchi"));
+}
+
+#[test]
+fn test_parse_uses_unit() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query = StructuralQuery::try_parse("fc4 uses:room_north4_1_tsuchi & any + ship + hole", &mgr).unwrap();
+    let displayed = query.to_string();
+    assert!(displayed.contains("uses:room_north4_1_tsuchi"), "{displayed}");
+    // Prefixes are matched ignoring case.
+    let query = StructuralQuery::try_parse("fc4 uses:Room_North4", &mgr).unwrap();
+    assert!(query.to_string().contains("uses:room_north4"), "{query}");
+    assert!(StructuralQuery::try_parse("fc4 uses:not_a_room", &mgr).is_err());
+}
+
+#[test]
+fn test_ship_exit_same_room() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    // Finds the map unit containing a spawn object by position rather than by where it was
+    // placed, so this doesn't just repeat the query's own logic.
+    let unit_of = |layout: &Layout, pred: fn(&SpawnObject) -> bool| -> Vec<usize> {
+        layout
+            .get_spawn_objects()
+            .filter(|(so, _)| pred(so))
+            .filter_map(|(_, pos)| {
+                layout.map_units.iter().position(|unit| {
+                    let x = pos[0] / 170.0;
+                    let z = pos[2] / 170.0;
+                    x >= unit.x as f32
+                        && x < (unit.x + unit.unit.width as i32) as f32
+                        && z >= unit.z as f32
+                        && z < (unit.z + unit.unit.height as i32) as f32
+                })
+            })
+            .collect()
+    };
+
+    for sublevel in ["bk4", "scx7", "sh6"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel, &mgr).unwrap()).unwrap();
+        let any_exit = StructuralQuery::try_parse(&format!("{sublevel} ship_exit_same_room"), &mgr).unwrap();
+        let hole = StructuralQuery::try_parse(&format!("{sublevel} ship_exit_same_room:hole"), &mgr).unwrap();
+        let geyser = StructuralQuery::try_parse(&format!("{sublevel} ship_exit_same_room:geyser"), &mgr).unwrap();
+        for seed in 0..50 {
+            let layout = Layout::generate(seed, caveinfo);
+            let ship_units = unit_of(&layout, |so| matches!(so, SpawnObject::Ship));
+            let hole_units = unit_of(&layout, |so| matches!(so, SpawnObject::Hole(_)));
+            let geyser_units = unit_of(&layout, |so| matches!(so, SpawnObject::Geyser(_)));
+            let hole_same = hole_units.iter().any(|unit| ship_units.contains(unit));
+            let geyser_same = geyser_units.iter().any(|unit| ship_units.contains(unit));
+
+            assert_eq!(hole.matches(seed, &mgr), hole_same, "{sublevel} {seed:#010X}");
+            assert_eq!(geyser.matches(seed, &mgr), geyser_same, "{sublevel} {seed:#010X}");
+            assert_eq!(any_exit.matches(seed, &mgr), hole_same || geyser_same, "{sublevel} {seed:#010X}");
+        }
+    }
+}
+
+#[test]
+fn test_parse_ship_exit_same_room() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    for query_string in [
+        "bk4 ship_exit_same_room",
+        "bk4 ship_exit_same_room:hole",
+        "scx7 ship_exit_same_room:Geyser",
+    ] {
+        let query =
+            StructuralQuery::try_parse(query_string, &mgr).unwrap_or_else(|_| panic!("Failed to parse query string \"{query_string}\""));
+        let clause = query_string.split_once(' ').unwrap().1.to_ascii_lowercase();
+        let displayed = query.to_string();
+        assert!(displayed.contains(&clause), "{displayed}");
+    }
+    assert!(StructuralQuery::try_parse("bk4 ship_exit_same_room:pod", &mgr).is_err());
+}
+
+#[test]
+fn test_near_ship() {
+    let mgr = FsAssetManager::init_unchecked().expect("Couldn't init asset manager");
+    let kochappy = teki("Kochappy", 0);
+    // The ship and one teki share the first room's spawn point at (85, 85), 30 units apart,
+    // and another teki sits on the seam at (170, 85), 85 units from the ship.
+    let units = seam_units();
+    let layout = seam_layout(
+        &units,
+        Some(SpawnObject::Teki(&kochappy, Point([0.0, 0.0, 0.0]))),
+        vec![SpawnObject::Ship, SpawnObject::Teki(&kochappy, Point([30.0, 0.0, 0.0]))],
+    );
+    let near_ship = |entity: &str, radius, relationship, amount| {
+        QueryKind::NearShip {
+            entity: entity.into(),
+            radius,
+            relationship,
+            amount,
+        }
+        .matches(&layout, &mgr)
+    };
+
+    assert!(near_ship("kochappy", 20.0, Ordering::Equal, 0));
+    assert!(near_ship("kochappy", 30.0, Ordering::Equal, 1));
+    assert!(near_ship("kochappy", 80.0, Ordering::Equal, 1));
+    // The seam teki is held by both doors on its seam but is only one teki.
+    assert!(near_ship("kochappy", 90.0, Ordering::Equal, 2));
+    assert!(near_ship("kochappy", 10000.0, Ordering::Equal, 2));
+    // Without a comparison, a near_ship query checks for at least one.
+    assert!(near_ship("kochappy", 30.0, Ordering::Greater, 0));
+    assert!(!near_ship("kochappy", 20.0, Ordering::Greater, 0));
+    assert!(near_ship("ship", 0.0, Ordering::Equal, 1));
+    assert!(near_ship("hole", 10000.0, Ordering::Equal, 0));
+}
+
+#[test]
+fn test_parse_near_ship() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    for query_string in ["bk4 near_ship:hole within 400", "bk4 near_ship:hole within 400 < 1"] {
+        let query =
+            StructuralQuery::try_parse(query_string, &mgr).unwrap_or_else(|_| panic!("Failed to parse query string \"{query_string}\""));
+        let displayed = query.to_string();
+        assert!(displayed.contains(query_string.split_once(' ').unwrap().1), "{displayed}");
+    }
+    assert!(StructuralQuery::try_parse("bk4 near_ship:hole within", &mgr).is_err());
+    assert!(StructuralQuery::try_parse("bk4 near_ship:kochapy within 400", &mgr).is_err());
+    assert!(StructuralQuery::try_parse("bk4 near_ship:carrying:not_a_treasure within 400", &mgr).is_err());
+}
+
+#[test]
+fn test_alcoves_in_reading_order() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("hob4", &mgr).unwrap()).unwrap();
+    for seed in 0..20 {
+        let layout = Layout::generate(seed, caveinfo);
+        let alcoves = alcoves_in_reading_order(&layout);
+        let num_dead_ends = layout
+            .map_units
+            .iter()
+            .filter(|unit| unit.unit.room_type == RoomType::DeadEnd)
+            .count();
+        assert_eq!(alcoves.len(), num_dead_ends);
+        assert!(alcoves.iter().tuple_windows().all(|(a, b)| (a.z, a.x) < (b.z, b.x)), "{seed:#010X}");
+    }
+}
+
+#[test]
+fn test_alcove_spawn() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("hob4", &mgr).unwrap()).unwrap();
+    let cap_teki_names = caveinfo.cap_info.iter().map(|cap| cap.internal_name.clone()).unique().collect_vec();
+    for seed in 0..20 {
+        let layout = Layout::generate(seed, caveinfo);
+        let alcoves = alcoves_in_reading_order(&layout);
+        for name in cap_teki_names.iter() {
+            for (i, alcove) in alcoves.iter().enumerate() {
+                let query = StructuralQuery::try_parse(&format!("hob4 cap{}:{name}", i + 1), &mgr).unwrap();
+                let expected = alcove.spawn_objects().any(|so| so.name().eq_ignore_ascii_case(name));
+                assert_eq!(query.matches(seed, &mgr), expected, "cap{}:{name} {seed:#010X}", i + 1);
+            }
+            // Alcoves past the last one never match.
+            let past_end = StructuralQuery::try_parse(&format!("hob4 cap{}:{name}", alcoves.len() + 1), &mgr).unwrap();
+            assert!(!past_end.matches(seed, &mgr));
+        }
+    }
+}
+
+#[test]
+fn test_parse_alcove_spawn() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query = StructuralQuery::try_parse("hob4 cap2:hole & cap1:carrying:any", &mgr).unwrap();
+    let displayed = query.to_string();
+    assert!(displayed.contains("cap2:hole"), "{displayed}");
+    assert!(StructuralQuery::try_parse("hob4 cap0:hole", &mgr).is_err());
+}
+
+#[test]
+fn test_count_matches_in_range() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query = StructuralQuery::try_parse("scx7 minihoudai < 2", &mgr).unwrap();
+    let start = 0x1234ABCD;
+    let expected = PikminRng::new(start).take(500).filter(|seed| query.matches(*seed, &mgr)).count() as u64;
+    assert_eq!(query.count_matches_in_range(&mgr, start, 500), expected);
+    assert_eq!(query.count_matches_in_range(&mgr, start, 0), 0);
+}
+
+#[test]
+fn test_gate_hp() {
+    let mgr = FsAssetManager::init_unchecked().expect("Couldn't init asset manager");
+    let units = seam_units();
+    let gate = gate(300.0);
+    let gate_hp = |layout: &Layout, relationship, req_hp| QueryKind::GateHp { relationship, req_hp }.matches(layout, &mgr);
+
+    // The gate is shared by the doors on both sides of its seam, but only exists once.
+    let gated = seam_layout(&units, Some(SpawnObject::Gate(&gate, 0)), Vec::new());
+    assert!(gate_hp(&gated, Ordering::Equal, 300.0));
+    assert!(gate_hp(&gated, Ordering::Less, 301.0));
+    assert!(!gate_hp(&gated, Ordering::Greater, 300.0));
+
+    let gateless = seam_layout(&units, None, Vec::new());
+    assert!(gate_hp(&gateless, Ordering::Equal, 0.0));
+}
+
+#[test]
+fn test_petals() {
+    let mgr = FsAssetManager::init_unchecked().expect("Couldn't init asset manager");
+    let pom = |internal_name: &str| CapInfo {
+        game: "pikmin2".to_string(),
+        internal_name: internal_name.to_string(),
+        carrying: None,
+        minimum_amount: 1,
+        filler_distribution_weight: 0,
+        group: 1,
+        spawn_method: None,
+    };
+    let (black_pom, rand_pom) = (pom("BlackPom"), pom("RandPom"));
+    let petals = |layout: &Layout, relationship, amount| QueryKind::Petals { relationship, amount }.matches(layout, &mgr);
+    let units = seam_units();
+
+    // A Violet Candypop Bud has 5 petals and a Queen Candypop Bud has 1.
+    let layout = seam_layout(
+        &units,
+        None,
+        vec![SpawnObject::CapTeki(&black_pom, 1), SpawnObject::CapTeki(&rand_pom, 1)],
+    );
+    assert!(petals(&layout, Ordering::Equal, 6));
+    assert!(petals(&layout, Ordering::Less, 7));
+    assert!(!petals(&layout, Ordering::Greater, 6));
+
+    // Every bud in a group counts.
+    let layout = seam_layout(&units, None, vec![SpawnObject::CapTeki(&black_pom, 3)]);
+    assert!(petals(&layout, Ordering::Equal, 15));
+
+    let layout = seam_layout(&units, None, vec![SpawnObject::Ship]);
+    assert!(petals(&layout, Ordering::Equal, 0));
+}
+
+#[test]
+fn test_isolated_units() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let mut num_branched = 0;
+    for sublevel_name in ["fc4", "scx7", "bk4"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel_name, &mgr).unwrap()).unwrap();
+        for seed in 0..20 {
+            let layout = Layout::generate(seed, caveinfo);
+            let connections = door_connections(&layout);
+            let main_path = main_path_units(&layout, &connections);
+            let isolated = isolated_units(&layout);
+
+            let ship_unit = layout
+                .map_units
+                .iter()
+                .position(|unit| unit.spawn_objects().any(|so| matches!(so, SpawnObject::Ship)))
+                .unwrap();
+            assert!(main_path[ship_unit], "{sublevel_name} {seed:#010X}");
+            for (on_path, isolated) in main_path.iter().zip(isolated.iter()) {
+                assert!(!(*on_path && *isolated), "{sublevel_name} {seed:#010X}");
+            }
+
+            // Without any loops, every unit off the main path is at the end of a branch.
+            if connections.len() == layout.map_units.len() - 1 {
+                assert!(
+                    main_path.iter().zip(isolated.iter()).all(|(on_path, isolated)| on_path != isolated),
+                    "{sublevel_name} {seed:#010X}"
+                );
+            }
+            num_branched += isolated.contains(&true) as u32;
+        }
+    }
+    assert!(num_branched > 0, "expected some layouts with dead-end branches");
+}
+
+#[test]
+fn test_isolated_treasure() {
+    let mgr = FsAssetManager::init_unchecked().expect("Couldn't init asset manager");
+    let treasure = ItemInfo {
+        game: "pikmin2".to_string(),
+        internal_name: "ahiru_head".to_string(),
+        min_amount: 1,
+        filler_distribution_weight: 0,
+    };
+    let carrier = TekiInfo {
+        carrying: Some("bane_red".to_string()),
+        ..teki("Kochappy", 0)
+    };
+
+    // The ship's room has the exit room to its east and a hallway to its south, which
+    // leads on to a dead end. The main path is just the two rooms, so the hallway and the
+    // dead end are a branch.
+    let units = [
+        test_unit(RoomType::Room, &[1, 2]),
+        test_unit(RoomType::Room, &[3]),
+        test_unit(RoomType::Hallway, &[0, 2]),
+        test_unit(RoomType::DeadEnd, &[0]),
+    ];
+    let mut map_units = vec![
+        PlacedMapUnit::new(&units[0], 0, 0),
+        PlacedMapUnit::new(&units[1], 1, 0),
+        PlacedMapUnit::new(&units[2], 0, 1),
+        PlacedMapUnit::new(&units[3], 0, 2),
+    ];
+    connect_doors(&map_units, (0, 0), (1, 0));
+    connect_doors(&map_units, (0, 1), (2, 0));
+    connect_doors(&map_units, (2, 1), (3, 0));
+    map_units[0].spawnpoints[0].contains = vec![SpawnObject::Ship, SpawnObject::Item(&treasure)];
+    map_units[1].spawnpoints[0].contains = vec![SpawnObject::Hole(false), SpawnObject::Item(&treasure)];
+    map_units[2].spawnpoints[0].contains = vec![SpawnObject::Teki(&carrier, Point([0.0, 0.0, 0.0]))];
+    map_units[3].spawnpoints[0].contains = vec![SpawnObject::Item(&treasure)];
+    let layout = Layout::from_placed_units(test_sublevel(), map_units);
+
+    assert_eq!(isolated_units(&layout), [false, false, true, true]);
+    let isolated_treasures = |relationship, amount| QueryKind::IsolatedTreasures { relationship, amount }.matches(&layout, &mgr);
+    // The carried treasure in the hallway and the loose one in the dead end.
+    assert!(isolated_treasures(Ordering::Equal, 2));
+    assert!(!isolated_treasures(Ordering::Greater, 2));
+}
+
+#[test]
+fn test_tour_len_grows_with_treasures() {
+    let ship = Point([0.0, 0.0, 0.0]);
+    let treasures = [
+        Point([300.0, 0.0, 100.0]),
+        Point([-200.0, 0.0, 50.0]),
+        Point([0.0, 0.0, -400.0]),
+        Point([600.0, 0.0, 600.0]),
+    ];
+    assert_eq!(nearest_neighbor_tour_len(ship, &[]), 0.0);
+    let lengths = (0..=treasures.len())
+        .map(|n| nearest_neighbor_tour_len(ship, &treasures[..n]))
+        .collect_vec();
+    assert!(lengths.windows(2).all(|w| w[0] < w[1]), "{lengths:?}");
+}
+
+#[test]
+fn test_tour_len() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let seed = 0x1234ABCD;
+    let short = StructuralQuery::try_parse("fc4 tour_len < 1", &mgr).unwrap();
+    let long = StructuralQuery::try_parse("fc4 tour_len < 1000000", &mgr).unwrap();
+    assert!(!short.matches(seed, &mgr));
+    assert!(long.matches(seed, &mgr));
+    assert!(StructuralQuery::try_parse("fc4 tour_len > 1", &mgr).unwrap().matches(seed, &mgr));
+
+    // A larger threshold can only let more layouts through.
+    let matching_thresholds = [1000, 3000, 6000, 10000, 20000].map(|n| {
+        StructuralQuery::try_parse(&format!("fc4 tour_len < {n}"), &mgr)
+            .unwrap()
+            .matches(seed, &mgr)
+    });
+    assert!(matching_thresholds.windows(2).all(|w| w[0] <= w[1]), "{matching_thresholds:?}");
+}
+
+#[test]
+fn test_spawn_probability() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let sublevel = Sublevel::try_from_str("bk4", &mgr).unwrap();
+    let caveinfo = mgr.load_caveinfo(&sublevel).unwrap();
+
+    let ship = spawn_probability(&mgr, &sublevel, "ship", 50).unwrap();
+    assert_eq!(ship.num_matched, 50);
+    assert_eq!(ship.estimate(), 1.0);
+
+    let absent_teki = mgr
+        .all_teki(Some("pikmin2"))
+        .unwrap()
+        .into_iter()
+        .find(|name| {
+            !caveinfo.teki_info.iter().any(|t| t.internal_name.eq_ignore_ascii_case(name))
+                && !caveinfo.cap_info.iter().any(|c| c.internal_name.eq_ignore_ascii_case(name))
+        })
+        .unwrap();
+    let absent = spawn_probability(&mgr, &sublevel, &absent_teki, 50).unwrap();
+    assert_eq!(absent.num_matched, 0, "{absent_teki}");
+
+    let sampled = SpawnProbability {
+        num_matched: 37,
+        samples: 100,
+    };
+    for probability in [ship, absent, sampled] {
+        let (low, high) = probability.confidence_interval();
+        assert!(
+            0.0 <= low && low <= probability.estimate() && probability.estimate() <= high && high <= 1.0,
+            "{probability:?}"
+        );
+    }
+
+    // More samples with the same proportion give a narrower interval.
+    let wide = sampled.confidence_interval();
+    let narrow = SpawnProbability {
+        num_matched: 3700,
+        samples: 10_000,
+    }
+    .confidence_interval();
+    assert!(narrow.1 - narrow.0 < wide.1 - wide.0);
+}
+
+#[test]
+fn test_alcove_treasure() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("fc4", &mgr).unwrap()).unwrap();
+    let in_alcove = StructuralQuery::try_parse("fc4 alcove_treasure > 0", &mgr).unwrap();
+    let none_in_alcove = StructuralQuery::try_parse("fc4 alcove_treasure = 0", &mgr).unwrap();
+    let room_paths: Vec<StructuralQuery> = caveinfo
+        .item_info
+        .iter()
+        .map(|item| StructuralQuery::try_parse(&format!("fc4 alcove + {}", item.internal_name), &mgr).unwrap())
+        .collect();
+    for seed in 0..100 {
+        let expected = room_paths.iter().any(|q| q.matches(seed, &mgr));
+        assert_eq!(in_alcove.matches(seed, &mgr), expected, "{seed:#010X}");
+        assert_eq!(none_in_alcove.matches(seed, &mgr), !expected, "{seed:#010X}");
+    }
+}
+
+#[test]
+fn test_door_count() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("bk4", &mgr).unwrap()).unwrap();
+    let four_door_hole = StructuralQuery::try_parse("bk4 room#4 + hole", &mgr).unwrap();
+    let four_door_rooms = StructuralQuery::try_parse("bk4 room#4 > 1", &mgr).unwrap();
+    for seed in 0..100 {
+        let layout = Layout::generate(seed, caveinfo);
+        let hole_unit = layout
+            .map_units
+            .iter()
+            .find(|unit| unit.spawn_objects().any(|so| matches!(so, SpawnObject::Hole(_))))
+            .unwrap();
+        let expected = hole_unit.unit.room_type == RoomType::Room && hole_unit.unit.num_doors == 4;
+        assert_eq!(four_door_hole.matches(seed, &mgr), expected, "{seed:#010X}");
+
+        let num_four_door_rooms = layout
+            .map_units
+            .iter()
+            .filter(|unit| unit.unit.room_type == RoomType::Room && unit.unit.num_doors == 4)
+            .count();
+        assert_eq!(four_door_rooms.matches(seed, &mgr), num_four_door_rooms > 1, "{seed:#010X}");
+    }
+}
+
+#[test]
+fn test_parse_door_count() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query = StructuralQuery::try_parse("sh6 any#1 > 2 & alcove#1 + hole -> hallway#2", &mgr).unwrap();
+    let displayed = query.to_string();
+    assert!(displayed.contains("any#1 > 2"), "{displayed}");
+    assert!(displayed.contains("cap#1 + hole -> hallway#2"), "{displayed}");
+    assert!(StructuralQuery::try_parse("sh6 notaroom#4 > 0", &mgr).is_err());
+}
+
+#[test]
+fn test_seam_spawn() {
+    let mgr = FsAssetManager::init_unchecked().expect("Couldn't init asset manager");
+    let gate = gate(300.0);
+    let seam_spawn = |layout: &Layout, room_type| {
+        QueryKind::SeamSpawn {
+            room_type,
+            entity: "gate".into(),
+        }
+        .matches(layout, &mgr)
+    };
+
+    // A gate between two rooms is only in a room seam.
+    let units = seam_units();
+    let layout = seam_layout(&units, Some(SpawnObject::Gate(&gate, 0)), Vec::new());
+    assert!(seam_spawn(&layout, RoomType::Room));
+    assert!(!seam_spawn(&layout, RoomType::Hallway));
+
+    // A gate between a room and a hallway is in both.
+    let mut units = seam_units();
+    units[1].room_type = RoomType::Hallway;
+    let layout = seam_layout(&units, Some(SpawnObject::Gate(&gate, 0)), Vec::new());
+    assert!(seam_spawn(&layout, RoomType::Room));
+    assert!(seam_spawn(&layout, RoomType::Hallway));
+
+    // Gates elsewhere don't count.
+    let layout = seam_layout(&units, None, vec![SpawnObject::Gate(&gate, 0)]);
+    assert!(!seam_spawn(&layout, RoomType::Room));
+    assert!(!seam_spawn(&layout, RoomType::Hallway));
+}
+
+#[test]
+fn test_parse_seam_spawn() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query = StructuralQuery::try_parse("sh6 seam:hall:gate & seam:room: gate", &mgr).unwrap();
+    let displayed = query.to_string();
+    assert!(displayed.contains("seam:hallway:gate"), "{displayed}");
+    assert!(displayed.contains("seam:room:gate"), "{displayed}");
+    assert!(StructuralQuery::try_parse("sh6 seam:nowhere:gate", &mgr).is_err());
+}
+
+#[test]
+fn test_roaming_chokepoint() {
+    let mgr = FsAssetManager::init_unchecked().expect("Couldn't init asset manager");
+    let (roaming, stationary) = (teki("minihoudai", 0), teki("kochappy", 0));
+    let [room, mut hallway] = seam_units();
+    hallway.room_type = RoomType::Hallway;
+    let mut wide_hallway = hallway.clone();
+    (wide_hallway.width, wide_hallway.height) = (2, 2);
+
+    // The teki spawns in the center of the room at (85, 85), and the hallway is placed
+    // `hallway_x` cells to the right of the room.
+    let at_chokepoint = |teki: &TekiInfo, hallway: &CaveUnit, hallway_x: i32| {
+        let mut room = PlacedMapUnit::new(&room, 0, 0);
+        room.spawnpoints[0].contains = vec![SpawnObject::Teki(teki, Point([0.0, 0.0, 0.0]))];
+        let map_units = vec![room, PlacedMapUnit::new(hallway, hallway_x, 0)];
+        let layout = Layout::from_placed_units(test_sublevel(), map_units);
+        let present = QueryKind::RoamingChokepoint(true).matches(&layout, &mgr);
+        assert_ne!(present, QueryKind::RoamingChokepoint(false).matches(&layout, &mgr));
+        present
+    };
+
+    // The teki is 85 units from a hallway right next to the room, but 255 units from one
+    // with a cell in between, more than the 170 unit margin.
+    assert!(at_chokepoint(&roaming, &hallway, 1));
+    assert!(!at_chokepoint(&roaming, &hallway, 2));
+    // Only roaming teki and one-wide hallways count.
+    assert!(!at_chokepoint(&stationary, &hallway, 1));
+    assert!(!at_chokepoint(&roaming, &wide_hallway, 1));
+}
+
+#[test]
+fn test_parse_roaming_chokepoint() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    for query_string in ["scx7 roaming chokepoint", "scx7 no roaming chokepoint"] {
+        let query = StructuralQuery::try_parse(query_string, &mgr).unwrap();
+        let displayed = query.to_string();
+        assert!(displayed.contains(query_string.split_once(' ').unwrap().1), "{displayed}");
+    }
+}
+
+#[test]
+fn test_hazard() {
+    let mgr = FsAssetManager::init_unchecked().expect("Couldn't init asset manager");
+    let (fire, electric, kochappy) = (teki("Hiba", 0), teki("elechiba", 0), teki("kochappy", 0));
+    let hazards = |layout: &Layout| {
+        ["fire", "electric", "poison", "water"]
+            .into_iter()
+            .filter(|hazard| QueryKind::Hazard(hazard.to_string()).matches(layout, &mgr))
+            .collect_vec()
+    };
+
+    let units = seam_units();
+    let layout = seam_layout(&units, None, vec![SpawnObject::Teki(&kochappy, Point([0.0, 0.0, 0.0]))]);
+    assert!(hazards(&layout).is_empty());
+
+    // Hazard teki names are matched regardless of case, and seam teki count too.
+    let layout = seam_layout(
+        &units,
+        Some(SpawnObject::Teki(&electric, Point([0.0, 0.0, 0.0]))),
+        vec![SpawnObject::Teki(&fire, Point([0.0, 0.0, 0.0]))],
+    );
+    assert_eq!(hazards(&layout), ["fire", "electric"]);
+
+    // Any waterbox counts as water.
+    let mut units = seam_units();
+    units[1].waterboxes.push(Waterbox {
+        p1: Point([-50.0, -20.0, -50.0]),
+        p2: Point([50.0, 0.0, 50.0]),
+    });
+    let layout = seam_layout(&units, None, Vec::new());
+    assert_eq!(hazards(&layout), ["water"]);
+}
+
+#[test]
+fn test_parse_hazard() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query = StructuralQuery::try_parse("scx7 hazard:Electric & hazard:water", &mgr).unwrap();
+    let displayed = query.to_string();
+    assert!(displayed.contains("hazard:electric"), "{displayed}");
+    assert!(displayed.contains("hazard:water"), "{displayed}");
+    assert!(StructuralQuery::try_parse("scx7 hazard:lava", &mgr).is_err());
+}
+
+#[test]
+fn test_group_matches_only_given_group() {
+    let group_0 = teki("Chappy", 0);
+    let group_1 = teki("Chappy", 1);
+
+    let matcher: EntityMatcher = "group:1:chappy".into();
+    assert!(matcher.matches(&SpawnObject::Teki(&group_1, Point([0.0, 0.0, 0.0]))));
+    assert!(!matcher.matches(&SpawnObject::Teki(&group_0, Point([0.0, 0.0, 0.0]))));
+
+    let any: EntityMatcher = "group:0:any".into();
+    assert!(any.matches(&SpawnObject::Teki(&group_0, Point([0.0, 0.0, 0.0]))));
+    assert!(!any.matches(&SpawnObject::Teki(&group_1, Point([0.0, 0.0, 0.0]))));
+}
+
+#[test]
+fn test_plant_matches_species_regardless_of_group() {
+    let plant_group_clover = teki("Clover", 6);
+    let filler_clover = teki("Clover", 0);
+    let figwort = teki("Ooinu_l", 6);
+    let romhack_plant = teki("Chappy", 6);
+    let chappy = teki("Chappy", 0);
+    let so = |info| SpawnObject::Teki(info, Point([0.0, 0.0, 0.0]));
+
+    let any: EntityMatcher = "plant".into();
+    for plant in [&plant_group_clover, &filler_clover, &figwort, &romhack_plant] {
+        assert!(any.matches(&so(plant)), "{}", plant.internal_name);
+    }
+    assert!(!any.matches(&so(&chappy)));
+
+    let clover: EntityMatcher = "plant:clover".into();
+    assert!(clover.matches(&so(&plant_group_clover)));
+    assert!(clover.matches(&so(&filler_clover)));
+    assert!(!clover.matches(&so(&figwort)));
+
+    let figworts: EntityMatcher = "plant:figwort".into();
+    assert!(figworts.matches(&so(&figwort)));
+    assert!(!figworts.matches(&so(&plant_group_clover)));
+
+    let plant_group: EntityMatcher = "group:6:plant".into();
+    assert!(plant_group.matches(&so(&plant_group_clover)));
+    assert!(plant_group.matches(&so(&romhack_plant)));
+    assert!(!plant_group.matches(&so(&filler_clover)));
+}
+
+#[test]
+fn test_plant_group_count_matches_is_plant() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let plant_group: EntityMatcher = "group:6:plant".into();
+    let any: EntityMatcher = "plant".into();
+    for sublevel in ["ec1", "hob1", "fc1", "wfg1", "sh1"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel, &mgr).unwrap()).unwrap();
+        for seed in 0..50 {
+            let layout = Layout::generate(seed, caveinfo);
+            let plants = layout.get_spawn_objects().filter(|(so, _)| so.is_plant()).count();
+            assert_eq!(layout.get_spawn_objects().filter(|(so, _)| plant_group.matches(so)).count(), plants);
+            assert!(layout.get_spawn_objects().filter(|(so, _)| any.matches(so)).count() >= plants);
+        }
+    }
+}
+
+#[test]
+fn test_parse_plant() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query_strings = [
+        "fc1 plant > 10",
+        "fc1 plant:clover = 0",
+        "fc1 plant:Figwort > 2",
+        "bk4 group:6:plant = 0",
+    ];
+    for query_string in query_strings {
+        let query = StructuralQuery::try_parse(query_string, &mgr).unwrap_or_else(|e| panic!("{query_string}: {e:?}"));
+        let reparsed = StructuralQuery::try_parse(&query.to_string(), &mgr).unwrap();
+        assert_eq!(query.to_string(), reparsed.to_string());
+    }
+    assert!(StructuralQuery::try_parse("fc1 plant:bouquet > 0", &mgr).is_err());
+}
+
+#[test]
+fn test_group_counts_add_up_to_name_count() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+
+    // Find a sublevel where the same teki is listed in more than one spawn group.
+    let (caveinfo, name, groups) = ["ec", "scx", "fc", "hob", "wfg", "bk", "sh", "cos", "gk", "sr", "smc"]
+        .into_iter()
+        .flat_map(|cave| mgr.caveinfos_from_cave(cave).unwrap())
+        .find_map(|caveinfo| {
+            caveinfo.teki_info.iter().find_map(|teki| {
+                let groups: Vec<u32> = caveinfo
+                    .teki_info
+                    .iter()
+                    .filter(|other| other.internal_name.eq_ignore_ascii_case(&teki.internal_name))
+                    .map(|other| other.group)
+                    .unique()
+                    .collect();
+                (groups.len() > 1).then(|| (caveinfo, teki.internal_name.clone(), groups))
+            })
+        })
+        .expect("No sublevel lists a teki in more than one group");
+
+    let name_matcher = EntityMatcher::from(name.as_str());
+    let group_matchers: Vec<EntityMatcher> = groups.iter().map(|g| format!("group:{g}:{name}").as_str().into()).collect();
+    for seed in 0..50 {
+        let layout = Layout::generate(seed, caveinfo);
+        let count = |matcher: &EntityMatcher| layout.get_spawn_objects().filter(|(so, _)| matcher.matches(so)).count();
+        let group_total: usize = group_matchers.iter().map(count).sum();
+        assert_eq!(group_total, count(&name_matcher), "{} {name} seed {seed:#010X}", caveinfo.name());
+    }
+}
+
+#[test]
+fn test_parse_group() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query_strings = ["fc3 group:1:kochappy > 0", "scx7 group:0:any = 0", "bk4 group:8:Chappy < 2"];
+    for s in query_strings {
+        StructuralQuery::try_parse(s, &mgr).unwrap_or_else(|_| panic!("Failed to parse query string \"{s}\""));
+    }
+    assert!(StructuralQuery::try_parse("fc3 group:1:notateki > 0", &mgr).is_err());
+    assert!(StructuralQuery::try_parse("fc3 group:x:kochappy > 0", &mgr).is_err());
+}
+
+#[test]
+fn test_without_blue() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+
+    // Sublevels without any water never need Blue Pikmin.
+    let dry_sublevel = ["ec1", "scx1", "fc1", "hob1", "bk1", "sh1", "cos1", "gk1"]
+        .into_iter()
+        .find(|s| {
+            let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(s, &mgr).unwrap()).unwrap();
+            caveinfo.cave_units.iter().all(|unit| unit.waterboxes.is_empty())
+        })
+        .expect("No sublevel without water");
+    let query = StructuralQuery::try_parse(&format!("{dry_sublevel} without:blue"), &mgr).unwrap();
+    for seed in 0..20 {
+        assert!(query.matches(seed, &mgr), "{dry_sublevel} {seed:#010X}");
+    }
+
+    // Submerged Castle is full of water, so at least some layouts should need Blue Pikmin.
+    let mut num_needing_blue = 0;
+    for sublevel in ["sr1", "sr2", "sr3", "sr4"] {
+        let query = StructuralQuery::try_parse(&format!("{sublevel} without:blue"), &mgr).unwrap();
+        num_needing_blue += (0..50).filter(|seed| !query.matches(*seed, &mgr)).count();
+    }
+    assert!(num_needing_blue > 0);
+}
+
+#[test]
+fn test_parse_without() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    for s in ["fc3 without:blue", "sr2 Without:Blue", "scx7 minihoudai < 2 & without:blue"] {
+        StructuralQuery::try_parse(s, &mgr).unwrap_or_else(|_| panic!("Failed to parse query string \"{s}\""));
+    }
+    assert!(StructuralQuery::try_parse("fc3 without:red", &mgr).is_err());
+}
+
+#[test]
+fn test_carry_with() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let mut saw_heavy_treasure = false;
+    for sublevel in ["bk4", "sh6"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel, &mgr).unwrap()).unwrap();
+        for seed in 0..30 {
+            let layout = Layout::generate(seed, caveinfo);
+            let heaviest = layout
+                .get_spawn_objects()
+                .filter_map(|(so, _)| match so {
+                    SpawnObject::Item(info) => Some(mgr.get_treasure_info(&info.game, &info.internal_name).unwrap()),
+                    SpawnObject::Teki(
+                        TekiInfo {
+                            carrying: Some(name),
+                            game,
+                            ..
+                        },
+                        _,
+                    ) => Some(mgr.get_treasure_info(game, name).unwrap()),
+                    _ => None,
+                })
+                .map(|treasure| treasure.min_carry)
+                .max()
+                .unwrap_or(0);
+            saw_heavy_treasure |= heaviest > 1;
+
+            let enough = StructuralQuery::try_parse(&format!("{sublevel} carry_with<={heaviest}"), &mgr).unwrap();
+            assert!(enough.matches(seed, &mgr), "{sublevel} {seed:#010X}");
+            if heaviest > 0 {
+                let too_few = StructuralQuery::try_parse(&format!("{sublevel} carry_with<={}", heaviest - 1), &mgr).unwrap();
+                assert!(!too_few.matches(seed, &mgr), "{sublevel} {seed:#010X}");
+            }
+        }
+    }
+    assert!(saw_heavy_treasure);
+}
+
+#[test]
+fn test_parse_carry_with() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query = StructuralQuery::try_parse("bk4 carry_with<=10", &mgr).unwrap();
+    assert!(query.to_string().contains("carry_with<=10"), "{query}");
+    assert!(StructuralQuery::try_parse("bk4 CARRY_WITH <= 5", &mgr).is_ok());
+    assert!(StructuralQuery::try_parse("bk4 carry_with<10", &mgr).is_err());
+    assert!(StructuralQuery::try_parse("bk4 carry_with<=", &mgr).is_err());
+}
+
+#[test]
+fn test_bomb_rocks() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let is_bomb = |name: &str| name.eq_ignore_ascii_case("bomb");
+    // Any story mode floor that can have bomb rocks will do.
+    let caveinfo = mgr
+        .cave_cfg
+        .iter()
+        .filter(|cfg| cfg.game == "pikmin2" && !cfg.is_challenge_mode)
+        .flat_map(|cfg| load_cave_floors(&mgr, cfg))
+        .find(|caveinfo| {
+            caveinfo.teki_info.iter().any(|teki| is_bomb(&teki.internal_name))
+                || caveinfo.cap_info.iter().any(|cap| is_bomb(&cap.internal_name))
+        })
+        .expect("No sublevel with bomb rocks");
+    let sublevel = Sublevel::from_cfg(&caveinfo.cave_cfg, caveinfo.floor_num as usize + 1).short_name();
+
+    let mut saw_bombs = false;
+    for seed in 0..100 {
+        let layout = Layout::generate(seed, caveinfo);
+        let num_bombs = layout.get_spawn_objects().filter(|(so, _)| is_bomb(so.name())).count();
+        let num_gates = layout
+            .get_spawn_objects()
+            .filter(|(so, _)| matches!(so, SpawnObject::Gate(..)))
+            .count();
+        saw_bombs |= num_bombs > 0;
+
+        let bombs = StructuralQuery::try_parse(&format!("{sublevel} bombs = {num_bombs}"), &mgr).unwrap();
+        assert!(bombs.matches(seed, &mgr), "{sublevel} {seed:#010X}");
+        let reachable_at_most = StructuralQuery::try_parse(&format!("{sublevel} reachable_bombs < {}", num_bombs + 1), &mgr).unwrap();
+        assert!(reachable_at_most.matches(seed, &mgr), "{sublevel} {seed:#010X}");
+        if num_gates == 0 {
+            let reachable = StructuralQuery::try_parse(&format!("{sublevel} reachable_bombs = {num_bombs}"), &mgr).unwrap();
+            assert!(reachable.matches(seed, &mgr), "{sublevel} {seed:#010X}");
+        }
+    }
+    assert!(saw_bombs, "{sublevel}");
+
+    let query = StructuralQuery::try_parse(&format!("{sublevel} reachable_bombs > 0"), &mgr).unwrap();
+    assert!(query.to_string().contains("reachable_bombs > 0"), "{query}");
+}
+
+#[test]
+fn test_shortest_path() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let parse = |query: &str| StructuralQuery::try_parse(query, &mgr).unwrap_or_else(|e| panic!("{query}\n{e}"));
+    for sublevel in ["scx7", "bk4", "sh6", "fc3"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel, &mgr).unwrap()).unwrap();
+        let all_clean = parse(&format!("{sublevel} path(ship -> exit): !hard_teki"));
+        let any_clean = parse(&format!("{sublevel} any_path(ship -> exit): !hard_teki"));
+        let all_ship = parse(&format!("{sublevel} path(ship -> exit): ship"));
+        let any_ship = parse(&format!("{sublevel} any_path(ship -> exit): ship"));
+        let same_room = parse(&format!("{sublevel} ship_exit_same_room"));
+        let to_self = parse(&format!("{sublevel} path(ship -> ship): ship = 1"));
+        let at_most_one_ship = parse(&format!("{sublevel} path(ship -> exit): ship < 2"));
+
+        for seed in 0..30 {
+            let layout = Layout::generate(seed, caveinfo);
+            let connections = door_connections(&layout);
+            let main_path = main_path_units(&layout, &connections);
+            let main_path_clean = layout
+                .map_units
+                .iter()
+                .zip(main_path.iter())
+                .filter(|(_, on_path)| **on_path)
+                .all(|(unit, _)| {
+                    !unit
+                        .spawn_objects()
+                        .any(|so| matches!(so, SpawnObject::Teki(TekiInfo { group: 1, .. }, _)))
+                });
+
+            // The main path is one of the shortest routes, so it passes whenever every route
+            // does, and some route passes whenever it does.
+            let all_clean_matches = all_clean.matches(seed, &mgr);
+            let any_clean_matches = any_clean.matches(seed, &mgr);
+            assert!(!all_clean_matches || main_path_clean, "{sublevel} {seed:#010X}");
+            assert!(!main_path_clean || any_clean_matches, "{sublevel} {seed:#010X}");
+            assert!(!all_clean_matches || any_clean_matches, "{sublevel} {seed:#010X}");
+
+            // Every unit on the route only contains the ship if the route is a single unit.
+            let same_room_matches = same_room.matches(seed, &mgr);
+            assert_eq!(all_ship.matches(seed, &mgr), same_room_matches, "{sublevel} {seed:#010X}");
+            assert_eq!(any_ship.matches(seed, &mgr), same_room_matches, "{sublevel} {seed:#010X}");
+
+            assert!(to_self.matches(seed, &mgr), "{sublevel} {seed:#010X}");
+            assert!(at_most_one_ship.matches(seed, &mgr), "{sublevel} {seed:#010X}");
+        }
+    }
+}
+
+#[test]
+fn test_shortest_path_missing_endpoint() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    // Story mode sublevels before the last never have a geyser.
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("scx1", &mgr).unwrap()).unwrap();
+    let to_geyser = StructuralQuery::try_parse("scx1 any_path(ship -> geyser): !hard_teki", &mgr).unwrap();
+    let to_hole = StructuralQuery::try_parse("scx1 any_path(ship -> hole): any < 1000", &mgr).unwrap();
+    for seed in 0..10 {
+        let layout = Layout::generate(seed, caveinfo);
+        assert!(!layout.get_spawn_objects().any(|(so, _)| matches!(so, SpawnObject::Geyser(_))));
+        assert!(!to_geyser.matches(seed, &mgr), "{seed:#010X}");
+        assert!(to_hole.matches(seed, &mgr), "{seed:#010X}");
+    }
+}
+
+#[test]
+fn test_parse_shortest_path() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query = StructuralQuery::try_parse("scx7 path(ship -> exit): !hard_teki", &mgr).unwrap();
+    assert!(query.to_string().contains("path(ship -> exit): !group:1:any"), "{query}");
+    let query = StructuralQuery::try_parse("scx7 ANY_PATH(ship->hole):minihoudai < 2", &mgr).unwrap();
+    assert!(query.to_string().contains("any_path(ship -> hole): minihoudai < 2"), "{query}");
+    let query = StructuralQuery::try_parse("scx7 path(hole -> carrying:any): any & scx7 hole < 2", &mgr).unwrap();
+    assert!(query.to_string().contains("path(hole -> carrying:any): any"), "{query}");
+
+    assert!(StructuralQuery::try_parse("scx7 path(ship exit): !hard_teki", &mgr).is_err());
+    assert!(StructuralQuery::try_parse("scx7 path(ship -> exit)", &mgr).is_err());
+    assert!(StructuralQuery::try_parse("scx7 path(ship -> exit): hard_teki <", &mgr).is_err());
+}
+
+#[test]
+fn test_linear() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let parse = |query: &str| StructuralQuery::try_parse(query, &mgr).unwrap_or_else(|e| panic!("{query}\n{e}"));
+    let (mut saw_linear, mut saw_branchy) = (false, false);
+    for sublevel in ["ec1", "hob1", "scx7", "bk4"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel, &mgr).unwrap()).unwrap();
+        let linear = parse(&format!("{sublevel} linear"));
+        let one_branch = parse(&format!("{sublevel} linear:1"));
+        let any_branches = parse(&format!("{sublevel} linear:1000"));
+        for seed in 0..30 {
+            let layout = Layout::generate(seed, caveinfo);
+            let connections = door_connections(&layout);
+            let main_path = main_path_units(&layout, &connections);
+            // Clearly linear: everything off the route from the ship to the exit is an alcove.
+            let only_alcoves_off_path = layout
+                .map_units
+                .iter()
+                .zip(main_path.iter())
+                .all(|(unit, on_path)| *on_path || unit.unit.room_type == RoomType::DeadEnd);
+
+            let linear_matches = linear.matches(seed, &mgr);
+            assert_eq!(linear_matches, only_alcoves_off_path, "{sublevel} {seed:#010X}");
+            assert!(!linear_matches || one_branch.matches(seed, &mgr), "{sublevel} {seed:#010X}");
+            assert!(any_branches.matches(seed, &mgr), "{sublevel} {seed:#010X}");
+            saw_linear |= linear_matches;
+            saw_branchy |= !one_branch.matches(seed, &mgr);
+        }
+    }
+    assert!(saw_linear);
+    assert!(saw_branchy);
+}
+
+#[test]
+fn test_parse_linear() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query = StructuralQuery::try_parse("scx7 LINEAR", &mgr).unwrap();
+    assert!(query.to_string().ends_with("linear"), "{query}");
+    let query = StructuralQuery::try_parse("scx7 linear:2 & bk4 linear:0", &mgr).unwrap();
+    assert!(query.to_string().contains("linear:2"), "{query}");
+    assert!(query.to_string().ends_with("linear"), "{query}");
+    assert!(StructuralQuery::try_parse("scx7 linear:", &mgr).is_err());
+    assert!(StructuralQuery::try_parse("scx7 linear:two", &mgr).is_err());
+}
+
+#[test]
+fn test_treasure_rank() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let parse = |query: &str| StructuralQuery::try_parse(query, &mgr).unwrap_or_else(|e| panic!("{query}\n{e}"));
+    for sublevel in ["scx7", "fc3", "bk4"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel, &mgr).unwrap()).unwrap();
+        for seed in 0..10 {
+            let layout = Layout::generate(seed, caveinfo);
+            let waypoint_graph = layout.waypoint_graph();
+            let treasures = layout
+                .get_spawn_objects()
+                .filter_map(|(so, pos)| {
+                    let name = match so {
+                        SpawnObject::Item(info) => info.internal_name.clone(),
+                        SpawnObject::Teki(
+                            TekiInfo {
+                                carrying: Some(carrying), ..
+                            },
+                            _,
+                        ) => format!("carrying:{carrying}"),
+                        _ => return None,
+                    };
+                    Some((name, waypoint_graph.carry_path_len(pos)?))
+                })
+                .collect_vec();
+
+            for (name, dist) in treasures.iter() {
+                let farthest_rank = treasures.iter().filter(|(_, other)| other > dist).count() + 1;
+                let nearest_rank = treasures.iter().filter(|(_, other)| other < dist).count() + 1;
+                let matches = |query: String| parse(&format!("{sublevel} {query}")).matches(seed, &mgr);
+                assert!(matches(format!("farthest{farthest_rank}:{name}")), "{sublevel} {seed:#010X} {name}");
+                assert!(matches(format!("nearest{nearest_rank}:{name}")), "{sublevel} {seed:#010X} {name}");
+                assert!(
+                    !matches(format!("farthest{}:{name}", treasures.len() + 1)),
+                    "{sublevel} {seed:#010X} {name}"
+                );
+            }
+            // Leaving out the rank means first place.
+            if let Some((name, _)) = treasures.iter().max_by(|(_, a), (_, b)| a.total_cmp(b))
+                && treasures.iter().filter(|(other, _)| other == name).count() == 1
+            {
+                let farthest = parse(&format!("{sublevel} farthest:{name}"));
+                assert!(farthest.matches(seed, &mgr), "{sublevel} {seed:#010X} {name}");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_treasure_rank() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query = StructuralQuery::try_parse("fc3 FARTHEST:bey_goma", &mgr).unwrap();
+    assert!(query.to_string().ends_with("farthest:bey_goma"), "{query}");
+    let query = StructuralQuery::try_parse("fc3 nearest2:carrying:any", &mgr).unwrap();
+    assert!(query.to_string().ends_with("nearest2:carrying:any"), "{query}");
+    let query = StructuralQuery::try_parse("fc3 nearest1:bey_goma", &mgr).unwrap();
+    assert!(query.to_string().ends_with("nearest:bey_goma"), "{query}");
+    assert!(StructuralQuery::try_parse("fc3 farthest0:bey_goma", &mgr).is_err());
+    assert!(StructuralQuery::try_parse("fc3 farthest:", &mgr).is_err());
+}
+
+#[test]
+fn test_placement_counts() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    for sublevel in ["scx7", "bk4", "fc1"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel, &mgr).unwrap()).unwrap();
+        let teki_names = caveinfo
+            .teki_info
+            .iter()
+            .map(|teki| teki.internal_name.to_ascii_lowercase())
+            .unique()
+            .collect_vec();
+        for seed in 0..10 {
+            let layout = Layout::generate(seed, caveinfo);
+            for name in teki_names.iter() {
+                let count = |placement: TekiPlacement| {
+                    layout
+                        .teki_placements()
+                        .filter(|(so, p)| {
+                            *p == placement && matches!(so, SpawnObject::Teki(t, _) if t.internal_name.eq_ignore_ascii_case(name))
+                        })
+                        .count()
+                };
+                let num_minimum = count(TekiPlacement::Minimum);
+                let num_filler = count(TekiPlacement::Filler);
+                let minimum = StructuralQuery::try_parse(&format!("{sublevel} minimum:{name} = {num_minimum}"), &mgr).unwrap();
+                let filler = StructuralQuery::try_parse(&format!("{sublevel} filler:{name} = {num_filler}"), &mgr).unwrap();
+                assert!(minimum.matches(seed, &mgr), "{sublevel} {seed:#010X} {name}");
+                assert!(filler.matches(seed, &mgr), "{sublevel} {seed:#010X} {name}");
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_placement_count() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query = StructuralQuery::try_parse("scx7 MINIMUM:minihoudai > 1", &mgr).unwrap();
+    assert!(query.to_string().contains("minimum:minihoudai > 1"), "{query}");
+    let query = StructuralQuery::try_parse("bk4 filler:group:1:any < 3", &mgr).unwrap();
+    assert!(query.to_string().contains("filler:group:1:any < 3"), "{query}");
+    assert!(StructuralQuery::try_parse("scx7 minimum:minihoudai", &mgr).is_err());
+    assert!(StructuralQuery::try_parse("scx7 extra:minihoudai > 1", &mgr).is_err());
+}
+
+#[test]
+fn test_matches_batch() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let mut queries = [
+        "scx7 minihoudai > 1",
+        "bk4 room + hole",
+        "scx7 tour_len < 5000 & bk4 gate = 0",
+        "sh6 all_species > 4",
+    ]
+    .map(|query_str| StructuralQuery::try_parse(query_str, &mgr).unwrap())
+    .to_vec();
+    let mut ignoring_plants = queries[3].clone();
+    ignoring_plants.ignore_plants = true;
+    queries.push(ignoring_plants);
+
+    for seed in 0..100 {
+        let expected = queries.iter().map(|query| query.matches(seed, &mgr)).collect_vec();
+        assert_eq!(matches_batch(&queries, seed, &mgr), expected, "{seed:#010X}");
+    }
+}
+
+#[test]
+fn test_matches_layout() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let sublevel = Sublevel::try_from_str("sh6", &mgr).unwrap();
+    let caveinfo = mgr.load_caveinfo(&sublevel).unwrap();
+    let mut queries = [
+        "sh6 all_species > 4",
+        "sh6 room + hole & scx7 minihoudai > 1",
+        "scx7 minihoudai > 1",
+    ]
+    .map(|query_str| StructuralQuery::try_parse(query_str, &mgr).unwrap())
+    .to_vec();
+    let mut ignoring_plants = queries[0].clone();
+    ignoring_plants.ignore_plants = true;
+    queries.push(ignoring_plants);
+
+    for seed in 0..100 {
+        let layout = Layout::generate(seed, caveinfo);
+        for query in queries.iter() {
+            assert_eq!(
+                query.matches_layout(&layout, &mgr),
+                query.matches(seed, &mgr),
+                "{query} {seed:#010X}"
+            );
+        }
+    }
+
+    // Clauses about the layout's own sublevel should look at the layout itself, even one
+    // that couldn't be generated from its seed with the real CaveInfo.
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("scx7", &mgr).unwrap()).unwrap();
+    let modified = caveinfo
+        .to_builder()
+        .teki_weight("minihoudai", 0)
+        .unwrap()
+        .teki_min_amount("minihoudai", 0)
+        .unwrap()
+        .build();
+    let query = StructuralQuery::try_parse("scx7 minihoudai = 0", &mgr).unwrap();
+    for seed in 0..100 {
+        assert!(query.matches_layout(&Layout::generate(seed, &modified), &mgr), "{seed:#010X}");
+    }
+}
+
+#[test]
+fn test_count_enemies() {
+    let mgr = FsAssetManager::init_unchecked().expect("Couldn't init asset manager");
+    // One seam teki between two rooms, and in a room two easy teki, one hard teki, one
+    // special teki, a plant, and the ship.
+    let (seam_teki, easy_teki, hard_teki, special_teki, plant) = (
+        teki("Kochappy", 5),
+        teki("Kochappy", 0),
+        teki("Chappy", 1),
+        teki("Tank", 8),
+        teki("Clover", 6),
+    );
+    let units = seam_units();
+    let layout = seam_layout(
+        &units,
+        Some(SpawnObject::Teki(&seam_teki, Point([0.0, 0.0, 0.0]))),
+        vec![
+            SpawnObject::Ship,
+            SpawnObject::Teki(&easy_teki, Point([0.0, 0.0, 0.0])),
+            SpawnObject::Teki(&easy_teki, Point([10.0, 0.0, 0.0])),
+            SpawnObject::Teki(&hard_teki, Point([20.0, 0.0, 0.0])),
+            SpawnObject::Teki(&special_teki, Point([30.0, 0.0, 0.0])),
+            SpawnObject::Teki(&plant, Point([40.0, 0.0, 0.0])),
+        ],
+    );
+    let enemies = |include_seam_and_plants, amount| {
+        QueryKind::CountEnemies {
+            include_seam_and_plants,
+            relationship: Ordering::Equal,
+            amount,
+        }
+        .matches(&layout, &mgr)
+    };
+    assert!(enemies(false, 4));
+    // The seam teki is held by the doors on both sides of its seam but only counts once.
+    assert!(enemies(true, 6));
+}
+
+#[test]
+fn test_count_species() {
+    let mgr = FsAssetManager::init_unchecked().expect("Couldn't init asset manager");
+    // A Wollywog only in the seam, a Dwarf Bulborb both in the seam and in a room, and a
+    // clover in the plant group.
+    let (seam_frog, seam_chappy, chappy, clover) = (teki("Frog", 5), teki("Kochappy", 5), teki("Kochappy", 0), teki("Clover", 6));
+    let units = seam_units();
+    let layout = |seam_teki| {
+        seam_layout(
+            &units,
+            Some(SpawnObject::Teki(seam_teki, Point([0.0, 0.0, 0.0]))),
+            vec![
+                SpawnObject::Teki(&chappy, Point([0.0, 0.0, 0.0])),
+                SpawnObject::Teki(&clover, Point([10.0, 0.0, 0.0])),
+            ],
+        )
+    };
+    let species = |layout: &Layout, include_plants, include_seam| {
+        [0, 1, 2, 3]
+            .into_iter()
+            .find(|amount| {
+                QueryKind::CountSpecies {
+                    include_plants,
+                    include_seam,
+                    relationship: Ordering::Equal,
+                    amount: *amount,
+                }
+                .matches(layout, &mgr)
+            })
+            .unwrap()
+    };
+
+    let frog_in_seam = layout(&seam_frog);
+    assert_eq!(species(&frog_in_seam, false, true), 2);
+    assert_eq!(species(&frog_in_seam, true, true), 3);
+    assert_eq!(species(&frog_in_seam, false, false), 1);
+
+    // Leaving out seam teki doesn't leave out their species if it also spawned elsewhere.
+    let chappy_in_seam = layout(&seam_chappy);
+    assert_eq!(species(&chappy_in_seam, false, true), 1);
+    assert_eq!(species(&chappy_in_seam, false, false), 1);
+}
+
+#[test]
+fn test_parse_enemies() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query = StructuralQuery::try_parse("bk4 Enemies < 10 & all_enemies > 5", &mgr).unwrap();
+    let displayed = query.to_string();
+    assert!(displayed.contains("enemies < 10"), "{displayed}");
+    assert!(displayed.contains("all_enemies > 5"), "{displayed}");
+    assert!(StructuralQuery::try_parse("bk4 enemies", &mgr).is_err());
+}
+
+#[test]
+fn test_item_alcoves() {
+    let mgr = FsAssetManager::init_unchecked().expect("Couldn't init asset manager");
+
+    // The ship's room has an item alcove to its east and a plain cap to its south. Both
+    // have a group 9 spawn point; only the folder name tells them apart.
+    let mut item_alcove = CaveUnit {
+        unit_folder_name: "item_test".to_string(),
+        ..test_unit(RoomType::DeadEnd, &[3])
+    };
+    let mut cap = CaveUnit {
+        unit_folder_name: "cap_test".to_string(),
+        ..test_unit(RoomType::DeadEnd, &[0])
+    };
+    item_alcove.spawnpoints[0].group = 9;
+    cap.spawnpoints[0].group = 9;
+    let units = [test_unit(RoomType::Room, &[1, 2]), item_alcove, cap];
+    let mut map_units = vec![
+        PlacedMapUnit::new(&units[0], 0, 0),
+        PlacedMapUnit::new(&units[1], 1, 0),
+        PlacedMapUnit::new(&units[2], 0, 1),
+    ];
+    connect_doors(&map_units, (0, 0), (1, 0));
+    connect_doors(&map_units, (0, 1), (2, 0));
+    map_units[0].spawnpoints[0].contains = vec![SpawnObject::Ship];
+    let layout = Layout::from_placed_units(test_sublevel(), map_units);
+
+    let item_alcoves = |relationship, amount| QueryKind::ItemAlcoves { relationship, amount }.matches(&layout, &mgr);
+    assert!(item_alcoves(Ordering::Equal, 1));
+    assert!(!item_alcoves(Ordering::Greater, 1));
+}
+
+#[test]
+fn test_parse_item_alcoves() {
+    let mgr = FsAssetManager::init().expect("Couldn't init asset manager");
+    let query = StructuralQuery::try_parse("fc4 ALCOVES > 3", &mgr).unwrap();
+    assert!(query.to_string().contains("alcoves > 3"), "{query}");
+    assert!(StructuralQuery::try_parse("fc4 alcoves", &mgr).is_err());
+}
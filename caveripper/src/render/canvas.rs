@@ -27,10 +27,18 @@ impl Canvas {
 
     /// Create a [CanvasView] into this Canvas that treats `offset` as (0,0).
     pub fn view(&mut self, offset: Point<2, f32>) -> CanvasView {
+        self.scaled_view(offset, 1.0)
+    }
+
+    /// Create a [CanvasView] into this Canvas that treats `offset` as (0,0) and draws
+    /// everything `scale` times as large, e.g. to draw a whole image at a smaller size
+    /// without drawing it at full size first.
+    pub fn scaled_view(&mut self, offset: Point<2, f32>, scale: f32) -> CanvasView {
         CanvasView {
             canvas: self,
             offset,
             opacity: 1.0,
+            scale,
         }
     }
 
@@ -98,31 +106,57 @@ impl From<RgbaImage> for Canvas {
 ///
 /// Negative coordinates are valid and represent the appropriate locations on the
 /// parent Canvas. You can assume a CanvasView has infinite size.
+///
+/// Views made with [Canvas::scaled_view] multiply every position and size by their scale
+/// on the way to the Canvas, and images are resized to match as they're drawn. Single
+/// pixels can't be resized, so renderables that draw pixel by pixel should do so through
+/// [CanvasView::unscaled] at [CanvasView::scale] times their normal size instead.
 pub struct CanvasView<'c> {
     canvas: &'c mut Canvas,
     offset: Point<2, f32>,
     opacity: f32, // [0,1] inclusive
+    scale: f32,
 }
 
 impl<'c> CanvasView<'c> {
     pub fn draw_pixel(&mut self, pos: Point<2, f32>, color: Rgba<u8>) {
-        self.canvas.draw_pixel(pos + self.offset, color.mul_alpha(self.opacity));
+        self.canvas
+            .draw_pixel((pos + self.offset) * self.scale, color.mul_alpha(self.opacity));
     }
 
     pub fn fill(&mut self, start: Point<2, f32>, end: Point<2, f32>, color: Rgba<u8>) {
-        self.canvas
-            .fill(start + self.offset, end + self.offset, color.mul_alpha(self.opacity));
+        self.canvas.fill(
+            (start + self.offset) * self.scale,
+            (end + self.offset) * self.scale,
+            color.mul_alpha(self.opacity),
+        );
     }
 
     pub fn overlay(&mut self, top: &RgbaImage, pos: Point<2, f32>) {
+        let scaled;
+        let top = if self.scale != 1.0 {
+            let width = ((top.width() as f32 * self.scale).round() as u32).max(1);
+            let height = ((top.height() as f32 * self.scale).round() as u32).max(1);
+            scaled = resize(top, width, height, FilterType::Lanczos3);
+            &scaled
+        } else {
+            top
+        };
+        let pos = (pos + self.offset) * self.scale;
+
         if self.opacity < 1.0 {
             let mut top2 = top.clone();
             top2.pixels_mut().for_each(|pixel| {
                 *pixel = pixel.mul_alpha(self.opacity);
             });
-            self.canvas.overlay(&top2, pos + self.offset);
+            self.canvas.overlay(&top2, pos);
         }
-        self.canvas.overlay(top, pos + self.offset);
+        self.canvas.overlay(top, pos);
+    }
+
+    /// How many pixels on the underlying Canvas one unit in this view takes up.
+    pub fn scale(&self) -> f32 {
+        self.scale
     }
 }
 
@@ -132,6 +166,18 @@ impl<'d, 'c: 'd> CanvasView<'c> {
             canvas: &mut *self.canvas,
             offset: self.offset + offset,
             opacity: self.opacity,
+            scale: self.scale,
+        }
+    }
+
+    /// A view of the same location where one unit is one pixel of the underlying Canvas,
+    /// regardless of this view's scale.
+    pub fn unscaled(&'d mut self) -> CanvasView<'d> {
+        CanvasView {
+            canvas: &mut *self.canvas,
+            offset: self.offset * self.scale,
+            opacity: self.opacity,
+            scale: 1.0,
         }
     }
 
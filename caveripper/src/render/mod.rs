@@ -1,8 +1,13 @@
 mod canvas;
 mod coords;
 mod pixel_ext;
+mod render_cave_strip;
 mod render_caveinfo;
+mod render_heatmap;
 mod render_layout;
+mod render_spawn_cloud;
+mod render_template;
+mod render_units;
 mod renderer;
 mod shapes;
 mod text;
@@ -11,28 +16,40 @@ mod util;
 #[cfg(test)]
 mod test;
 
-use std::{borrow::Cow, marker::PhantomData, path::Path};
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{BufReader, BufWriter},
+    marker::PhantomData,
+    path::Path,
+};
 
 use error_stack::{Result, ResultExt};
 use fontdue::{Font, FontSettings};
 use image::{
-    imageops::{colorops::brighten_in_place, resize, rotate90, FilterType},
+    imageops::{colorops::brighten_in_place, overlay, resize, rotate90, FilterType},
     Rgba, RgbaImage,
 };
+pub use render_cave_strip::*;
 pub use render_caveinfo::*;
+pub use render_heatmap::*;
 pub use render_layout::*;
+pub use render_spawn_cloud::*;
+pub use render_template::*;
+pub use render_units::*;
 
 use self::{
     canvas::CanvasView,
-    renderer::Render,
+    renderer::{Render, StickerRenderer},
     shapes::Rectangle,
     util::{CropRelative, Resize},
 };
 use crate::{
-    assets::{get_special_texture_name, AssetManager, ImageKind, Treasure},
+    assets::{get_special_texture_name, AssetManager, ImageKind, NameKind, Treasure},
     caveinfo::{CapInfo, CaveUnit, TekiInfo},
     errors::CaveripperError,
-    layout::SpawnObject,
+    layout::{Layout, SpawnObject},
+    format_seed, parse_seed,
     point::Point,
     render::{coords::Origin, renderer::Layer, text::Text},
 };
@@ -45,11 +62,14 @@ const RENDER_SCALE: f32 = 16.0;
 const GRID_FACTOR: f32 = 8.0 * RENDER_SCALE;
 const COORD_FACTOR: f32 = (8.0 * RENDER_SCALE) / 170.0;
 const TEKI_SIZE: f32 = 4.0 * RENDER_SCALE;
+/// Size teki images are scaled to before being resized to [TEKI_SIZE].
+const TEKI_SPRITE_SIZE: f32 = 40.0;
 const GATE_SIZE: f32 = 8.0 * RENDER_SCALE;
 const CARRIED_TREASURE_SIZE: f32 = TEKI_SIZE * 0.75;
 const FALLING_CAP_TEKI_SIZE: f32 = TEKI_SIZE * 0.8;
 const FALLING_ICON_SIZE: f32 = 1.6 * RENDER_SCALE;
 const QUICKGLANCE_CIRCLE_RADIUS: f32 = 5.0 * RENDER_SCALE;
+const QUICKGLANCE_CIRCLE_OPACITY: f32 = 0.45;
 const LAYOUT_BACKGROUND_COLOR: [u8; 4] = [15, 15, 15, 255];
 const CAVEINFO_UNIT_BORDER_COLOR: [u8; 4] = [225, 0, 0, 255];
 const QUICKGLANCE_TREASURE_COLOR: [u8; 4] = [230, 115, 0, 255];
@@ -61,8 +81,37 @@ const QUICKGLANCE_ROAMING_COLOR: [u8; 4] = [200, 0, 130, 255];
 const QUICKGLANCE_ONION_RED: [u8; 4] = [245, 39, 24, 255];
 const QUICKGLANCE_ONION_YELLOW: [u8; 4] = [34, 235, 12, 255];
 const QUICKGLANCE_ONION_BLUE: [u8; 4] = [34, 12, 235, 255];
+const QUICKGLANCE_GLYPH_COLOR: [u8; 4] = [255, 255, 255, 255];
 const WAYPOINT_COLOR: [u8; 4] = [130, 199, 56, 255];
 const WATERBOX_COLOR: [u8; 4] = [0, 100, 230, 255];
+const SPAWN_RADIUS_COLOR: [u8; 4] = [230, 60, 40, 255];
+const SPAWN_PUSH_LINE_COLOR: [u8; 4] = [255, 255, 255, 255];
+const GAUGE_RANGE_COLOR: [u8; 4] = [255, 255, 255, 30];
+const GAUGE_RANGE_BORDER_COLOR: [u8; 4] = [255, 255, 255, 150];
+const GAUGE_RANGE_BORDER_THICKNESS: f32 = 3.0;
+// Distances from a treasure (in game units) at which the treasure gauge reacts. These are
+// approximate and should be updated if more exact values are found in the game's code.
+const GAUGE_NEEDLE_RANGE: f32 = 1050.0;
+const GAUGE_PING_RANGE: f32 = 150.0;
+const TEKI_GROUP_LABEL_OFFSET: Point<2, f32> = Point([12.0, -12.0]);
+const GATE_HP_TEXT_COLOR: [u8; 4] = [255, 255, 255, 255];
+const UNIT_NAME_TEXT_COLOR: [u8; 4] = [255, 230, 120, 255];
+const UNIT_NAME_FONT_SIZE: f32 = 24.0;
+const UNIT_NAME_CHAR_WIDTH: f32 = UNIT_NAME_FONT_SIZE * 0.55; // Rough average for the layout font
+const WATERBOX_SHADING_MAX_DEPTH: f32 = 150.0; // In game units. Anything deeper is drawn as dark as possible.
+const WATERBOX_SHADING_MIN_OPACITY: f32 = 0.1;
+const WATERBOX_SHADING_MAX_OPACITY: f32 = 0.65;
+// Drawn over the starting room. Same color as the layout background so only the room itself gets darker.
+const START_ROOM_DIM_COLOR: [u8; 4] = [15, 15, 15, 160];
+const SKELETON_LINE_COLOR: [u8; 4] = [255, 255, 255, 220];
+const SKELETON_DOOR_COLOR: [u8; 4] = [255, 170, 40, 255];
+const SKELETON_DOOR_RADIUS: f32 = 0.5 * RENDER_SCALE;
+const WORLD_COORDS_LINE_COLOR: [u8; 4] = [255, 255, 255, 90];
+const WORLD_COORDS_TEXT_COLOR: [u8; 4] = [255, 255, 255, 255];
+const WORLD_COORDS_LABEL_OFFSET: Point<2, f32> = Point([4.0, 4.0]);
+const SHADOW_COLOR: [u8; 4] = [0, 0, 0, 255];
+const SHADOW_OFFSET: Point<2, f32> = Point([0.3 * RENDER_SCALE, 0.3 * RENDER_SCALE]);
+const SHADOW_OPACITY: f32 = 0.4;
 const CARRY_PATH_COLOR: [u8; 4] = [83, 125, 29, 200];
 const CAVEINFO_WIDTH: f32 = 1250.0;
 const WAYPOINT_DIST_TXT_COLOR: [u8; 4] = [36, 54, 14, 255];
@@ -77,6 +126,18 @@ const CAVEINFO_UNIT_MARGIN: f32 = CAVEINFO_MARGIN * 3.0;
 const CAVEINFO_ICON_SIZE: f32 = 64.0;
 const OFF_BLACK: [u8; 4] = [0, 0, 0, 255];
 const CAVEINFO_BOXES_FONT_SIZE: f32 = 42.0;
+const HEATMAP_COLD_COLOR: [u8; 4] = [255, 220, 40, 170];
+const HEATMAP_HOT_COLOR: [u8; 4] = [230, 20, 20, 230];
+const HEATMAP_MIN_RADIUS: f32 = 1.0 * RENDER_SCALE;
+const HEATMAP_MAX_RADIUS: f32 = 3.0 * RENDER_SCALE;
+const DOOR_MARKER_COLOR: [u8; 4] = [255, 40, 200, 255];
+const DOOR_MARKER_RADIUS: f32 = 0.4 * RENDER_SCALE;
+const SPAWN_CLOUD_OPACITY: f32 = 0.15;
+const CAVE_STRIP_FLOOR_SPACING: f32 = 4.0 * RENDER_SCALE;
+const TEMPLATE_BACKGROUND_COLOR: [u8; 4] = [255, 255, 255, 255];
+const TEMPLATE_LINE_COLOR: [u8; 4] = [0, 0, 0, 255];
+const TEMPLATE_GRID_COLOR: [u8; 4] = [0, 0, 0, 40];
+const TEMPLATE_OUTLINE_THICKNESS: u32 = 2; // In pixels, before the template is scaled up
 
 pub struct RenderHelper<'a, M: AssetManager> {
     mgr: &'a M,
@@ -98,6 +159,8 @@ impl<'a, M: AssetManager> RenderHelper<'a, M> {
         }
     }
 
+    /// `size` is the nominal size before any canvas scaling, so the same label gets the same
+    /// font at every output resolution.
     fn cropped_text(&self, text: impl Into<String>, size: f32, outline: u32, color: impl Into<Rgba<u8>>) -> impl Render<M> + '_ {
         CropRelative {
             inner: Text {
@@ -114,6 +177,13 @@ impl<'a, M: AssetManager> RenderHelper<'a, M> {
             phantom: PhantomData,
         }
     }
+
+    /// The display name of a teki or treasure for labels, falling back to the internal name.
+    fn display_name(&self, kind: NameKind, internal_name: &str) -> String {
+        self.mgr
+            .display_name(kind, internal_name)
+            .unwrap_or_else(|| internal_name.to_string())
+    }
 }
 
 /// Saves a layout image to disc.
@@ -124,57 +194,137 @@ pub fn save_image<P: AsRef<Path>>(img: &RgbaImage, filename: P) -> Result<(), Ca
     Ok(())
 }
 
-impl<M: AssetManager> Render<M> for CaveUnit {
-    fn render(&self, mut canvas: CanvasView, helper: &M) {
-        let mut img = helper
-            .load_image(ImageKind::CaveUnit, &self.game, &self.unit_folder_name)
-            .unwrap()
-            .to_owned();
+const IMAGE_METADATA_SUBLEVEL_KEY: &str = "Sublevel";
+const IMAGE_METADATA_SEED_KEY: &str = "Seed";
 
-        // Radar images are somewhat dark by default; this improves visibility.
-        brighten_in_place(&mut img, 75);
+/// Identifying information embedded in a saved layout image.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImageMetadata {
+    pub sublevel: Option<String>,
+    pub seed: Option<u32>,
+}
 
-        for _ in 0..self.rotation {
-            img = rotate90(&img);
-        }
+/// Saves a rendered layout image to disc, embedding the sublevel name and seed as
+/// PNG text chunks so the layout can still be identified if the file is renamed.
+/// Filename must end with a `.png` extension.
+pub fn save_layout_image<P: AsRef<Path>>(img: &RgbaImage, filename: P, layout: &Layout) -> Result<(), CaveripperError> {
+    let file = File::create(filename.as_ref())
+        .change_context(CaveripperError::RenderingError)
+        .attach_printable_lazy(|| filename.as_ref().display().to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), img.width(), img.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .add_text_chunk(IMAGE_METADATA_SUBLEVEL_KEY.to_string(), layout.cave_name.clone())
+        .change_context(CaveripperError::RenderingError)?;
+    encoder
+        .add_text_chunk(IMAGE_METADATA_SEED_KEY.to_string(), format_seed(layout.starting_seed))
+        .change_context(CaveripperError::RenderingError)?;
+    encoder
+        .write_header()
+        .and_then(|mut writer| {
+            writer.write_image_data(img.as_raw())?;
+            writer.finish()
+        })
+        .change_context(CaveripperError::RenderingError)?;
+    Ok(())
+}
 
-        img = resize(
-            &img,
-            (self.width as f32 * GRID_FACTOR) as u32,
-            (self.height as f32 * GRID_FACTOR) as u32,
-            FilterType::Nearest,
-        );
-        canvas.overlay(&img, Point([0.0, 0.0]));
-
-        // Waterboxes
-        for waterbox in self.waterboxes.iter() {
-            let mut view = canvas.sub_view((self.center() * GRID_FACTOR) + (waterbox.p1.two_d() * COORD_FACTOR));
-            let view2 = view.with_opacity(0.2);
-            Rectangle {
-                width: waterbox.width() * COORD_FACTOR,
-                height: waterbox.height() * COORD_FACTOR,
-                color: WATERBOX_COLOR.into(),
-            }
-            .render(view2, helper);
+/// Reads the sublevel and seed back out of an image saved with [save_layout_image].
+/// Fields are `None` if the image doesn't contain the corresponding metadata.
+pub fn read_image_metadata<P: AsRef<Path>>(filename: P) -> Result<ImageMetadata, CaveripperError> {
+    let file = File::open(filename.as_ref())
+        .change_context(CaveripperError::AssetLoadingError)
+        .attach_printable_lazy(|| filename.as_ref().display().to_string())?;
+    let reader = png::Decoder::new(BufReader::new(file))
+        .read_info()
+        .change_context(CaveripperError::AssetLoadingError)?;
+
+    let mut metadata = ImageMetadata::default();
+    for chunk in reader.info().uncompressed_latin1_text.iter() {
+        match chunk.keyword.as_str() {
+            IMAGE_METADATA_SUBLEVEL_KEY => metadata.sublevel = Some(chunk.text.clone()),
+            IMAGE_METADATA_SEED_KEY => metadata.seed = Some(parse_seed(&chunk.text)?.as_u32()),
+            _ => {}
         }
     }
+    Ok(metadata)
+}
+
+impl<M: AssetManager> Render<M> for CaveUnit {
+    fn render(&self, canvas: CanvasView, helper: &M) {
+        render_cave_unit(self, canvas, helper, false);
+    }
 
     fn dimensions(&self) -> Point<2, f32> {
         Point([self.width as f32 * GRID_FACTOR, self.height as f32 * GRID_FACTOR])
     }
 }
 
+/// Renders a [CaveUnit] with its waterboxes shaded according to how deep they are
+/// rather than as flat rectangles. Deeper water is drawn darker.
+pub(crate) struct DepthShadedCaveUnit<'a>(pub &'a CaveUnit);
+
+impl<M: AssetManager> Render<M> for DepthShadedCaveUnit<'_> {
+    fn render(&self, canvas: CanvasView, helper: &M) {
+        render_cave_unit(self.0, canvas, helper, true);
+    }
+
+    fn dimensions(&self) -> Point<2, f32> {
+        <CaveUnit as Render<M>>::dimensions(self.0)
+    }
+}
+
+fn render_cave_unit<M: AssetManager>(unit: &CaveUnit, mut canvas: CanvasView, helper: &M, shade_waterbox_depth: bool) {
+    let mut img = helper
+        .load_image(ImageKind::CaveUnit, &unit.game, &unit.unit_folder_name)
+        .unwrap()
+        .to_owned();
+
+    // Radar images are somewhat dark by default; this improves visibility.
+    brighten_in_place(&mut img, 75);
+
+    for _ in 0..unit.rotation {
+        img = rotate90(&img);
+    }
+
+    img = resize(
+        &img,
+        (unit.width as f32 * GRID_FACTOR) as u32,
+        (unit.height as f32 * GRID_FACTOR) as u32,
+        FilterType::Nearest,
+    );
+    canvas.overlay(&img, Point([0.0, 0.0]));
+
+    // Waterboxes
+    for waterbox in unit.waterboxes.iter() {
+        let opacity = if shade_waterbox_depth {
+            let depth_fraction = (waterbox.depth() / WATERBOX_SHADING_MAX_DEPTH).clamp(0.0, 1.0);
+            WATERBOX_SHADING_MIN_OPACITY + (WATERBOX_SHADING_MAX_OPACITY - WATERBOX_SHADING_MIN_OPACITY) * depth_fraction
+        } else {
+            0.2
+        };
+        let mut view = canvas.sub_view((unit.center() * GRID_FACTOR) + (waterbox.p1.two_d() * COORD_FACTOR));
+        let view2 = view.with_opacity(opacity);
+        Rectangle {
+            width: waterbox.width() * COORD_FACTOR,
+            height: waterbox.height() * COORD_FACTOR,
+            color: WATERBOX_COLOR.into(),
+            ..Default::default()
+        }
+        .render(view2, helper);
+    }
+}
+
 impl<M: AssetManager> Render<M> for SpawnObject<'_> {
     fn render(&self, mut canvas: CanvasView, helper: &M) {
         match self {
-            SpawnObject::Teki(TekiInfo { game, .. }, _) | SpawnObject::CapTeki(CapInfo { game, .. }, _) => {
-                let (name, kind) = get_special_texture_name(self.name())
-                    .map(ToOwned::to_owned)
-                    .map(|special_name| (special_name, ImageKind::Special))
-                    .unwrap_or_else(|| (self.name().to_ascii_lowercase(), ImageKind::Teki));
-                let teki_img = resize(helper.load_image(kind, game, &name).unwrap(), 40, 40, FilterType::Lanczos3);
-                canvas.overlay(&teki_img, Point([0.0, 0.0]));
+            SpawnObject::Teki(TekiInfo { game, .. }, _) | SpawnObject::CapTeki(CapInfo { game, .. }, _) => TekiSprite {
+                name: self.name().to_string(),
+                game: game.clone(),
+                scale: 1.0,
             }
+            .render(canvas, helper),
             SpawnObject::Item(info) => TreasureRenderer {
                 treasure: helper
                     .get_treasure_info(&info.game, &info.internal_name)
@@ -219,10 +369,8 @@ impl<M: AssetManager> Render<M> for SpawnObject<'_> {
 
     fn dimensions(&self) -> Point<2, f32> {
         match self {
-            // TODO: Boss teki and potentially some romhack teki have larger
-            // image dimensions. Currently these are all scaled to 40x40 but
-            // quality could be better if this can be avoided.
-            SpawnObject::Teki(_, _) | SpawnObject::CapTeki(_, _) => Point([40.0, 40.0]),
+            // Large teki are drawn through [TekiSprite] directly so they can be bigger.
+            SpawnObject::Teki(_, _) | SpawnObject::CapTeki(_, _) => Point([TEKI_SPRITE_SIZE, TEKI_SPRITE_SIZE]),
             SpawnObject::Item(_) => {
                 let renderer = TreasureRenderer {
                     treasure: &Treasure::default(),
@@ -238,6 +386,36 @@ impl<M: AssetManager> Render<M> for SpawnObject<'_> {
     }
 }
 
+/// A teki's image, drawn `scale` times larger than normal. Large teki such as bosses
+/// (see [TekiSizes](crate::assets::TekiSizes)) are resized straight from their source
+/// image to their final size instead of going through the normal size first, so they
+/// don't lose detail.
+struct TekiSprite {
+    name: String,
+    game: String,
+    scale: f32,
+}
+impl<M: AssetManager> Render<M> for TekiSprite {
+    fn render(&self, mut canvas: CanvasView, helper: &M) {
+        let (name, kind) = get_special_texture_name(&self.name)
+            .map(ToOwned::to_owned)
+            .map(|special_name| (special_name, ImageKind::Special))
+            .unwrap_or_else(|| (self.name.to_ascii_lowercase(), ImageKind::Teki));
+        let size = (TEKI_SPRITE_SIZE * self.scale).round() as u32;
+        let teki_img = resize(
+            helper.load_image(kind, &self.game, &name).unwrap(),
+            size,
+            size,
+            FilterType::Lanczos3,
+        );
+        canvas.overlay(&teki_img, Point([0.0, 0.0]));
+    }
+
+    fn dimensions(&self) -> Point<2, f32> {
+        Point([TEKI_SPRITE_SIZE * self.scale, TEKI_SPRITE_SIZE * self.scale])
+    }
+}
+
 /// Helper to reduce asset manager lookups
 struct TreasureRenderer<'a> {
     pub treasure: &'a Treasure,
@@ -298,25 +476,70 @@ impl<M: AssetManager> Render<M> for Icon {
     }
 }
 
+/// Renders a single spawn object on its own, with the same carried treasure and falling
+/// indicator it gets in layout renders, fit into the middle of a transparent square `size`
+/// pixels across. For legends and other UI that shows objects outside of a layout.
+pub fn render_object_icon<M: AssetManager>(object: &SpawnObject, helper: &RenderHelper<M>, size: u32) -> RgbaImage {
+    let size = size.max(1);
+    let mut layer = Layer::new();
+    layer.place(
+        render_spawn_object(Cow::Borrowed(object), helper.mgr),
+        Point([0.0, 0.0]),
+        Origin::TopLeft,
+    );
+    let mut renderer = StickerRenderer::new();
+    renderer.add_layer(layer);
+    let object_img = renderer.render(helper.mgr);
+
+    // Decorations can make the object wider or taller than it is square, so scale the
+    // longer side to fit and keep the aspect ratio.
+    let scale = size as f32 / object_img.width().max(object_img.height()).max(1) as f32;
+    let width = ((object_img.width() as f32 * scale).round() as u32).clamp(1, size);
+    let height = ((object_img.height() as f32 * scale).round() as u32).clamp(1, size);
+    let object_img = resize(&object_img, width, height, FilterType::Lanczos3);
+
+    let mut icon = RgbaImage::new(size, size);
+    overlay(&mut icon, &object_img, ((size - width) / 2) as i64, ((size - height) / 2) as i64);
+    icon
+}
+
 fn render_spawn_object<'a, 'b: 'a, M: AssetManager>(spawn_object: Cow<'a, SpawnObject<'b>>, mgr: &'a M) -> impl Render<M> + 'a {
     let mut layer = Layer::new();
     let mut pos = Point([0.0, 0.0]);
 
     // Main Spawn Object image
+    let teki_scale = match spawn_object.as_ref() {
+        SpawnObject::Teki(TekiInfo { game, .. }, _) | SpawnObject::CapTeki(CapInfo { game, .. }, _) => {
+            mgr.teki_sizes().scale(game, spawn_object.name())
+        }
+        _ => 1.0,
+    };
     let size = match spawn_object.as_ref() {
         SpawnObject::Gate(_, _) => GATE_SIZE,
         SpawnObject::CapTeki(CapInfo { spawn_method: Some(_), .. }, _) => {
             pos = pos - RENDER_SCALE;
-            FALLING_CAP_TEKI_SIZE
+            FALLING_CAP_TEKI_SIZE * teki_scale
         }
-        _ => TEKI_SIZE,
+        _ => TEKI_SIZE * teki_scale,
     };
 
-    layer.place(
-        Resize::new(spawn_object.clone().into_owned(), size, size, FilterType::Lanczos3),
-        pos,
-        Origin::TopLeft,
-    );
+    match spawn_object.as_ref() {
+        SpawnObject::Teki(TekiInfo { game, .. }, _) | SpawnObject::CapTeki(CapInfo { game, .. }, _) if teki_scale != 1.0 => {
+            let sprite = TekiSprite {
+                name: spawn_object.name().to_string(),
+                game: game.clone(),
+                scale: teki_scale,
+            };
+            layer.place(Resize::new(sprite, size, size, FilterType::Lanczos3), pos, Origin::TopLeft);
+        }
+        _ => {
+            layer.place(
+                Resize::new(spawn_object.clone().into_owned(), size, size, FilterType::Lanczos3),
+                pos,
+                Origin::TopLeft,
+            );
+        }
+    }
 
     // Carrying Treasures
     if let SpawnObject::Teki(
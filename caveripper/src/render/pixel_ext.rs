@@ -2,6 +2,8 @@ use image::Rgba;
 
 pub trait PixelExt {
     fn mul_alpha(&self, multiplier: f32) -> Self;
+    /// Linear interpolation between two colors. `t` is from 0.0 (all self) to 1.0 (all other).
+    fn lerp(&self, other: &Self, t: f32) -> Self;
 }
 
 impl PixelExt for Rgba<u8> {
@@ -11,4 +13,13 @@ impl PixelExt for Rgba<u8> {
         new.0[3] = (self.0[3] as f32 * multiplier).round() as u8;
         new
      }
+
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        let mut new = *self;
+        for (c, o) in new.0.iter_mut().zip(other.0) {
+            *c = (*c as f32 + (o as f32 - *c as f32) * t).round() as u8;
+        }
+        new
+    }
 }
\ No newline at end of file
@@ -0,0 +1,79 @@
+use error_stack::{report, Result, ResultExt};
+use image::RgbaImage;
+
+use super::{
+    coords::{Offset, Origin},
+    render_layout,
+    renderer::{Layer, Render, StickerRenderer},
+    LayoutRenderOptions, RenderHelper, CAVEINFO_UNIT_MARGIN, CAVE_STRIP_FLOOR_SPACING, HEADER_BACKGROUND, MAPTILES_BACKGROUND,
+};
+use crate::{
+    assets::{load_cave_floors, AssetManager, CaveConfig},
+    errors::CaveripperError,
+    format_seed,
+    layout::Layout,
+    point::Point,
+};
+
+/// Renders every floor of a cave at the same seed side by side in one image, each labeled
+/// with its floor number, to show a whole run of the cave at a glance.
+///
+/// Floors can be very different sizes, so they're lined up along their bottom edges on a
+/// shared baseline with the labels underneath, rather than hanging from the top.
+pub fn render_cave_strip<M: AssetManager>(
+    cave_cfg: &CaveConfig,
+    seed: u32,
+    helper: &RenderHelper<M>,
+    options: LayoutRenderOptions,
+) -> Result<RgbaImage, CaveripperError> {
+    let caveinfos = load_cave_floors(helper.mgr, cave_cfg);
+    if caveinfos.is_empty() {
+        return Err(report!(CaveripperError::CaveinfoError))
+            .attach_printable_lazy(|| format!("No floors found for {}", cave_cfg.full_name));
+    }
+
+    let floor_images = caveinfos
+        .into_iter()
+        .map(|caveinfo| render_layout(&Layout::generate(seed, caveinfo), helper, options.clone()))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| report!(e))
+        .attach_printable_lazy(|| format!("Couldn't render every floor of {}", cave_cfg.full_name))?;
+    let baseline = floor_images.iter().map(|image| image.height()).max().unwrap_or(0) as f32;
+
+    let mut floors_layer = Layer::new();
+    let mut x = 0.0;
+    for (i, image) in floor_images.iter().enumerate() {
+        let label = helper.cropped_text(format!("Floor {}", i + 1), 64.0, 0, HEADER_BACKGROUND);
+        let column_width = f32::max(image.width() as f32, label.dimensions()[0]);
+        floors_layer.place(image, Point([x, baseline]), Origin::BottomLeft);
+        floors_layer.place(label, Point([x, baseline + CAVEINFO_UNIT_MARGIN]), Origin::TopLeft);
+        x += column_width + CAVE_STRIP_FLOOR_SPACING;
+    }
+
+    let mut layer = Layer::new();
+    layer.set_margin(CAVEINFO_UNIT_MARGIN);
+    layer.set_background_color(MAPTILES_BACKGROUND);
+    layer.place(
+        helper.cropped_text(
+            format!("{} - {}", cave_cfg.full_name, format_seed(seed)),
+            42.0,
+            0,
+            HEADER_BACKGROUND,
+        ),
+        Point([0.0, 0.0]),
+        Origin::TopLeft,
+    );
+    layer.place_relative(
+        floors_layer,
+        Origin::TopLeft,
+        Offset {
+            from: Origin::BottomLeft,
+            amount: Point([0.0, CAVEINFO_UNIT_MARGIN]),
+        },
+    );
+
+    let mut renderer = StickerRenderer::new();
+    renderer.set_global_background_color(MAPTILES_BACKGROUND);
+    renderer.add_layer(layer);
+    Ok(renderer.render(helper.mgr))
+}
@@ -1,6 +1,7 @@
 use std::{borrow::Cow, marker::PhantomData};
 
 use clap::Args;
+use error_stack::{report, Result, ResultExt};
 use image::{imageops::FilterType, Rgba, RgbaImage};
 use itertools::Itertools;
 
@@ -15,7 +16,7 @@ use super::{
     CAVEINFO_WIDTH, COORD_FACTOR, GRID_FACTOR, HEADER_BACKGROUND, MAPTILES_BACKGROUND, OFF_BLACK, QUICKGLANCE_ONION_BLUE,
 };
 use crate::{
-    assets::AssetManager,
+    assets::{load_cave_floors, AssetManager, CaveConfig, NameKind},
     caveinfo::{CapInfo, CaveInfo, CaveUnit, ItemInfo, RoomType, TekiInfo},
     errors::CaveripperError,
     layout::SpawnObject,
@@ -53,9 +54,60 @@ pub fn render_caveinfo<M: AssetManager>(
     helper: &RenderHelper<M>,
     options: CaveinfoRenderOptions,
 ) -> Result<RgbaImage, CaveripperError> {
+    let mut renderer = StickerRenderer::new();
+    renderer.set_global_background_color(HEADER_BACKGROUND);
+    renderer.place(caveinfo_layer(caveinfo, helper, &options), Point([0.0, 0.0]), Origin::TopLeft);
+    Ok(renderer.render(helper.mgr))
+}
+
+/// Renders the caveinfo for every floor of a cave into a single image, one floor
+/// below the next with a header naming each floor.
+pub fn render_cave_caveinfo<M: AssetManager>(
+    cave_cfg: &CaveConfig,
+    helper: &RenderHelper<M>,
+    options: CaveinfoRenderOptions,
+) -> Result<RgbaImage, CaveripperError> {
+    let caveinfos = load_cave_floors(helper.mgr, cave_cfg);
+    if caveinfos.is_empty() {
+        return Err(report!(CaveripperError::CaveinfoError))
+            .attach_printable_lazy(|| format!("No floors found for {}", cave_cfg.full_name));
+    }
+
     let mut renderer = StickerRenderer::new();
     renderer.set_global_background_color(HEADER_BACKGROUND);
 
+    for (i, caveinfo) in caveinfos.into_iter().enumerate() {
+        let mut header = Layer::of(helper.cropped_text(format!("Floor {}", i + 1), 64.0, 0, OFF_BLACK));
+        header.set_margin(CAVEINFO_MARGIN);
+        renderer
+            .place_relative(
+                header,
+                Origin::TopLeft,
+                Offset {
+                    from: Origin::BottomLeft,
+                    amount: Point([0.0, 0.0]),
+                },
+            )
+            .place_relative(
+                caveinfo_layer(caveinfo, helper, &options),
+                Origin::TopLeft,
+                Offset {
+                    from: Origin::BottomLeft,
+                    amount: Point([0.0, 0.0]),
+                },
+            );
+    }
+
+    Ok(renderer.render(helper.mgr))
+}
+
+fn caveinfo_layer<'r, M: AssetManager>(
+    caveinfo: &'r CaveInfo,
+    helper: &'r RenderHelper<M>,
+    options: &CaveinfoRenderOptions,
+) -> Layer<'r, M> {
+    let mut layer = Layer::new();
+
     let mut title_row = Layer::new();
     title_row.set_margin(CAVEINFO_MARGIN);
 
@@ -133,7 +185,7 @@ pub fn render_caveinfo<M: AssetManager>(
     }
 
     let title_row_width = title_row.dimensions()[0];
-    renderer.place(title_row, Point([0.0, 0.0]), Origin::TopLeft);
+    layer.place(title_row, Point([0.0, 0.0]), Origin::TopLeft);
 
     // --- Spawn Object Info Boxes -- //
     let mut max_width = CAVEINFO_WIDTH;
@@ -230,7 +282,7 @@ pub fn render_caveinfo<M: AssetManager>(
     spawn_object_layer.set_margin(CAVEINFO_MARGIN);
 
     let top_width = f32::max(spawn_object_layer.dimensions()[0], title_row_width);
-    renderer.place_relative(
+    layer.place_relative(
         spawn_object_layer,
         Origin::TopLeft,
         Offset {
@@ -254,7 +306,7 @@ pub fn render_caveinfo<M: AssetManager>(
         let cap_and_hall_width = CAVEINFO_GRID_FACTOR * (num_per_row * 1.05) + (CAVEINFO_UNIT_MARGIN * (num_per_row - 1.0));
         let mut cap_and_hall_box = Rows::new(cap_and_hall_width, CAVEINFO_UNIT_MARGIN, CAVEINFO_UNIT_MARGIN);
         for unit in caps_and_1x1_halls.iter() {
-            cap_and_hall_box.add(render_unit_caveinfo(unit, helper, &options));
+            cap_and_hall_box.add(render_unit_caveinfo(unit, helper, options));
         }
 
         unit_box.add(cap_and_hall_box);
@@ -269,7 +321,7 @@ pub fn render_caveinfo<M: AssetManager>(
         if unit.room_type != RoomType::Room && options.hide_small_units {
             continue;
         }
-        unit_box.add(render_unit_caveinfo(unit, helper, &options));
+        unit_box.add(render_unit_caveinfo(unit, helper, options));
     }
 
     let mut unit_layer = Layer::new();
@@ -328,7 +380,7 @@ pub fn render_caveinfo<M: AssetManager>(
     }
     unit_layer.set_background_color(MAPTILES_BACKGROUND);
 
-    renderer.place_relative(
+    layer.place_relative(
         unit_layer,
         Origin::TopLeft,
         Offset {
@@ -365,10 +417,10 @@ pub fn render_caveinfo<M: AssetManager>(
     //         );
     //     }
 
-    Ok(renderer.render(helper.mgr))
+    layer
 }
 
-const fn group_color(group: u32) -> [u8; 4] {
+pub(super) const fn group_color(group: u32) -> [u8; 4] {
     match group {
         0 => [250, 87, 207, 255],  // Easy Teki (120 Alpha for circles)
         1 => [201, 2, 52, 255],    // Hard Teki
@@ -540,7 +592,12 @@ fn caveinfo_entity_box<'r, 'h: 'r, M: AssetManager>(
             {
                 // This is just way too obtrusive in challenge mode
                 text_layer.place_relative(
-                    helper.cropped_text(format!(" ({carrying})"), 18.0, 0, OFF_BLACK),
+                    helper.cropped_text(
+                        format!(" ({})", helper.display_name(NameKind::Treasure, carrying)),
+                        18.0,
+                        0,
+                        OFF_BLACK,
+                    ),
                     Origin::CenterLeft,
                     Offset {
                         from: Origin::CenterRight,
@@ -559,7 +616,7 @@ fn caveinfo_entity_box<'r, 'h: 'r, M: AssetManager>(
             );
         } else if let SpawnObject::Item(info) = so {
             full_so_layer.place_relative(
-                helper.cropped_text(&info.internal_name, 18.0, 0, OFF_BLACK),
+                helper.cropped_text(helper.display_name(NameKind::Treasure, &info.internal_name), 18.0, 0, OFF_BLACK),
                 Origin::TopCenter,
                 Offset {
                     from: Origin::BottomCenter,
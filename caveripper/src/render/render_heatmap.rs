@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use error_stack::Result;
+use image::{imageops::FilterType, RgbaImage};
+use itertools::Itertools;
+
+use super::{
+    coords::{Offset, Origin},
+    renderer::{Layer, StickerRenderer},
+    shapes::Circle,
+    util::{Resize, Rows},
+    RenderHelper, CAVEINFO_UNIT_MARGIN, CAVEINFO_WIDTH, COORD_FACTOR, GRID_FACTOR, HEADER_BACKGROUND, HEATMAP_COLD_COLOR,
+    HEATMAP_HOT_COLOR, HEATMAP_MAX_RADIUS, HEATMAP_MIN_RADIUS, MAPTILES_BACKGROUND,
+};
+use crate::{
+    assets::AssetManager,
+    caveinfo::{CaveInfo, CaveUnit},
+    errors::CaveripperError,
+    layout::Layout,
+    point::Point,
+    query::EntityMatcher,
+};
+
+const HEATMAP_GRID_FACTOR: f32 = GRID_FACTOR * 0.75;
+const HEATMAP_COORD_FACTOR: f32 = COORD_FACTOR * 0.75;
+
+/// How often a kind of object spawned in each spawn point of each map unit, accumulated
+/// over many layouts of the same sublevel.
+///
+/// Layouts are binned by which spawn point of which unit the object came from rather than
+/// by world position, since the arrangement of units differs from seed to seed but the
+/// units themselves don't. Rotations of the same unit share a bin. Objects placed in door
+/// seams (e.g. gates) aren't counted since they don't belong to a spawn point.
+#[derive(Debug, Clone, Default)]
+pub struct SpawnHeatmap {
+    /// Keyed by unit folder name, then indexed the same as [CaveUnit::spawnpoints].
+    counts: HashMap<String, Vec<u32>>,
+    pub num_layouts: u32,
+}
+
+impl SpawnHeatmap {
+    /// Records which spawn points in the layout hold at least one object matching `object`.
+    pub fn add_layout(&mut self, layout: &Layout, object: &EntityMatcher) {
+        self.num_layouts += 1;
+        for map_unit in layout.map_units.iter() {
+            for (i, sp) in map_unit.spawnpoints.iter().enumerate() {
+                if sp.contains.iter().any(|so| object.matches(so)) {
+                    let counts = self
+                        .counts
+                        .entry(map_unit.unit.unit_folder_name.clone())
+                        .or_insert_with(|| vec![0; map_unit.unit.spawnpoints.len()]);
+                    counts[i] += 1;
+                }
+            }
+        }
+    }
+
+    /// Combines two heatmaps of the same sublevel, e.g. ones accumulated on different threads.
+    pub fn merge(mut self, other: SpawnHeatmap) -> SpawnHeatmap {
+        self.num_layouts += other.num_layouts;
+        for (unit_name, other_counts) in other.counts {
+            let counts = self.counts.entry(unit_name).or_insert_with(|| vec![0; other_counts.len()]);
+            for (count, other_count) in counts.iter_mut().zip(other_counts) {
+                *count += other_count;
+            }
+        }
+        self
+    }
+
+    /// Number of layouts in which the given spawn point of the named unit held the object.
+    pub fn count(&self, unit_name: &str, spawnpoint_idx: usize) -> u32 {
+        self.counts
+            .get(unit_name)
+            .and_then(|counts| counts.get(spawnpoint_idx))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// Renders every unit that the object spawned in at least once, with a circle over each
+/// spawn point sized and colored by how often the object spawned there.
+pub fn render_spawn_heatmap<M: AssetManager>(
+    caveinfo: &CaveInfo,
+    heatmap: &SpawnHeatmap,
+    object_name: &str,
+    helper: &RenderHelper<M>,
+) -> Result<RgbaImage, CaveripperError> {
+    let max_count = heatmap.counts.values().flatten().copied().max().unwrap_or(0);
+
+    let mut layer = Layer::new();
+    layer.set_margin(CAVEINFO_UNIT_MARGIN);
+    layer.set_background_color(MAPTILES_BACKGROUND);
+    layer.place(
+        helper.cropped_text(
+            format!("{}: {} over {} layouts", caveinfo.long_name(), object_name, heatmap.num_layouts),
+            42.0,
+            0,
+            HEADER_BACKGROUND,
+        ),
+        Point([0.0, 0.0]),
+        Origin::TopLeft,
+    );
+
+    let mut unit_box = Rows::new(CAVEINFO_WIDTH, CAVEINFO_UNIT_MARGIN, CAVEINFO_UNIT_MARGIN);
+    let units = caveinfo
+        .cave_units
+        .iter()
+        .filter(|unit| unit.rotation == 0)
+        .filter_map(|unit| heatmap.counts.get(&unit.unit_folder_name).map(|counts| (unit, counts)))
+        .sorted_by_key(|(_, counts)| std::cmp::Reverse(counts.iter().max().copied().unwrap_or(0)));
+    for (unit, counts) in units {
+        unit_box.add(render_unit_heatmap(unit, counts, heatmap.num_layouts, max_count, helper));
+    }
+    layer.place_relative(
+        unit_box,
+        Origin::TopLeft,
+        Offset {
+            from: Origin::BottomLeft,
+            amount: Point([0.0, CAVEINFO_UNIT_MARGIN]),
+        },
+    );
+
+    let mut renderer = StickerRenderer::new();
+    renderer.set_global_background_color(MAPTILES_BACKGROUND);
+    renderer.add_layer(layer);
+    Ok(renderer.render(helper.mgr))
+}
+
+fn render_unit_heatmap<'h, M: AssetManager>(
+    unit: &'h CaveUnit,
+    counts: &[u32],
+    num_layouts: u32,
+    max_count: u32,
+    helper: &'h RenderHelper<M>,
+) -> Layer<'h, M> {
+    let mut unit_layer = Layer::new();
+    unit_layer.place(
+        Resize::new(
+            unit,
+            unit.width as f32 * HEATMAP_GRID_FACTOR,
+            unit.height as f32 * HEATMAP_GRID_FACTOR,
+            FilterType::Nearest,
+        ),
+        Point([0.0, 0.0]),
+        Origin::TopLeft,
+    );
+
+    let offset = unit.center() * HEATMAP_GRID_FACTOR;
+    let mut label_layer = Layer::new();
+    for (sp, count) in unit.spawnpoints.iter().zip(counts).filter(|(_, count)| **count > 0) {
+        let heat = *count as f32 / max_count as f32;
+        let pos = sp.pos.two_d() * HEATMAP_COORD_FACTOR + offset;
+        unit_layer.place(
+            Circle {
+                radius: HEATMAP_MIN_RADIUS + (HEATMAP_MAX_RADIUS - HEATMAP_MIN_RADIUS) * heat,
+                color: heat_color(heat).into(),
+                ..Default::default()
+            },
+            pos,
+            Origin::Center,
+        );
+        label_layer.place(
+            helper.cropped_text(
+                format!("{:.0}%", *count as f32 / num_layouts as f32 * 100.0),
+                18.0,
+                2,
+                [255, 255, 255, 255],
+            ),
+            pos,
+            Origin::Center,
+        );
+    }
+    // Labels go on top of every circle so nearby spawn points don't cover each other's text.
+    unit_layer.place(label_layer, Point([0.0, 0.0]), Origin::TopLeft);
+
+    let mut layer = Layer::new();
+    layer.place(
+        helper.cropped_text(&unit.unit_folder_name, 24.0, 0, HEADER_BACKGROUND),
+        Point([0.0, 0.0]),
+        Origin::TopLeft,
+    );
+    layer.place_relative(
+        unit_layer,
+        Origin::TopLeft,
+        Offset {
+            from: Origin::BottomLeft,
+            amount: Point([0.0, CAVEINFO_UNIT_MARGIN / 2.0]),
+        },
+    );
+    layer
+}
+
+/// Interpolates between the cold and hot heatmap colors. `heat` is from 0.0 to 1.0.
+fn heat_color(heat: f32) -> [u8; 4] {
+    let mut color = [0; 4];
+    for (i, c) in color.iter_mut().enumerate() {
+        let cold = HEATMAP_COLD_COLOR[i] as f32;
+        let hot = HEATMAP_HOT_COLOR[i] as f32;
+        *c = (cold + (hot - cold) * heat.clamp(0.0, 1.0)).round() as u8;
+    }
+    color
+}
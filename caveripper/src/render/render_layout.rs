@@ -1,10 +1,19 @@
-use std::{borrow::Cow, cell::RefCell, cmp::max};
+use std::{borrow::Cow, cell::RefCell, cmp::max, marker::PhantomData};
 
-use clap::Args;
-use image::{imageops::FilterType, RgbaImage};
+use clap::{Args, ValueEnum};
+use image::{
+    imageops::{crop_imm, replace, rotate90, FilterType},
+    RgbaImage,
+};
 use log::info;
+use serde::Serialize;
 
-use super::{util::Resize, RenderHelper};
+use super::{
+    coords::Bounds,
+    render_caveinfo::group_color,
+    util::{Colorize, CropAbsolute, Resize},
+    DepthShadedCaveUnit, RenderHelper,
+};
 use crate::{
     assets::AssetManager,
     caveinfo::{CapInfo, TekiInfo},
@@ -15,15 +24,19 @@ use crate::{
         coords::Origin,
         render_spawn_object,
         renderer::{Layer, StickerRenderer},
-        shapes::{Circle, Line},
-        CARRY_PATH_COLOR, COORD_FACTOR, DISTANCE_SCORE_TEXT_COLOR, GRID_COLOR, GRID_FACTOR, LAYOUT_BACKGROUND_COLOR,
-        QUICKGLANCE_CIRCLE_RADIUS, QUICKGLANCE_EXIT_COLOR, QUICKGLANCE_IVORY_CANDYPOP_COLOR, QUICKGLANCE_ONION_BLUE, QUICKGLANCE_ONION_RED,
-        QUICKGLANCE_ONION_YELLOW, QUICKGLANCE_ROAMING_COLOR, QUICKGLANCE_SHIP_COLOR, QUICKGLANCE_TREASURE_COLOR,
-        QUICKGLANCE_VIOLET_CANDYPOP_COLOR, SCORE_TEXT_COLOR, WAYPOINT_COLOR,
+        shapes::{Circle, Line, Rectangle},
+        CARRY_PATH_COLOR, COORD_FACTOR, DISTANCE_SCORE_TEXT_COLOR, GATE_HP_TEXT_COLOR, GAUGE_NEEDLE_RANGE, GAUGE_PING_RANGE,
+        GAUGE_RANGE_BORDER_COLOR, GAUGE_RANGE_BORDER_THICKNESS, GAUGE_RANGE_COLOR, GRID_COLOR, GRID_FACTOR, LAYOUT_BACKGROUND_COLOR,
+        QUICKGLANCE_CIRCLE_OPACITY, QUICKGLANCE_CIRCLE_RADIUS, QUICKGLANCE_EXIT_COLOR, QUICKGLANCE_GLYPH_COLOR,
+        QUICKGLANCE_IVORY_CANDYPOP_COLOR, QUICKGLANCE_ONION_BLUE, QUICKGLANCE_ONION_RED, QUICKGLANCE_ONION_YELLOW,
+        QUICKGLANCE_ROAMING_COLOR, QUICKGLANCE_SHIP_COLOR, QUICKGLANCE_TREASURE_COLOR, QUICKGLANCE_VIOLET_CANDYPOP_COLOR, SCORE_TEXT_COLOR,
+        SHADOW_COLOR, SHADOW_OFFSET, SHADOW_OPACITY, SKELETON_DOOR_COLOR, SKELETON_DOOR_RADIUS, SKELETON_LINE_COLOR, SPAWN_PUSH_LINE_COLOR,
+        SPAWN_RADIUS_COLOR, START_ROOM_DIM_COLOR, TEKI_GROUP_LABEL_OFFSET, UNIT_NAME_CHAR_WIDTH, UNIT_NAME_FONT_SIZE, UNIT_NAME_TEXT_COLOR,
+        WAYPOINT_COLOR, WORLD_COORDS_LABEL_OFFSET, WORLD_COORDS_LINE_COLOR, WORLD_COORDS_TEXT_COLOR,
     },
 };
 
-#[derive(Default, Debug, Args)]
+#[derive(Default, Debug, Clone, Args)]
 #[clap(next_help_heading = "Rendering options")]
 pub struct LayoutRenderOptions {
     /// Draw grid lines corresponding to map unit grid boundaries.
@@ -34,6 +47,25 @@ pub struct LayoutRenderOptions {
     #[clap(long, short='q', default_value_t=true, action=clap::ArgAction::Set)]
     pub quickglance: bool,
 
+    /// Radius of the quickglance circles, in pixels. Defaults to 80.
+    #[clap(long)]
+    pub quickglance_radius: Option<f32>,
+
+    /// Opacity of the quickglance circles, from 0.0 to 1.0. Defaults to 0.45.
+    #[clap(long)]
+    pub quickglance_opacity: Option<f32>,
+
+    /// Color scheme for the quickglance circles. `deuteranopia` avoids relying on
+    /// red/green differences, and `grayscale` only varies brightness.
+    #[clap(long, value_enum, default_value_t)]
+    pub palette: Palette,
+
+    /// Write a letter next to each quickglance circle saying what it highlights, e.g.
+    /// "T" for treasures and "E" for exits. Always on with the grayscale palette since
+    /// brightness alone isn't enough to tell every category apart.
+    #[clap(long)]
+    pub quickglance_glyphs: bool,
+
     /// Draw circles indicating gauge activation range around treasures.
     /// The larger circle indicates when the gauge needle will start to go
     /// up, and the smaller circle indicates when you'll start to get
@@ -45,12 +77,204 @@ pub struct LayoutRenderOptions {
     #[clap(long, short = 's')]
     pub draw_score: bool,
 
+    /// Label each door with its door score, i.e. the unit score of the cheapest route
+    /// from the ship to that door. Combine with `--draw-score` to also see the distances
+    /// between doors that the door scores are built from.
+    #[clap(long)]
+    pub draw_door_scores: bool,
+
     /// Draws waypoints and their connections in the layout
     #[clap(long, short = 'w')]
     pub draw_waypoints: bool,
 
     #[clap(long, short = 'c')]
     pub draw_comedown_square: bool,
+
+    /// Label each teki with the number of the spawn group it came from, e.g. 0 for
+    /// easy teki and 1 for hard teki. Seam teki (5) and plants (6) are labeled too.
+    #[clap(long)]
+    pub draw_teki_groups: bool,
+
+    /// Leave out plants, i.e. teki spawned from group 6. Plant species spawned in
+    /// other groups count toward score and are still drawn.
+    #[clap(long)]
+    pub hide_plants: bool,
+
+    /// Write each gate's HP on top of its icon. Gate HP varies between sublevels.
+    #[clap(long)]
+    pub draw_gate_hp: bool,
+
+    /// Draw the spawn radius of spawn points holding group 0 teki bunches. Teki in a
+    /// bunch are placed randomly within this circle and then pushed apart from each
+    /// other, which explains most oddly-shaped clusters.
+    #[clap(long)]
+    pub draw_spawn_radius: bool,
+
+    /// Draw a faint line from where each group 0 teki was first placed to where it
+    /// ended up after its bunch was pushed apart from each other.
+    #[clap(long)]
+    pub draw_spawn_push: bool,
+
+    /// Label each map unit with its internal name. Names too long to fit across the
+    /// unit are wrapped at underscores.
+    #[clap(long)]
+    pub draw_unit_names: bool,
+
+    /// Shade waterboxes according to their depth instead of drawing them flat.
+    /// Deeper water is drawn darker.
+    #[clap(long)]
+    pub shade_waterbox_depth: bool,
+
+    /// Darken the room the ship starts in so the rest of the map stands out more.
+    /// Useful when the starting room is large and draws the eye away from everything else.
+    #[clap(long)]
+    pub dim_start_room: bool,
+
+    /// Rotate the finished image in 90 degree steps so the direction from the ship
+    /// to the exit points as close to straight up as possible. Makes layouts with
+    /// different rotations easier to compare. Object regions and other coordinates
+    /// reported alongside the image are NOT rotated.
+    #[clap(long)]
+    pub normalize_orientation: bool,
+
+    /// Draw circles and lines with hard pixel edges instead of anti-aliasing them.
+    #[clap(long)]
+    pub crisp_shapes: bool,
+
+    /// Only draw the map units and lines showing which doors connect them, leaving out
+    /// every object and overlay. Makes the structure of the layout easier to see, and
+    /// is faster to render.
+    #[clap(long)]
+    pub skeleton: bool,
+
+    /// Draw a grid line on every map unit boundary, labeled with its X or Z position in
+    /// in-game world units along the top and left edges of the map. Positions use the
+    /// same origin as every other coordinate Caveripper reports, so overlays from other
+    /// tools can be lined up against them.
+    #[clap(long)]
+    pub draw_world_coords: bool,
+
+    /// Draw a subtle drop shadow behind each object icon, which helps them stand out
+    /// against light-colored map units.
+    #[clap(long)]
+    pub draw_shadows: bool,
+}
+
+/// Color schemes for quickglance circles.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Palette {
+    #[default]
+    Default,
+    /// Based on the Okabe-Ito palette, which stays distinguishable for the common
+    /// forms of red-green color blindness.
+    Deuteranopia,
+    Grayscale,
+}
+
+impl Palette {
+    fn quickglance_color(self, category: QuickglanceCategory) -> [u8; 4] {
+        use QuickglanceCategory::*;
+        match self {
+            Palette::Default => match category {
+                Treasure => QUICKGLANCE_TREASURE_COLOR,
+                Exit => QUICKGLANCE_EXIT_COLOR,
+                Ship => QUICKGLANCE_SHIP_COLOR,
+                VioletCandypop => QUICKGLANCE_VIOLET_CANDYPOP_COLOR,
+                IvoryCandypop => QUICKGLANCE_IVORY_CANDYPOP_COLOR,
+                Roaming => QUICKGLANCE_ROAMING_COLOR,
+                RedOnion => QUICKGLANCE_ONION_RED,
+                YellowOnion => QUICKGLANCE_ONION_YELLOW,
+                BlueOnion => QUICKGLANCE_ONION_BLUE,
+            },
+            Palette::Deuteranopia => match category {
+                Treasure => [230, 159, 0, 255],
+                Exit => [0, 114, 178, 255],
+                Ship => [0, 158, 115, 255],
+                VioletCandypop => [204, 121, 167, 255],
+                IvoryCandypop => [100, 100, 100, 255],
+                Roaming => [0, 0, 0, 255],
+                RedOnion => [213, 94, 0, 255],
+                YellowOnion => [240, 228, 66, 255],
+                BlueOnion => [86, 180, 233, 255],
+            },
+            Palette::Grayscale => {
+                let level = match category {
+                    Treasure => 245,
+                    Ship => 205,
+                    Exit => 165,
+                    Roaming => 130,
+                    VioletCandypop => 100,
+                    IvoryCandypop => 75,
+                    RedOnion | YellowOnion | BlueOnion => 185,
+                };
+                [level, level, level, 255]
+            }
+        }
+    }
+}
+
+/// The kinds of spawn object that get a quickglance circle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QuickglanceCategory {
+    Treasure,
+    Exit,
+    Ship,
+    VioletCandypop,
+    IvoryCandypop,
+    Roaming,
+    RedOnion,
+    YellowOnion,
+    BlueOnion,
+}
+
+impl QuickglanceCategory {
+    fn of(spawn_object: &SpawnObject) -> Option<Self> {
+        match spawn_object {
+            SpawnObject::Teki(TekiInfo { carrying: Some(_), .. }, _) | SpawnObject::Item(_) => Some(Self::Treasure),
+            SpawnObject::Teki(TekiInfo { internal_name, .. }, _) | SpawnObject::CapTeki(CapInfo { internal_name, .. }, _) => {
+                match internal_name.to_ascii_lowercase().as_str() {
+                    "whitepom" => Some(Self::IvoryCandypop),
+                    "blackpom" => Some(Self::VioletCandypop),
+                    _ if spawn_object.is_roaming() => Some(Self::Roaming),
+                    _ => None,
+                }
+            }
+            SpawnObject::Hole(_) | SpawnObject::Geyser(_) => Some(Self::Exit),
+            SpawnObject::Ship => Some(Self::Ship),
+            SpawnObject::Onion(color) => match color {
+                0 => Some(Self::BlueOnion),
+                1 => Some(Self::RedOnion),
+                2 => Some(Self::YellowOnion),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn glyph(self) -> &'static str {
+        match self {
+            Self::Treasure => "T",
+            Self::Exit => "E",
+            Self::Ship => "S",
+            Self::VioletCandypop => "V",
+            Self::IvoryCandypop => "I",
+            Self::Roaming => "!",
+            Self::RedOnion => "R",
+            Self::YellowOnion => "Y",
+            Self::BlueOnion => "B",
+        }
+    }
+}
+
+/// The area of a rendered layout image occupied by a single spawn object.
+#[derive(Debug, Clone, Serialize)]
+pub struct ObjectRegion {
+    pub name: String,
+    /// In-game coordinates of the object.
+    pub pos: Point<3, f32>,
+    /// Pixel coordinates of the object's icon in the rendered image.
+    pub topleft: Point<2, f32>,
+    pub bottomright: Point<2, f32>,
 }
 
 pub fn render_layout<M: AssetManager>(
@@ -58,15 +282,53 @@ pub fn render_layout<M: AssetManager>(
     helper: &RenderHelper<M>,
     options: LayoutRenderOptions,
 ) -> Result<RgbaImage, CaveripperError> {
+    render_layout_with_regions(layout, helper, options).map(|(image, _)| image)
+}
+
+/// Renders a layout the same way as [render_layout], and additionally returns the
+/// pixel region of every spawn object in the resulting image. Useful for building
+/// image maps or other interactive views on top of the rendered layout.
+///
+/// Regions are always in the coordinates of the unrotated image, even if
+/// `normalize_orientation` is set.
+pub fn render_layout_with_regions<M: AssetManager>(
+    layout: &Layout,
+    helper: &RenderHelper<M>,
+    options: LayoutRenderOptions,
+) -> Result<(RgbaImage, Vec<ObjectRegion>), CaveripperError> {
+    let (renderer, regions) = layout_renderer(layout, helper, &options)?;
+    Ok((orient_image(renderer.render_shifted(helper.mgr), layout, &options), regions))
+}
+
+/// Lays out everything [render_layout_with_regions] draws without drawing it yet, so the
+/// size of the image is known up front.
+fn layout_renderer<'a, M: AssetManager>(
+    layout: &'a Layout,
+    helper: &'a RenderHelper<M>,
+    options: &LayoutRenderOptions,
+) -> Result<(StickerRenderer<'a, M>, Vec<ObjectRegion>), CaveripperError> {
     info!("Drawing layout image...");
+    let mut regions = Vec::new();
 
     let mut renderer = StickerRenderer::new();
     renderer.set_global_background_color(LAYOUT_BACKGROUND_COLOR);
 
     /* Map Units */
-    let map_unit_layer = render_map_units(layout.map_units.iter());
+    let map_unit_layer = render_map_units(layout.map_units.iter(), options.shade_waterbox_depth, options.dim_start_room);
     renderer.add_layer(map_unit_layer);
 
+    /* Skeleton */
+    // Skips every pass below, so there are no objects and no regions.
+    if options.skeleton {
+        renderer.add_layer(render_door_connections(
+            layout,
+            SKELETON_LINE_COLOR,
+            SKELETON_DOOR_COLOR,
+            options.crisp_shapes,
+        ));
+        return Ok((renderer, regions));
+    }
+
     /* Waypoints */
     if options.draw_waypoints {
         let mut waypoint_circle_layer = Layer::new();
@@ -76,6 +338,7 @@ pub fn render_layout<M: AssetManager>(
                 Circle {
                     radius: wp.r * COORD_FACTOR / 1.7,
                     color: WAYPOINT_COLOR.into(),
+                    crisp: options.crisp_shapes,
                     ..Default::default()
                 },
                 wp.pos.two_d() * COORD_FACTOR,
@@ -98,6 +361,7 @@ pub fn render_layout<M: AssetManager>(
                         shorten_end: 6.0,
                         forward_arrow: true,
                         color: CARRY_PATH_COLOR.into(),
+                        crisp: options.crisp_shapes,
                         ..Default::default()
                     },
                     Point([0.0, 0.0]),
@@ -108,90 +372,217 @@ pub fn render_layout<M: AssetManager>(
         renderer.add_layer(waypoint_arrow_layer);
     }
 
-    /* Spawn Objects */
-    let mut spawn_object_layer = Layer::new();
-    let mut quickglance_circle_layer = Layer::new();
-    quickglance_circle_layer.set_opacity(0.45);
-
-    for (spawn_object, pos) in layout.get_spawn_objects() {
-        let so_renderable = render_spawn_object(Cow::Borrowed(spawn_object), helper.mgr);
-        spawn_object_layer.place(so_renderable, pos.two_d() * COORD_FACTOR, Origin::Center);
+    /* Group 0 Spawn Radii */
+    if options.draw_spawn_radius {
+        let mut spawn_radius_layer = Layer::new();
+        spawn_radius_layer.set_opacity(0.3);
+        for sp in layout.map_units.iter().flat_map(|unit| unit.spawnpoints.iter()) {
+            let is_bunch = sp.spawnpoint_unit.group == 0 && sp.contains.iter().any(|so| matches!(so, SpawnObject::Teki(..)));
+            if is_bunch {
+                spawn_radius_layer.place(
+                    Circle {
+                        radius: sp.spawnpoint_unit.radius * COORD_FACTOR,
+                        color: SPAWN_RADIUS_COLOR.into(),
+                        crisp: options.crisp_shapes,
+                        ..Default::default()
+                    },
+                    sp.pos.two_d() * COORD_FACTOR,
+                    Origin::Center,
+                );
+            }
+        }
+        renderer.add_layer(spawn_radius_layer);
+    }
 
-        // Quickglance Circles
-        if options.quickglance {
-            let color = match spawn_object {
-                SpawnObject::Teki(TekiInfo { carrying: Some(_), .. }, _) | SpawnObject::Item(_) => Some(QUICKGLANCE_TREASURE_COLOR),
-                SpawnObject::Teki(TekiInfo { internal_name, .. }, _) | SpawnObject::CapTeki(CapInfo { internal_name, .. }, _) => {
-                    match internal_name.to_ascii_lowercase().as_str() {
-                        "whitepom" => Some(QUICKGLANCE_IVORY_CANDYPOP_COLOR),
-                        "blackpom" => Some(QUICKGLANCE_VIOLET_CANDYPOP_COLOR),
-                        "minihoudai" | "kumochappy" | "leafchappy" | "bigtreasure" => Some(QUICKGLANCE_ROAMING_COLOR),
-                        _ => None,
-                    }
-                }
-                SpawnObject::Hole(_) | SpawnObject::Geyser(_) => Some(QUICKGLANCE_EXIT_COLOR),
-                SpawnObject::Ship => Some(QUICKGLANCE_SHIP_COLOR),
-                SpawnObject::Onion(color) => match color {
-                    0 => Some(QUICKGLANCE_ONION_BLUE),
-                    1 => Some(QUICKGLANCE_ONION_RED),
-                    2 => Some(QUICKGLANCE_ONION_YELLOW),
-                    _ => None,
-                },
+    /* Group 0 Push-Apart Lines */
+    if options.draw_spawn_push {
+        let mut spawn_push_layer = Layer::new();
+        spawn_push_layer.set_opacity(0.5);
+        for sp in layout.map_units.iter().flat_map(|unit| unit.spawnpoints.iter()) {
+            let teki_offsets = sp.contains.iter().filter_map(|so| match so {
+                SpawnObject::Teki(_, offset) => Some(*offset),
                 _ => None,
-            };
-            if let Some(color) = color {
-                quickglance_circle_layer.place(
-                    Circle {
-                        radius: QUICKGLANCE_CIRCLE_RADIUS,
-                        color: color.into(),
+            });
+            for (initial, pushed) in sp.initial_teki_offsets.iter().zip(teki_offsets) {
+                spawn_push_layer.place(
+                    Line {
+                        start: ((sp.pos + *initial) * COORD_FACTOR).two_d(),
+                        end: ((sp.pos + pushed) * COORD_FACTOR).two_d(),
+                        color: SPAWN_PUSH_LINE_COLOR.into(),
+                        crisp: options.crisp_shapes,
                         ..Default::default()
                     },
+                    Point([0.0, 0.0]),
+                    Origin::TopLeft,
+                );
+            }
+        }
+        renderer.add_layer(spawn_push_layer);
+    }
+
+    /* Treasure Gauge Ranges */
+    if options.draw_gauge_range {
+        let mut gauge_range_layer = Layer::new();
+        for (spawn_object, pos) in layout.get_spawn_objects() {
+            if QuickglanceCategory::of(spawn_object) != Some(QuickglanceCategory::Treasure) {
+                continue;
+            }
+            for range in [GAUGE_NEEDLE_RANGE, GAUGE_PING_RANGE] {
+                gauge_range_layer.place(
+                    Circle {
+                        radius: range * COORD_FACTOR,
+                        border_thickness: GAUGE_RANGE_BORDER_THICKNESS,
+                        color: GAUGE_RANGE_COLOR.into(),
+                        border_color: GAUGE_RANGE_BORDER_COLOR.into(),
+                        crisp: options.crisp_shapes,
+                    },
                     pos.two_d() * COORD_FACTOR,
                     Origin::Center,
                 );
             }
         }
-    }
-    renderer.add_layer(quickglance_circle_layer);
-    renderer.add_layer(spawn_object_layer);
 
-    /* Unit Grid */
-    if options.draw_grid {
-        let mut grid_layer = Layer::new();
+        // The outer circles reach far past the edges of the map, which would otherwise pad the image with empty space.
         let map_dims = layout.map_units.iter().fold((0, 0), |dims, unit| {
             (
                 max(dims.0, unit.x + unit.unit.width as i32),
                 max(dims.1, unit.z + unit.unit.height as i32),
             )
         });
+        renderer.add_layer(Layer::of(CropAbsolute {
+            inner: gauge_range_layer,
+            bounds: Bounds {
+                topleft: Point([0.0, 0.0]),
+                bottomright: Point([map_dims.0 as f32 * GRID_FACTOR, map_dims.1 as f32 * GRID_FACTOR]),
+            },
+            phantom: PhantomData,
+        }));
+    }
 
-        for x in 0..map_dims.0 {
-            grid_layer.place(
-                Line {
-                    start: Point([x as f32 * GRID_FACTOR, 0.0]),
-                    end: Point([x as f32 * GRID_FACTOR, map_dims.1 as f32 * GRID_FACTOR]),
-                    color: GRID_COLOR.into(),
-                    ..Default::default()
+    /* Spawn Objects */
+    let mut spawn_object_layer = Layer::new();
+    let mut shadow_layer = Layer::new();
+    shadow_layer.set_opacity(SHADOW_OPACITY);
+    let mut quickglance_circle_layer = Layer::new();
+    quickglance_circle_layer.set_opacity(options.quickglance_opacity.unwrap_or(QUICKGLANCE_CIRCLE_OPACITY));
+    let quickglance_radius = options.quickglance_radius.unwrap_or(QUICKGLANCE_CIRCLE_RADIUS);
+    let mut quickglance_glyph_layer = Layer::new();
+    let draw_glyphs = options.quickglance_glyphs || options.palette == Palette::Grayscale;
+
+    for (spawn_object, pos) in layout.get_spawn_objects() {
+        if options.hide_plants && spawn_object.is_plant() {
+            continue;
+        }
+        let so_renderable = render_spawn_object(Cow::Borrowed(spawn_object), helper.mgr);
+        let bounds = Origin::Center.to_bounds(&so_renderable, pos.two_d() * COORD_FACTOR);
+        regions.push(ObjectRegion {
+            name: spawn_object.name().to_string(),
+            pos,
+            topleft: bounds.topleft,
+            bottomright: bounds.bottomright,
+        });
+        spawn_object_layer.place(so_renderable, pos.two_d() * COORD_FACTOR, Origin::Center);
+
+        // Drop Shadows
+        if options.draw_shadows {
+            shadow_layer.place(
+                Colorize {
+                    renderable: render_spawn_object(Cow::Borrowed(spawn_object), helper.mgr),
+                    color: SHADOW_COLOR.into(),
+                    phantom: PhantomData,
                 },
-                Point::zero(),
-                Origin::TopLeft,
+                pos.two_d() * COORD_FACTOR + SHADOW_OFFSET,
+                Origin::Center,
             );
         }
 
-        for y in 0..map_dims.1 {
-            grid_layer.place(
-                Line {
-                    start: Point([0.0, y as f32 * GRID_FACTOR]),
-                    end: Point([map_dims.0 as f32 * GRID_FACTOR, y as f32 * GRID_FACTOR]),
-                    color: GRID_COLOR.into(),
+        // Quickglance Circles
+        if options.quickglance
+            && let Some(category) = QuickglanceCategory::of(spawn_object)
+        {
+            quickglance_circle_layer.place(
+                Circle {
+                    radius: quickglance_radius,
+                    color: options.palette.quickglance_color(category).into(),
+                    crisp: options.crisp_shapes,
                     ..Default::default()
                 },
-                Point::zero(),
-                Origin::TopLeft,
+                pos.two_d() * COORD_FACTOR,
+                Origin::Center,
             );
+            if draw_glyphs {
+                quickglance_glyph_layer.place(
+                    helper.cropped_text(category.glyph(), 32.0, 3, QUICKGLANCE_GLYPH_COLOR),
+                    pos.two_d() * COORD_FACTOR + Point([quickglance_radius, -quickglance_radius]) * 0.7,
+                    Origin::Center,
+                );
+            }
         }
+    }
+    renderer.add_layer(quickglance_circle_layer);
+    renderer.add_layer(shadow_layer);
+    renderer.add_layer(spawn_object_layer);
+    renderer.add_layer(quickglance_glyph_layer);
 
-        renderer.add_layer(grid_layer);
+    /* Teki Groups */
+    if options.draw_teki_groups {
+        let mut teki_group_layer = Layer::new();
+        for (spawn_object, pos) in layout.get_spawn_objects() {
+            if let SpawnObject::Teki(TekiInfo { group, .. }, _) = spawn_object
+                && !(options.hide_plants && spawn_object.is_plant())
+            {
+                teki_group_layer.place(
+                    helper.cropped_text(format!("{group}"), 24.0, 2, group_color(*group)),
+                    pos.two_d() * COORD_FACTOR + TEKI_GROUP_LABEL_OFFSET,
+                    Origin::Center,
+                );
+            }
+        }
+        renderer.add_layer(teki_group_layer);
+    }
+
+    /* Gate HP */
+    if options.draw_gate_hp {
+        let mut gate_hp_layer = Layer::new();
+        for (spawn_object, pos) in layout.get_spawn_objects() {
+            if let SpawnObject::Gate(gateinfo, _) = spawn_object {
+                gate_hp_layer.place(
+                    helper.cropped_text(format!("{}HP", gateinfo.health.round() as u32), 28.0, 2, GATE_HP_TEXT_COLOR),
+                    pos.two_d() * COORD_FACTOR,
+                    Origin::Center,
+                );
+            }
+        }
+        renderer.add_layer(gate_hp_layer);
+    }
+
+    /* Unit Grid */
+    if options.draw_grid {
+        renderer.add_layer(render_grid(layout, GRID_COLOR, options.crisp_shapes));
+    }
+
+    /* World Coordinates */
+    if options.draw_world_coords {
+        renderer.add_layer(render_world_coords(layout, helper, options.crisp_shapes));
+    }
+
+    /* Unit Names */
+    if options.draw_unit_names {
+        let mut unit_name_layer = Layer::new();
+        for unit in layout.map_units.iter() {
+            let max_chars = (unit.unit.width as f32 * GRID_FACTOR / UNIT_NAME_CHAR_WIDTH) as usize;
+            unit_name_layer.place(
+                helper.cropped_text(
+                    wrap_unit_name(&unit.unit.unit_folder_name, max_chars),
+                    UNIT_NAME_FONT_SIZE,
+                    2,
+                    UNIT_NAME_TEXT_COLOR,
+                ),
+                unit_center(unit),
+                Origin::Center,
+            );
+        }
+        renderer.add_layer(unit_name_layer);
     }
 
     /* Score */
@@ -209,10 +600,7 @@ pub fn render_layout<M: AssetManager>(
             };
             score_text_layer.place(
                 helper.cropped_text(text, 24.0, 2, SCORE_TEXT_COLOR),
-                Point([
-                    (unit.x as f32 + (unit.unit.width as f32 / 2.0)) * GRID_FACTOR,
-                    (unit.z as f32 + (unit.unit.height as f32 / 2.0)) * GRID_FACTOR,
-                ]),
+                unit_center(unit),
                 Origin::Center,
             );
 
@@ -229,6 +617,7 @@ pub fn render_layout<M: AssetManager>(
                             shorten_start: 8.0,
                             shorten_end: 8.0,
                             color: DISTANCE_SCORE_TEXT_COLOR.into(),
+                            crisp: options.crisp_shapes,
                             ..Default::default()
                         },
                         Point::zero(),
@@ -251,14 +640,349 @@ pub fn render_layout<M: AssetManager>(
         renderer.add_layer(score_text_layer);
     }
 
-    Ok(renderer.render(helper.mgr))
+    /* Door Scores */
+    if options.draw_door_scores {
+        let mut door_score_layer = Layer::new();
+        for (idx, unit) in layout.map_units.iter().enumerate() {
+            for door in unit.doors.iter() {
+                let door = RefCell::borrow(door);
+                let Some(door_score) = door.door_score else {
+                    continue;
+                };
+                // Both sides of a connection share the same score and position, so only
+                // label it from the unit that comes first.
+                let adjacent_idx = door
+                    .adjacent_door
+                    .as_ref()
+                    .and_then(|d| d.upgrade())
+                    .and_then(|d| RefCell::borrow(&d).parent_idx);
+                if adjacent_idx.is_some_and(|adjacent_idx| adjacent_idx < idx) {
+                    continue;
+                }
+                door_score_layer.place(
+                    helper.cropped_text(format!("{door_score}"), 24.0, 2, DISTANCE_SCORE_TEXT_COLOR),
+                    door.center().two_d() * COORD_FACTOR,
+                    Origin::Center,
+                );
+            }
+        }
+        renderer.add_layer(door_score_layer);
+    }
+
+    // Large teki near the top or left edge of the map push the whole image over.
+    let origin_offset = renderer.origin_offset();
+    for region in regions.iter_mut() {
+        region.topleft += origin_offset;
+        region.bottomright += origin_offset;
+    }
+    Ok((renderer, regions))
+}
+
+/// Applies `normalize_orientation` to a finished layout image.
+fn orient_image(mut image: RgbaImage, layout: &Layout, options: &LayoutRenderOptions) -> RgbaImage {
+    if options.normalize_orientation {
+        for _ in 0..north_up_rotations(layout) {
+            image = rotate90(&image);
+        }
+    }
+    image
+}
+
+/// Renders a layout scaled to fit within `max_width` by `max_height` pixels, keeping its
+/// aspect ratio. Useful for UIs with a fixed display area. Also returns the scale that was
+/// applied, so pixel coordinates in the full-size image (e.g. the regions returned by
+/// [render_layout_with_regions]) can be mapped onto the fitted one by multiplying by it.
+///
+/// The scale is worked out from the size the layout would be drawn at, and the layout is
+/// then drawn at that scale directly rather than drawn at full size and shrunk afterwards.
+/// Layouts that already fit are drawn at full size with a scale of 1.0; they're never
+/// enlarged.
+pub fn render_layout_fit<M: AssetManager>(
+    layout: &Layout,
+    helper: &RenderHelper<M>,
+    options: LayoutRenderOptions,
+    max_width: u32,
+    max_height: u32,
+) -> Result<(RgbaImage, f32), CaveripperError> {
+    if max_width == 0 || max_height == 0 {
+        return Err(CaveripperError::RenderingError);
+    }
+
+    let (renderer, _) = layout_renderer(layout, helper, &options)?;
+    let dims = renderer.shifted_dims();
+    // Odd numbers of quarter turns swap the width and height of the finished image.
+    let (width, height) = if options.normalize_orientation && north_up_rotations(layout) % 2 == 1 {
+        (dims[1], dims[0])
+    } else {
+        (dims[0], dims[1])
+    };
+    let scale = f32::min(max_width as f32 / width, max_height as f32 / height);
+    if scale >= 1.0 {
+        return Ok((orient_image(renderer.render_shifted(helper.mgr), layout, &options), 1.0));
+    }
+    Ok((
+        orient_image(renderer.render_shifted_scaled(helper.mgr, scale), layout, &options),
+        scale,
+    ))
+}
+
+/// Renders a layout and slices the image into `tile_size` by `tile_size` pixel tiles, for
+/// slippy-map style viewers that would struggle with one huge image. Each tile comes with
+/// its column and row, counting from the top left, and tiles are returned in row-major
+/// order.
+///
+/// The whole layout is rendered once and then cut up, so anything straddling a tile
+/// boundary shows up in every tile it overlaps and tiles line up seamlessly. Tiles along
+/// the right and bottom edges are padded with the layout background color to keep every
+/// tile the same size.
+pub fn render_layout_tiled<M: AssetManager>(
+    layout: &Layout,
+    helper: &RenderHelper<M>,
+    options: LayoutRenderOptions,
+    tile_size: u32,
+) -> Result<Vec<(u32, u32, RgbaImage)>, CaveripperError> {
+    if tile_size == 0 {
+        return Err(CaveripperError::RenderingError);
+    }
+
+    let image = render_layout(layout, helper, options)?;
+    let columns = image.width().div_ceil(tile_size);
+    let rows = image.height().div_ceil(tile_size);
+    let mut tiles = Vec::with_capacity((columns * rows) as usize);
+    for tile_y in 0..rows {
+        for tile_x in 0..columns {
+            let (x, y) = (tile_x * tile_size, tile_y * tile_size);
+            let section = crop_imm(&image, x, y, tile_size.min(image.width() - x), tile_size.min(image.height() - y)).to_image();
+            let mut tile = RgbaImage::from_pixel(tile_size, tile_size, LAYOUT_BACKGROUND_COLOR.into());
+            replace(&mut tile, &section, 0, 0);
+            tiles.push((tile_x, tile_y, tile));
+        }
+    }
+    Ok(tiles)
+}
+
+/// Pixel position of the center of a placed map unit in the rendered layout.
+fn unit_center(unit: &PlacedMapUnit) -> Point<2, f32> {
+    Point([
+        (unit.x as f32 + (unit.unit.width as f32 / 2.0)) * GRID_FACTOR,
+        (unit.z as f32 + (unit.unit.height as f32 / 2.0)) * GRID_FACTOR,
+    ])
+}
+
+/// Splits a unit name into lines of at most `max_chars` characters, breaking at
+/// underscores. Pieces that are too long on their own are cut short and end in '.'.
+pub(super) fn wrap_unit_name(name: &str, max_chars: usize) -> String {
+    let max_chars = max_chars.max(2);
+    let mut lines: Vec<String> = Vec::new();
+    for piece in name.split('_') {
+        let piece = if piece.len() > max_chars {
+            format!("{}.", &piece[..max_chars - 1])
+        } else {
+            piece.to_string()
+        };
+        match lines.last_mut() {
+            Some(line) if line.len() + 1 + piece.len() <= max_chars => {
+                line.push('_');
+                line.push_str(&piece);
+            }
+            _ => lines.push(piece),
+        }
+    }
+    lines.join("\n")
+}
+
+/// Number of clockwise 90 degree rotations needed to make the ship-to-exit vector
+/// point upwards in the rendered image. Layouts without an exit aren't rotated.
+fn north_up_rotations(layout: &Layout) -> usize {
+    let find_pos = |pred: fn(&SpawnObject) -> bool| layout.get_spawn_objects().find(|(so, _)| pred(so)).map(|(_, pos)| pos);
+    let Some(ship_pos) = find_pos(|so| matches!(so, SpawnObject::Ship)) else {
+        return 0;
+    };
+    let Some(exit_pos) = find_pos(|so| matches!(so, SpawnObject::Hole(_))).or_else(|| find_pos(|so| matches!(so, SpawnObject::Geyser(_))))
+    else {
+        return 0;
+    };
+
+    // Image coordinates have +z pointing down, so "up" is the most negative z.
+    // Rotating the image clockwise maps a vector (x, z) to (-z, x).
+    let mut dir = (exit_pos - ship_pos).two_d();
+    let mut best = (0, dir[1]);
+    for rotations in 1..4 {
+        dir = Point([-dir[1], dir[0]]);
+        if dir[1] < best.1 {
+            best = (rotations, dir[1]);
+        }
+    }
+    best.0
+}
+
+/// Grid lines on every map unit boundary, labeled along the top and left edges of the map
+/// with their position in world coordinates.
+///
+/// World coordinates are the ones every position in a [Layout] is given in. Generation
+/// places map units on a grid of 170-unit cells and then shifts the whole map so that its
+/// top-left corner is at (0, 0), so a unit placed at grid cell (x, z) covers world X from
+/// `x * 170` to `(x + width) * 170`, and likewise for Z. X increases to the right and Z
+/// increases downward. Layout images draw world position `p` at pixel `p * COORD_FACTOR`
+/// before margins are added, which puts each label exactly on its grid line. The one
+/// exception is when a large teki pokes out past the top or left edge of the map: then
+/// the whole image, grid included, is moved down and right by
+/// [StickerRenderer::origin_offset] to make room for it.
+fn render_world_coords<'h, M: AssetManager>(layout: &Layout, helper: &'h RenderHelper<M>, crisp: bool) -> Layer<'h, M> {
+    let mut line_layer = Layer::new();
+    let mut label_layer = Layer::new();
+    let (width, height) = layout.map_units.iter().fold((0, 0), |dims, unit| {
+        (
+            max(dims.0, unit.x + unit.unit.width as i32),
+            max(dims.1, unit.z + unit.unit.height as i32),
+        )
+    });
+
+    for x in 0..=width {
+        line_layer.place(
+            Line {
+                start: Point([x as f32 * GRID_FACTOR, 0.0]),
+                end: Point([x as f32 * GRID_FACTOR, height as f32 * GRID_FACTOR]),
+                color: WORLD_COORDS_LINE_COLOR.into(),
+                crisp,
+                ..Default::default()
+            },
+            Point::zero(),
+            Origin::TopLeft,
+        );
+        if x > 0 && x < width {
+            label_layer.place(
+                helper.cropped_text(format!("{}", x * 170), 18.0, 2, WORLD_COORDS_TEXT_COLOR),
+                Point([x as f32 * GRID_FACTOR, 0.0]) + WORLD_COORDS_LABEL_OFFSET,
+                Origin::TopLeft,
+            );
+        }
+    }
+    for z in 0..=height {
+        line_layer.place(
+            Line {
+                start: Point([0.0, z as f32 * GRID_FACTOR]),
+                end: Point([width as f32 * GRID_FACTOR, z as f32 * GRID_FACTOR]),
+                color: WORLD_COORDS_LINE_COLOR.into(),
+                crisp,
+                ..Default::default()
+            },
+            Point::zero(),
+            Origin::TopLeft,
+        );
+        if z > 0 && z < height {
+            label_layer.place(
+                helper.cropped_text(format!("{}", z * 170), 18.0, 2, WORLD_COORDS_TEXT_COLOR),
+                Point([0.0, z as f32 * GRID_FACTOR]) + WORLD_COORDS_LABEL_OFFSET,
+                Origin::TopLeft,
+            );
+        }
+    }
+    label_layer.place(
+        helper.cropped_text("0, 0", 18.0, 2, WORLD_COORDS_TEXT_COLOR),
+        WORLD_COORDS_LABEL_OFFSET,
+        Origin::TopLeft,
+    );
+
+    line_layer.place(label_layer, Point::zero(), Origin::TopLeft);
+    line_layer
+}
+
+/// Lines along every map unit boundary across the whole map.
+pub(super) fn render_grid<'a, M: AssetManager + 'a>(layout: &Layout, color: [u8; 4], crisp: bool) -> Layer<'a, M> {
+    let mut grid_layer = Layer::new();
+    let map_dims = layout.map_units.iter().fold((0, 0), |dims, unit| {
+        (
+            max(dims.0, unit.x + unit.unit.width as i32),
+            max(dims.1, unit.z + unit.unit.height as i32),
+        )
+    });
+
+    for x in 0..map_dims.0 {
+        grid_layer.place(
+            Line {
+                start: Point([x as f32 * GRID_FACTOR, 0.0]),
+                end: Point([x as f32 * GRID_FACTOR, map_dims.1 as f32 * GRID_FACTOR]),
+                color: color.into(),
+                crisp,
+                ..Default::default()
+            },
+            Point::zero(),
+            Origin::TopLeft,
+        );
+    }
+
+    for y in 0..map_dims.1 {
+        grid_layer.place(
+            Line {
+                start: Point([0.0, y as f32 * GRID_FACTOR]),
+                end: Point([map_dims.0 as f32 * GRID_FACTOR, y as f32 * GRID_FACTOR]),
+                color: color.into(),
+                crisp,
+                ..Default::default()
+            },
+            Point::zero(),
+            Origin::TopLeft,
+        );
+    }
+
+    grid_layer
+}
+
+/// A line from the center of each map unit to each of its doors that leads into another
+/// unit, with a dot on the door, so the layout reads as a graph of connected units.
+pub(super) fn render_door_connections<'a, M: AssetManager + 'a>(
+    layout: &Layout,
+    line_color: [u8; 4],
+    door_color: [u8; 4],
+    crisp: bool,
+) -> Layer<'a, M> {
+    let mut line_layer = Layer::new();
+    let mut door_layer = Layer::new();
+    for unit in layout.map_units.iter() {
+        for door in unit.doors.iter() {
+            let door = RefCell::borrow(door);
+            if door.adjacent_door.is_none() {
+                continue;
+            }
+            let door_pos = door.center().two_d() * COORD_FACTOR;
+            line_layer.place(
+                Line {
+                    start: unit_center(unit),
+                    end: door_pos,
+                    color: line_color.into(),
+                    crisp,
+                    ..Default::default()
+                },
+                Point::zero(),
+                Origin::TopLeft,
+            );
+            door_layer.place(
+                Circle {
+                    radius: SKELETON_DOOR_RADIUS,
+                    color: door_color.into(),
+                    crisp,
+                    ..Default::default()
+                },
+                door_pos,
+                Origin::Center,
+            );
+        }
+    }
+    // Door dots go on top so lines from both sides of a door meet underneath them.
+    line_layer.place(door_layer, Point::zero(), Origin::TopLeft);
+    line_layer
 }
 
 /// Places map unit images for a layout
-fn render_map_units<'a, 'l: 'a, M: AssetManager + 'a>(map_units: impl Iterator<Item = &'a PlacedMapUnit<'l>>) -> Layer<'a, M> {
+pub(super) fn render_map_units<'a, 'l: 'a, M: AssetManager + 'a>(
+    map_units: impl Iterator<Item = &'a PlacedMapUnit<'l>>,
+    shade_waterbox_depth: bool,
+    dim_start_room: bool,
+) -> Layer<'a, M> {
     let mut radar_image_layer = Layer::new();
 
-    for map_unit in map_units {
+    for (i, map_unit) in map_units.enumerate() {
         let unit_def = map_unit.unit;
         let render_pos_x = map_unit.x as f32 * GRID_FACTOR;
         let render_pos_z = map_unit.z as f32 * GRID_FACTOR;
@@ -266,11 +990,33 @@ fn render_map_units<'a, 'l: 'a, M: AssetManager + 'a>(map_units: impl Iterator<I
         // Radar images
         let unit_img_width = unit_def.width as f32 * GRID_FACTOR;
         let unit_img_height = unit_def.height as f32 * GRID_FACTOR;
-        radar_image_layer.place(
-            Resize::new(unit_def, unit_img_width, unit_img_height, FilterType::Nearest),
-            Point([render_pos_x, render_pos_z]),
-            Origin::TopLeft,
-        );
+        if shade_waterbox_depth {
+            radar_image_layer.place(
+                Resize::new(DepthShadedCaveUnit(unit_def), unit_img_width, unit_img_height, FilterType::Nearest),
+                Point([render_pos_x, render_pos_z]),
+                Origin::TopLeft,
+            );
+        } else {
+            radar_image_layer.place(
+                Resize::new(unit_def, unit_img_width, unit_img_height, FilterType::Nearest),
+                Point([render_pos_x, render_pos_z]),
+                Origin::TopLeft,
+            );
+        }
+
+        // The ship is always placed in the first map unit.
+        if dim_start_room && i == 0 {
+            radar_image_layer.place(
+                Rectangle {
+                    width: unit_img_width,
+                    height: unit_img_height,
+                    color: START_ROOM_DIM_COLOR.into(),
+                    ..Default::default()
+                },
+                Point([render_pos_x, render_pos_z]),
+                Origin::TopLeft,
+            );
+        }
     }
 
     radar_image_layer
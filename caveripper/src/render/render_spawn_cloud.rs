@@ -0,0 +1,99 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use error_stack::{report, Result};
+use image::RgbaImage;
+use rand::random;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use super::{
+    coords::{Offset, Origin},
+    render_layout::render_map_units,
+    render_spawn_object,
+    renderer::{Layer, StickerRenderer},
+    RenderHelper, CAVEINFO_UNIT_MARGIN, COORD_FACTOR, HEADER_BACKGROUND, MAPTILES_BACKGROUND, SPAWN_CLOUD_OPACITY,
+};
+use crate::{assets::AssetManager, errors::CaveripperError, layout::Layout, point::Point, sublevel::Sublevel};
+
+/// Generates layouts for `num_seeds` random seeds, keeps only the ones sharing the most
+/// common structure (see [Layout::structure_slug]), and draws every spawn object from all
+/// of them faintly on top of that one shared map. Spots where objects often land build up
+/// into dense clouds, while rare positions stay faint.
+///
+/// Plants are left out since they'd cover up everything else.
+pub fn render_spawn_cloud<M: AssetManager>(
+    sublevel: &Sublevel,
+    num_seeds: u32,
+    helper: &RenderHelper<M>,
+) -> Result<RgbaImage, CaveripperError> {
+    let caveinfo = helper.mgr.load_caveinfo(sublevel)?;
+
+    let seeds_by_structure = (0..num_seeds)
+        .into_par_iter()
+        .fold(HashMap::<String, Vec<u32>>::new, |mut groups, _| {
+            let seed = random();
+            groups
+                .entry(Layout::generate(seed, caveinfo).structure_slug())
+                .or_default()
+                .push(seed);
+            groups
+        })
+        .reduce(HashMap::new, |mut a, b| {
+            for (structure, seeds) in b {
+                a.entry(structure).or_default().extend(seeds);
+            }
+            a
+        });
+    // Ties are broken by the structure slug itself so the chosen structure doesn't depend
+    // on hash map iteration order.
+    let (_, seeds) = seeds_by_structure
+        .into_iter()
+        .max_by(|(slug_a, seeds_a), (slug_b, seeds_b)| seeds_a.len().cmp(&seeds_b.len()).then_with(|| slug_b.cmp(slug_a)))
+        .ok_or_else(|| report!(CaveripperError::RenderingError))?;
+    let layouts: Vec<Layout> = seeds.iter().map(|seed| Layout::generate(*seed, caveinfo)).collect();
+
+    let mut map_layer = render_map_units(layouts[0].map_units.iter(), false, false);
+    for layout in layouts.iter() {
+        let mut cloud_layer = Layer::new();
+        cloud_layer.set_opacity(SPAWN_CLOUD_OPACITY);
+        for (spawn_object, pos) in layout.get_spawn_objects().filter(|(so, _)| !so.is_plant()) {
+            cloud_layer.place(
+                render_spawn_object(Cow::Borrowed(spawn_object), helper.mgr),
+                pos.two_d() * COORD_FACTOR,
+                Origin::Center,
+            );
+        }
+        map_layer.place(cloud_layer, Point::zero(), Origin::TopLeft);
+    }
+
+    let mut layer = Layer::new();
+    layer.set_margin(CAVEINFO_UNIT_MARGIN);
+    layer.set_background_color(MAPTILES_BACKGROUND);
+    layer.place(
+        helper.cropped_text(
+            format!(
+                "{}: {} out of {} layouts with the same structure",
+                caveinfo.long_name(),
+                layouts.len(),
+                num_seeds
+            ),
+            42.0,
+            0,
+            HEADER_BACKGROUND,
+        ),
+        Point([0.0, 0.0]),
+        Origin::TopLeft,
+    );
+    layer.place_relative(
+        map_layer,
+        Origin::TopLeft,
+        Offset {
+            from: Origin::BottomLeft,
+            amount: Point([0.0, CAVEINFO_UNIT_MARGIN]),
+        },
+    );
+
+    let mut renderer = StickerRenderer::new();
+    renderer.set_global_background_color(MAPTILES_BACKGROUND);
+    renderer.add_layer(layer);
+    Ok(renderer.render(helper.mgr))
+}
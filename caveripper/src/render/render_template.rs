@@ -0,0 +1,76 @@
+use error_stack::Result;
+use image::{
+    imageops::{resize, FilterType},
+    Rgba, RgbaImage,
+};
+
+use super::{
+    canvas::{Canvas, CanvasView},
+    coords::Origin,
+    render_layout::{render_door_connections, render_grid},
+    renderer::{Layer, Render, StickerRenderer},
+    RenderHelper, GRID_FACTOR, TEMPLATE_BACKGROUND_COLOR, TEMPLATE_GRID_COLOR, TEMPLATE_LINE_COLOR, TEMPLATE_OUTLINE_THICKNESS,
+};
+use crate::{assets::AssetManager, caveinfo::CaveUnit, errors::CaveripperError, layout::Layout, point::Point};
+
+/// Renders only the structure of a layout in black on white, meant to be printed and
+/// drawn on by hand: the walls of every map unit, the door connections from skeleton
+/// mode, and a faint grid on the map unit boundaries. No objects are drawn.
+///
+/// The finished image is scaled up by `scale` with nearest-neighbor filtering so the
+/// lines stay sharp at print sizes.
+pub fn render_template<M: AssetManager>(layout: &Layout, helper: &RenderHelper<M>, scale: u32) -> Result<RgbaImage, CaveripperError> {
+    let mut unit_layer = Layer::new();
+    for map_unit in layout.map_units.iter() {
+        unit_layer.place(
+            UnitOutline(map_unit.unit),
+            Point([map_unit.x as f32 * GRID_FACTOR, map_unit.z as f32 * GRID_FACTOR]),
+            Origin::TopLeft,
+        );
+    }
+
+    let mut renderer = StickerRenderer::new();
+    renderer.set_global_background_color(TEMPLATE_BACKGROUND_COLOR);
+    renderer.add_layer(render_grid(layout, TEMPLATE_GRID_COLOR, true));
+    renderer.add_layer(unit_layer);
+    renderer.add_layer(render_door_connections(layout, TEMPLATE_LINE_COLOR, TEMPLATE_LINE_COLOR, true));
+    let image = renderer.render(helper.mgr);
+
+    let scale = scale.max(1);
+    Ok(resize(&image, image.width() * scale, image.height() * scale, FilterType::Nearest))
+}
+
+/// The walls of a map unit, traced from the edges of its radar image.
+struct UnitOutline<'a>(&'a CaveUnit);
+
+impl<M: AssetManager> Render<M> for UnitOutline<'_> {
+    fn render(&self, mut canvas: CanvasView, helper: &M) {
+        let mut unit_canvas = Canvas::new(<Self as Render<M>>::dimensions(self));
+        self.0.render(unit_canvas.view(Point::zero()), helper);
+        canvas.overlay(&trace_edges(&unit_canvas.into_inner()), Point::zero());
+    }
+
+    fn dimensions(&self) -> Point<2, f32> {
+        <CaveUnit as Render<M>>::dimensions(self.0)
+    }
+}
+
+/// Marks every opaque pixel that's within [TEMPLATE_OUTLINE_THICKNESS] pixels of a
+/// transparent one, and clears everything else. The border of the image itself doesn't
+/// count as an edge so doorways on the edge of a unit are left open.
+fn trace_edges(img: &RgbaImage) -> RgbaImage {
+    let t = TEMPLATE_OUTLINE_THICKNESS;
+    let mut edges = RgbaImage::new(img.width(), img.height());
+    for (x, y, pix) in img.enumerate_pixels() {
+        if pix.0[3] == 0 {
+            continue;
+        }
+        let near_transparent = (x.saturating_sub(t)..=(x + t).min(img.width() - 1))
+            .flat_map(|nx| (y.saturating_sub(t)..=(y + t).min(img.height() - 1)).map(move |ny| (nx, ny)))
+            .any(|(nx, ny)| img.get_pixel(nx, ny).0[3] == 0);
+        if near_transparent {
+            edges.put_pixel(x, y, Rgba(TEMPLATE_LINE_COLOR));
+        }
+    }
+    edges
+}
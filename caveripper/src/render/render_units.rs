@@ -0,0 +1,143 @@
+use error_stack::Result;
+use image::{imageops::FilterType, RgbaImage};
+
+use super::{
+    coords::{Offset, Origin},
+    renderer::{Layer, StickerRenderer},
+    shapes::{Circle, Line},
+    util::Resize,
+    RenderHelper, CAVEINFO_MARGIN, CAVEINFO_UNIT_MARGIN, DOOR_MARKER_COLOR, DOOR_MARKER_RADIUS, GRID_FACTOR, HEADER_BACKGROUND,
+    MAPTILES_BACKGROUND,
+};
+use crate::{
+    assets::AssetManager,
+    caveinfo::{CaveInfo, CaveUnit},
+    errors::CaveripperError,
+    point::Point,
+};
+
+const UNIT_SHEET_GRID_FACTOR: f32 = GRID_FACTOR * 0.75;
+const DOOR_ARROW_LENGTH: f32 = UNIT_SHEET_GRID_FACTOR * 0.4;
+
+/// Renders every map unit a sublevel can use in all four rotations, one unit per row,
+/// with each door marked by an arrow pointing out of the unit. Useful for checking door
+/// positions and rotations by eye when debugging generation.
+pub fn render_unit_sheet<M: AssetManager>(caveinfo: &CaveInfo, helper: &RenderHelper<M>) -> Result<RgbaImage, CaveripperError> {
+    let mut layer = Layer::new();
+    layer.set_margin(CAVEINFO_UNIT_MARGIN);
+    layer.set_background_color(MAPTILES_BACKGROUND);
+    layer.place(
+        helper.cropped_text(format!("{}: map units", caveinfo.long_name()), 42.0, 0, HEADER_BACKGROUND),
+        Point([0.0, 0.0]),
+        Origin::TopLeft,
+    );
+
+    for unit in caveinfo.cave_units.iter().filter(|unit| unit.rotation == 0) {
+        let mut rotations = caveinfo
+            .cave_units
+            .iter()
+            .filter(|other| other.unit_folder_name == unit.unit_folder_name)
+            .collect::<Vec<_>>();
+        rotations.sort_by_key(|other| other.rotation);
+
+        let mut row = Layer::new();
+        row.place(
+            helper.cropped_text(
+                format!("{} ({}, {} doors)", unit.unit_folder_name, unit.room_type, unit.num_doors),
+                24.0,
+                0,
+                HEADER_BACKGROUND,
+            ),
+            Point([0.0, 0.0]),
+            Origin::TopLeft,
+        );
+
+        let mut rotations_layer = Layer::new();
+        let mut x = 0.0;
+        for rotated in rotations {
+            let rendered = render_rotated_unit(rotated, helper);
+            rotations_layer.place(rendered, Point([x, 0.0]), Origin::TopLeft);
+            x += rotated.width as f32 * UNIT_SHEET_GRID_FACTOR + CAVEINFO_UNIT_MARGIN;
+        }
+        row.place_relative(
+            rotations_layer,
+            Origin::TopLeft,
+            Offset {
+                from: Origin::BottomLeft,
+                amount: Point([0.0, CAVEINFO_UNIT_MARGIN / 2.0]),
+            },
+        );
+
+        layer.place_relative(
+            row,
+            Origin::TopLeft,
+            Offset {
+                from: Origin::BottomLeft,
+                amount: Point([0.0, CAVEINFO_UNIT_MARGIN]),
+            },
+        );
+    }
+
+    let mut renderer = StickerRenderer::new();
+    renderer.set_global_background_color(MAPTILES_BACKGROUND);
+    renderer.add_layer(layer);
+    Ok(renderer.render(helper.mgr))
+}
+
+/// One rotation of a unit with its doors marked, labeled with the rotation underneath.
+fn render_rotated_unit<'h, M: AssetManager>(unit: &'h CaveUnit, helper: &'h RenderHelper<M>) -> Layer<'h, M> {
+    let mut unit_layer = Layer::new();
+    unit_layer.place(
+        Resize::new(
+            unit,
+            unit.width as f32 * UNIT_SHEET_GRID_FACTOR,
+            unit.height as f32 * UNIT_SHEET_GRID_FACTOR,
+            FilterType::Nearest,
+        ),
+        Point([0.0, 0.0]),
+        Origin::TopLeft,
+    );
+
+    for door in unit.doors.iter() {
+        let door_pos = unit.center_of_door(door) * UNIT_SHEET_GRID_FACTOR;
+        // Unit vector pointing out of the unit through the door.
+        let direction = match door.direction {
+            0 => Point([0.0, -1.0]),
+            1 => Point([1.0, 0.0]),
+            2 => Point([0.0, 1.0]),
+            _ => Point([-1.0, 0.0]),
+        };
+        unit_layer.place(
+            Circle {
+                radius: DOOR_MARKER_RADIUS,
+                color: DOOR_MARKER_COLOR.into(),
+                ..Default::default()
+            },
+            door_pos,
+            Origin::Center,
+        );
+        unit_layer.place(
+            Line {
+                start: door_pos - direction * DOOR_ARROW_LENGTH,
+                end: door_pos,
+                forward_arrow: true,
+                color: DOOR_MARKER_COLOR.into(),
+                ..Default::default()
+            },
+            Point([0.0, 0.0]),
+            Origin::TopLeft,
+        );
+    }
+
+    let mut layer = Layer::new();
+    layer.place(unit_layer, Point([0.0, 0.0]), Origin::TopLeft);
+    layer.place_relative(
+        helper.cropped_text(format!("{}°", unit.rotation as u32 * 90), 18.0, 0, HEADER_BACKGROUND),
+        Origin::TopLeft,
+        Offset {
+            from: Origin::BottomLeft,
+            amount: Point([0.0, CAVEINFO_MARGIN]),
+        },
+    );
+    layer
+}
@@ -45,6 +45,45 @@ impl<'r, M: AssetManager + 'r> StickerRenderer<'r, M> {
         self.root_layer.render(canvas.view(Point([0.0, 0.0])), helper);
         canvas.into_inner()
     }
+
+    /// Same as [StickerRenderer::render], except that anything drawn above or left of
+    /// (0,0), even from inside a nested layer, is kept instead of being cut off. Everything
+    /// is moved down and right by [StickerRenderer::origin_offset] to make room for it,
+    /// and the image grows to fit.
+    pub fn render_shifted(&self, helper: &M) -> RgbaImage {
+        let mut canvas = Canvas::new(self.shifted_dims());
+        self.root_layer.render(canvas.view(self.origin_offset()), helper);
+        canvas.into_inner()
+    }
+
+    /// Same as [StickerRenderer::render_shifted], but draws everything `scale` times as
+    /// large. Sprites are resized one at a time as they're drawn and shapes are drawn at
+    /// the new size directly, so the full size image is never drawn. The image size is
+    /// rounded to the nearest pixel.
+    pub fn render_shifted_scaled(&self, helper: &M, scale: f32) -> RgbaImage {
+        let dims = self.shifted_dims() * scale;
+        let mut canvas = Canvas::new(Point([dims[0].round().max(1.0), dims[1].round().max(1.0)]));
+        self.root_layer.render(canvas.scaled_view(self.origin_offset(), scale), helper);
+        canvas.into_inner()
+    }
+
+    /// Size of the image [StickerRenderer::render_shifted] produces, without rendering it.
+    pub fn shifted_dims(&self) -> Point<2, f32> {
+        Bounds {
+            topleft: Point([0.0, 0.0]),
+            bottomright: self.root_layer.bounds().dims(),
+        }
+        .combine(self.root_layer.extent() + self.origin_offset())
+        .dims()
+    }
+
+    /// Where (0,0) ends up in images from [StickerRenderer::render_shifted]. Usually (0,0)
+    /// itself, but anything drawn above or left of the origin (e.g. a large teki near the
+    /// edge of the map) pushes everything else down and right so it doesn't get cut off.
+    pub fn origin_offset(&self) -> Point<2, f32> {
+        let topleft = self.root_layer.extent().topleft;
+        Point([f32::max(0.0, -topleft[0]), f32::max(0.0, -topleft[1])])
+    }
 }
 
 /// A grouping of renderables to be drawn together.
@@ -216,6 +255,19 @@ impl<'r, M: AssetManager> Render<M> for Layer<'r, M> {
     fn dimensions(&self) -> Point<2, f32> {
         self.bounds().dims()
     }
+
+    fn extent(&self) -> Bounds {
+        let inset = self.margin + self.border;
+        let contents = self
+            .renderables
+            .iter()
+            .map(|(renderable, bounds)| renderable.extent() + (bounds.topleft + inset));
+        if self.background_color.0[3] > 0 || self.border > 0.0 {
+            contents.fold(self.bounds() + Point([inset, inset]), Bounds::combine)
+        } else {
+            contents.reduce(Bounds::combine).unwrap_or_default()
+        }
+    }
 }
 
 #[auto_impl(&, &mut, Box)]
@@ -224,6 +276,27 @@ pub trait Render<M: AssetManager> {
 
     /// The dimensions of the image produced by [render].
     fn dimensions(&self) -> Point<2, f32>;
+
+    /// The area this actually draws to, relative to where it's placed. Usually the same as
+    /// [Render::dimensions], but a [Layer] still draws anything placed above or left of its
+    /// origin there, outside its dimensions.
+    fn extent(&self) -> Bounds {
+        Bounds {
+            topleft: Point([0.0, 0.0]),
+            bottomright: self.dimensions(),
+        }
+    }
+}
+
+/// Draws an already-rendered image as-is, e.g. to combine several finished renders into one.
+impl<M: AssetManager> Render<M> for RgbaImage {
+    fn render(&self, mut canvas: CanvasView, _: &M) {
+        canvas.overlay(self, Point([0.0, 0.0]));
+    }
+
+    fn dimensions(&self) -> Point<2, f32> {
+        Point([self.width() as f32, self.height() as f32])
+    }
 }
 
 impl<M: AssetManager> Render<M> for () {
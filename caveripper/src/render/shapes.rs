@@ -1,27 +1,46 @@
 use image::Rgba;
 
-use super::{canvas::CanvasView, coords::Bounds, renderer::Render};
+use super::{canvas::CanvasView, coords::Bounds, pixel_ext::PixelExt, renderer::Render};
 use crate::{assets::AssetManager, point::Point};
 
+/// A filled circle with an optional border. Edges are anti-aliased unless `crisp` is set.
 pub struct Circle {
     pub radius: f32,
     pub border_thickness: f32,
     pub color: Rgba<u8>,
     pub border_color: Rgba<u8>,
+    pub crisp: bool,
 }
 
 impl<M: AssetManager> Render<M> for Circle {
     fn render(&self, mut canvas: CanvasView, _helper: &M) {
-        for x in 0..=(self.radius + 1.0) as u32 * 2 {
-            for z in 0..=(self.radius + 1.0) as u32 * 2 {
-                let dist = ((self.radius - x as f32).powi(2) + (self.radius - z as f32).powi(2)).sqrt();
-                if dist <= self.radius {
-                    let color = if dist >= self.radius - self.border_thickness {
-                        self.border_color
+        let (radius, border_thickness) = (self.radius * canvas.scale(), self.border_thickness * canvas.scale());
+        let mut canvas = canvas.unscaled();
+        for x in 0..=(radius + 1.0) as u32 * 2 {
+            for z in 0..=(radius + 1.0) as u32 * 2 {
+                let dist = ((radius - x as f32).powi(2) + (radius - z as f32).powi(2)).sqrt();
+                if self.crisp {
+                    if dist <= radius {
+                        let color = if dist >= radius - border_thickness {
+                            self.border_color
+                        } else {
+                            self.color
+                        };
+                        canvas.draw_pixel(Point([x as f32, z as f32]), color);
+                    }
+                    continue;
+                }
+
+                // Approximate how much of this pixel the circle covers by how far inside the edge its center is.
+                let coverage = (radius + 0.5 - dist).clamp(0.0, 1.0);
+                if coverage > 0.0 {
+                    let color = if border_thickness > 0.0 {
+                        let border_amount = dist - (radius - border_thickness) + 0.5;
+                        self.color.lerp(&self.border_color, border_amount)
                     } else {
                         self.color
                     };
-                    canvas.draw_pixel(Point([x as f32, z as f32]), color);
+                    canvas.draw_pixel(Point([x as f32, z as f32]), color.mul_alpha(coverage));
                 }
             }
         }
@@ -39,19 +58,45 @@ impl Default for Circle {
             border_thickness: 0.0,
             color: [0, 0, 0, 0].into(),
             border_color: [0, 0, 0, 0].into(),
+            crisp: false,
         }
     }
 }
 
+/// A filled rectangle. Fractional widths and heights partially cover the last column
+/// and row of pixels unless `crisp` is set.
 pub struct Rectangle {
     pub width: f32,
     pub height: f32,
     pub color: Rgba<u8>,
+    pub crisp: bool,
 }
 
 impl<M: AssetManager> Render<M> for Rectangle {
     fn render(&self, mut canvas: CanvasView, _helper: &M) {
-        canvas.fill(Point([0.0, 0.0]), Point([self.width, self.height]), self.color);
+        if self.crisp {
+            canvas.fill(Point([0.0, 0.0]), Point([self.width, self.height]), self.color);
+            return;
+        }
+
+        let (width, height) = (self.width * canvas.scale(), self.height * canvas.scale());
+        let mut canvas = canvas.unscaled();
+        let (full_width, full_height) = (width.floor(), height.floor());
+        let (frac_width, frac_height) = (width - full_width, height - full_height);
+        canvas.fill(Point([0.0, 0.0]), Point([full_width, full_height]), self.color);
+        if frac_width > 0.0 {
+            for y in 0..full_height as u32 {
+                canvas.draw_pixel(Point([full_width, y as f32]), self.color.mul_alpha(frac_width));
+            }
+        }
+        if frac_height > 0.0 {
+            for x in 0..full_width as u32 {
+                canvas.draw_pixel(Point([x as f32, full_height]), self.color.mul_alpha(frac_height));
+            }
+        }
+        if frac_width > 0.0 && frac_height > 0.0 {
+            canvas.draw_pixel(Point([full_width, full_height]), self.color.mul_alpha(frac_width * frac_height));
+        }
     }
 
     fn dimensions(&self) -> Point<2, f32> {
@@ -59,7 +104,18 @@ impl<M: AssetManager> Render<M> for Rectangle {
     }
 }
 
-/// A line with optional arrows ar either end.
+impl Default for Rectangle {
+    fn default() -> Self {
+        Self {
+            width: 0.0,
+            height: 0.0,
+            color: [0, 0, 0, 0].into(),
+            crisp: false,
+        }
+    }
+}
+
+/// A line with optional arrows ar either end. Anti-aliased unless `crisp` is set.
 /// Currently malfunctions when coordinates are negative and I'm not sure why
 pub struct Line {
     pub start: Point<2, f32>,
@@ -68,6 +124,7 @@ pub struct Line {
     pub shorten_end: f32,   // Units, not percentage
     pub forward_arrow: bool,
     pub color: Rgba<u8>,
+    pub crisp: bool,
 }
 
 impl Default for Line {
@@ -79,14 +136,17 @@ impl Default for Line {
             shorten_end: 0.0,
             forward_arrow: false,
             color: [255, 255, 255, 255].into(),
+            crisp: false,
         }
     }
 }
 
 impl<M: AssetManager> Render<M> for Line {
     fn render(&self, mut canvas: CanvasView, _helper: &M) {
-        let mut start = self.start;
-        let mut end = self.end;
+        let scale = canvas.scale();
+        let mut canvas = canvas.unscaled();
+        let mut start = self.start * scale;
+        let mut end = self.end * scale;
         // canvas.reserve(
         //     f32::max(start[0] + 1.0, end[0] + 1.0) as u32,
         //     f32::max(start[1] + 1.0, end[1] + 1.0) as u32,
@@ -98,18 +158,18 @@ impl<M: AssetManager> Render<M> for Line {
         }
 
         // Shorten the line slightly on each end without changing the origin point
-        start += vector * self.shorten_start;
-        end -= vector * self.shorten_end;
+        start += vector * (self.shorten_start * scale);
+        end -= vector * (self.shorten_end * scale);
 
         // Draw main line
-        render_basic_line(&mut canvas, start, end, self.color);
+        render_basic_line(&mut canvas, start, end, self.color, self.crisp);
 
         // Draw arrow arms
         if self.forward_arrow {
-            let arrow_start_left = end - (vector * 12.0) + (vector.perpendicular() * 6.0);
-            let arrow_start_right = end - (vector * 12.0) - (vector.perpendicular() * 6.0);
-            render_basic_line(&mut canvas, arrow_start_left, end, self.color);
-            render_basic_line(&mut canvas, arrow_start_right, end, self.color);
+            let arrow_start_left = end - (vector * (12.0 * scale)) + (vector.perpendicular() * (6.0 * scale));
+            let arrow_start_right = end - (vector * (12.0 * scale)) - (vector.perpendicular() * (6.0 * scale));
+            render_basic_line(&mut canvas, arrow_start_left, end, self.color, self.crisp);
+            render_basic_line(&mut canvas, arrow_start_right, end, self.color, self.crisp);
         }
     }
 
@@ -122,7 +182,7 @@ impl<M: AssetManager> Render<M> for Line {
     }
 }
 
-fn render_basic_line(canvas: &mut CanvasView, start: Point<2, f32>, end: Point<2, f32>, color: Rgba<u8>) {
+fn render_basic_line(canvas: &mut CanvasView, start: Point<2, f32>, end: Point<2, f32>, color: Rgba<u8>, crisp: bool) {
     let (mut x1, mut y1, mut x2, mut y2) = (start[0], start[1], end[0], end[1]);
     let steep = (y2 - y1).abs() > (x2 - x1).abs();
 
@@ -137,7 +197,7 @@ fn render_basic_line(canvas: &mut CanvasView, start: Point<2, f32>, end: Point<2
         for y in (y1.round() as u32)..(y2.round() as u32) {
             let true_y = y as f32 + 0.5;
             let true_x = x1 + (slope * (true_y - y1));
-            canvas.draw_pixel(Point([true_x, true_y]), color);
+            draw_line_pixel(canvas, Point([true_x, true_y]), 0, color, crisp);
         }
     } else {
         let slope = (y2 - y1) / (x2 - x1);
@@ -145,7 +205,28 @@ fn render_basic_line(canvas: &mut CanvasView, start: Point<2, f32>, end: Point<2
         for x in (x1.round() as u32)..(x2.round() as u32) {
             let true_x = x as f32 + 0.5;
             let true_y = y1 + (slope * (true_x - x1));
-            canvas.draw_pixel(Point([true_x, true_y]), color);
+            draw_line_pixel(canvas, Point([true_x, true_y]), 1, color, crisp);
         }
     }
 }
+
+/// Draws one step of a line. When anti-aliasing, the step is split between the two
+/// pixels on either side of the line along `minor_axis`, weighted by how close the
+/// line passes to each one.
+fn draw_line_pixel(canvas: &mut CanvasView, pos: Point<2, f32>, minor_axis: usize, color: Rgba<u8>, crisp: bool) {
+    if crisp {
+        canvas.draw_pixel(pos, color);
+        return;
+    }
+
+    let near = pos[minor_axis].floor();
+    let frac = pos[minor_axis] - near;
+    let mut near_pos = pos;
+    near_pos[minor_axis] = near;
+    canvas.draw_pixel(near_pos, color.mul_alpha(1.0 - frac));
+    if frac > 0.0 {
+        let mut far_pos = pos;
+        far_pos[minor_axis] = near + 1.0;
+        canvas.draw_pixel(far_pos, color.mul_alpha(frac));
+    }
+}
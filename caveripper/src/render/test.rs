@@ -1,8 +1,29 @@
+use std::borrow::Cow;
+
+use clap::ValueEnum;
+use fontdue::{Font, FontSettings};
+use image::{Rgba, RgbaImage};
+use itertools::Itertools;
 use paste::paste;
 use rand::{rngs::SmallRng, Rng, SeedableRng};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
-use crate::{assets::fs_asset_manager::FsAssetManager, layout::Layout, render::*};
+use super::{
+    canvas::Canvas,
+    coords::Origin,
+    renderer::{Layer, Render, StickerRenderer},
+    shapes::{Circle, Line, Rectangle},
+    text::Text,
+};
+use crate::{
+    assets::{fs_asset_manager::FsAssetManager, load_cave_floors, AssetManager},
+    caveinfo::TekiInfo,
+    layout::{Layout, SpawnObject},
+    point::Point,
+    query::EntityMatcher,
+    render::*,
+    sublevel::Sublevel,
+};
 
 macro_rules! test_render {
     ($($name: literal),+) => {
@@ -33,6 +54,8 @@ macro_rules! test_render {
                     let mgr = FsAssetManager::init().unwrap();
                     let helper = RenderHelper::new(&mgr);
                     let caveinfos = mgr.caveinfos_from_cave($name.replace('_', ":").as_str()).unwrap();
+                    let cave_cfg = &caveinfos[0].cave_cfg;
+                    render_cave_caveinfo(cave_cfg, &helper, CaveinfoRenderOptions::default()).unwrap();
                     caveinfos.into_par_iter().panic_fuse().for_each(|caveinfo| {
                         render_caveinfo(&caveinfo, &helper, CaveinfoRenderOptions::default()).unwrap();
                     });
@@ -237,3 +260,622 @@ test_render!(
     "216_ch29",
     "216_ch30"
 );
+
+#[test]
+fn test_render_palettes() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("fc4", &mgr).unwrap()).unwrap();
+    let layout = Layout::generate(0x1234ABCD, caveinfo);
+    let images: Vec<_> = Palette::value_variants()
+        .iter()
+        .map(|palette| {
+            let options = LayoutRenderOptions {
+                palette: *palette,
+                quickglance: true,
+                quickglance_glyphs: true,
+                ..Default::default()
+            };
+            render_layout(&layout, &helper, options).unwrap_or_else(|e| panic!("{palette:?}: {e}"))
+        })
+        .collect();
+    for (i, image) in images.iter().enumerate() {
+        assert!(images[i + 1..].iter().all(|other| other != image));
+    }
+}
+
+/// Pixels that differ between two renders of the same layout, as `(x, y, before, after)`.
+fn changed_pixels(before: &RgbaImage, after: &RgbaImage) -> Vec<(u32, u32, Rgba<u8>, Rgba<u8>)> {
+    assert_eq!(before.dimensions(), after.dimensions());
+    before
+        .enumerate_pixels()
+        .zip(after.pixels())
+        .filter(|((_, _, b), a)| b != a)
+        .map(|((x, y, b), a)| (x, y, *b, *a))
+        .collect()
+}
+
+/// Whether every color channel of `a` is at most as bright as in `b`.
+fn channels_at_most(a: Rgba<u8>, b: Rgba<u8>) -> bool {
+    a.0[..3].iter().zip(&b.0[..3]).all(|(a, b)| a <= b)
+}
+
+/// Where (0,0) ends up in a rendered layout image. Large teki near the top or left edge of
+/// the map push everything over, and the ship's icon is centered on its position with
+/// nothing attached to it, so its region gives the shift away.
+fn image_origin(layout: &Layout, regions: &[ObjectRegion]) -> Point<2, f32> {
+    let (region, (_, pos)) = regions
+        .iter()
+        .zip(layout.get_spawn_objects())
+        .find(|(_, (spawn_object, _))| matches!(spawn_object, SpawnObject::Ship))
+        .expect("Every layout has a ship");
+    (region.topleft + region.bottomright) / 2.0 - pos.two_d() * COORD_FACTOR
+}
+
+#[test]
+fn test_render_gauge_range() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("fc4", &mgr).unwrap()).unwrap();
+    let layout = Layout::generate(0x1234ABCD, caveinfo);
+    let options = LayoutRenderOptions {
+        draw_gauge_range: true,
+        ..Default::default()
+    };
+    let (with_range, regions) = render_layout_with_regions(&layout, &helper, options).unwrap();
+    let without_range = render_layout(&layout, &helper, LayoutRenderOptions::default()).unwrap();
+
+    let origin = image_origin(&layout, &regions);
+    let treasures = layout
+        .get_spawn_objects()
+        .filter(|(spawn_object, _)| {
+            matches!(
+                spawn_object,
+                SpawnObject::Item(_) | SpawnObject::Teki(TekiInfo { carrying: Some(_), .. }, _)
+            )
+        })
+        .map(|(_, pos)| pos.two_d() * COORD_FACTOR + origin)
+        .collect_vec();
+    assert!(!treasures.is_empty());
+    let (map_width, map_height) = layout.map_units.iter().fold((0, 0), |dims, unit| {
+        (
+            dims.0.max(unit.x + unit.unit.width as i32),
+            dims.1.max(unit.z + unit.unit.height as i32),
+        )
+    });
+    let map_bottomright = Point([map_width as f32, map_height as f32]) * GRID_FACTOR + origin;
+
+    // The circles are translucent white, so they only ever lighten what's under them, and
+    // they stay within the needle range of a treasure and within the map.
+    let changed = changed_pixels(&without_range, &with_range);
+    assert!(!changed.is_empty());
+    let reach = GAUGE_NEEDLE_RANGE * COORD_FACTOR + GAUGE_RANGE_BORDER_THICKNESS + 1.0;
+    for (x, y, before, after) in changed {
+        let pixel = Point([x as f32, y as f32]);
+        assert!(channels_at_most(before, after), "({x}, {y}) got darker");
+        assert!(
+            treasures.iter().any(|t| t.dist(&pixel) <= reach),
+            "({x}, {y}) is out of range of every treasure"
+        );
+        assert!(
+            pixel[0] >= origin[0] - 1.0
+                && pixel[1] >= origin[1] - 1.0
+                && pixel[0] <= map_bottomright[0] + 1.0
+                && pixel[1] <= map_bottomright[1] + 1.0,
+            "({x}, {y}) is outside the map"
+        );
+    }
+}
+
+#[test]
+fn test_wrap_unit_name() {
+    assert_eq!(wrap_unit_name("way4_1_tsuchi", 20), "way4_1_tsuchi");
+    assert_eq!(wrap_unit_name("room_north4_1_tsuchi", 10), "room\nnorth4_1\ntsuchi");
+    assert_eq!(wrap_unit_name("room_ooheya_1_metal", 4), "room\nooh.\n1\nmet.");
+}
+
+#[test]
+fn test_image_metadata_roundtrip() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("scx7", &mgr).unwrap()).unwrap();
+    let layout = Layout::generate(0x1234ABCD, caveinfo);
+    let img = render_layout(&layout, &helper, LayoutRenderOptions::default()).unwrap();
+
+    let path = std::env::temp_dir().join("caveripper_test_image_metadata.png");
+    save_layout_image(&img, &path, &layout).unwrap();
+    let metadata = read_image_metadata(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(metadata.sublevel.as_deref(), Some(layout.cave_name.as_str()));
+    assert_eq!(metadata.seed, Some(0x1234ABCD));
+}
+
+#[test]
+fn test_spawn_heatmap() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("bk4", &mgr).unwrap()).unwrap();
+    let hole = EntityMatcher::from("hole");
+
+    let mut first_half = SpawnHeatmap::default();
+    let mut second_half = SpawnHeatmap::default();
+    for seed in 0..50 {
+        first_half.add_layout(&Layout::generate(seed, caveinfo), &hole);
+        second_half.add_layout(&Layout::generate(seed + 50, caveinfo), &hole);
+    }
+    let heatmap = first_half.merge(second_half);
+    assert_eq!(heatmap.num_layouts, 100);
+
+    // Every layout has exactly one hole, so the counts across all spawn points add up to the number of layouts.
+    let total: u32 = caveinfo
+        .cave_units
+        .iter()
+        .filter(|unit| unit.rotation == 0)
+        .flat_map(|unit| (0..unit.spawnpoints.len()).map(|i| heatmap.count(&unit.unit_folder_name, i)))
+        .sum();
+    assert_eq!(total, 100);
+
+    render_spawn_heatmap(caveinfo, &heatmap, "hole", &helper).unwrap();
+}
+
+#[test]
+fn test_render_dim_start_room() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("scx7", &mgr).unwrap()).unwrap();
+    let layout = Layout::generate(0x1234ABCD, caveinfo);
+    let options = LayoutRenderOptions {
+        dim_start_room: true,
+        ..Default::default()
+    };
+    let (dimmed, regions) = render_layout_with_regions(&layout, &helper, options).unwrap();
+    let plain = render_layout(&layout, &helper, LayoutRenderOptions::default()).unwrap();
+
+    // Only the ship's room changes, and it only gets darker.
+    let origin = image_origin(&layout, &regions);
+    let start_room = &layout.map_units[0];
+    let topleft = Point([start_room.x as f32, start_room.z as f32]) * GRID_FACTOR + origin;
+    let bottomright = topleft + Point([start_room.unit.width as f32, start_room.unit.height as f32]) * GRID_FACTOR;
+    let changed = changed_pixels(&plain, &dimmed);
+    assert!(!changed.is_empty());
+    for (x, y, before, after) in changed {
+        assert!(channels_at_most(after, before), "({x}, {y}) got brighter");
+        assert!(
+            x as f32 >= topleft[0] - 1.0
+                && y as f32 >= topleft[1] - 1.0
+                && x as f32 <= bottomright[0] + 1.0
+                && y as f32 <= bottomright[1] + 1.0,
+            "({x}, {y}) is outside the start room"
+        );
+    }
+}
+
+#[test]
+fn test_render_skeleton() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("scx7", &mgr).unwrap()).unwrap();
+    let layout = Layout::generate(0x1234ABCD, caveinfo);
+    let options = LayoutRenderOptions {
+        skeleton: true,
+        ..Default::default()
+    };
+    let (skeleton, skeleton_regions) = render_layout_with_regions(&layout, &helper, options).unwrap();
+    let (full, full_regions) = render_layout_with_regions(&layout, &helper, LayoutRenderOptions::default()).unwrap();
+    assert_ne!(skeleton, full);
+    assert!(skeleton_regions.is_empty());
+    assert!(!full_regions.is_empty());
+}
+
+#[test]
+fn test_render_template() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("scx7", &mgr).unwrap()).unwrap();
+    let layout = Layout::generate(0x1234ABCD, caveinfo);
+
+    let template = render_template(&layout, &helper, 1).unwrap();
+    let skeleton = render_layout(
+        &layout,
+        &helper,
+        LayoutRenderOptions {
+            skeleton: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(template.dimensions(), skeleton.dimensions());
+
+    // Only black, white, and the faint gray of the grid.
+    assert!(template.pixels().all(|p| p.0[0] == p.0[1] && p.0[1] == p.0[2]));
+    assert_eq!(template.get_pixel(0, 0).0, [255, 255, 255, 255]);
+    assert!(template.pixels().any(|p| p.0 == [0, 0, 0, 255]));
+
+    let scaled = render_template(&layout, &helper, 3).unwrap();
+    assert_eq!(scaled.dimensions(), (template.width() * 3, template.height() * 3));
+}
+
+#[test]
+fn test_render_door_scores() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("fc4", &mgr).unwrap()).unwrap();
+    let layout = Layout::generate(0x1234ABCD, caveinfo);
+    let options = LayoutRenderOptions {
+        draw_door_scores: true,
+        ..Default::default()
+    };
+    let (labeled, regions) = render_layout_with_regions(&layout, &helper, options).unwrap();
+    let plain = render_layout(&layout, &helper, LayoutRenderOptions::default()).unwrap();
+
+    let origin = image_origin(&layout, &regions);
+    let scored_doors = layout
+        .map_units
+        .iter()
+        .flat_map(|unit| unit.doors.iter())
+        .filter(|door| door.borrow().door_score.is_some())
+        .map(|door| door.borrow().center().two_d() * COORD_FACTOR + origin)
+        .collect_vec();
+    assert!(!scored_doors.is_empty());
+
+    // Each label is a few digits centered on its door, so every door gets changed pixels
+    // right at its center and nothing changes anywhere else.
+    let changed = changed_pixels(&plain, &labeled);
+    let near = |pixel: Point<2, f32>, door: &Point<2, f32>, distance: f32| {
+        (pixel[0] - door[0]).abs() <= distance && (pixel[1] - door[1]).abs() <= distance
+    };
+    for door in scored_doors.iter() {
+        assert!(
+            changed.iter().any(|&(x, y, ..)| near(Point([x as f32, y as f32]), door, 12.0)),
+            "No label at door {door}"
+        );
+    }
+    for (x, y, ..) in changed {
+        assert!(
+            scored_doors.iter().any(|door| near(Point([x as f32, y as f32]), door, 48.0)),
+            "({x}, {y}) is far from every door"
+        );
+    }
+}
+
+#[test]
+fn test_render_spawn_cloud() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let sublevel = Sublevel::try_from_str("fc1", &mgr).unwrap();
+    let cloud = render_spawn_cloud(&sublevel, 50, &helper).unwrap();
+    assert!(cloud.width() > 0 && cloud.height() > 0);
+    assert!(render_spawn_cloud(&sublevel, 0, &helper).is_err());
+}
+
+#[test]
+fn test_render_world_coords() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("fc4", &mgr).unwrap()).unwrap();
+    let layout = Layout::generate(0x1234ABCD, caveinfo);
+    let options = LayoutRenderOptions {
+        draw_world_coords: true,
+        ..Default::default()
+    };
+    let (labeled, regions) = render_layout_with_regions(&layout, &helper, options).unwrap();
+    let plain = render_layout(&layout, &helper, LayoutRenderOptions::default()).unwrap();
+    assert_eq!(labeled.dimensions(), plain.dimensions());
+
+    let origin = image_origin(&layout, &regions);
+    let (map_width, map_height) = layout.map_units.iter().fold((0, 0), |dims, unit| {
+        (
+            dims.0.max(unit.x + unit.unit.width as i32),
+            dims.1.max(unit.z + unit.unit.height as i32),
+        )
+    });
+    // Below the labels along the top edge, down to the bottom of the map.
+    let rows = (origin[1] as u32 + 40)..(origin[1] + map_height as f32 * GRID_FACTOR) as u32;
+    let changed_in_column = |column: f32| {
+        let column = column.round() as u32;
+        rows.clone()
+            .filter(|&y| (column - 1..=column + 1).any(|x| labeled.get_pixel(x, y) != plain.get_pixel(x, y)))
+            .count()
+    };
+
+    // A vertical line every 170 in-game units, and nothing halfway between them apart from
+    // where the horizontal lines cross.
+    for x in 1..map_width {
+        let line = x as f32 * 170.0 * COORD_FACTOR + origin[0];
+        let between = line + GRID_FACTOR / 2.0;
+        assert!(changed_in_column(line) * 10 >= rows.len() * 9, "No line at x = {}", x * 170);
+        assert!(changed_in_column(between) * 10 < rows.len(), "Stray line near x = {}", x * 170);
+    }
+}
+
+#[test]
+fn test_render_shadows() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("fc4", &mgr).unwrap()).unwrap();
+    let layout = Layout::generate(0x1234ABCD, caveinfo);
+    let options = LayoutRenderOptions {
+        draw_shadows: true,
+        ..Default::default()
+    };
+    let (shadowed, regions) = render_layout_with_regions(&layout, &helper, options).unwrap();
+    let plain = render_layout(&layout, &helper, LayoutRenderOptions::default()).unwrap();
+
+    // Shadows only darken, and only just below and to the right of an object's icon. Falling
+    // cap teki are drawn a little up and left of their region, hence the extra margin.
+    let changed = changed_pixels(&plain, &shadowed);
+    assert!(!changed.is_empty());
+    for (x, y, before, after) in changed {
+        assert!(channels_at_most(after, before), "({x}, {y}) got brighter");
+        let pixel = Point([x as f32, y as f32]);
+        assert!(
+            regions.iter().any(|region| {
+                let topleft = region.topleft - RENDER_SCALE;
+                let bottomright = region.bottomright + SHADOW_OFFSET + 1.0;
+                (0..2).all(|i| pixel[i] >= topleft[i] && pixel[i] <= bottomright[i])
+            }),
+            "({x}, {y}) isn't next to any object"
+        );
+    }
+}
+
+#[test]
+fn test_render_layout_fit() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("fc4", &mgr).unwrap()).unwrap();
+    let layout = Layout::generate(0x1234ABCD, caveinfo);
+    let full = render_layout(&layout, &helper, LayoutRenderOptions::default()).unwrap();
+
+    let (fitted, scale) = render_layout_fit(&layout, &helper, LayoutRenderOptions::default(), 500, 400).unwrap();
+    assert!(fitted.width() <= 500 && fitted.height() <= 400, "{:?}", fitted.dimensions());
+    assert!(fitted.width() == 500 || fitted.height() == 400, "{:?}", fitted.dimensions());
+    assert!((full.width() as f32 * scale - fitted.width() as f32).abs() <= 1.0);
+    assert!((full.height() as f32 * scale - fitted.height() as f32).abs() <= 1.0);
+
+    let (unscaled, scale) = render_layout_fit(&layout, &helper, LayoutRenderOptions::default(), u32::MAX, u32::MAX).unwrap();
+    assert_eq!(scale, 1.0);
+    assert_eq!(unscaled, full);
+
+    assert!(render_layout_fit(&layout, &helper, LayoutRenderOptions::default(), 0, 400).is_err());
+}
+
+#[test]
+fn test_render_layout_tiled() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("fc4", &mgr).unwrap()).unwrap();
+    let layout = Layout::generate(0x1234ABCD, caveinfo);
+    let full = render_layout(&layout, &helper, LayoutRenderOptions::default()).unwrap();
+
+    let tile_size = 300;
+    let tiles = render_layout_tiled(&layout, &helper, LayoutRenderOptions::default(), tile_size).unwrap();
+    let (columns, rows) = (full.width().div_ceil(tile_size), full.height().div_ceil(tile_size));
+    assert!(tiles.len() > 1);
+    assert_eq!(tiles.len() as u32, columns * rows);
+    for (tile_x, tile_y, tile) in tiles.iter() {
+        assert_eq!(tile.dimensions(), (tile_size, tile_size));
+        for (x, y, pixel) in tile.enumerate_pixels() {
+            let (full_x, full_y) = (tile_x * tile_size + x, tile_y * tile_size + y);
+            if full_x < full.width() && full_y < full.height() {
+                assert_eq!(pixel, full.get_pixel(full_x, full_y), "tile ({tile_x}, {tile_y}) pixel ({x}, {y})");
+            }
+        }
+    }
+
+    assert!(render_layout_tiled(&layout, &helper, LayoutRenderOptions::default(), 0).is_err());
+}
+
+#[test]
+fn test_render_unit_sheet() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    for sublevel in ["bk4", "scx7", "ch24-1"] {
+        let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str(sublevel, &mgr).unwrap()).unwrap();
+        let image = render_unit_sheet(caveinfo, &helper).unwrap();
+        assert!(image.width() > 0 && image.height() > 0, "{sublevel}");
+    }
+}
+
+#[test]
+fn test_anti_aliased_shapes() {
+    let mgr = FsAssetManager::init().unwrap();
+    let render_shapes = |crisp: bool| {
+        let mut layer = Layer::new();
+        layer.place(
+            Circle {
+                radius: 20.0,
+                color: [255, 255, 255, 255].into(),
+                crisp,
+                ..Default::default()
+            },
+            Point([0.0, 0.0]),
+            Origin::TopLeft,
+        );
+        layer.place(
+            Line {
+                start: Point([5.0, 60.0]),
+                end: Point([45.0, 75.0]),
+                crisp,
+                ..Default::default()
+            },
+            Point([0.0, 0.0]),
+            Origin::TopLeft,
+        );
+        let mut renderer = StickerRenderer::new();
+        renderer.add_layer(layer);
+        renderer.render(&mgr)
+    };
+    let is_partial = |pixel: &Rgba<u8>| pixel.0[3] > 0 && pixel.0[3] < 255;
+
+    let crisp = render_shapes(true);
+    assert!(!crisp.pixels().any(is_partial));
+
+    let smooth = render_shapes(false);
+    assert_eq!(smooth.dimensions(), crisp.dimensions());
+    assert!(smooth.pixels().any(is_partial));
+}
+
+#[test]
+fn test_render_large_teki() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("bk7", &mgr).unwrap()).unwrap();
+    let boss = caveinfo
+        .teki_info
+        .iter()
+        .find(|teki| teki.internal_name.eq_ignore_ascii_case("KingChappy"))
+        .unwrap();
+
+    let scale = mgr.teki_sizes().scale(&boss.game, &boss.internal_name);
+    let sprite = render_spawn_object(Cow::Owned(SpawnObject::Teki(boss, Point([0.0, 0.0, 0.0]))), &mgr);
+    assert_eq!(sprite.dimensions(), Point([TEKI_SIZE * scale, TEKI_SIZE * scale]));
+
+    let layout = Layout::generate(0x1234ABCD, caveinfo);
+    render_layout(&layout, &helper, LayoutRenderOptions::default()).unwrap();
+}
+
+#[test]
+fn test_render_negative_placement() {
+    let mgr = FsAssetManager::init_unchecked().unwrap();
+    let mut layer = Layer::new();
+    layer.place(
+        Rectangle {
+            width: 20.0,
+            height: 20.0,
+            color: [255, 0, 0, 255].into(),
+            ..Default::default()
+        },
+        Point([-10.0, -10.0]),
+        Origin::TopLeft,
+    );
+    let mut renderer = StickerRenderer::new();
+    renderer.add_layer(layer);
+    assert_eq!(renderer.origin_offset(), Point([10.0, 10.0]));
+
+    let shifted = renderer.render_shifted(&mgr);
+    assert_eq!(shifted.dimensions(), (20, 20));
+    assert_eq!(shifted.get_pixel(0, 0).0[3], 255);
+    assert_eq!(shifted.get_pixel(15, 15).0[3], 255);
+
+    // Only layouts get shifted. Everything else still draws (0,0) at pixel (0,0) and cuts
+    // off whatever is above or left of it.
+    let unshifted = renderer.render(&mgr);
+    assert_eq!(unshifted.get_pixel(0, 0).0[3], 255);
+    assert_eq!(unshifted.get_pixel(15, 15).0[3], 0);
+}
+
+#[test]
+fn test_render_scaled() {
+    let mgr = FsAssetManager::init_unchecked().unwrap();
+    let mut layer = Layer::new();
+    layer.place(
+        Rectangle {
+            width: 40.0,
+            height: 40.0,
+            color: [255, 0, 0, 255].into(),
+            crisp: true,
+        },
+        Point([0.0, 0.0]),
+        Origin::TopLeft,
+    );
+    layer.place(
+        Circle {
+            radius: 20.0,
+            color: [0, 0, 255, 255].into(),
+            crisp: true,
+            ..Default::default()
+        },
+        Point([60.0, 0.0]),
+        Origin::TopLeft,
+    );
+    layer.place(
+        RgbaImage::from_pixel(20, 20, [0, 255, 0, 255].into()),
+        Point([0.0, 60.0]),
+        Origin::TopLeft,
+    );
+    let mut renderer = StickerRenderer::new();
+    renderer.add_layer(layer);
+
+    let full = renderer.render_shifted(&mgr);
+    let half = renderer.render_shifted_scaled(&mgr, 0.5);
+    assert_eq!(half.width(), (full.width() as f32 * 0.5).round() as u32);
+    assert_eq!(half.height(), (full.height() as f32 * 0.5).round() as u32);
+
+    // Everything lands at half its position and size.
+    assert_eq!(half.get_pixel(10, 10).0, [255, 0, 0, 255]);
+    assert_eq!(half.get_pixel(25, 10).0[3], 0);
+    assert_eq!(half.get_pixel(40, 10).0, [0, 0, 255, 255]);
+    assert_eq!(half.get_pixel(5, 35).0, [0, 255, 0, 255]);
+    assert_eq!(half.get_pixel(15, 35).0[3], 0);
+}
+
+#[test]
+fn test_render_text_scaled() {
+    let mgr = FsAssetManager::init_unchecked().unwrap();
+    let font = Font::from_bytes(
+        include_bytes!("../../../resources/BalooChettan2-SemiBold.ttf").as_slice(),
+        FontSettings::default(),
+    )
+    .unwrap();
+    let text = |size: f32, outline: u32| Text {
+        text: "Bulborb 12".to_string(),
+        font: &font,
+        size,
+        color: [255, 255, 255, 255].into(),
+        outline,
+    };
+
+    // Text drawn at double scale is rasterized at double the size, not blown up
+    // from a bitmap of the nominal size.
+    let mut scaled = Canvas::new(Point([400.0, 120.0]));
+    Render::render(&text(24.0, 2), scaled.scaled_view(Point([5.0, 5.0]), 2.0), &mgr);
+    let mut direct = Canvas::new(Point([400.0, 120.0]));
+    Render::render(&text(48.0, 4), direct.view(Point([10.0, 10.0])), &mgr);
+    assert!(scaled.into_inner() == direct.into_inner());
+}
+
+#[test]
+fn test_render_cave_strip() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let sublevel = Sublevel::try_from_str("sh1", &mgr).unwrap();
+    let strip = render_cave_strip(&sublevel.cfg, 0x1234ABCD, &helper, LayoutRenderOptions::default()).unwrap();
+
+    let floors: Vec<_> = load_cave_floors(&mgr, &sublevel.cfg)
+        .into_iter()
+        .map(|caveinfo| render_layout(&Layout::generate(0x1234ABCD, caveinfo), &helper, LayoutRenderOptions::default()).unwrap())
+        .collect();
+    assert!(floors.len() > 1);
+    assert!(strip.width() >= floors.iter().map(|floor| floor.width()).sum::<u32>());
+    assert!(strip.height() > floors.iter().map(|floor| floor.height()).max().unwrap());
+}
+
+#[test]
+fn test_render_object_icon() {
+    let mgr = FsAssetManager::init().unwrap();
+    let helper = RenderHelper::new(&mgr);
+    let caveinfo = mgr.load_caveinfo(&Sublevel::try_from_str("sh6", &mgr).unwrap()).unwrap();
+    let layout = Layout::generate(0x1234ABCD, caveinfo);
+    let carrier = EntityMatcher::from("carrying:any");
+
+    let objects = layout
+        .get_spawn_objects()
+        .map(|(so, _)| so)
+        .filter(|so| matches!(so, SpawnObject::Ship | SpawnObject::Hole(_)) || carrier.matches(so))
+        .collect::<Vec<_>>();
+    assert!(objects.iter().any(|so| carrier.matches(so)));
+
+    for object in objects {
+        for size in [16, 64, 101] {
+            let icon = render_object_icon(object, &helper, size);
+            assert_eq!(icon.dimensions(), (size, size), "{}", object.name());
+            assert!(icon.pixels().any(|p| p.0[3] > 0), "{}", object.name());
+            if carrier.matches(object) {
+                // The carried treasure hangs off the bottom right, leaving the top right empty.
+                assert_eq!(icon.get_pixel(size - 1, 0).0[3], 0, "{}", object.name());
+            }
+        }
+    }
+}
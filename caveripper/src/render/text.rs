@@ -21,7 +21,7 @@ pub struct Text<'f> {
 }
 
 impl Text<'_> {
-    fn layout(&self) -> FontLayout {
+    fn layout(&self, size: f32) -> FontLayout {
         let mut layout = FontLayout::new(fontdue::layout::CoordinateSystem::PositiveYDown);
         layout.reset(&LayoutSettings {
             x: 0f32,
@@ -34,7 +34,7 @@ impl Text<'_> {
             wrap_style: WrapStyle::Letter,
             wrap_hard_breaks: true,
         });
-        layout.append(&[self.font], &TextStyle::new(&self.text, self.size, 0));
+        layout.append(&[self.font], &TextStyle::new(&self.text, size, 0));
         layout
     }
 }
@@ -51,12 +51,17 @@ fn width_from_layout(layout: &FontLayout) -> f32 {
 
 impl<M: AssetManager> Render<M> for Text<'_> {
     fn render(&self, mut canvas: CanvasView, _helper: &M) {
-        let layout = self.layout();
+        // Rasterize at the size the text will actually take up on the underlying canvas
+        // rather than resizing a bitmap rasterized at the nominal size.
+        let scale = canvas.scale();
+        let layout = self.layout(self.size * scale);
+        let outline_width = (self.outline as f32 * scale).round() as u32;
+        let mut canvas = canvas.unscaled();
 
         for glyph in layout.glyphs().iter() {
             let mut base_glyph_canvas = Canvas::new(Point([
-                glyph.width as f32 + self.outline as f32,
-                glyph.height as f32 + self.outline as f32,
+                glyph.width as f32 + outline_width as f32,
+                glyph.height as f32 + outline_width as f32,
             ]));
 
             let (metrics, bitmap) = self.font.rasterize_config(glyph.key);
@@ -64,7 +69,7 @@ impl<M: AssetManager> Render<M> for Text<'_> {
                 let x = i % metrics.width;
                 let y = i / metrics.width;
                 base_glyph_canvas.draw_pixel(
-                    Point([x as f32 + self.outline as f32, y as f32 + self.outline as f32]),
+                    Point([x as f32 + outline_width as f32, y as f32 + outline_width as f32]),
                     [
                         self.color.0[0].saturating_add(255 - v),
                         self.color.0[1].saturating_add(255 - v),
@@ -77,17 +82,17 @@ impl<M: AssetManager> Render<M> for Text<'_> {
 
             let base_glyph = base_glyph_canvas.into_inner();
 
-            let outline_canvas = outline(&base_glyph, self.outline);
+            let outline_canvas = outline(&base_glyph, outline_width);
             canvas.overlay(
                 &outline_canvas,
-                Point([glyph.x - self.outline as f32, glyph.y - self.outline as f32]),
+                Point([glyph.x - outline_width as f32, glyph.y - outline_width as f32]),
             );
             canvas.overlay(&base_glyph, Point([glyph.x, glyph.y]));
         }
     }
 
     fn dimensions(&self) -> Point<2, f32> {
-        let layout = self.layout();
+        let layout = self.layout(self.size);
         Point([
             width_from_layout(&layout) + (self.outline as f32 * 2.0) + 1.0,
             layout.height() + (self.outline as f32 * 2.0),
@@ -59,13 +59,14 @@ impl<M: AssetManager, R: Render<M>> Render<M> for Resize<M, R> {
     fn render(&self, mut canvas: CanvasView, helper: &M) {
         let mut subcanvas = Canvas::new(self.renderable.dimensions());
         self.renderable.render(subcanvas.view(Point([0.0, 0.0])), helper);
+        // Resize straight to the size this ends up on the canvas so it's only resampled once.
         let buffer = resize(
             &subcanvas.into_inner(),
-            self.width.round() as u32,
-            self.height.round() as u32,
+            (self.width * canvas.scale()).round() as u32,
+            (self.height * canvas.scale()).round() as u32,
             self.filter,
         );
-        canvas.overlay(&buffer, Point([0.0, 0.0]));
+        canvas.unscaled().overlay(&buffer, Point([0.0, 0.0]));
     }
 
     fn dimensions(&self) -> Point<2, f32> {
@@ -85,9 +86,11 @@ pub struct CropRelative<M: AssetManager, R: Render<M>> {
 
 impl<M: AssetManager, R: Render<M>> Render<M> for CropRelative<M, R> {
     fn render(&self, mut canvas: CanvasView, helper: &M) {
-        let mut sub_canvas = Canvas::new(self.dimensions());
-        self.inner.render(sub_canvas.view(Point([-self.left, -self.top])), helper);
-        canvas.overlay(&sub_canvas.into_inner(), Point([0.0, 0.0]));
+        let scale = canvas.scale();
+        let mut sub_canvas = Canvas::new(self.dimensions() * scale);
+        self.inner
+            .render(sub_canvas.scaled_view(Point([-self.left, -self.top]), scale), helper);
+        canvas.unscaled().overlay(&sub_canvas.into_inner(), Point([0.0, 0.0]));
     }
 
     fn dimensions(&self) -> Point<2, f32> {
@@ -105,9 +108,10 @@ pub struct CropAbsolute<M: AssetManager, R: Render<M>> {
 
 impl<M: AssetManager, R: Render<M>> Render<M> for CropAbsolute<M, R> {
     fn render(&self, mut canvas: CanvasView, helper: &M) {
-        let mut sub_canvas = Canvas::new(self.dimensions());
-        self.inner.render(sub_canvas.view(-self.bounds.topleft), helper);
-        canvas.overlay(&sub_canvas.into_inner(), Point([0.0, 0.0]));
+        let scale = canvas.scale();
+        let mut sub_canvas = Canvas::new(self.dimensions() * scale);
+        self.inner.render(sub_canvas.scaled_view(-self.bounds.topleft, scale), helper);
+        canvas.unscaled().overlay(&sub_canvas.into_inner(), Point([0.0, 0.0]));
     }
 
     fn dimensions(&self) -> Point<2, f32> {
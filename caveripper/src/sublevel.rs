@@ -11,6 +11,8 @@ use crate::{
 };
 
 pub static DIRECT_MODE_TAG: &str = "caveinfo";
+/// Appended to the names of sublevels generated from a modified CaveInfo.
+pub static MODIFIED_TAG: &str = "modified";
 
 /// Uniquely represents a sublevel and handles parsing to and from strings
 /// for sublevel specifiers.
@@ -18,11 +20,20 @@ pub static DIRECT_MODE_TAG: &str = "caveinfo";
 pub struct Sublevel {
     pub cfg: CaveConfig,
     pub floor: usize,
+    /// Whether this sublevel's CaveInfo was altered with a [CaveInfoBuilder], in which
+    /// case its layouts aren't ones the real game can generate.
+    ///
+    /// [CaveInfoBuilder]: crate::caveinfo::CaveInfoBuilder
+    pub modified: bool,
 }
 
 impl Sublevel {
     pub fn from_cfg(cfg: &CaveConfig, floor: usize) -> Sublevel {
-        Sublevel { cfg: cfg.clone(), floor }
+        Sublevel {
+            cfg: cfg.clone(),
+            floor,
+            modified: false,
+        }
     }
 
     pub fn try_from_str(input: &str, mgr: &impl AssetManager) -> Result<Self, CaveripperError> {
@@ -45,6 +56,7 @@ impl Sublevel {
                             .change_context(CaveripperError::UnrecognizedSublevel)?
                             .clone(),
                         floor: 1,
+                        modified: false,
                     })
                 } else {
                     let (name, floor) = from_short_specifier(c1)?;
@@ -54,6 +66,7 @@ impl Sublevel {
                             .change_context(CaveripperError::UnrecognizedSublevel)?
                             .clone(),
                         floor,
+                        modified: false,
                     })
                 }
             }
@@ -67,6 +80,7 @@ impl Sublevel {
                         .change_context(CaveripperError::UnrecognizedSublevel)?
                         .clone(),
                     floor,
+                    modified: false,
                 })
             }
 
@@ -80,6 +94,7 @@ impl Sublevel {
                         .change_context(CaveripperError::UnrecognizedSublevel)?
                         .clone(),
                     floor,
+                    modified: false,
                 })
             }
 
@@ -98,6 +113,7 @@ impl Sublevel {
                         caveinfo_filename: caveinfo_path.into(),
                     },
                     floor,
+                    modified: false,
                 })
             }
 
@@ -115,22 +131,45 @@ impl Sublevel {
 
     /// Constructs the short cave name of this sublevel, e.g. "SCx3" with no hyphen.
     /// For challenge mode sublevels, this forwards to the normalized_name implementation.
+    /// Modified sublevels have "-modified" on the end, e.g. "SCx3-modified".
     pub fn short_name(&self) -> String {
-        if self.cfg.is_challenge_mode {
+        let name = if self.cfg.is_challenge_mode {
             self.normalized_name()
         } else {
             format!("{}{}", self.cfg.shortened_names.first().unwrap(), self.floor)
+        };
+        if self.modified {
+            format!("{name}-{MODIFIED_TAG}")
+        } else {
+            name
         }
     }
 
     /// Constructs the long name of this sublevel, e.g. "Subterranean Complex 3" with the full cave name.
     pub fn long_name(&self) -> String {
-        format!("{} {}", self.cfg.full_name, self.floor)
+        if self.modified {
+            format!("{} {} ({MODIFIED_TAG})", self.cfg.full_name, self.floor)
+        } else {
+            format!("{} {}", self.cfg.full_name, self.floor)
+        }
     }
 
     pub fn is_challenge_mode(&self) -> bool {
         self.cfg.is_challenge_mode
     }
+
+    /// The game or romhack this sublevel belongs to, e.g. "pikmin2" or "216". Short names
+    /// like "SCx6" can exist in more than one game, so this is whichever one the name
+    /// resolved to when parsed.
+    pub fn resolved_game(&self) -> &str {
+        &self.cfg.game
+    }
+
+    /// The long name of this sublevel along with the game it resolved to, e.g.
+    /// "Subterranean Complex 6 [pikmin2]", for telling the user which sublevel was picked.
+    pub fn resolved_name(&self) -> String {
+        format!("{} [{}]", self.long_name(), self.resolved_game())
+    }
 }
 
 static DIGIT: OnceLock<Regex> = OnceLock::new();
@@ -164,13 +203,13 @@ fn from_short_specifier(input: &str) -> Result<(&str, usize), CaveripperError> {
 
 impl Ord for Sublevel {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.normalized_name().cmp(&other.normalized_name())
+        (self.normalized_name(), self.modified).cmp(&(other.normalized_name(), other.modified))
     }
 }
 
 impl PartialOrd for Sublevel {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.normalized_name().partial_cmp(&other.normalized_name())
+        Some(self.cmp(other))
     }
 }
 
@@ -3,6 +3,7 @@ use std::path::PathBuf;
 use caveripper::{
     parse_seed,
     render::{CaveinfoRenderOptions, LayoutRenderOptions},
+    Seed,
 };
 use clap::{Parser, Subcommand};
 
@@ -19,6 +20,15 @@ pub struct Cli {
         help = VERBOSE_HELP,
     )]
     pub verbosity: u8,
+
+    #[clap(global = true, long = "no-plants", help = NO_PLANTS_HELP)]
+    pub no_plants: bool,
+
+    #[clap(global = true, short = 'o', long = "out-dir", long_help = OUT_DIR_HELP)]
+    pub out_dir: Option<PathBuf>,
+
+    #[clap(global = true, long = "iso", long_help = ISO_HELP)]
+    pub iso: Option<PathBuf>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -35,12 +45,107 @@ pub enum Commands {
             value_parser = |s: &str| parse_seed(s).map_err(|e| format!("{e:#?}")),
             help = SEED_HELP,
         )]
-        seed: u32,
+        seed: Seed,
+
+        #[clap(
+            long = "raw-stdout",
+            long_help = RAW_STDOUT_HELP,
+        )]
+        raw_stdout: bool,
+
+        #[clap(
+            long,
+            conflicts_with = "story",
+            help = "Generate the layout the Challenge Mode way, even if the sublevel is from Story Mode. The output is marked as modified if this changes the mode, since the game never generates such layouts."
+        )]
+        challenge: bool,
+
+        #[clap(
+            long,
+            help = "Generate the layout the Story Mode way, even if the sublevel is from Challenge Mode. The output is marked as modified if this changes the mode, since the game never generates such layouts."
+        )]
+        story: bool,
+
+        #[clap(
+            long = "whole-cave",
+            conflicts_with_all = ["challenge", "story"],
+            help = "Render every floor of the sublevel's cave at this seed side by side in a single image"
+        )]
+        whole_cave: bool,
+
+        #[clap(
+            long,
+            conflicts_with = "whole_cave",
+            help = "Also print the layout's slug, a compact description of everything in it. Handy for bug reports, since `find-slug` can confirm which seed it came from. Printed to STDERR if the image is going to STDOUT."
+        )]
+        slug: bool,
+
+        #[clap(flatten)]
+        render_options: LayoutRenderOptions,
+    },
+
+    /// Generate a layout from a CaveInfo file outside the assets folder and render an
+    /// image of it. Useful for quickly testing changes to a romhack's caves.
+    #[clap(arg_required_else_help = true)]
+    GenerateRaw {
+        #[clap(help = "The CaveInfo file to read. Pass \"-\" to read it from STDIN.")]
+        caveinfo_file: String,
+
+        #[clap(help = "Which floor of the CaveInfo file to generate, starting from 1.")]
+        floor: usize,
+
+        #[clap(
+            value_parser = |s: &str| parse_seed(s).map_err(|e| format!("{e:#?}")),
+            help = SEED_HELP,
+        )]
+        seed: Seed,
+
+        #[clap(
+            long,
+            default_value = "pikmin2",
+            help = "The game or romhack whose unit files, map units, and images the CaveInfo uses."
+        )]
+        game: String,
+
+        #[clap(flatten)]
+        render_options: LayoutRenderOptions,
+    },
+
+    /// Print a permalink for a layout and set of render options. Add it to the end of
+    /// the web viewer's URL after a '#' to open the same layout there.
+    #[clap(arg_required_else_help = true)]
+    Permalink {
+        #[clap(
+            help = SUBLEVEL_HELP,
+        )]
+        sublevel: String,
+
+        #[clap(
+            value_parser = |s: &str| parse_seed(s).map_err(|e| format!("{e:#?}")),
+            help = SEED_HELP,
+        )]
+        seed: Seed,
 
         #[clap(flatten)]
         render_options: LayoutRenderOptions,
     },
 
+    /// Print the map units of a layout and the doors between them as a GraphViz DOT graph.
+    /// Pipe it into `dot -Tpng` to see the layout's topology.
+    #[clap(arg_required_else_help = true)]
+    Dot {
+        #[clap(
+            help = SUBLEVEL_HELP,
+        )]
+        sublevel: String,
+
+        #[clap(
+            value_parser = |s: &str| parse_seed(s).map_err(|e| format!("{e:#?}")),
+            help = SEED_HELP,
+        )]
+        seed: Seed,
+    },
+
     /// Display a particular sublevel's CaveInfo.
     #[clap(arg_required_else_help = true)]
     Caveinfo {
@@ -52,10 +157,39 @@ pub enum Commands {
         #[clap(short = 't', long = "text", help = "Only show text instead of rendering an image")]
         text: bool,
 
+        #[clap(
+            long = "json",
+            conflicts_with = "text",
+            help = "Print the full caveinfo as JSON instead of rendering an image"
+        )]
+        json: bool,
+
+        #[clap(
+            long = "whole-cave",
+            conflicts_with_all = ["text", "json"],
+            help = "Render every floor of the sublevel's cave into a single image"
+        )]
+        whole_cave: bool,
+
         #[clap(flatten)]
         render_options: CaveinfoRenderOptions,
     },
 
+    /// Compare the CaveInfo of two sublevels field by field, e.g. a romhack's
+    /// version of a sublevel against vanilla.
+    #[clap(arg_required_else_help = true)]
+    CaveinfoDiff {
+        #[clap(
+            help = SUBLEVEL_HELP,
+        )]
+        sublevel_a: String,
+
+        #[clap(
+            help = SUBLEVEL_HELP,
+        )]
+        sublevel_b: String,
+    },
+
     /// Search for a seed matching a specified condition.
     #[clap(arg_required_else_help = true)]
     Search {
@@ -74,6 +208,79 @@ pub enum Commands {
 
         #[clap(default_value_t = 1, short = 'n', long = "num", help = "Number of seeds to attempt to find.")]
         num: usize,
+
+        #[clap(
+            long = "count-only",
+            help = "Don't print each matching seed, just the total number found once the search ends."
+        )]
+        count_only: bool,
+    },
+
+    /// Find the seed that generates a layout with the given slug. Useful for
+    /// confirming which seed a reported layout came from.
+    #[clap(arg_required_else_help = true)]
+    FindSlug {
+        #[clap(
+            help = SUBLEVEL_HELP,
+        )]
+        sublevel: String,
+
+        #[clap(help = "The layout slug to search for.")]
+        slug: String,
+
+        #[clap(
+            default_value_t = 60,
+            short = 't',
+            long = "timeout",
+            help = "The maximum time to search for a layout, in seconds. If set to 0, search indefinitely"
+        )]
+        timeout_s: u64,
+    },
+
+    /// Generate layouts for a range of sequential seeds and write each seed's layout
+    /// slug to a file, for building an offline index of layouts.
+    ///
+    /// The output contains one tab-separated `seed\tslug` pair per line. Lines are
+    /// written as layouts finish generating, so they are not sorted by seed.
+    #[clap(arg_required_else_help = true)]
+    IndexSeeds {
+        #[clap(
+            help = SUBLEVEL_HELP,
+        )]
+        sublevel: String,
+
+        #[clap(
+            help = "The first seed to index.",
+            value_parser = |s: &str| parse_seed(s).map_err(|e| format!("{e:#?}")),
+        )]
+        start: Seed,
+
+        #[clap(help = "How many sequential seeds to index, starting from and including the first seed.")]
+        count: u32,
+
+        #[clap(help = "The file to write the index to. Will be overwritten if it already exists.")]
+        out: PathBuf,
+    },
+
+    /// Regenerate the layouts recorded in a golden file and report any seeds whose slug
+    /// has changed. Run this after changing layout generation to check that it still
+    /// matches the game. Golden files are made with `index-seeds`.
+    #[clap(arg_required_else_help = true)]
+    SelfTest {
+        #[clap(
+            help = SUBLEVEL_HELP,
+        )]
+        sublevel: String,
+
+        #[clap(help = "A file of tab-separated `seed\tslug` lines, as written by `index-seeds`.")]
+        golden: PathBuf,
+
+        #[clap(
+            short = 'n',
+            long = "num",
+            help = "Only check the first this many seeds in the golden file. Checks all of them by default."
+        )]
+        num: Option<usize>,
     },
 
     /// Search for matching seeds along sequential RNG calls. Useful for TAS RNG manipulation.
@@ -85,7 +292,7 @@ pub enum Commands {
             help = "Start from this seed. Further seeds are obtained by calling Pikmin 2's RNG function.",
             value_parser = |s: &str| parse_seed(s).map_err(|e| format!("{e:#?}")),
         )]
-        start_from: u32,
+        start_from: Seed,
 
         #[clap(
             help = SEARCH_COND_HELP,
@@ -111,6 +318,144 @@ pub enum Commands {
         args: String,
     },
 
+    /// Render a heatmap of where an object tends to spawn on a sublevel across many
+    /// random seeds. Each map unit the object appeared in is drawn separately, with its
+    /// spawn points colored by how often they held the object.
+    #[clap(arg_required_else_help = true)]
+    Heatmap {
+        #[clap(
+            help = SUBLEVEL_HELP,
+        )]
+        sublevel: String,
+
+        #[clap(help = "The object to track. Accepts anything a query accepts as an entity name, e.g. \"hole\", \"blackpom\", or \"carrying:any\".")]
+        object: String,
+
+        #[clap(
+            default_value_t = 10_000,
+            short = 'n',
+            long = "num-seeds",
+            help = "Number of random seeds to generate layouts for."
+        )]
+        num_seeds: u32,
+    },
+
+    /// Generate layouts for random seeds and report how many iterations of the map unit
+    /// placement loop each one took, flagging seeds that came close to its limit. Useful
+    /// for finding sublevels where the generation algorithm nearly fails.
+    #[clap(arg_required_else_help = true)]
+    CheckGeneration {
+        #[clap(
+            help = SUBLEVEL_HELP,
+        )]
+        sublevel: String,
+
+        #[clap(
+            default_value_t = 10_000,
+            short = 'n',
+            long = "num-seeds",
+            help = "Number of random seeds to generate layouts for."
+        )]
+        num: u32,
+    },
+
+    /// Print how long each phase of generation takes for one layout. Useful for finding
+    /// what makes slow sublevels slow. Requires building with `--features gen_timing`.
+    #[clap(arg_required_else_help = true)]
+    Profile {
+        #[clap(
+            help = SUBLEVEL_HELP,
+        )]
+        sublevel: String,
+
+        #[clap(
+            value_parser = |s: &str| parse_seed(s).map_err(|e| format!("{e:#?}")),
+            help = SEED_HELP,
+        )]
+        seed: Seed,
+
+        #[clap(
+            default_value_t = 100,
+            short = 'n',
+            long = "runs",
+            help = "Number of times to generate the layout. Timings are averaged over every run to smooth out noise."
+        )]
+        runs: u32,
+    },
+
+    /// Estimate the chance that a sublevel has at least one of some object, by generating
+    /// layouts for random seeds and counting how many have it.
+    #[clap(arg_required_else_help = true)]
+    Probability {
+        #[clap(
+            help = SUBLEVEL_HELP,
+        )]
+        sublevel: String,
+
+        #[clap(help = "The object to look for. Accepts anything a query accepts as an entity name, e.g. \"hole\", \"blackpom\", or \"carrying:any\".")]
+        object: String,
+
+        #[clap(
+            default_value_t = 10_000,
+            short = 'n',
+            long = "num-seeds",
+            help = "Number of random seeds to generate layouts for. More seeds give a narrower confidence interval."
+        )]
+        num_seeds: u32,
+    },
+
+    /// Render a "spawn cloud" of a sublevel: out of many random seeds, every layout sharing
+    /// the most common map unit arrangement is drawn faintly on top of the same map, showing
+    /// where each kind of object tends to land.
+    #[clap(arg_required_else_help = true)]
+    SpawnCloud {
+        #[clap(
+            help = SUBLEVEL_HELP,
+        )]
+        sublevel: String,
+
+        #[clap(
+            default_value_t = 1_000,
+            short = 'n',
+            long = "num-seeds",
+            help = "Number of random seeds to generate layouts for. Only the layouts with the most common structure are drawn."
+        )]
+        num_seeds: u32,
+    },
+
+    /// Render every map unit a sublevel can use in all four rotations, with door positions
+    /// and directions marked. Useful for checking how units rotate and connect.
+    #[clap(arg_required_else_help = true)]
+    Units {
+        #[clap(
+            help = SUBLEVEL_HELP,
+        )]
+        sublevel: String,
+    },
+
+    /// Render a black-and-white template of a layout for printing: just the walls of each
+    /// map unit, the door connections, and a faint grid, with room to draw routes by hand.
+    #[clap(arg_required_else_help = true)]
+    Template {
+        #[clap(
+            help = SUBLEVEL_HELP,
+        )]
+        sublevel: String,
+
+        #[clap(
+            value_parser = |s: &str| parse_seed(s).map_err(|e| format!("{e:#?}")),
+            help = SEED_HELP,
+        )]
+        seed: Seed,
+
+        #[clap(
+            default_value_t = 2,
+            long,
+            help = "How many times larger than a normal layout image to make the template. Higher values print more sharply."
+        )]
+        scale: u32,
+    },
+
     /// Calculate statistics on what proportion of seeds match a given condition.
     #[clap(arg_required_else_help = true)]
     Stats {
@@ -126,6 +471,35 @@ pub enum Commands {
             help = "Number of seeds to check. Larger sample sizes will produce more reliable results."
         )]
         num_to_search: usize,
+
+        #[clap(
+            long = "sample-seed",
+            help = "Seed for the random number generator that picks which seeds to check. Runs with the same sample seed and sample size always check the same seeds and give the same result."
+        )]
+        sample_seed: Option<u64>,
+    },
+
+    /// Like `stats`, but checks many queries against the same sample of seeds at once.
+    /// Each layout is only generated once, so this is much faster than running `stats`
+    /// once per query.
+    #[clap(arg_required_else_help = true)]
+    StatsBatch {
+        #[clap(help = "File with one query per line. Blank lines and lines starting with '#' are skipped.")]
+        queries_file: PathBuf,
+
+        #[clap(
+            default_value = "100000",
+            short = 'n',
+            long = "num-to-search",
+            help = "Number of seeds to check. Larger sample sizes will produce more reliable results."
+        )]
+        num_to_search: usize,
+
+        #[clap(
+            long = "sample-seed",
+            help = "Seed for the random number generator that picks which seeds to check. Runs with the same sample seed and sample size always check the same seeds and give the same result."
+        )]
+        sample_seed: Option<u64>,
     },
 
     /// Accepts input seeds from a file or stdin, and only prints those that
@@ -141,9 +515,20 @@ pub enum Commands {
             long_help = SEED_FILE_HELP,
         )]
         file: Option<String>,
+
+        #[clap(
+            long = "glob",
+            conflicts_with = "file",
+            help = "Read seeds from every file matching this pattern, e.g. 'results/*.seeds'. Seeds found in more than one file are only checked and printed once."
+        )]
+        glob: Option<String>,
     },
 
-    /// Extracts a game ISO into Caveripper's config folder.
+    /// Checks that all the files Caveripper needs have been installed and extracted,
+    /// and reports any that are missing or unreadable.
+    CheckAssets,
+
+    /// Extracts a game ISO into Caveripper's config folder, or into `--out-dir` if given.
     #[clap(arg_required_else_help = true)]
     Extract {
         #[clap(help = "The ISO file to extract.")]
@@ -153,13 +538,25 @@ pub enum Commands {
         game_name: Option<String>,
 
         #[clap(
-            help = "Where to place the extracted files. Defaults to ~/.config/caveripper/assets",
-            short = 'o',
-            long = "out-dir"
+            long = "only",
+            value_delimiter = ',',
+            help = "Only extract these asset categories, separated by commas. Valid categories: caveinfo, treasures, mapunits, unitfiles, teki, otakara_config, item_config"
+        )]
+        only: Option<Vec<String>>,
+
+        #[clap(
+            long = "resume",
+            help = "Skip files that have already been extracted. Useful for resuming an interrupted extraction."
         )]
-        out_dir: Option<String>,
+        resume: bool,
     },
 
+    /// Start an interactive session that reads commands from stdin one line at a time,
+    /// e.g. `stats "scx7 minihoudai < 2"`. Assets are only loaded once, so this is much
+    /// faster than running caveripper repeatedly when trying out queries. Type `:quit`
+    /// to exit.
+    Repl,
+
     /// Extracts a single SZS compressed file
     #[clap(arg_required_else_help = true, name = "extract-szs")]
     ExtractSzs {
@@ -175,12 +572,37 @@ pub enum Commands {
 }
 
 const SUBLEVEL_HELP: &str = "The sublevel in question. Examples: \"SCx6\", \"SmC-3\", \"bk4\"";
-const SEARCH_COND_HELP: &str = "A condition to search for in the sublevel.";
+const SEARCH_COND_HELP: &str = r##"A condition to search for in the sublevel. Queries saved in
+~/.config/caveripper/queries.toml can be used by writing "@name" in place of the query text.
+"##;
+const RAW_STDOUT_HELP: &str = r##"Write the rendered image to stdout as raw pixels instead of saving a
+PNG, for piping into other tools such as ffmpeg or ImageMagick.
+
+The output is the image width and then the image height, each as a 4-byte little-endian
+unsigned integer, followed by width * height * 4 bytes of 8-bit RGBA pixel data in
+row-major order starting from the top left. Nothing else is written to stdout, so don't
+combine this with -v.
+"##;
+const OUT_DIR_HELP: &str = r##"The directory to write output files to, created if it doesn't exist.
+
+Commands that render an image default to the CAVERIPPER_OUT_DIR environment variable if
+it's set, or "output" in the current directory otherwise. Passing "-" writes the image to
+stdout as raw pixels instead, in the same format as `generate --raw-stdout`.
+
+`extract` defaults to ~/.config/caveripper/assets and ignores CAVERIPPER_OUT_DIR.
+"##;
+const ISO_HELP: &str = r##"Read game assets straight from this ISO instead of from extracted assets, so
+`extract` doesn't need to be run first. Resource files and saved queries still come from
+~/.config/caveripper. Only ISOs that `extract` can recognize without a game name work.
+"##;
 const SEED_HELP: &str = r##"The seed to check. Must be an 8-digit hexadecimal number, optionally prefixed
 with "0x". Not case sensitive.
 Examples: "0x1234ABCD", "baba2233".
 "##;
 const VERBOSE_HELP: &str = "Enable debug logging. Repeat up to 3 times to increase verbosity.";
+const NO_PLANTS_HELP: &str = r##"Ignore plants (teki from spawn group 6) when rendering layouts and evaluating
+queries. Plant species spawned in other groups still count, since they affect score.
+"##;
 const SEED_FILE_HELP: &str = r##"The file to read seeds from. Should contain one seed on each line with no extra
 punctuation. If not specified, reads from STDIN.
 "##;
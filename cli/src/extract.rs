@@ -1,4 +1,5 @@
 pub mod bti;
+mod iso_asset_manager;
 /// File extraction from Pikmin 2 & romhack ISOs.
 mod rarc;
 mod util;
@@ -7,7 +8,7 @@ use std::{
     fs::{self, create_dir_all, read_to_string, write, File},
     io::{BufReader, Cursor, Read, Seek, SeekFrom},
     panic::catch_unwind,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
 use anyhow::anyhow;
@@ -21,39 +22,154 @@ use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 use regex::Regex;
 use yaz0::{Error as Yaz0Error, Yaz0Archive};
 
+pub use iso_asset_manager::IsoAssetManager;
+
+/// Asset categories that can be extracted individually. These correspond to the top-level
+/// entries of the extracted asset folder for each game.
+pub const ASSET_CATEGORIES: [&str; 7] = [
+    "caveinfo",
+    "treasures",
+    "mapunits",
+    "unitfiles",
+    "teki",
+    "otakara_config",
+    "item_config",
+];
+
+/// Extracts the files Caveripper needs from a game ISO.
+///
+/// If `only` is provided, only the listed asset categories (see [ASSET_CATEGORIES]) are extracted.
+/// If `resume` is set, files that already exist at their destination with the expected size are
+/// skipped rather than rewritten.
 pub fn extract_iso<P: AsRef<Path>>(
     game_name: Option<String>,
     iso_path: P,
     progress: &ProgressBar,
     out_dir: &str,
+    only: Option<&[String]>,
+    resume: bool,
 ) -> Result<(), anyhow::Error> {
     let iso_path = iso_path.as_ref();
     let iso = GcmFile::open(iso_path).map_err(|_| anyhow!("Couldn't parse ISO!"))?;
     let all_files = traverse_filesystem(&iso);
-    let game_id_raw = format!("{:?}", iso.game_id);
-    let game_id = game_id_raw.trim_matches('"');
+    let game_id = game_id(&iso);
 
     let game_name = if let Some(override_name) = game_name {
         override_name
     } else {
-        match game_id {
-            "GPVE01" | "GPVJ01" | "GPVP01" => "pikmin2",
-            "PIKE25" => "251",
-            "POKE42" => "216",
-            "WSAE64" => "newyear",
-            _ => return Err(anyhow!("Unrecognized game ISO {} and no game override provided", game_id)),
-        }
-        .to_string()
+        game_name_for_id(&game_id)
+            .ok_or_else(|| anyhow!("Unrecognized game ISO {} and no game override provided", game_id))?
+            .to_string()
     };
 
-    if PathBuf::from_iter(["assets", &game_name]).exists() {
+    if !resume && PathBuf::from_iter(["assets", &game_name]).exists() {
         warn!("Extracted filesystem for {game_name} already exists. It will be overwritten.");
     }
 
     progress.set_message("Reading ISO file system");
     progress.inc(1);
 
-    let matchers: Vec<DesiredFileMatcher> = match game_id {
+    if let Some(unknown) = only.and_then(|only| only.iter().find(|c| !ASSET_CATEGORIES.contains(&c.as_str()))) {
+        return Err(anyhow!(
+            "Unrecognized asset category '{unknown}'. Valid categories are: {}",
+            ASSET_CATEGORIES.join(", ")
+        ));
+    }
+
+    let mut matchers = desired_files(&game_id);
+    if let Some(only) = only {
+        matchers.retain(|m| only.iter().any(|c| c.eq_ignore_ascii_case(m.category())));
+    }
+
+    all_files
+        .into_par_iter()
+        .progress_with(progress.clone())
+        .try_for_each(|f| -> Result<(), anyhow::Error> {
+            progress.set_message(f.path.to_string_lossy().to_string());
+            let mut iso_reader = BufReader::new(File::open(iso_path)?);
+
+            if let Some("szs") = f.path.extension().and_then(|e| e.to_str()) {
+                let is_prefix_of_desired_path = matchers.iter().any(|m| {
+                    let p_components = f.path.components();
+                    m.source
+                        .iter()
+                        .zip(p_components)
+                        .all(|(m, p)| m.is_match(p.as_os_str().to_str().unwrap_or_default()))
+                });
+                if !is_prefix_of_desired_path {
+                    return Ok(());
+                }
+
+                let data = f.read(&mut iso_reader)?;
+
+                for (subpath, data) in extract_szs(data)?.into_iter() {
+                    let mut full_path = f.path.clone();
+                    full_path.extend(&subpath);
+                    progress.set_message(full_path.to_string_lossy().to_string());
+
+                    for matcher in matchers.iter() {
+                        if let Some(dest) = matcher.matches(&full_path) {
+                            let mut full_dest = PathBuf::from_iter([&out_dir, game_name.as_str()]);
+                            full_dest.push(dest);
+                            if !(resume && already_extracted(&full_dest, data.len() as u64)) {
+                                write_file(&full_dest, &data)?;
+                            }
+                            break;
+                        }
+                    }
+                }
+            } else {
+                for matcher in matchers.iter() {
+                    if let Some(dest) = matcher.matches(&f.path) {
+                        let mut full_dest = PathBuf::from_iter([&out_dir, game_name.as_str()]);
+                        full_dest.push(dest);
+                        if resume && already_extracted(&full_dest, f.size()) {
+                            break;
+                        }
+                        let data = f.read(&mut iso_reader)?;
+                        write_file(&full_dest, &data)?;
+                        break;
+                    }
+                }
+            }
+            Ok(())
+        })?;
+
+    write_file(
+        &PathBuf::from_iter([&out_dir, game_name.as_str(), ".cr_extract_version"]),
+        format!("{}", FsAssetManager::ASSET_VERSION).as_bytes(),
+    )?;
+
+    // The patches aren't idempotent, so don't re-apply them to unitfiles we didn't just extract.
+    let extracted_unitfiles = matchers.iter().any(|m| m.category() == "unitfiles");
+    if game_name.eq_ignore_ascii_case("colossal") && extracted_unitfiles {
+        apply_colossal_patches(&out_dir).expect("Failed to apply Colossal Caverns unitfile patches. Cave generation may not work.");
+    }
+
+    Ok(())
+}
+
+/// The game ID stored in an ISO's header, e.g. "GPVE01".
+fn game_id(iso: &GcmFile) -> String {
+    format!("{:?}", iso.game_id).trim_matches('"').to_string()
+}
+
+/// The name Caveripper uses for the game with this ID, if it's one Caveripper recognizes.
+/// Romhacks that don't change the game ID need their name given explicitly.
+fn game_name_for_id(game_id: &str) -> Option<&'static str> {
+    match game_id {
+        "GPVE01" | "GPVJ01" | "GPVP01" => Some("pikmin2"),
+        "PIKE25" => Some("251"),
+        "POKE42" => Some("216"),
+        "WSAE64" => Some("newyear"),
+        _ => None,
+    }
+}
+
+/// Which files in the ISO Caveripper needs, and where each one goes in the extracted
+/// asset folder.
+fn desired_files(game_id: &str) -> Vec<DesiredFileMatcher> {
+    match game_id {
         "PIKE25" => {
             vec![
                 // TODO: figure out how to not duplicate these dest strings, since they'll be the same for every arm
@@ -111,67 +227,7 @@ pub fn extract_iso<P: AsRef<Path>>(
                 vec!["user", "Abe", "Pellet", "us", "pelletlist_us.szs", "item_config.txt"],
             ),
         ],
-    };
-
-    all_files
-        .into_par_iter()
-        .progress_with(progress.clone())
-        .try_for_each(|f| -> Result<(), anyhow::Error> {
-            progress.set_message(f.path.to_string_lossy().to_string());
-            let mut iso_reader = BufReader::new(File::open(iso_path)?);
-
-            if let Some("szs") = f.path.extension().and_then(|e| e.to_str()) {
-                let is_prefix_of_desired_path = matchers.iter().any(|m| {
-                    let p_components = f.path.components();
-                    m.source
-                        .iter()
-                        .zip(p_components)
-                        .all(|(m, p)| m.is_match(p.as_os_str().to_str().unwrap_or_default()))
-                });
-                if !is_prefix_of_desired_path {
-                    return Ok(());
-                }
-
-                let data = f.read(&mut iso_reader)?;
-
-                for (subpath, data) in extract_szs(data)?.into_iter() {
-                    let mut full_path = f.path.clone();
-                    full_path.extend(&subpath);
-                    progress.set_message(full_path.to_string_lossy().to_string());
-
-                    for matcher in matchers.iter() {
-                        if let Some(dest) = matcher.matches(&full_path) {
-                            let mut full_dest = PathBuf::from_iter([&out_dir, game_name.as_str()]);
-                            full_dest.push(dest);
-                            write_file(&full_dest, &data)?;
-                            break;
-                        }
-                    }
-                }
-            } else {
-                for matcher in matchers.iter() {
-                    if let Some(dest) = matcher.matches(&f.path) {
-                        let data = f.read(&mut iso_reader)?;
-                        let mut full_dest = PathBuf::from_iter([&out_dir, game_name.as_str()]);
-                        full_dest.push(dest);
-                        write_file(&full_dest, &data)?;
-                        break;
-                    }
-                }
-            }
-            Ok(())
-        })?;
-
-    write_file(
-        &PathBuf::from_iter([&out_dir, game_name.as_str(), ".cr_extract_version"]),
-        format!("{}", FsAssetManager::ASSET_VERSION).as_bytes(),
-    )?;
-
-    if game_name.eq_ignore_ascii_case("colossal") {
-        apply_colossal_patches(&out_dir).expect("Failed to apply Colossal Caverns unitfile patches. Cave generation may not work.");
     }
-
-    Ok(())
 }
 
 fn apply_colossal_patches(out_dir: &str) -> std::io::Result<()> {
@@ -228,6 +284,17 @@ fn write_file(dest: &Path, data: &[u8]) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+/// Checks whether a file has already been extracted to `dest` by a previous run.
+/// BTI images are converted to PNG on extraction, so for those we can only check
+/// that the converted file exists.
+fn already_extracted(dest: &Path, expected_size: u64) -> bool {
+    if let Some("bti") = dest.extension().and_then(|e| e.to_str()) {
+        dest.with_extension("png").exists()
+    } else {
+        fs::metadata(dest).is_ok_and(|meta| meta.len() == expected_size)
+    }
+}
+
 pub fn extract_szs(data: Vec<u8>) -> Result<Vec<(PathBuf, Vec<u8>)>, Yaz0Error> {
     let arc = if &data[..4] == b"Yaz0" {
         Yaz0Archive::new(Cursor::new(data))?.decompress()?
@@ -251,19 +318,47 @@ impl DesiredFileMatcher {
         }
     }
 
+    /// The asset category this matcher extracts, i.e. the first component of its destination.
+    pub fn category(&self) -> &str {
+        self.destination
+            .components()
+            .next()
+            .and_then(|c| Path::new(c.as_os_str()).file_stem())
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+    }
+
     /// Returns the reified final path upon successful match
     pub fn matches(&self, path: &Path) -> Option<PathBuf> {
         let path_components = path.components().collect::<Vec<_>>();
-        let all_components_match = self
-            .source
-            .iter()
-            .zip(path_components.iter())
-            .all(|(m, p)| m.is_match(p.as_os_str().to_str().unwrap_or_default()));
+        if self.source.len() != path_components.len() || !self.components_match(&path_components) {
+            return None;
+        }
+        Some(PathBuf::from(self.fill_destination(&path_components)))
+    }
 
-        if self.source.len() != path_components.len() || !all_components_match {
+    /// If the given archive could contain files this matcher wants, returns the part of the
+    /// destination path that all of them share, as far as the archive's own path determines
+    /// it. E.g. a map unit's `arc.szs` gives `mapunits/UNIT_NAME/arc/`.
+    pub fn archive_prefix(&self, archive_path: &Path) -> Option<String> {
+        let path_components = archive_path.components().collect::<Vec<_>>();
+        if self.source.len() <= path_components.len() || !self.components_match(&path_components) {
             return None;
         }
+        let destination = self.fill_destination(&path_components);
+        Some(destination.split('{').next().unwrap_or_default().to_string())
+    }
+
+    fn components_match(&self, path_components: &[Component]) -> bool {
+        self.source
+            .iter()
+            .zip(path_components.iter())
+            .all(|(m, p)| m.is_match(p.as_os_str().to_str().unwrap_or_default()))
+    }
 
+    /// Fills in the destination's placeholders with what the source patterns captured from
+    /// the path. Placeholders past the end of the path are left as they are.
+    fn fill_destination(&self, path_components: &[Component]) -> String {
         let fillers = self
             .source
             .iter()
@@ -271,11 +366,11 @@ impl DesiredFileMatcher {
             .filter_map(|(m, p)| m.captures(p.as_os_str().to_str()?))
             .flat_map(|c| c.iter().skip(1).filter_map(|c| Some(c?.as_str().trim())).collect::<Vec<_>>());
 
-        let mut final_path = self.destination.to_string_lossy();
+        let mut final_path = self.destination.to_string_lossy().into_owned();
         for (i, filler) in fillers.enumerate() {
-            final_path = final_path.replace(&format!("{{{i}}}"), filler).into();
+            final_path = final_path.replace(&format!("{{{i}}}"), filler);
         }
-        Some(PathBuf::from(&*final_path))
+        final_path
     }
 }
 
@@ -290,10 +385,34 @@ impl<'a> VirtualFile<'a> {
         Self { path, entry }
     }
 
-    fn read(&self, iso_reader: &mut BufReader<File>) -> std::io::Result<Vec<u8>> {
+    fn size(&self) -> u64 {
+        self.location().size
+    }
+
+    fn location(&self) -> FileLocation {
         let file_location = self.entry.as_file().unwrap();
-        let mut data = vec![0u8; file_location.size as usize];
-        iso_reader.seek(SeekFrom::Start(file_location.offset as u64))?;
+        FileLocation {
+            offset: file_location.offset as u64,
+            size: file_location.size as u64,
+        }
+    }
+
+    fn read(&self, iso_reader: &mut BufReader<File>) -> std::io::Result<Vec<u8>> {
+        self.location().read(iso_reader)
+    }
+}
+
+/// Where a file's data is stored in the ISO.
+#[derive(Debug, Clone, Copy)]
+struct FileLocation {
+    offset: u64,
+    size: u64,
+}
+
+impl FileLocation {
+    fn read(&self, iso_reader: &mut BufReader<File>) -> std::io::Result<Vec<u8>> {
+        let mut data = vec![0u8; self.size as usize];
+        iso_reader.seek(SeekFrom::Start(self.offset))?;
         iso_reader.read_exact(&mut data)?;
         Ok(data)
     }
@@ -0,0 +1,344 @@
+//! Reads assets straight out of a game ISO, so Caveripper can be used without extracting
+//! anything first. Only the files that are actually used get read, and SZS archives are
+//! decompressed in memory the first time something inside them is needed.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::File,
+    io::BufReader,
+    panic::catch_unwind,
+    path::{Component, Path, PathBuf},
+    sync::OnceLock,
+};
+
+use caveripper::{
+    assets::{
+        fs_asset_manager::FsAssetManager, parse_treasure_config, pinmap::PinMap, AssetManager, CaveConfig, DisplayNames, ImageKind,
+        TekiSizes, Treasure,
+    },
+    caveinfo::CaveInfo,
+    errors::CaveripperError,
+    sublevel::Sublevel,
+};
+use encoding_rs::SHIFT_JIS;
+use error_stack::{report, Result, ResultExt};
+use gc_gcm::GcmFile;
+use image::RgbaImage;
+use log::info;
+
+use super::{bti::BtiImage, desired_files, extract_szs, game_id, game_name_for_id, traverse_filesystem, DesiredFileMatcher, FileLocation};
+
+pub struct IsoAssetManager {
+    iso_path: PathBuf,
+    game: String,
+
+    /// Resource files (fonts, the cave list, etc.) aren't in the ISO, so they're still read
+    /// from Caveripper's config folder.
+    resources: FsAssetManager,
+
+    matchers: Vec<DesiredFileMatcher>,
+
+    /// Wanted files that aren't inside an archive, keyed by where they'd be extracted to
+    /// relative to the game's asset folder, e.g. `caveinfo/tutorial_1.txt`.
+    files: HashMap<String, FileLocation>,
+
+    /// SZS archives containing wanted files. See [IsoArchive].
+    archives: Vec<IsoArchive>,
+
+    /// Decompressed contents of each archive, keyed by index into `archives`. Files in
+    /// each archive are keyed the same way as `files`.
+    archive_cache: PinMap<usize, HashMap<String, Vec<u8>>>,
+
+    caveinfo_cache: PinMap<Sublevel, CaveInfo>,
+    img_cache: PinMap<String, RgbaImage>,
+    treasures: OnceLock<Vec<Treasure>>,
+    teki: OnceLock<Vec<String>>,
+}
+
+/// An SZS archive in the ISO that holds files Caveripper wants.
+struct IsoArchive {
+    path: PathBuf,
+    location: FileLocation,
+    /// The start of the extracted path of every wanted file inside this archive, as far
+    /// as the archive's own path determines it. Archives that can't hold the file being
+    /// looked for are skipped based on this, so they don't have to be decompressed.
+    prefix: String,
+}
+
+impl IsoAssetManager {
+    /// Reads the ISO's file system table. Nothing else is read from the ISO until it's
+    /// needed. Only ISOs whose game is recognized from the game ID are supported; romhacks
+    /// that reuse the vanilla game ID have to be extracted with an explicit game name instead.
+    pub fn open(iso_path: &Path) -> Result<IsoAssetManager, CaveripperError> {
+        // Regular init sets up the resources folder if it's missing, but fails if no
+        // games have been extracted, which doesn't matter here.
+        let resources = FsAssetManager::init().or_else(|_| FsAssetManager::init_unchecked())?;
+
+        let iso = GcmFile::open(iso_path)
+            .map_err(|_| report!(CaveripperError::AssetLoadingError))
+            .attach_printable_lazy(|| format!("Couldn't parse ISO {}", iso_path.to_string_lossy()))?;
+        let game_id = game_id(&iso);
+        let game = game_name_for_id(&game_id)
+            .ok_or(report!(CaveripperError::UnrecognizedGame))
+            .attach_printable_lazy(|| format!("Unrecognized game ISO {game_id}. Extract it with an explicit game name instead."))?;
+
+        let matchers = desired_files(&game_id);
+        let mut files = HashMap::new();
+        let mut archives = Vec::new();
+        for file in traverse_filesystem(&iso) {
+            if let Some("szs") = file.path.extension().and_then(|e| e.to_str()) {
+                if let Some(prefix) = matchers.iter().find_map(|m| m.archive_prefix(&file.path)) {
+                    archives.push(IsoArchive {
+                        location: file.location(),
+                        path: file.path,
+                        prefix,
+                    });
+                }
+            } else if let Some(dest) = matchers.iter().find_map(|m| m.matches(&file.path)) {
+                files.insert(dest.to_string_lossy().into_owned(), file.location());
+            }
+        }
+
+        Ok(IsoAssetManager {
+            iso_path: iso_path.to_path_buf(),
+            game: game.to_string(),
+            resources,
+            matchers,
+            files,
+            archives,
+            archive_cache: PinMap::new(),
+            caveinfo_cache: PinMap::new(),
+            img_cache: PinMap::new(),
+            treasures: OnceLock::new(),
+            teki: OnceLock::new(),
+        })
+    }
+
+    /// Reads a file by the path it would have in an extracted asset folder, relative to
+    /// the game's folder.
+    fn read_file(&self, path: &str) -> Result<Vec<u8>, CaveripperError> {
+        if let Some(location) = self.files.get(path) {
+            return self.read_location(location);
+        }
+        for (i, archive) in self.archives.iter().enumerate() {
+            if path.starts_with(&archive.prefix)
+                && let Some(data) = self.archive_contents(i)?.get(path)
+            {
+                return Ok(data.clone());
+            }
+        }
+        Err(report!(CaveripperError::AssetLoadingError)).attach_printable_lazy(|| format!("{path} not found in ISO"))
+    }
+
+    fn read_location(&self, location: &FileLocation) -> Result<Vec<u8>, CaveripperError> {
+        File::open(&self.iso_path)
+            .and_then(|file| location.read(&mut BufReader::new(file)))
+            .change_context(CaveripperError::AssetLoadingError)
+            .attach_printable_lazy(|| self.iso_path.to_string_lossy().into_owned())
+    }
+
+    /// Decompresses an archive the first time it's needed, keeping only the files that
+    /// Caveripper wants from it.
+    fn archive_contents(&self, idx: usize) -> Result<&HashMap<String, Vec<u8>>, CaveripperError> {
+        if let Some(contents) = self.archive_cache.get(&idx) {
+            return Ok(contents);
+        }
+
+        let archive = &self.archives[idx];
+        info!("Decompressing {}...", archive.path.to_string_lossy());
+        let data = self.read_location(&archive.location)?;
+        let contents = extract_szs(data)
+            .map_err(|_| report!(CaveripperError::AssetLoadingError))
+            .attach_printable_lazy(|| format!("Couldn't decompress {}", archive.path.to_string_lossy()))?
+            .into_iter()
+            .filter_map(|(subpath, data)| {
+                let full_path = archive.path.join(subpath);
+                let dest = self.matchers.iter().find_map(|m| m.matches(&full_path))?;
+                Some((dest.to_string_lossy().into_owned(), data))
+            })
+            .collect();
+        let _ = self.archive_cache.insert(idx, contents);
+        Ok(self.archive_cache.get(&idx).unwrap())
+    }
+
+    /// Names of everything directly inside the given folder of the extracted assets, like
+    /// listing a directory. Archives are only decompressed if their path alone doesn't
+    /// say what's in the folder.
+    fn list_dir(&self, dir: &str) -> Result<BTreeSet<String>, CaveripperError> {
+        let dir = format!("{dir}/");
+        let child = |path: &str| {
+            path.strip_prefix(&dir)
+                .and_then(|rest| rest.split('/').next())
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+        };
+
+        let mut entries: BTreeSet<String> = self.files.keys().filter_map(|path| child(path)).collect();
+        for (i, archive) in self.archives.iter().enumerate() {
+            match child(&archive.prefix) {
+                // Everything in this archive is further down inside one entry of the folder.
+                Some(name) if archive.prefix.len() > dir.len() + name.len() => {
+                    entries.insert(name);
+                }
+                _ if archive.prefix.starts_with(&dir) || dir.starts_with(&archive.prefix) => {
+                    entries.extend(self.archive_contents(i)?.keys().filter_map(|path| child(path)));
+                }
+                _ => {}
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Converts a path relative to the asset directory, e.g. `assets/pikmin2/teki/chappy.bti`,
+    /// into one relative to the game's folder. None for anything that isn't a game asset
+    /// of this ISO.
+    fn game_path(&self, path: &Path) -> Option<String> {
+        let mut components = path.components().map(Component::as_os_str);
+        if components.next()? != "assets" || components.next()? != self.game.as_str() {
+            return None;
+        }
+        let parts: Vec<&str> = components.map(|c| c.to_str()).collect::<Option<_>>()?;
+        Some(parts.join("/"))
+    }
+
+    fn is_this_game(&self, game: Option<&str>) -> bool {
+        game.is_none_or(|game| game.eq_ignore_ascii_case(&self.game))
+    }
+
+    fn treasures(&self) -> Result<&Vec<Treasure>, CaveripperError> {
+        if let Some(treasures) = self.treasures.get() {
+            return Ok(treasures);
+        }
+        let mut treasures = Vec::new();
+        for config_file in ["otakara_config.txt", "item_config.txt"] {
+            let config_txt = SHIFT_JIS.decode(&self.read_file(config_file)?).0.into_owned();
+            treasures.extend(parse_treasure_config(&config_txt, &self.game));
+        }
+        Ok(self.treasures.get_or_init(|| treasures))
+    }
+
+    fn teki(&self) -> Result<&Vec<String>, CaveripperError> {
+        if let Some(teki) = self.teki.get() {
+            return Ok(teki);
+        }
+        // Eggs and bombs are not listed in enemytex, so they have to be added manually
+        let mut teki = vec!["egg".to_string(), "bomb".to_string(), "hiba".to_string()];
+        teki.extend(
+            self.list_dir("teki")?
+                .iter()
+                .filter_map(|name| name.strip_suffix(".bti"))
+                .map(str::to_ascii_lowercase),
+        );
+        Ok(self.teki.get_or_init(|| teki))
+    }
+}
+
+impl AssetManager for IsoAssetManager {
+    fn load_txt<P: AsRef<Path>>(&self, path: P) -> Result<String, CaveripperError> {
+        let path = path.as_ref();
+        match self.game_path(path) {
+            Some(game_path) => {
+                info!("Loading {game_path} from ISO...");
+                let data = self.read_file(&game_path)?;
+                Ok(SHIFT_JIS.decode(&data).0.into_owned())
+            }
+            None => self.resources.load_txt(path),
+        }
+    }
+
+    fn load_raw<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>, CaveripperError> {
+        let path = path.as_ref();
+        match self.game_path(path) {
+            Some(game_path) => self.read_file(&game_path),
+            None => self.resources.load_raw(path),
+        }
+    }
+
+    fn load_caveinfo<'a>(&'a self, sublevel: &Sublevel) -> Result<&'a CaveInfo, CaveripperError> {
+        if let Some(value) = self.caveinfo_cache.get(sublevel) {
+            return Ok(value);
+        }
+
+        info!("Loading CaveInfo for {} from ISO...", sublevel.cfg.full_name);
+        for mut caveinfo in CaveInfo::parse_from(&sublevel.cfg, self)?.into_iter() {
+            let floor_sublevel = Sublevel::from_cfg(&sublevel.cfg, (caveinfo.floor_num + 1) as usize);
+            caveinfo.cave_cfg = sublevel.cfg.clone();
+            let _ = self.caveinfo_cache.insert(floor_sublevel, caveinfo);
+        }
+        self.caveinfo_cache
+            .get(sublevel)
+            .ok_or(report!(CaveripperError::UnrecognizedSublevel))
+            .attach_printable_lazy(|| sublevel.clone())
+    }
+
+    fn load_image(&self, kind: ImageKind, game: &str, name: &str) -> Result<&RgbaImage, CaveripperError> {
+        let path = match kind {
+            ImageKind::Special => return self.resources.load_image(kind, game, name),
+            ImageKind::CaveUnit => format!("{kind}/{name}/arc/texture.bti"),
+            _ => format!("{kind}/{name}.bti"),
+        };
+        if let Some(img) = self.img_cache.get(&path) {
+            return Ok(img);
+        }
+
+        info!("Loading image {path} from ISO...");
+        let data = self.read_file(&path)?;
+        let img = catch_unwind(|| {
+            let bti = BtiImage::decode(&data);
+            RgbaImage::from_raw(bti.width, bti.height, bti.pixels().flatten().cloned().collect())
+        })
+        .ok()
+        .flatten()
+        .ok_or(report!(CaveripperError::AssetLoadingError))
+        .attach_printable_lazy(|| format!("Couldn't decode {path}"))?;
+        let _ = self.img_cache.insert(path.clone(), img);
+        Ok(self.img_cache.get(&path).unwrap())
+    }
+
+    fn all_teki(&self, game: Option<&str>) -> Result<Vec<String>, CaveripperError> {
+        if !self.is_this_game(game) {
+            return Ok(Vec::new());
+        }
+        self.teki().cloned()
+    }
+
+    fn all_units(&self, game: Option<&str>) -> Result<Vec<String>, CaveripperError> {
+        if !self.is_this_game(game) {
+            return Ok(Vec::new());
+        }
+        Ok(self.list_dir("mapunits")?.iter().map(|name| name.to_ascii_lowercase()).collect())
+    }
+
+    fn all_treasures(&self, game: Option<&str>) -> Result<Vec<Treasure>, CaveripperError> {
+        if !self.is_this_game(game) {
+            return Ok(Vec::new());
+        }
+        self.treasures().cloned()
+    }
+
+    fn get_treasure_info(&self, game: &str, name: &str) -> Result<&Treasure, CaveripperError> {
+        if !self.is_this_game(Some(game)) {
+            return Err(report!(CaveripperError::UnrecognizedGame)).attach_printable_lazy(|| game.to_string());
+        }
+        self.treasures()?
+            .iter()
+            .find(|treasure| treasure.internal_name.eq_ignore_ascii_case(name))
+            .ok_or(report!(CaveripperError::AssetLoadingError))
+            .attach_printable_lazy(|| name.to_string())
+    }
+
+    fn get_cave_cfg(&self, name: &str, game: Option<&str>, force_challenge_mode: bool) -> Result<&CaveConfig, CaveripperError> {
+        if !self.is_this_game(game) {
+            return Err(report!(CaveripperError::UnrecognizedGame)).attach_printable_lazy(|| game.unwrap_or_default().to_string());
+        }
+        self.resources.get_cave_cfg(name, Some(&self.game), force_challenge_mode)
+    }
+
+    fn teki_sizes(&self) -> &TekiSizes {
+        self.resources.teki_sizes()
+    }
+
+    fn display_names(&self) -> &DisplayNames {
+        self.resources.display_names()
+    }
+}
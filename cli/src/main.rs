@@ -2,101 +2,327 @@ mod cli;
 mod extract;
 
 use std::{
-    fs::read_to_string,
-    io::stdin,
-    path::PathBuf,
+    collections::{BTreeSet, HashMap},
+    fs::{read_to_string, File},
+    io::{stdin, stdout, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::sync_channel,
+        OnceLock,
+    },
     time::{Duration, Instant},
 };
 
 use anyhow::anyhow;
 use atty::Stream;
 use caveripper::{
-    assets::{fs_asset_manager::FsAssetManager, AssetManager},
+    assets::{find_missing_assets, fs_asset_manager::FsAssetManager, AssetManager, CaveConfig},
+    caveinfo::{diff_caveinfo, CaveInfo},
     errors::CaveripperError,
-    layout::Layout,
+    format_seed,
+    layout::{GenerationStats, GenerationTimings, Layout, MAX_MAP_UNIT_LOOPS},
     parse_seed,
+    permalink::encode_permalink,
     pikmin_math::PikminRng,
-    query::{find_matching_layouts_parallel, special::ConsecutiveIdenticalSeedsQuery, Query, StructuralQuery},
-    render::{render_caveinfo, render_layout, save_image, RenderHelper},
+    query::{
+        find_matching_layouts_parallel, matches_batch, spawn_probability,
+        special::{ConsecutiveIdenticalSeedsQuery, SlugQuery},
+        EntityMatcher, Query, QueryParseError, StructuralQuery,
+    },
+    render::{
+        render_cave_caveinfo, render_cave_strip, render_caveinfo, render_layout, render_spawn_cloud, render_spawn_heatmap, render_template,
+        render_unit_sheet, save_image, save_layout_image, RenderHelper, SpawnHeatmap,
+    },
     sublevel::Sublevel,
+    Seed,
 };
 use clap::Parser;
 use cli::*;
-use error_stack::Result;
-use extract::{bti::BtiImage, extract_iso, extract_szs};
+use error_stack::{report, Result, ResultExt};
+use extract::{bti::BtiImage, extract_iso, extract_szs, IsoAssetManager};
+use image::RgbaImage;
 use indicatif::{ParallelProgressIterator, ProgressBar, ProgressIterator, ProgressStyle};
 use rand::prelude::*;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use regex::Regex;
 use simple_logger::SimpleLogger;
 
-fn main() -> Result<(), CaveripperError> {
-    // The asset manager has to be initialized as the very first thing because
-    // command parsing can involve sublevel string parsing, which requires
-    // loading assets.
-    let mgr = FsAssetManager::init()?;
-    let helper = RenderHelper::new(&mgr);
+/// How many generated slugs can be waiting to be written to disk at once by the
+/// `index-seeds` command.
+const INDEX_CHANNEL_CAPACITY: usize = 4096;
+
+/// How many seeds the `stats` command draws from each per-chunk RNG.
+const STATS_CHUNK_SIZE: usize = 1024;
+
+/// Seeds whose map unit placement loop ran at least this fraction of its limit get listed
+/// by `check-generation`.
+const NEAR_LOOP_CAP_FRACTION: f32 = 0.9;
+/// Most seeds `check-generation` lists for each kind of problem, so huge runs stay readable.
+const MAX_FLAGGED_SEEDS: usize = 20;
+
+/// File in the asset directory that maps names to query strings, so frequently used
+/// queries can be written as `@name` on the command line.
+const NAMED_QUERIES_FILE: &str = "queries.toml";
+static NAMED_QUERY_RE: OnceLock<Regex> = OnceLock::new();
+
+/// Environment variable that overrides where image commands write to when `--out-dir`
+/// isn't given.
+const OUT_DIR_ENV: &str = "CAVERIPPER_OUT_DIR";
+const DEFAULT_OUT_DIR: &str = "output";
+
+const REPL_PROMPT: &str = "caveripper> ";
+const REPL_QUIT: &str = ":quit";
 
+fn main() -> Result<(), CaveripperError> {
     let args = Cli::parse();
-    match args.verbosity {
-        0 => SimpleLogger::new().with_level(log::LevelFilter::Warn).init().unwrap(),
-        1 => SimpleLogger::new().with_level(log::LevelFilter::Info).init().unwrap(),
-        2.. => SimpleLogger::new().with_level(log::LevelFilter::max()).init().unwrap(),
+    // The logger itself lets everything through and the level is only set globally, so
+    // the REPL can raise it for a single command.
+    SimpleLogger::new().with_level(log::LevelFilter::max()).init().unwrap();
+    log::set_max_level(log_level(args.verbosity));
+
+    // Checking assets has to happen before the asset manager is initialized
+    // properly, since initialization fails outright on broken installs.
+    if let Commands::CheckAssets = args.subcommand {
+        check_assets()?;
+        return Ok(());
     }
 
-    // Run the desired command.
-    match args.subcommand {
+    match args.iso {
+        Some(iso_path) => {
+            let mgr = IsoAssetManager::open(&iso_path)?;
+            run_command(args.subcommand, args.no_plants, args.out_dir, &mgr)
+        }
+        None => {
+            let mgr = FsAssetManager::init()?;
+            run_command(args.subcommand, args.no_plants, args.out_dir, &mgr)
+        }
+    }
+}
+
+/// Runs a single command against an already-initialized asset manager. Shared by `main`
+/// and the REPL so the REPL only has to load assets once.
+fn run_command<M: AssetManager + Send + Sync>(
+    command: Commands,
+    no_plants: bool,
+    out_dir: Option<PathBuf>,
+    mgr: &M,
+) -> Result<(), CaveripperError> {
+    let helper = RenderHelper::new(mgr);
+    let output = ImageOutput::new(out_dir.as_deref());
+    match command {
         Commands::Generate {
             sublevel,
             seed,
-            render_options,
+            raw_stdout,
+            challenge,
+            story,
+            whole_cave,
+            slug,
+            mut render_options,
         } => {
-            let sublevel = Sublevel::try_from_str(&sublevel, &mgr)?;
+            let sublevel = Sublevel::try_from_str(&sublevel, mgr)?;
+            if !(raw_stdout || output.is_stdout()) {
+                println!("Generating {} {}...", sublevel.resolved_name(), format_seed(seed.as_u32()));
+            }
+            render_options.hide_plants |= no_plants;
+            if whole_cave {
+                let image = render_cave_strip(&sublevel.cfg, seed.as_u32(), &helper, render_options)?;
+                if raw_stdout {
+                    write_raw_image(&image)?;
+                } else {
+                    let filename = format!("{}_{}.png", sublevel.cfg.full_name, format_seed(seed.as_u32()));
+                    output.save(&image, &filename, "cave strip", None)?;
+                }
+                return Ok(());
+            }
+
             let caveinfo = mgr.load_caveinfo(&sublevel)?;
-            let layout = Layout::generate(seed, caveinfo);
-            let _ = std::fs::create_dir("output");
-            save_image(
+            let forced_caveinfo = force_generation_mode(caveinfo, challenge, story);
+            let caveinfo = forced_caveinfo.as_ref().unwrap_or(caveinfo);
+            let layout = Layout::generate(seed.as_u32(), caveinfo);
+            if slug {
+                if raw_stdout || output.is_stdout() {
+                    eprintln!("{}", layout.slug());
+                } else {
+                    println!("{}", layout.slug());
+                }
+            }
+            let image = render_layout(&layout, &helper, render_options)?;
+            if raw_stdout {
+                write_raw_image(&image)?;
+            } else {
+                let filename = format!("{}_{}.png", layout.cave_name, format_seed(layout.starting_seed));
+                output.save(&image, &filename, "layout", Some(&layout))?;
+            }
+        }
+        Commands::GenerateRaw {
+            caveinfo_file,
+            floor,
+            seed,
+            game,
+            mut render_options,
+        } => {
+            let caveinfo_txt = if caveinfo_file == "-" {
+                let mut txt = String::new();
+                stdin()
+                    .read_to_string(&mut txt)
+                    .change_context(CaveripperError::AssetLoadingError)
+                    .attach_printable("Couldn't read CaveInfo from STDIN")?;
+                txt
+            } else {
+                read_to_string(&caveinfo_file)
+                    .change_context(CaveripperError::AssetLoadingError)
+                    .attach_printable_lazy(|| caveinfo_file.clone())?
+            };
+            let cave_cfg = CaveConfig {
+                game,
+                full_name: format!("[Raw] {caveinfo_file}"),
+                is_challenge_mode: false,
+                shortened_names: vec!["raw".to_string()],
+                caveinfo_filename: caveinfo_file,
+            };
+            let caveinfos = CaveInfo::parse_from_txt(&caveinfo_txt, &cave_cfg, mgr)?;
+            let num_floors = caveinfos.len();
+            let caveinfo = floor
+                .checked_sub(1)
+                .and_then(|idx| caveinfos.get(idx))
+                .ok_or(report!(CaveripperError::UnrecognizedSublevel))
+                .attach_printable_lazy(|| format!("Floor {floor} requested, but the CaveInfo file has {num_floors} floor(s)"))?;
+
+            let layout = Layout::generate(seed.as_u32(), caveinfo);
+            render_options.hide_plants |= no_plants;
+            let filename = format!("{}_{}.png", layout.cave_name, format_seed(layout.starting_seed));
+            output.save(
                 &render_layout(&layout, &helper, render_options)?,
-                format!("output/{}_{:#010X}.png", layout.cave_name, layout.starting_seed),
+                &filename,
+                "layout",
+                Some(&layout),
             )?;
-            println!(
-                "🍞 Saved layout image as \"output/{}_{:#010X}.png\"",
-                layout.cave_name, layout.starting_seed
-            );
+        }
+        Commands::Permalink {
+            sublevel,
+            seed,
+            mut render_options,
+        } => {
+            let sublevel = Sublevel::try_from_str(&sublevel, mgr)?;
+            render_options.hide_plants |= no_plants;
+            println!("{}", encode_permalink(&sublevel, seed.as_u32(), &render_options));
+        }
+        Commands::Dot { sublevel, seed } => {
+            let sublevel = Sublevel::try_from_str(&sublevel, mgr)?;
+            let layout = Layout::generate(seed.as_u32(), mgr.load_caveinfo(&sublevel)?);
+            print!("{}", layout.waypoint_graph_dot());
         }
         Commands::Caveinfo {
             sublevel,
             text,
+            json,
+            whole_cave,
             render_options,
         } => {
-            let sublevel = Sublevel::try_from_str(&sublevel, &mgr)?;
+            let sublevel = Sublevel::try_from_str(&sublevel, mgr)?;
             let caveinfo = mgr.load_caveinfo(&sublevel)?;
-            if text {
-                println!("{caveinfo}");
+            if !(text || json || whole_cave || output.is_stdout()) {
+                println!("Rendering caveinfo for {}...", sublevel.resolved_name());
+            }
+            if whole_cave {
+                output.save(
+                    &render_cave_caveinfo(&sublevel.cfg, &helper, render_options)?,
+                    &format!("{}_Caveinfo.png", sublevel.cfg.full_name),
+                    "caveinfo",
+                    None,
+                )?;
+            } else if text {
+                println!("{}", caveinfo.with_display_names(mgr));
+            } else if json {
+                println!("{}", serde_json::to_string_pretty(caveinfo).expect("Failed to serialize caveinfo"));
             } else {
-                let _ = std::fs::create_dir("output");
-                save_image(
+                output.save(
                     &render_caveinfo(caveinfo, &helper, render_options)?,
-                    format!("output/{}_Caveinfo.png", caveinfo.name()),
+                    &format!("{}_Caveinfo.png", caveinfo.name()),
+                    "caveinfo",
+                    None,
                 )?;
-                println!("🍞 Saved caveinfo image as \"{}_Caveinfo.png\"", caveinfo.name());
             }
         }
-        Commands::Search { query, timeout_s, num } => {
-            let query = StructuralQuery::try_parse(&query, &mgr)?;
+        Commands::CaveinfoDiff { sublevel_a, sublevel_b } => {
+            let caveinfo_a = mgr.load_caveinfo(&Sublevel::try_from_str(&sublevel_a, mgr)?)?;
+            let caveinfo_b = mgr.load_caveinfo(&Sublevel::try_from_str(&sublevel_b, mgr)?)?;
+            let diffs = diff_caveinfo(caveinfo_a, caveinfo_b);
+            let label = |caveinfo: &CaveInfo| format!("{} ({})", caveinfo.long_name(), caveinfo.cave_cfg.game);
+            if diffs.is_empty() {
+                println!("🍞 No differences between {} and {}.", label(caveinfo_a), label(caveinfo_b));
+            } else {
+                println!("--- {}\n+++ {}", label(caveinfo_a), label(caveinfo_b));
+                for diff in diffs {
+                    println!("{diff}");
+                }
+            }
+        }
+        Commands::Search {
+            query,
+            timeout_s,
+            num,
+            count_only,
+        } => {
+            let query = parse_query(&query, mgr, no_plants)?;
             let timeout = if timeout_s > 0 {
                 Some(Duration::from_secs(timeout_s))
             } else {
                 None
             };
-            search(query, &mgr, timeout, num);
+            search(query, mgr, timeout, num, count_only);
         }
+        Commands::FindSlug { sublevel, slug, timeout_s } => {
+            let query = SlugQuery {
+                sublevel: Sublevel::try_from_str(&sublevel, mgr)?,
+                slug,
+            };
+            let timeout = if timeout_s > 0 {
+                Some(Duration::from_secs(timeout_s))
+            } else {
+                None
+            };
+            search(query, mgr, timeout, 1, false);
+        }
+        Commands::IndexSeeds {
+            sublevel,
+            start,
+            count,
+            out,
+        } => {
+            let sublevel = Sublevel::try_from_str(&sublevel, mgr)?;
+            let caveinfo = mgr.load_caveinfo(&sublevel)?;
+            let mut writer = BufWriter::new(File::create(&out).expect("Couldn't create index file!"));
+
+            // Layouts are generated in parallel, but only one thread writes to the file. The
+            // channel is bounded so generation can't get too far ahead of the writer.
+            let (sender, receiver) = sync_channel::<(u32, String)>(INDEX_CHANNEL_CAPACITY);
+            std::thread::scope(|s| {
+                s.spawn(move || {
+                    for (seed, slug) in receiver {
+                        writeln!(writer, "{}\t{slug}", format_seed(seed)).expect("Failed to write to index file!");
+                    }
+                    writer.flush().expect("Failed to write to index file!");
+                });
+
+                (0..count).into_par_iter().progress().for_each_with(sender, |sender, i| {
+                    let seed = start.as_u32().wrapping_add(i);
+                    let layout = Layout::generate(seed, caveinfo);
+                    sender.send((seed, layout.slug())).expect("Index writer stopped unexpectedly!");
+                });
+            });
+            println!("🍞 Indexed {count} seeds into \"{}\"", out.to_string_lossy());
+        }
+        Commands::SelfTest { sublevel, golden, num } => self_test(&sublevel, &golden, num, mgr)?,
         Commands::SearchSpecial { name, args } => {
             let query = match name.to_ascii_lowercase().as_str() {
                 "consecutive_identical_seeds" => {
                     let (sublevel_arg, num_consecutive_arg) = args.split_once(' ').unwrap_or((&args, "2"));
 
                     ConsecutiveIdenticalSeedsQuery {
-                        sublevel: Sublevel::try_from_str(&sublevel_arg, &mgr)?,
+                        sublevel: Sublevel::try_from_str(&sublevel_arg, mgr)?,
                         num_consecutive: num_consecutive_arg.parse().expect("Second argument must be a number"),
                     }
                 }
@@ -106,49 +332,258 @@ fn main() -> Result<(), CaveripperError> {
                 }
             };
 
-            search(query, &mgr, None, 1);
+            search(query, mgr, None, 1, false);
         }
         Commands::SearchFrom { start_from, query, max } => {
-            let query = StructuralQuery::try_parse(&query, &mgr)?;
-            let rng = PikminRng::new(start_from);
+            let query = parse_query(&query, mgr, no_plants)?;
+            let rng = PikminRng::new(start_from.as_u32());
             let progress_bar = ProgressBar::new(max as u64);
 
             rng.take(max)
                 .enumerate()
                 .progress_with(progress_bar.clone())
-                .filter(|(_, seed)| query.matches(*seed, &mgr))
+                .filter(|(_, seed)| query.matches(*seed, mgr))
                 .for_each(|(offset, seed)| {
-                    progress_bar.suspend(|| println!("{seed:#010X}\tOffset: {} ({:#0X})", offset + 1, offset + 1));
+                    progress_bar.suspend(|| println!("{}\tOffset: {} ({:#0X})", format_seed(seed), offset + 1, offset + 1));
                 });
         }
-        Commands::Stats { query, num_to_search } => {
-            let query = StructuralQuery::try_parse(&query, &mgr)?;
-            let num_matched = (0..num_to_search)
+        Commands::Heatmap {
+            sublevel,
+            object,
+            num_seeds,
+        } => {
+            let sublevel = Sublevel::try_from_str(&sublevel, mgr)?;
+            let caveinfo = mgr.load_caveinfo(&sublevel)?;
+            let object_matcher = EntityMatcher::from(object.as_str());
+            let heatmap = (0..num_seeds)
                 .into_par_iter()
                 .progress()
-                .filter(|_| {
-                    let seed: u32 = random();
-                    query.matches(seed, &mgr)
+                .fold(SpawnHeatmap::default, |mut heatmap, _| {
+                    let mut layout = Layout::generate(random(), caveinfo);
+                    if no_plants {
+                        layout.remove_plants();
+                    }
+                    heatmap.add_layout(&layout, &object_matcher);
+                    heatmap
                 })
-                .count();
+                .reduce(SpawnHeatmap::default, SpawnHeatmap::merge);
+
+            let filename = format!("{}_{}_Heatmap.png", caveinfo.name(), object.replace(':', "-"));
+            output.save(
+                &render_spawn_heatmap(caveinfo, &heatmap, &object, &helper)?,
+                &filename,
+                "heatmap",
+                None,
+            )?;
+        }
+        Commands::CheckGeneration { sublevel, num } => {
+            let sublevel = Sublevel::try_from_str(&sublevel, mgr)?;
+            let caveinfo = mgr.load_caveinfo(&sublevel)?;
+            let mut results: Vec<(u32, GenerationStats)> = (0..num)
+                .into_par_iter()
+                .progress()
+                .map(|_| {
+                    let seed = random();
+                    (seed, Layout::generate(seed, caveinfo).generation_stats)
+                })
+                .collect();
+            if results.is_empty() {
+                return Ok(());
+            }
+            results.sort_by_key(|(_, stats)| stats.map_unit_loops);
+
+            let loops_at = |fraction: f32| results[((results.len() - 1) as f32 * fraction).round() as usize].1.map_unit_loops;
+            println!("🍞 Generated {num} layouts of {}.", sublevel.resolved_name());
+            println!(
+                "Map unit placement loops: min {}, median {}, 99th percentile {}, max {} (limit {MAX_MAP_UNIT_LOOPS})",
+                loops_at(0.0),
+                loops_at(0.5),
+                loops_at(0.99),
+                loops_at(1.0)
+            );
+
+            let hit_cap: Vec<_> = results.iter().filter(|(_, stats)| stats.hit_loop_cap).collect();
+            let near_cap: Vec<_> = results
+                .iter()
+                .filter(|(_, stats)| {
+                    !stats.hit_loop_cap && stats.map_unit_loops as f32 >= MAX_MAP_UNIT_LOOPS as f32 * NEAR_LOOP_CAP_FRACTION
+                })
+                .collect();
+            for (description, flagged) in [
+                ("hit the loop limit (not always a problem; some sublevels always do)", hit_cap),
+                ("came close to the loop limit", near_cap),
+            ] {
+                if flagged.is_empty() {
+                    continue;
+                }
+                println!(
+                    "{} layouts ({:.02}%) {description}:",
+                    flagged.len(),
+                    flagged.len() as f32 / num as f32 * 100.0
+                );
+                for (seed, stats) in flagged.iter().take(MAX_FLAGGED_SEEDS) {
+                    println!("  {}\t{} loops", format_seed(*seed), stats.map_unit_loops);
+                }
+                if flagged.len() > MAX_FLAGGED_SEEDS {
+                    println!("  ...and {} more", flagged.len() - MAX_FLAGGED_SEEDS);
+                }
+            }
+        }
+        Commands::Profile { sublevel, seed, runs } => {
+            if !cfg!(feature = "gen_timing") {
+                eprintln!("Generation timings are only recorded when built with `--features gen_timing`.");
+                return Ok(());
+            }
+            let sublevel = Sublevel::try_from_str(&sublevel, mgr)?;
+            let caveinfo = mgr.load_caveinfo(&sublevel)?;
+            let runs = runs.max(1);
+            let timings: Vec<GenerationTimings> = (0..runs).map(|_| Layout::generate(seed.as_u32(), caveinfo).timings).collect();
+
+            println!(
+                "🍞 Generation timings for {} {}, averaged over {runs} runs:",
+                sublevel.resolved_name(),
+                format_seed(seed.as_u32())
+            );
+            let total = timings.iter().map(|t| t.total).sum::<Duration>() / runs;
+            let mut accounted = Duration::ZERO;
+            for (i, (name, _)) in timings[0].phases().into_iter().enumerate() {
+                let phase = timings.iter().map(|t| t.phases()[i].1).sum::<Duration>() / runs;
+                accounted += phase;
+                print_phase_timing(name, phase, total);
+            }
+            print_phase_timing("Other", total.saturating_sub(accounted), total);
+            println!("{:<24}{:>12.3?}", "Total", total);
+        }
+        Commands::Probability {
+            sublevel,
+            object,
+            num_seeds,
+        } => {
+            let sublevel = Sublevel::try_from_str(&sublevel, mgr)?;
+            let probability = spawn_probability(mgr, &sublevel, &object, num_seeds)?;
+            let (low, high) = probability.confidence_interval();
+            println!(
+                "🍞 {} had at least one '{object}' in {} out of {num_seeds} layouts ({:.03}%, 95% confidence interval {:.03}% to {:.03}%).",
+                sublevel.resolved_name(),
+                probability.num_matched,
+                probability.estimate() * 100.0,
+                low * 100.0,
+                high * 100.0
+            );
+        }
+        Commands::SpawnCloud { sublevel, num_seeds } => {
+            let sublevel = Sublevel::try_from_str(&sublevel, mgr)?;
+            let filename = format!("{}_SpawnCloud.png", sublevel.short_name());
+            output.save(&render_spawn_cloud(&sublevel, num_seeds, &helper)?, &filename, "spawn cloud", None)?;
+        }
+        Commands::Units { sublevel } => {
+            let sublevel = Sublevel::try_from_str(&sublevel, mgr)?;
+            let caveinfo = mgr.load_caveinfo(&sublevel)?;
+            let filename = format!("{}_Units.png", caveinfo.name());
+            output.save(&render_unit_sheet(caveinfo, &helper)?, &filename, "map unit", None)?;
+        }
+        Commands::Template { sublevel, seed, scale } => {
+            let sublevel = Sublevel::try_from_str(&sublevel, mgr)?;
+            let caveinfo = mgr.load_caveinfo(&sublevel)?;
+            let layout = Layout::generate(seed.as_u32(), caveinfo);
+            let filename = format!("{}_{}_Template.png", layout.cave_name, format_seed(layout.starting_seed));
+            output.save(&render_template(&layout, &helper, scale)?, &filename, "template", Some(&layout))?;
+        }
+        Commands::Stats {
+            query,
+            num_to_search,
+            sample_seed,
+        } => {
+            let query = parse_query(&query, mgr, no_plants)?;
+            let num_matched: usize = sample_chunks(sample_seed, num_to_search)
+                .into_par_iter()
+                .progress()
+                .map(|(chunk_seed, chunk_len)| {
+                    let mut rng = StdRng::seed_from_u64(chunk_seed);
+                    (0..chunk_len).filter(|_| query.matches(rng.gen(), mgr)).count()
+                })
+                .sum();
             println!(
                 "🍞 {num_matched} out of {num_to_search} ({:.03}%) match the condition '{query}'.",
                 (num_matched as f32 / num_to_search as f32) * 100.0
             );
         }
-        Commands::Filter { query, file } => {
-            let query = StructuralQuery::try_parse(&query, &mgr)?;
+        Commands::StatsBatch {
+            queries_file,
+            num_to_search,
+            sample_seed,
+        } => {
+            let queries_txt = read_to_string(&queries_file)
+                .change_context(CaveripperError::AssetLoadingError)
+                .attach_printable_lazy(|| queries_file.to_string_lossy().into_owned())?;
+            let queries: Vec<StructuralQuery> = queries_txt
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| parse_query(line, mgr, no_plants))
+                .collect::<Result<_, _>>()?;
+
+            // Same sampling as `stats`, so a query checked here with a given sample seed gets
+            // exactly the same result as checking it on its own.
+            let num_matched: Vec<usize> = sample_chunks(sample_seed, num_to_search)
+                .into_par_iter()
+                .progress()
+                .map(|(chunk_seed, chunk_len)| {
+                    let mut rng = StdRng::seed_from_u64(chunk_seed);
+                    let mut counts = vec![0; queries.len()];
+                    for _ in 0..chunk_len {
+                        for (count, matched) in counts.iter_mut().zip(matches_batch(&queries, rng.gen(), mgr)) {
+                            *count += matched as usize;
+                        }
+                    }
+                    counts
+                })
+                .reduce(|| vec![0; queries.len()], |a, b| a.into_iter().zip(b).map(|(a, b)| a + b).collect());
+            for (query, num_matched) in queries.iter().zip(num_matched) {
+                println!(
+                    "🍞 {num_matched} out of {num_to_search} ({:.03}%) match the condition '{query}'.",
+                    (num_matched as f32 / num_to_search as f32) * 100.0
+                );
+            }
+        }
+        Commands::Filter { query, file, glob } => {
+            let query = parse_query(&query, mgr, no_plants)?;
+            // Read from every file matching a glob pattern. Seeds are merged and deduplicated
+            // across files before checking, and printed in sorted order.
+            if let Some(pattern) = glob {
+                let paths: Vec<PathBuf> = glob::glob(&pattern)
+                    .change_context(CaveripperError::AssetLoadingError)
+                    .attach_printable_lazy(|| format!("Invalid glob pattern '{pattern}'"))?
+                    .filter_map(|path| path.ok())
+                    .collect();
+                let contents = paths
+                    .par_iter()
+                    .map(|path| {
+                        read_to_string(path)
+                            .change_context(CaveripperError::AssetLoadingError)
+                            .attach_printable_lazy(|| path.to_string_lossy().into_owned())
+                    })
+                    .collect::<Result<Vec<String>, CaveripperError>>()?;
+                let seeds: BTreeSet<u32> = contents
+                    .par_iter()
+                    .flat_map_iter(|text| text.lines().filter_map(|line| parse_seed(line).ok()).map(Seed::as_u32))
+                    .collect();
+                let matching: Vec<u32> = seeds.into_par_iter().filter(|seed| query.matches(*seed, mgr)).collect();
+                for seed in matching {
+                    println!("{}", format_seed(seed));
+                }
+            }
             // Read from a file. In this case, we can check the seeds in parallel.
-            if let Some(filename) = file {
+            else if let Some(filename) = file {
                 read_to_string(filename)
                     .unwrap()
                     .lines()
                     .collect::<Vec<_>>()
                     .into_par_iter()
                     .filter_map(|line| parse_seed(line).ok())
-                    .filter(|seed| query.matches(*seed, &mgr))
+                    .filter(|seed| query.matches(seed.as_u32(), mgr))
                     .for_each(|seed| {
-                        println!("{seed:#010X}");
+                        println!("{seed}");
                     });
             }
             // Read from stdin and print as results become ready
@@ -156,19 +591,22 @@ fn main() -> Result<(), CaveripperError> {
                 stdin()
                     .lines()
                     .filter_map(|line| parse_seed(&line.ok()?).ok())
-                    .filter(|seed| query.matches(*seed, &mgr))
+                    .filter(|seed| query.matches(seed.as_u32(), mgr))
                     .for_each(|seed| {
-                        println!("{seed:#010X}");
+                        println!("{seed}");
                     });
             }
         }
+        Commands::CheckAssets => check_assets()?,
+        Commands::Repl => repl(no_plants, out_dir.as_deref(), mgr)?,
         Commands::Extract {
             iso_path,
             game_name,
-            out_dir,
+            only,
+            resume,
         } => {
             let progress_bar = ProgressBar::new_spinner().with_style(ProgressStyle::default_spinner().template("{spinner} {msg}").unwrap());
-            let output_directory = out_dir.unwrap_or_else(|| {
+            let output_directory = out_dir.map(|dir| dir.to_string_lossy().into_owned()).unwrap_or_else(|| {
                 let home_dir = dirs::home_dir()
                     .ok_or(anyhow!("Couldn't locate home directory!"))
                     .expect("Couldn't locate home directory!");
@@ -176,7 +614,7 @@ fn main() -> Result<(), CaveripperError> {
                 out_path.to_string_lossy().into_owned()
             });
 
-            extract_iso(game_name, iso_path, &progress_bar, &output_directory).expect("Failed to extract ISO");
+            extract_iso(game_name, iso_path, &progress_bar, &output_directory, only.as_deref(), resume).expect("Failed to extract ISO");
             progress_bar.finish_and_clear();
             println!("🍞 Done extracting ISO.");
         }
@@ -218,7 +656,318 @@ fn main() -> Result<(), CaveripperError> {
     Ok(())
 }
 
-fn search(query: impl Query + Send + Sync, mgr: &FsAssetManager, timeout: Option<Duration>, num: usize) {
+/// Applies the `--challenge` and `--story` flags. Returns a modified CaveInfo only if the
+/// requested mode differs from the sublevel's own, so layouts in the sublevel's real mode
+/// aren't marked as modified.
+fn force_generation_mode(caveinfo: &CaveInfo, challenge: bool, story: bool) -> Option<CaveInfo> {
+    let challenge_mode = match (challenge, story) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => return None,
+    };
+    (challenge_mode != caveinfo.is_challenge_mode()).then(|| caveinfo.to_builder().challenge_mode(challenge_mode).build())
+}
+
+/// Where commands that render a single image write it: `--out-dir` if given, then
+/// [OUT_DIR_ENV], then [DEFAULT_OUT_DIR]. An out dir of `-` means stdout, in the same
+/// format as `--raw-stdout`.
+struct ImageOutput {
+    dir: PathBuf,
+}
+
+impl ImageOutput {
+    fn new(out_dir: Option<&Path>) -> Self {
+        let dir = out_dir
+            .map(Path::to_path_buf)
+            .or_else(|| std::env::var_os(OUT_DIR_ENV).map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_OUT_DIR));
+        ImageOutput { dir }
+    }
+
+    fn is_stdout(&self) -> bool {
+        self.dir.as_os_str() == "-"
+    }
+
+    /// Saves the image as `filename` in the output directory, creating the directory if
+    /// needed, and reports where it went. Layout images have their seed and sublevel
+    /// embedded (see [save_layout_image]). `kind` is only used in the message.
+    fn save(&self, image: &RgbaImage, filename: &str, kind: &str, layout: Option<&Layout>) -> Result<(), CaveripperError> {
+        if self.is_stdout() {
+            return write_raw_image(image);
+        }
+        std::fs::create_dir_all(&self.dir)
+            .change_context(CaveripperError::RenderingError)
+            .attach_printable_lazy(|| format!("Couldn't create output directory \"{}\"", self.dir.display()))?;
+        let path = self.dir.join(filename);
+        match layout {
+            Some(layout) => save_layout_image(image, &path, layout)?,
+            None => save_image(image, &path)?,
+        }
+        println!("🍞 Saved {kind} image as \"{}\"", path.display());
+        Ok(())
+    }
+}
+
+/// Splits `n` seeds to check into chunks of [STATS_CHUNK_SIZE], each with the seed for its own
+/// RNG. The chunk seeds are drawn in order from one master RNG, so the seeds checked depend
+/// only on the sample seed and not on how rayon schedules chunks.
+fn sample_chunks(sample_seed: Option<u64>, n: usize) -> Vec<(u64, usize)> {
+    let mut master_rng = StdRng::seed_from_u64(sample_seed.unwrap_or_else(random));
+    (0..n)
+        .step_by(STATS_CHUNK_SIZE)
+        .map(|start| (master_rng.gen(), STATS_CHUNK_SIZE.min(n - start)))
+        .collect()
+}
+
+/// Prints one row of the `timing` table: the phase's time and its share of `total`.
+fn print_phase_timing(name: &str, phase: Duration, total: Duration) {
+    let percent = if total.is_zero() {
+        0.0
+    } else {
+        phase.as_secs_f64() / total.as_secs_f64() * 100.0
+    };
+    println!("{name:<24}{phase:>12.3?}{percent:>8.1}%");
+}
+
+/// Writes an image to stdout in the format described by `--raw-stdout`: the width and
+/// height as little-endian u32s, then the RGBA8 pixels row by row.
+fn write_raw_image(image: &RgbaImage) -> Result<(), CaveripperError> {
+    let mut stdout = stdout().lock();
+    stdout
+        .write_all(&image.width().to_le_bytes())
+        .and_then(|_| stdout.write_all(&image.height().to_le_bytes()))
+        .and_then(|_| stdout.write_all(image.as_raw()))
+        .and_then(|_| stdout.flush())
+        .change_context(CaveripperError::RenderingError)
+        .attach_printable("Couldn't write image to stdout")
+}
+
+fn check_assets() -> Result<(), CaveripperError> {
+    let mgr = FsAssetManager::init_unchecked()?;
+    let problems = find_missing_assets(&mgr, &mgr.cave_cfg, &mgr.games);
+    if problems.is_empty() {
+        println!("🍞 All assets present in \"{}\".", mgr.asset_dir().to_string_lossy());
+        return Ok(());
+    }
+
+    eprintln!(
+        "Found {} problem(s) with assets in \"{}\":",
+        problems.len(),
+        mgr.asset_dir().to_string_lossy()
+    );
+    for problem in problems {
+        eprintln!("  - {problem}");
+    }
+    Err(report!(CaveripperError::AssetLoadingError)).attach_printable("Some assets are missing or unreadable")
+}
+
+/// Regenerates each seed in a golden file written by `index-seeds` and compares its slug
+/// against the recorded one. Returns an error if any of them differ.
+fn self_test(sublevel: &str, golden: &Path, num: Option<usize>, mgr: &(impl AssetManager + Sync)) -> Result<(), CaveripperError> {
+    let sublevel = Sublevel::try_from_str(sublevel, mgr)?;
+    let caveinfo = mgr.load_caveinfo(&sublevel)?;
+    let golden_txt = read_to_string(golden)
+        .change_context(CaveripperError::AssetLoadingError)
+        .attach_printable_lazy(|| golden.to_string_lossy().into_owned())?;
+    let expected = golden_txt
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .take(num.unwrap_or(usize::MAX))
+        .map(|line| -> Result<(u32, &str), CaveripperError> {
+            let (seed, slug) = line
+                .split_once('\t')
+                .ok_or(report!(CaveripperError::SeedError))
+                .attach_printable_lazy(|| format!("Expected a tab-separated seed and slug but found '{line}'"))?;
+            Ok((parse_seed(seed)?.as_u32(), slug.trim()))
+        })
+        .collect::<Result<Vec<(u32, &str)>, CaveripperError>>()?;
+
+    let mut diverged: Vec<(u32, usize)> = expected
+        .par_iter()
+        .progress()
+        .filter_map(|(seed, expected_slug)| {
+            let slug = Layout::generate(*seed, caveinfo).slug();
+            (slug != *expected_slug).then(|| {
+                let first_difference = slug.chars().zip(expected_slug.chars()).take_while(|(a, b)| a == b).count();
+                (*seed, first_difference)
+            })
+        })
+        .collect();
+    if diverged.is_empty() {
+        println!("🍞 All {} seeds match \"{}\".", expected.len(), golden.to_string_lossy());
+        return Ok(());
+    }
+
+    diverged.sort();
+    eprintln!(
+        "{} out of {} seeds no longer match the golden file:",
+        diverged.len(),
+        expected.len()
+    );
+    for (seed, first_difference) in diverged {
+        eprintln!("  - {} (slug differs from character {first_difference} on)", format_seed(seed));
+    }
+    Err(report!(CaveripperError::LayoutGenerationError)).attach_printable("Generated layouts don't match the golden file")
+}
+
+/// Reads commands from stdin one line at a time and runs them, reusing the already-loaded
+/// assets. Lines take the same arguments as the command line, minus the program name.
+/// Global flags given when starting the REPL apply to every line; flags on a line add
+/// to them for that command only.
+fn repl(no_plants: bool, out_dir: Option<&Path>, mgr: &(impl AssetManager + Send + Sync)) -> Result<(), CaveripperError> {
+    let session_level = log::max_level();
+    eprintln!("🍞 Enter commands as you would on the command line, e.g. stats \"scx7 minihoudai < 2\". Type {REPL_QUIT} to exit.");
+    let mut lines = stdin().lines();
+    loop {
+        eprint!("{REPL_PROMPT}");
+        let _ = std::io::stderr().flush();
+        let Some(Ok(line)) = lines.next() else {
+            return Ok(());
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == REPL_QUIT {
+            return Ok(());
+        }
+
+        let Some(words) = split_repl_line(line) else {
+            eprintln!("Unmatched quote in \"{line}\"");
+            continue;
+        };
+        let args = match Cli::try_parse_from(std::iter::once("caveripper".to_string()).chain(words)) {
+            Ok(args) => args,
+            Err(e) => {
+                let _ = e.print();
+                continue;
+            }
+        };
+        if let Commands::Repl = args.subcommand {
+            eprintln!("Already in the REPL.");
+            continue;
+        }
+        log::set_max_level(session_level.max(log_level(args.verbosity)));
+        let out_dir = args.out_dir.or_else(|| out_dir.map(Path::to_path_buf));
+        if let Err(e) = run_command(args.subcommand, no_plants || args.no_plants, out_dir, mgr) {
+            eprintln!("{e:?}");
+        }
+        log::set_max_level(session_level);
+    }
+}
+
+fn log_level(verbosity: u8) -> log::LevelFilter {
+    match verbosity {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        2.. => log::LevelFilter::max(),
+    }
+}
+
+/// Splits a REPL line into arguments on whitespace, keeping text in single or double
+/// quotes together the same way a shell would. Returns None if a quote is never closed.
+fn split_repl_line(line: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(current.take()),
+            (None, c) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return None;
+    }
+    words.extend(current);
+    Some(words)
+}
+
+/// Parses a query string, pointing out where the problem is on stderr if it's malformed.
+/// Any `@name` references to saved queries are expanded first.
+fn parse_query(query: &str, mgr: &impl AssetManager, ignore_plants: bool) -> Result<StructuralQuery, CaveripperError> {
+    let query = expand_named_queries(query, mgr)?;
+    let mut parsed = StructuralQuery::try_parse(&query, mgr).inspect_err(|report| {
+        if let Some(err) = report.downcast_ref::<QueryParseError>() {
+            eprintln!("{}", err.caret_diagram(&query));
+        }
+    })?;
+    parsed.ignore_plants = ignore_plants;
+    Ok(parsed)
+}
+
+/// Replaces each `@name` in the query with the query saved under that name in
+/// [NAMED_QUERIES_FILE]. Saved queries can refer to each other, but not in a cycle.
+fn expand_named_queries(query: &str, mgr: &impl AssetManager) -> Result<String, CaveripperError> {
+    if !query.contains('@') {
+        return Ok(query.to_string());
+    }
+
+    // Read through the asset manager rather than from the asset folder directly, since
+    // not every asset manager has one.
+    let named_queries: HashMap<String, String> = mgr
+        .load_txt(NAMED_QUERIES_FILE)
+        .change_context(CaveripperError::QueryParseError)
+        .attach_printable_lazy(|| format!("Couldn't read named queries from {NAMED_QUERIES_FILE} in the asset folder"))
+        .and_then(|text| {
+            toml::from_str(&text)
+                .change_context(CaveripperError::QueryParseError)
+                .attach_printable_lazy(|| format!("{NAMED_QUERIES_FILE} isn't a valid list of named queries"))
+        })?;
+
+    expand_named_queries_with(query, &named_queries, &mut Vec::new())
+        .attach_printable_lazy(|| format!("Named queries are defined in {NAMED_QUERIES_FILE} in the asset folder"))
+}
+
+/// `expanding` holds the names currently being expanded, outermost first, so that
+/// a query that (indirectly) refers to itself can be caught instead of recursing forever.
+fn expand_named_queries_with(
+    query: &str,
+    named_queries: &HashMap<String, String>,
+    expanding: &mut Vec<String>,
+) -> Result<String, CaveripperError> {
+    let name_re = NAMED_QUERY_RE.get_or_init(|| Regex::new(r"@([A-Za-z0-9_\-]+)").unwrap());
+
+    let mut expanded = String::new();
+    let mut last_end = 0;
+    for captures in name_re.captures_iter(query) {
+        let whole = captures.get(0).unwrap();
+        let name = &captures[1];
+        expanded.push_str(&query[last_end..whole.start()]);
+        last_end = whole.end();
+
+        if expanding.iter().any(|n| n == name) {
+            let chain: Vec<String> = expanding.iter().chain([&name.to_string()]).map(|n| format!("@{n}")).collect();
+            return Err(report!(CaveripperError::QueryParseError))
+                .attach_printable_lazy(|| format!("Named query '@{name}' refers to itself: {}", chain.join(" -> ")));
+        }
+        let Some(named_query) = named_queries.get(name) else {
+            return Err(report!(CaveripperError::QueryParseError)).attach_printable_lazy(|| format!("Unknown named query '@{name}'"));
+        };
+
+        expanding.push(name.to_string());
+        expanded.push_str(&expand_named_queries_with(named_query, named_queries, expanding)?);
+        expanding.pop();
+    }
+    expanded.push_str(&query[last_end..]);
+    Ok(expanded)
+}
+
+/// Searches for seeds matching the query, printing each one as it's found. With `count_only`,
+/// nothing is printed until the search ends, and then only the number of seeds found.
+fn search(
+    query: impl Query + Send + Sync,
+    mgr: &(impl AssetManager + Send + Sync),
+    timeout: Option<Duration>,
+    num: usize,
+    count_only: bool,
+) {
     let start_time = Instant::now();
     let deadline = timeout.map(|t| Instant::now() + t);
 
@@ -232,6 +981,7 @@ fn search(query: impl Query + Send + Sync, mgr: &FsAssetManager, timeout: Option
         progress_bar.finish_and_clear();
     }
 
+    let num_found = AtomicUsize::new(0);
     find_matching_layouts_parallel(
         &query,
         mgr,
@@ -241,11 +991,17 @@ fn search(query: impl Query + Send + Sync, mgr: &FsAssetManager, timeout: Option
             progress_bar.inc(1);
         }),
         |seed| {
-            progress_bar.suspend(|| println!("{seed:#010X}"));
+            num_found.fetch_add(1, Ordering::Relaxed);
+            if !count_only {
+                progress_bar.suspend(|| println!("{}", format_seed(seed)));
+            }
         },
     );
 
     progress_bar.finish_and_clear();
+    if count_only {
+        println!("{}", num_found.load(Ordering::Relaxed));
+    }
     if atty::is(Stream::Stdout) {
         eprintln!("🍞 Finished in {:0.3}s.", start_time.elapsed().as_secs_f32());
     }
@@ -1,7 +1,7 @@
 use std::path::Path;
 
 use caveripper::{
-    assets::{parse_treasure_config, pinmap::PinMap, AssetManager, CaveConfig, ImageKind, Treasure},
+    assets::{parse_treasure_config, pinmap::PinMap, AssetManager, CaveConfig, DisplayNames, ImageKind, TekiSizes, Treasure},
     caveinfo::CaveInfo,
     errors::CaveripperError,
     sublevel::Sublevel,
@@ -17,6 +17,8 @@ pub struct WebAssetManager {
     caveinfo_cache: PinMap<Sublevel, CaveInfo>,
     image_cache: PinMap<String, RgbaImage>,
     treasure_info: Vec<Treasure>,
+    teki_sizes: TekiSizes,
+    display_names: DisplayNames,
 }
 
 impl WebAssetManager {
@@ -36,11 +38,18 @@ impl WebAssetManager {
             "pikmin2",
         ));
 
+        let teki_sizes = TekiSizes::parse_from_file(RESOURCES.get_file("teki_sizes.txt").unwrap().contents_utf8().unwrap())
+            .expect("Invalid teki_sizes.txt");
+        let display_names = DisplayNames::parse_from_file(RESOURCES.get_file("display_names.txt").unwrap().contents_utf8().unwrap())
+            .expect("Invalid display_names.txt");
+
         Self {
             cave_cfg,
             caveinfo_cache: PinMap::new(),
             image_cache: PinMap::new(),
             treasure_info,
+            teki_sizes,
+            display_names,
         }
     }
 }
@@ -188,4 +197,12 @@ impl AssetManager for WebAssetManager {
             .ok_or(CaveripperError::UnrecognizedSublevel)
             .attach_printable_lazy(|| name.to_string())
     }
+
+    fn teki_sizes(&self) -> &TekiSizes {
+        &self.teki_sizes
+    }
+
+    fn display_names(&self) -> &DisplayNames {
+        &self.display_names
+    }
 }
@@ -6,8 +6,11 @@ use std::sync::OnceLock;
 use assets::WebAssetManager;
 use caveripper::{
     assets::AssetManager,
+    format_seed,
     layout::Layout,
-    query::{Query, StructuralQuery},
+    parse_seed,
+    permalink::{decode_permalink, encode_permalink},
+    query::{Query, QueryParseError, StructuralQuery},
     render::{render_layout, LayoutRenderOptions, RenderHelper},
     sublevel::Sublevel,
 };
@@ -40,11 +43,31 @@ pub fn cavegen(sublevel: &str, seed: u32) -> Result<Image, JsValue> {
     let sublevel = Sublevel::try_from_str(sublevel, mgr()).expect("Failed to parse sublevel");
     let caveinfo = mgr().load_caveinfo(&sublevel).expect("Failed to load caveinfo");
     let layout = Layout::generate(seed, caveinfo);
-    Ok(render(layout))
+    Ok(render(layout, LayoutRenderOptions::default()))
 }
 
-fn render(layout: Layout) -> Image {
-    let image = render_layout(&layout, &RenderHelper::new(mgr()), LayoutRenderOptions::default()).expect("Failed to render");
+/// Generates and renders the layout described by a permalink from the CLI's `permalink`
+/// command, with the same render options.
+#[wasm_bindgen]
+pub fn cavegen_permalink(permalink: &str) -> Result<Image, JsValue> {
+    set_panic_hook();
+
+    let permalink = decode_permalink(permalink, mgr()).map_err(|report| JsValue::from_str(&format!("{report}")))?;
+    let caveinfo = mgr().load_caveinfo(&permalink.sublevel).expect("Failed to load caveinfo");
+    let layout = Layout::generate(permalink.seed, caveinfo);
+    Ok(render(layout, permalink.options))
+}
+
+/// Makes a permalink for the given layout with default render options, in the same format
+/// as the CLI's `permalink` command.
+#[wasm_bindgen]
+pub fn permalink(sublevel: &str, seed: u32) -> Result<String, JsValue> {
+    let sublevel = Sublevel::try_from_str(sublevel, mgr()).map_err(|report| JsValue::from_str(&format!("{report}")))?;
+    Ok(encode_permalink(&sublevel, seed, &LayoutRenderOptions::default()))
+}
+
+fn render(layout: Layout, options: LayoutRenderOptions) -> Image {
+    let image = render_layout(&layout, &RenderHelper::new(mgr()), options).expect("Failed to render");
 
     let width = image.width();
     let height = image.height();
@@ -57,7 +80,11 @@ fn render(layout: Layout) -> Image {
 
 #[wasm_bindgen]
 pub fn query(query: &str) -> Result<Image, JsValue> {
-    let query = StructuralQuery::try_parse(query, mgr()).map_err(|_| JsValue::NULL)?;
+    let query = StructuralQuery::try_parse(query, mgr()).map_err(|report| {
+        report
+            .downcast_ref::<QueryParseError>()
+            .map_or(JsValue::NULL, |err| JsValue::from_str(&err.to_string()))
+    })?;
     let sublevel = &query.clauses[0].sublevel;
     let caveinfo = mgr().load_caveinfo(&sublevel).expect("Failed to load caveinfo");
 
@@ -70,11 +97,23 @@ pub fn query(query: &str) -> Result<Image, JsValue> {
         let seed = floor(random() * pow(2.0, 32.0)) as u32;
         if query.matches(seed, mgr()) {
             let layout = Layout::generate(seed, &caveinfo);
-            break Ok(render(layout));
+            break Ok(render(layout, LayoutRenderOptions::default()));
         }
     }
 }
 
+/// Formats a seed the same way the CLI does, e.g. "0x1234ABCD".
+#[wasm_bindgen(js_name = formatSeed)]
+pub fn format_seed_js(seed: u32) -> String {
+    format_seed(seed)
+}
+
+/// Parses a seed the same way the CLI does. Returns `null` for invalid seeds.
+#[wasm_bindgen(js_name = parseSeed)]
+pub fn parse_seed_js(seed: &str) -> Result<u32, JsValue> {
+    parse_seed(seed).map(|seed| seed.as_u32()).map_err(|_| JsValue::NULL)
+}
+
 #[wasm_bindgen]
 pub fn draw_to_canvas(image: Image, canvas_id: String) -> Result<(), JsValue> {
     let window = web_sys::window().unwrap();
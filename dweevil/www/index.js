@@ -30,19 +30,39 @@ async function generate() {
     var seed;
     if (seedInput.value === "") {
         seed = Math.floor(Math.random() * Math.pow(2, 32));
-        currentSeedText.innerText = "0x" + seed.toString(16).toUpperCase();
+        currentSeedText.innerText = dweevil.formatSeed(seed);
     }
     else {
-        seed = Number(seedInput.value);
+        try {
+            seed = dweevil.parseSeed(seedInput.value);
+        }
+        catch {
+            currentSeedText.innerText = "Invalid seed";
+            return;
+        }
         currentSeedText.innerText = "";
     }
     if (sublevelInput.value !== "") {
         var img = dweevil.cavegen(sublevelInput.value, seed);
         dweevil.draw_to_canvas(img, "cr-canvas");
+        history.replaceState(null, "", "#" + dweevil.permalink(sublevelInput.value, seed));
     }
     queryError.hidden = true;
 }
 
+// Open the layout described by a permalink, e.g. one printed by the CLI's `permalink` command.
+function openPermalink(permalink) {
+    try {
+        var img = dweevil.cavegen_permalink(permalink);
+        dweevil.draw_to_canvas(img, "cr-canvas");
+        currentSeedText.innerText = "";
+        return true;
+    }
+    catch {
+        return false;
+    }
+}
+
 async function query() {
     if (queryInput.value !== "") {
         try {
@@ -58,5 +78,7 @@ async function query() {
     
 }
 
-// Start off by generating a layout
-generate();
+// Start off by opening the permalink in the URL if there is one, or generating a layout otherwise
+if (window.location.hash.length <= 1 || !openPermalink(window.location.hash.substring(1))) {
+    generate();
+}